@@ -0,0 +1,186 @@
+use crate::protocol::protocol::ApiMessage;
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::process::Command;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+// ==========================================
+// VERROUILLAGE AUTOMATIQUE DE LA SESSION
+// ==========================================
+//
+// Trois déclencheurs, réglables dans bindkey_config.toml ([auto_lock]) : la BindKey de la
+// session est débranchée, l'utilisateur reste inactif trop longtemps, ou le verrouillage
+// d'écran du bureau s'enclenche (LockedHint de systemd-logind). Verrouiller efface les
+// jetons de la mémoire, démonte les volumes BindKey via udisks et revient à la connexion.
+
+const SCREEN_LOCK_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct LockPolicy {
+    pub on_unplug: bool,
+    // 0 : jamais de verrouillage pour inactivité
+    pub idle_minutes: u64,
+    pub on_screen_lock: bool,
+}
+
+impl Default for LockPolicy {
+    fn default() -> Self {
+        LockPolicy {
+            on_unplug: true,
+            idle_minutes: 15,
+            on_screen_lock: true,
+        }
+    }
+}
+
+impl LockPolicy {
+    // Une opération en cours sur la clé compte comme de l'activité : voir BindKeyApp::update
+    pub fn idle_expired(&self, last_activity: Instant, now: Instant) -> bool {
+        self.idle_minutes > 0
+            && now.saturating_duration_since(last_activity)
+                >= Duration::from_secs(self.idle_minutes * 60)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LockReason {
+    KeyUnplugged,
+    Idle(u64),
+    ScreenLocked,
+}
+
+impl fmt::Display for LockReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockReason::KeyUnplugged => write!(f, "BindKey débranchée"),
+            LockReason::Idle(minutes) => write!(f, "inactivité depuis {} min", minutes),
+            LockReason::ScreenLocked => write!(f, "écran verrouillé"),
+        }
+    }
+}
+
+// Surveille le verrouillage d'écran de la session graphique (loginctl, LockedHint).
+// Seul le passage à l'état verrouillé déclenche : l'écran déjà verrouillé au démarrage
+// ne verrouille rien.
+pub fn spawn_screen_lock_watcher(
+    policy: &LockPolicy,
+    sender: Sender<ApiMessage>,
+    ctx: egui::Context,
+) {
+    if !policy.on_screen_lock {
+        return;
+    }
+    let session_id = std::env::var("XDG_SESSION_ID").unwrap_or_else(|_| "auto".to_string());
+
+    let spawned = std::thread::Builder::new()
+        .name("bindkey-screen-lock".to_string())
+        .spawn(move || {
+            let mut was_locked = match screen_locked(&session_id) {
+                Ok(locked) => locked,
+                Err(e) => {
+                    eprintln!("[VERROUILLAGE] Verrouillage d'écran non surveillé ({})", e);
+                    return;
+                }
+            };
+            loop {
+                std::thread::sleep(SCREEN_LOCK_POLL_INTERVAL);
+                // Une erreur passagère (logind redémarré...) ne vaut pas verrouillage
+                let Ok(locked) = screen_locked(&session_id) else {
+                    continue;
+                };
+                if locked && !was_locked {
+                    let _ = sender.send(ApiMessage::Lock(LockReason::ScreenLocked));
+                    ctx.request_repaint();
+                }
+                was_locked = locked;
+            }
+        });
+
+    if let Err(e) = spawned {
+        eprintln!(
+            "[VERROUILLAGE] Impossible de lancer la surveillance de l'écran : {}",
+            e
+        );
+    }
+}
+
+fn screen_locked(session_id: &str) -> Result<bool, String> {
+    let output = Command::new("loginctl")
+        .args(["show-session", session_id, "-p", "LockedHint", "--value"])
+        .output()
+        .map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    match String::from_utf8_lossy(&output.stdout).trim() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        other => Err(format!("LockedHint inattendu : {}", other)),
+    }
+}
+
+// Démonte (de force) toute partition montée qui appartient à l'un de ces disques ou
+// volumes. Appel bloquant : à lancer hors du thread de l'interface.
+pub fn unmount_volumes(device_paths: &[String]) {
+    let mounts = std::fs::read_to_string("/proc/mounts").unwrap_or_default();
+    let mut mounted: Vec<&str> = mounts
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .filter(|source| device_paths.iter().any(|path| on_device(source, path)))
+        .collect();
+    mounted.sort();
+    mounted.dedup();
+
+    for source in mounted {
+        match Command::new("/usr/bin/udisksctl")
+            .args(["unmount", "-f", "-b", source])
+            .output()
+        {
+            Ok(output) if output.status.success() => {
+                println!("[VERROUILLAGE] {} démonté", source);
+            }
+            Ok(output) => eprintln!(
+                "[VERROUILLAGE] Échec du démontage de {} : {}",
+                source,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+            Err(e) => eprintln!("[VERROUILLAGE] udisksctl introuvable : {}", e),
+        }
+    }
+}
+
+// /dev/sdb, /dev/sdb1 et /dev/nvme0n1p2 relèvent de /dev/sdb ou /dev/nvme0n1 ; pas /dev/sdbc1
+fn on_device(source: &str, device_path: &str) -> bool {
+    source.strip_prefix(device_path).is_some_and(|rest| {
+        rest.strip_prefix('p')
+            .unwrap_or(rest)
+            .chars()
+            .all(|c| c.is_ascii_digit())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idle_lock_follows_the_configured_delay() {
+        let start = Instant::now();
+        let policy = LockPolicy::default();
+        assert!(!policy.idle_expired(start, start + Duration::from_secs(14 * 60)));
+        assert!(policy.idle_expired(start, start + Duration::from_secs(15 * 60)));
+
+        let never = LockPolicy {
+            idle_minutes: 0,
+            ..LockPolicy::default()
+        };
+        assert!(!never.idle_expired(start, start + Duration::from_secs(24 * 3600)));
+
+        let partial: LockPolicy = toml::from_str("idle_minutes = 5").unwrap();
+        assert!(partial.on_unplug && partial.on_screen_lock);
+        assert_eq!(partial.idle_minutes, 5);
+    }
+}
@@ -0,0 +1,1294 @@
+// Émulateur logiciel de BindKey : ouvre un pseudo-terminal et parle le même protocole
+// que la vraie clé (lignes texte, puis trames postcard après "proto=framed").
+// Les clés P-256 sont réelles, donc les signatures et les partages produits sont
+// vérifiables par le serveur.
+//
+// Usage : cargo run --bin bindkey_emulator -- [--sn BK-EMU-0001] [--link /tmp/bindkey0] [--text-only]
+//         [--firmware 1.2.0 | --firmware legacy] [--fault se|fingerprint|slave|medium|hang]
+//         [--org-key <clé publique de l'organisation, SEC1 hex>]
+// puis `device_port = "/tmp/bindkey0"` dans bindkey_config.toml.
+
+#[allow(dead_code)]
+#[path = "../server_challenge.rs"]
+mod server_challenge;
+#[allow(dead_code, unused_imports)]
+#[path = "../protocol/share_protocol.rs"]
+mod share_protocol;
+#[allow(dead_code)]
+#[path = "../share_signature.rs"]
+mod share_signature;
+#[allow(dead_code)]
+#[path = "../protocol/text_protocol.rs"]
+mod text_protocol;
+
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use nix::poll::{PollFd, PollFlags, poll};
+use nix::pty::openpty;
+use nix::sys::termios::{SetArg, cfmakeraw, tcgetattr, tcsetattr};
+use p256::ecdsa::signature::{Signer, Verifier};
+use p256::ecdsa::{Signature, SigningKey, VerifyingKey};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::{PublicKey, SecretKey};
+use rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use share_protocol::{
+    DeviceEvent, FRAME_MAGIC, FRAMED_NEGOTIATION_COMMAND, FRAMED_NEGOTIATION_REPLY,
+    FingerprintSlot, Frame, InventoryShare, InventoryVolume, MessageType, SuccessData, UsbCommand,
+    UsbResponse,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::AsFd;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use text_protocol::{LIST_SEPARATOR, escape, split_unescaped, unescape};
+
+const SHARE_KEK_CONTEXT: &[u8] = b"bindkey-share-v1";
+// Mêmes contextes que src/payload_bundle.rs
+const PAYLOAD_SIGNING_CONTEXT: &[u8] = b"bindkey-payload-v1";
+const PAYLOAD_KEK_CONTEXT: &[u8] = b"bindkey-payload-kek-v1";
+// Temps simulé de pose / retrait du doigt sur le capteur
+const FINGER_DELAY: Duration = Duration::from_millis(400);
+const EMULATED_FIRMWARE: &str = "1.2.0";
+const EMULATED_CAPABILITIES: [&str; 14] = [
+    "framed",
+    "events",
+    "lba",
+    "share",
+    "format",
+    "ota",
+    "payload",
+    "diag",
+    "fp_mgmt",
+    "inventory",
+    "abort",
+    "signed_challenge",
+    "share_sig",
+    "factory_wipe",
+];
+// Mêmes bornes que src/server_challenge.rs
+const CHALLENGE_CLOCK_SKEW_SECS: u64 = 60;
+const CHALLENGE_MAX_LIFETIME_SECS: u64 = 300;
+const EMULATED_SHARE_SLOTS: u16 = 8;
+const FINGERPRINT_CAPACITY: u16 = 5;
+
+struct Volume {
+    name: String,
+    lba_start: u64,
+    lba_end: u64,
+    key: [u8; 32],
+}
+
+struct InstalledShare {
+    volume_id: String,
+    #[allow(dead_code)]
+    key: [u8; 32],
+}
+
+// Image en cours de réception ; gardée d'une session à l'autre pour la reprise
+struct PendingFirmware {
+    version: String,
+    size: u64,
+    sha256: String,
+    data: Vec<u8>,
+    verified: bool,
+}
+
+struct EmulatedBindKey {
+    sn: String,
+    signing_key: SigningKey,
+    ecdh_key: SecretKey,
+    volumes: BTreeMap<String, Volume>,
+    share_slots: BTreeMap<u16, InstalledShare>,
+    // Champs reçus ligne par ligne pour les commandes multi-lignes (LBA, partages)
+    pending: HashMap<String, String>,
+    // Simule un firmware ancien qui ne connaît que le protocole texte
+    text_only: bool,
+    // Version annoncée ; None simule un firmware antérieur à la commande "version"
+    firmware: Option<String>,
+    pending_firmware: Option<PendingFirmware>,
+    // Composant déclaré en panne pour l'autotest (--fault) ; "hang" : la création de volume
+    // ne répond jamais, seul un abort l'interrompt
+    fault: Option<String>,
+    // Ligne série relue pendant les attentes du doigt, pour y guetter un abort
+    input: Option<File>,
+    // Empreintes enregistrées, par slot
+    templates: BTreeMap<u16, String>,
+    // Levé par un FingerprintVerify réussi, consommé par la commande suivante
+    fp_authorized: bool,
+    // Clé de l'organisation épinglée (--org-key) : seuls ses challenges sont signés
+    org_key: Option<VerifyingKey>,
+}
+
+impl EmulatedBindKey {
+    fn new(sn: String, text_only: bool, firmware: Option<String>, fault: Option<String>) -> Self {
+        EmulatedBindKey {
+            sn,
+            signing_key: SigningKey::random(&mut OsRng),
+            ecdh_key: SecretKey::random(&mut OsRng),
+            volumes: BTreeMap::new(),
+            share_slots: BTreeMap::new(),
+            pending: HashMap::new(),
+            text_only,
+            firmware,
+            pending_firmware: None,
+            fault,
+            input: None,
+            templates: BTreeMap::from([(0, "Doigt principal".to_string())]),
+            fp_authorized: false,
+            org_key: None,
+        }
+    }
+
+    fn pub_sign_hex(&self) -> String {
+        hex::encode(
+            self.signing_key
+                .verifying_key()
+                .to_encoded_point(false)
+                .as_bytes(),
+        )
+    }
+
+    fn pub_ecdh_hex(&self) -> String {
+        hex::encode(
+            self.ecdh_key
+                .public_key()
+                .to_encoded_point(false)
+                .as_bytes(),
+        )
+    }
+
+    fn share_kek(&self, peer_pubkey_hex: &str) -> Result<[u8; 32], String> {
+        self.derive_kek(SHARE_KEK_CONTEXT, peer_pubkey_hex)
+    }
+
+    fn derive_kek(&self, context: &[u8], peer_pubkey_hex: &str) -> Result<[u8; 32], String> {
+        let bytes = hex::decode(peer_pubkey_hex).map_err(|_| "bad_pubkey".to_string())?;
+        let peer = PublicKey::from_sec1_bytes(&bytes).map_err(|_| "bad_pubkey".to_string())?;
+        let shared =
+            p256::ecdh::diffie_hellman(self.ecdh_key.to_nonzero_scalar(), peer.as_affine());
+
+        let mut hasher = Sha256::new();
+        hasher.update(context);
+        hasher.update(shared.raw_secret_bytes());
+        Ok(hasher.finalize().into())
+    }
+
+    // Attente simulée du capteur ; un abort de l'hôte l'interrompt (réponse ERR=cancelled)
+    fn wait_finger(&mut self) -> Result<(), String> {
+        let Some(input) = self.input.as_mut() else {
+            std::thread::sleep(FINGER_DELAY);
+            return Ok(());
+        };
+        let deadline = Instant::now() + FINGER_DELAY;
+        let mut chunk = [0u8; 256];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(());
+            }
+            let millis = u16::try_from(remaining.as_millis()).unwrap_or(u16::MAX);
+            let mut fds = [PollFd::new(input.as_fd(), PollFlags::POLLIN)];
+            if poll(&mut fds, millis).map_err(|e| e.to_string())? == 0 {
+                continue;
+            }
+            let n = input.read(&mut chunk).map_err(|e| e.to_string())?;
+            if is_abort(&chunk[..n]) {
+                println!("[EMU] << abort : commande en cours interrompue");
+                return Err("cancelled".to_string());
+            }
+            println!("[EMU] {} octet(s) ignoré(s) pendant l'attente du doigt", n);
+        }
+    }
+
+    // ==========================================
+    // COMMANDES (communes aux deux transports)
+    // ==========================================
+
+    // `emit` envoie immédiatement un événement intermédiaire sur la ligne série
+    fn execute(
+        &mut self,
+        command: UsbCommand,
+        emit: &mut dyn FnMut(DeviceEvent),
+    ) -> Result<SuccessData, String> {
+        // L'autorisation biométrique ne vaut que pour la commande qui suit immédiatement
+        let fp_authorized = std::mem::take(&mut self.fp_authorized);
+
+        match command {
+            UsbCommand::Uid => Ok(SuccessData::Uid {
+                sn: self.sn.clone(),
+            }),
+            UsbCommand::GetDevice => Ok(SuccessData::DeviceInfo {
+                device_name: "BINDKEY EMULATOR".to_string(),
+                device_size: 0.0,
+                device_available_size: 0.0,
+            }),
+            UsbCommand::ConfirmModification => Ok(SuccessData::Ack),
+            UsbCommand::Version => match &self.firmware {
+                Some(version) => Ok(SuccessData::FirmwareInfo {
+                    version: version.clone(),
+                    capabilities: EMULATED_CAPABILITIES
+                        .iter()
+                        .map(|c| c.to_string())
+                        .collect(),
+                }),
+                None => Err("unknown_command".to_string()),
+            },
+            UsbCommand::Enroll => {
+                // Trois passages du doigt, comme le capteur réel
+                for pass in 1..=3 {
+                    emit(DeviceEvent::Prompt(if pass == 1 {
+                        "place_finger".to_string()
+                    } else {
+                        "place_again".to_string()
+                    }));
+                    self.wait_finger()?;
+                    emit(DeviceEvent::FingerprintQuality("good".to_string()));
+                    emit(DeviceEvent::Progress {
+                        current: pass,
+                        total: 3,
+                    });
+                    emit(DeviceEvent::Prompt("lift_finger".to_string()));
+                    self.wait_finger()?;
+                }
+                // Une nouvelle identité est générée à chaque enrôlement, comme sur la vraie clé
+                self.signing_key = SigningKey::random(&mut OsRng);
+                self.ecdh_key = SecretKey::random(&mut OsRng);
+                self.templates = BTreeMap::from([(0, "Doigt principal".to_string())]);
+                Ok(SuccessData::EnrollmentInfo {
+                    sn: self.sn.clone(),
+                    pub_sign: self.pub_sign_hex(),
+                    pub_ecdh: self.pub_ecdh_hex(),
+                })
+            }
+            // Une clé qui a épinglé l'organisation ne signe plus de challenge brut
+            UsbCommand::Challenge { .. } if self.org_key.is_some() => {
+                Err("unsigned_challenge".to_string())
+            }
+            UsbCommand::SignedChallenge {
+                context,
+                challenge,
+                session_id,
+                expires_at,
+                server_signature,
+            } => {
+                // Tout est vérifié avant de demander le doigt
+                let message = self.check_org_order(
+                    server_challenge::LOGIN_CHALLENGE_CONTEXT,
+                    &context,
+                    &challenge,
+                    &session_id,
+                    expires_at,
+                    &server_signature,
+                )?;
+
+                emit(DeviceEvent::Prompt("place_finger".to_string()));
+                self.wait_finger()?;
+                emit(DeviceEvent::FingerprintQuality("good".to_string()));
+                let signature: Signature = self.signing_key.sign(&message);
+                Ok(SuccessData::Signature {
+                    signature: hex::encode(signature.to_bytes()),
+                })
+            }
+            UsbCommand::FactoryWipe => {
+                if self.templates.is_empty() {
+                    return Err("no_fingerprint".to_string());
+                }
+                emit(DeviceEvent::Prompt("place_finger".to_string()));
+                self.wait_finger()?;
+                emit(DeviceEvent::FingerprintQuality("good".to_string()));
+                self.wipe();
+                Ok(SuccessData::Ack)
+            }
+            UsbCommand::FactoryWipeAuthorized {
+                context,
+                challenge,
+                session_id,
+                expires_at,
+                server_signature,
+            } => {
+                self.check_org_order(
+                    server_challenge::WIPE_CHALLENGE_CONTEXT,
+                    &context,
+                    &challenge,
+                    &session_id,
+                    expires_at,
+                    &server_signature,
+                )?;
+                self.wipe();
+                Ok(SuccessData::Ack)
+            }
+            UsbCommand::Challenge { challenge } => {
+                emit(DeviceEvent::Prompt("place_finger".to_string()));
+                self.wait_finger()?;
+                emit(DeviceEvent::FingerprintQuality("good".to_string()));
+                let signature: Signature = self.signing_key.sign(challenge.as_bytes());
+                Ok(SuccessData::Signature {
+                    signature: hex::encode(signature.to_bytes()),
+                })
+            }
+            UsbCommand::DeleteVolume { volume_id } => {
+                // Un volume reçu par partage n'existe que dans un slot : on le retire aussi
+                let had_volume = self.volumes.remove(&volume_id).is_some();
+                let shares_before = self.share_slots.len();
+                self.share_slots
+                    .retain(|_, share| share.volume_id != volume_id);
+                if had_volume || self.share_slots.len() != shares_before {
+                    Ok(SuccessData::Ack)
+                } else {
+                    Err("unknown_volume".to_string())
+                }
+            }
+            UsbCommand::InitFormat => {
+                let to_delete: Vec<String> = self.volumes.keys().cloned().collect();
+                self.volumes.clear();
+                self.share_slots.clear();
+                Ok(SuccessData::FormatInitialized { to_delete })
+            }
+            UsbCommand::RegisterLbaRange {
+                volume_name,
+                volume_id,
+                lba_start,
+                lba_end,
+            } => {
+                if self.fault.as_deref() == Some("hang") {
+                    println!("[EMU] --fault hang : pas de réponse avant un abort");
+                    loop {
+                        self.wait_finger()?;
+                    }
+                }
+                self.register_volume(volume_name, volume_id, lba_start, lba_end)
+            }
+            UsbCommand::WrapShare {
+                volume_id,
+                target_sn,
+                target_pubkey,
+                target_slot,
+            } => self.wrap_share(&volume_id, &target_sn, &target_pubkey, target_slot),
+            UsbCommand::InstallShare {
+                slot,
+                source_pubkey,
+                wrapped,
+            } => self.install_share(slot, &source_pubkey, &wrapped),
+            UsbCommand::FirmwareBegin {
+                version,
+                size,
+                sha256,
+            } => {
+                let resumable = self
+                    .pending_firmware
+                    .as_ref()
+                    .is_some_and(|p| p.sha256 == sha256 && p.size == size);
+                if !resumable {
+                    self.pending_firmware = Some(PendingFirmware {
+                        version,
+                        size,
+                        sha256,
+                        data: Vec::new(),
+                        verified: false,
+                    });
+                }
+                let offset = self.pending_firmware.as_ref().map_or(0, |p| p.data.len());
+                Ok(SuccessData::FirmwareProgress {
+                    offset: offset as u64,
+                })
+            }
+            UsbCommand::FirmwareChunk { offset, data } => {
+                let pending = self.pending_firmware.as_mut().ok_or("no_update")?;
+                // Bloc hors séquence : on ignore et on redonne l'offset attendu
+                if offset == pending.data.len() as u64 && offset + data.len() as u64 <= pending.size
+                {
+                    pending.data.extend_from_slice(&data);
+                }
+                Ok(SuccessData::FirmwareProgress {
+                    offset: pending.data.len() as u64,
+                })
+            }
+            UsbCommand::FirmwareVerify => {
+                let pending = self.pending_firmware.as_mut().ok_or("no_update")?;
+                if pending.data.len() as u64 != pending.size {
+                    return Err("incomplete".to_string());
+                }
+                let sha256 = hex::encode(Sha256::digest(&pending.data));
+                pending.verified = sha256 == pending.sha256;
+                Ok(SuccessData::FirmwareHash { sha256 })
+            }
+            UsbCommand::FirmwareCommit => match self.pending_firmware.take() {
+                Some(pending) if pending.verified => {
+                    println!("[EMU] Firmware {} installé", pending.version);
+                    self.firmware = Some(pending.version.trim_start_matches('v').to_string());
+                    Ok(SuccessData::Ack)
+                }
+                other => {
+                    self.pending_firmware = other;
+                    Err("not_verified".to_string())
+                }
+            },
+            UsbCommand::FirmwareAbort => {
+                self.pending_firmware = None;
+                Ok(SuccessData::Ack)
+            }
+            // Reçu ici, aucune commande n'était en cours : rien à interrompre
+            UsbCommand::Abort => Ok(SuccessData::Ack),
+            UsbCommand::PayloadSign { digest } => {
+                let digest = hex::decode(&digest)
+                    .ok()
+                    .filter(|d| d.len() == 32)
+                    .ok_or("bad_digest")?;
+                emit(DeviceEvent::Prompt("place_finger".to_string()));
+                self.wait_finger()?;
+                emit(DeviceEvent::FingerprintQuality("good".to_string()));
+
+                let mut message = PAYLOAD_SIGNING_CONTEXT.to_vec();
+                message.extend_from_slice(&digest);
+                let signature: Signature = self.signing_key.sign(&message);
+                Ok(SuccessData::PayloadSignature {
+                    signature: hex::encode(signature.to_bytes()),
+                    pub_sign: self.pub_sign_hex(),
+                })
+            }
+            UsbCommand::PayloadUnwrap {
+                ephemeral_pubkey,
+                wrapped,
+            } => {
+                let kek = self.derive_kek(PAYLOAD_KEK_CONTEXT, &ephemeral_pubkey)?;
+                let wrapped = match hex::decode(&wrapped) {
+                    Ok(bytes) if bytes.len() > 12 => bytes,
+                    _ => return Err("bad_wrapped".to_string()),
+                };
+                emit(DeviceEvent::Prompt("place_finger".to_string()));
+                self.wait_finger()?;
+
+                let cipher = Aes256Gcm::new_from_slice(&kek).expect("clé AES de 32 octets");
+                let key = cipher
+                    .decrypt(
+                        Nonce::from_slice(&wrapped[..12]),
+                        Payload {
+                            msg: &wrapped[12..],
+                            aad: self.sn.as_bytes(),
+                        },
+                    )
+                    .map_err(|_| "unwrap_failed".to_string())?;
+                Ok(SuccessData::PayloadKey {
+                    key: hex::encode(key),
+                })
+            }
+            UsbCommand::SelfTest => {
+                let status = |component: &str, error: &str, ok: &str| {
+                    if self.fault.as_deref() == Some(component) {
+                        error.to_string()
+                    } else {
+                        ok.to_string()
+                    }
+                };
+                Ok(SuccessData::SelfTest {
+                    secure_element: status("se", "absent", "ok:ATECC608B"),
+                    fingerprint_sensor: status("fingerprint", "no_response", "ok"),
+                    slave_link: status("slave", "timeout", "ok"),
+                    medium: status("medium", "none", "ok:émulé"),
+                    share_slots_free: EMULATED_SHARE_SLOTS
+                        .saturating_sub(self.share_slots.len() as u16),
+                    share_slots_total: EMULATED_SHARE_SLOTS,
+                })
+            }
+            UsbCommand::FingerprintVerify => {
+                emit(DeviceEvent::Prompt("place_enrolled_finger".to_string()));
+                self.wait_finger()?;
+                let Some(&slot) = self.templates.keys().next() else {
+                    return Err("no_template".to_string());
+                };
+                // --fault fingerprint : le doigt n'est jamais reconnu
+                if self.fault.as_deref() == Some("fingerprint") {
+                    return Err("no_match".to_string());
+                }
+                emit(DeviceEvent::FingerprintQuality("good".to_string()));
+                self.fp_authorized = true;
+                Ok(SuccessData::FingerprintMatch { slot })
+            }
+            UsbCommand::FingerprintList => {
+                if !fp_authorized {
+                    return Err("not_authorized".to_string());
+                }
+                Ok(self.fingerprint_slots())
+            }
+            UsbCommand::FingerprintEnroll { label } => {
+                if !fp_authorized {
+                    return Err("not_authorized".to_string());
+                }
+                let slot = (0..FINGERPRINT_CAPACITY)
+                    .find(|s| !self.templates.contains_key(s))
+                    .ok_or("no_free_slot")?;
+                for pass in 1..=3 {
+                    emit(DeviceEvent::Prompt(if pass == 1 {
+                        "place_finger".to_string()
+                    } else {
+                        "place_again".to_string()
+                    }));
+                    self.wait_finger()?;
+                    // Premier passage trop rapide : la clé fait recommencer
+                    if pass == 1 {
+                        emit(DeviceEvent::FingerprintQuality("low".to_string()));
+                        emit(DeviceEvent::Prompt("place_again".to_string()));
+                        self.wait_finger()?;
+                    }
+                    emit(DeviceEvent::FingerprintQuality("good".to_string()));
+                    emit(DeviceEvent::Progress {
+                        current: pass,
+                        total: 3,
+                    });
+                    emit(DeviceEvent::Prompt("lift_finger".to_string()));
+                    self.wait_finger()?;
+                }
+                let label = if label.is_empty() {
+                    format!("Doigt {}", slot + 1)
+                } else {
+                    label
+                };
+                println!(
+                    "[EMU] Empreinte \"{}\" enregistrée dans le slot {}",
+                    label, slot
+                );
+                self.templates.insert(slot, label);
+                Ok(self.fingerprint_slots())
+            }
+            UsbCommand::FingerprintDelete { slot } => {
+                if !fp_authorized {
+                    return Err("not_authorized".to_string());
+                }
+                if !self.templates.contains_key(&slot) {
+                    return Err("unknown_slot".to_string());
+                }
+                // Sans empreinte, la clé ne pourrait plus rien signer
+                if self.templates.len() == 1 {
+                    return Err("last_template".to_string());
+                }
+                self.templates.remove(&slot);
+                println!("[EMU] Empreinte du slot {} supprimée", slot);
+                Ok(self.fingerprint_slots())
+            }
+            UsbCommand::Inventory => Ok(SuccessData::Inventory {
+                volumes: self
+                    .volumes
+                    .iter()
+                    .map(|(volume_id, v)| InventoryVolume {
+                        volume_id: volume_id.clone(),
+                        name: v.name.clone(),
+                        lba_start: v.lba_start,
+                        lba_end: v.lba_end,
+                    })
+                    .collect(),
+                shares: self
+                    .share_slots
+                    .iter()
+                    .map(|(slot, share)| InventoryShare {
+                        slot: *slot,
+                        volume_id: share.volume_id.clone(),
+                    })
+                    .collect(),
+            }),
+        }
+    }
+
+    // Ordre signé par l'organisation (challenge de connexion, remise à zéro) : renvoie le
+    // message vérifié, que la clé signe ensuite s'il s'agit d'une connexion
+    fn check_org_order(
+        &self,
+        expected_context: &str,
+        context: &str,
+        challenge: &str,
+        session_id: &str,
+        expires_at: u64,
+        server_signature: &str,
+    ) -> Result<Vec<u8>, String> {
+        let org_key = self.org_key.ok_or("no_org_key")?;
+        if context != expected_context {
+            return Err("wrong_context".to_string());
+        }
+        let message =
+            server_challenge::login_message(context, challenge, session_id, &self.sn, expires_at);
+        let valid = hex::decode(server_signature)
+            .ok()
+            .and_then(|bytes| Signature::from_slice(&bytes).ok())
+            .is_some_and(|signature| org_key.verify(&message, &signature).is_ok());
+        if !valid {
+            return Err("bad_org_signature".to_string());
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        if expires_at + CHALLENGE_CLOCK_SKEW_SECS < now
+            || expires_at > now + CHALLENGE_MAX_LIFETIME_SECS + CHALLENGE_CLOCK_SKEW_SECS
+        {
+            return Err("expired".to_string());
+        }
+        Ok(message)
+    }
+
+    // Le SN (matériel) et la clé d'organisation épinglée survivent à la remise à zéro
+    fn wipe(&mut self) {
+        self.signing_key = SigningKey::random(&mut OsRng);
+        self.ecdh_key = SecretKey::random(&mut OsRng);
+        self.templates.clear();
+        self.volumes.clear();
+        self.share_slots.clear();
+        self.pending.clear();
+        println!("[EMU] Remise à zéro d'usine : identité, empreintes, volumes et partages effacés");
+    }
+
+    fn fingerprint_slots(&self) -> SuccessData {
+        SuccessData::FingerprintSlots {
+            slots: self
+                .templates
+                .iter()
+                .map(|(slot, label)| FingerprintSlot {
+                    slot: *slot,
+                    label: label.clone(),
+                })
+                .collect(),
+            capacity: FINGERPRINT_CAPACITY,
+        }
+    }
+
+    fn register_volume(
+        &mut self,
+        name: String,
+        volume_id: String,
+        lba_start: u64,
+        lba_end: u64,
+    ) -> Result<SuccessData, String> {
+        if lba_end < lba_start {
+            return Err("bad_lba".to_string());
+        }
+        let overlaps = self
+            .volumes
+            .values()
+            .any(|v| lba_start <= v.lba_end && v.lba_start <= lba_end);
+        if overlaps {
+            return Err("lba_overlap".to_string());
+        }
+
+        let mut key = [0u8; 32];
+        OsRng.fill_bytes(&mut key);
+        println!(
+            "[EMU] Volume {} ({}) enregistré : LBA {} -> {}",
+            name, volume_id, lba_start, lba_end
+        );
+        self.volumes.insert(
+            volume_id,
+            Volume {
+                name,
+                lba_start,
+                lba_end,
+                key,
+            },
+        );
+        Ok(SuccessData::Ack)
+    }
+
+    fn wrap_share(
+        &self,
+        volume_id: &str,
+        target_sn: &str,
+        target_pubkey: &str,
+        target_slot: u16,
+    ) -> Result<SuccessData, String> {
+        let volume = self
+            .volumes
+            .get(volume_id)
+            .ok_or_else(|| "unknown_volume".to_string())?;
+        let kek = self.share_kek(target_pubkey)?;
+
+        // Clair : clé du volume (32 octets) suivie de son identifiant
+        let mut plaintext = volume.key.to_vec();
+        plaintext.extend_from_slice(volume_id.as_bytes());
+
+        let mut nonce = [0u8; 12];
+        OsRng.fill_bytes(&mut nonce);
+        let cipher = Aes256Gcm::new_from_slice(&kek).expect("clé AES de 32 octets");
+        let slot = target_slot.to_string();
+        let ciphertext = cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &plaintext,
+                    aad: slot.as_bytes(),
+                },
+            )
+            .map_err(|_| "wrap_failed".to_string())?;
+
+        let mut wrapped = nonce.to_vec();
+        wrapped.extend_from_slice(&ciphertext);
+        println!(
+            "[EMU] Volume {} ({}) partagé vers {} (slot {})",
+            volume.name, volume_id, target_sn, target_slot
+        );
+        // Métadonnées signées : le destinataire vérifie l'émetteur avant d'installer
+        let wrapped = hex::encode(wrapped);
+        let message = share_signature::signed_message(
+            volume_id,
+            &self.sn,
+            &self.pub_ecdh_hex(),
+            target_sn,
+            target_slot,
+            &wrapped,
+        );
+        let signature: Signature = self.signing_key.sign(&message);
+        Ok(SuccessData::SignedWrappedShare {
+            source_sn: self.sn.clone(),
+            wrapped,
+            signature: hex::encode(signature.to_bytes()),
+        })
+    }
+
+    fn install_share(
+        &mut self,
+        slot: u16,
+        source_pubkey: &str,
+        wrapped_hex: &str,
+    ) -> Result<SuccessData, String> {
+        let kek = self.share_kek(source_pubkey)?;
+        let wrapped = match hex::decode(wrapped_hex) {
+            Ok(bytes) if bytes.len() > 12 + 32 => bytes,
+            _ => return Err("bad_wrapped".to_string()),
+        };
+
+        let cipher = Aes256Gcm::new_from_slice(&kek).expect("clé AES de 32 octets");
+        let slot_aad = slot.to_string();
+        let plaintext = cipher
+            .decrypt(
+                Nonce::from_slice(&wrapped[..12]),
+                Payload {
+                    msg: &wrapped[12..],
+                    aad: slot_aad.as_bytes(),
+                },
+            )
+            .map_err(|_| "unwrap_failed".to_string())?;
+        if plaintext.len() < 32 {
+            return Err("unwrap_failed".to_string());
+        }
+
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&plaintext[..32]);
+        let volume_id = String::from_utf8_lossy(&plaintext[32..]).to_string();
+        println!(
+            "[EMU] Partage du volume {} installé dans le slot {}",
+            volume_id, slot
+        );
+        self.share_slots
+            .insert(slot, InstalledShare { volume_id, key });
+        Ok(SuccessData::Ack)
+    }
+
+    // ==========================================
+    // TRANSPORT TEXTE
+    // ==========================================
+
+    // Renvoie les lignes de réponse pour une ligne reçue (vide si la commande est incomplète)
+    fn handle_line(&mut self, line: &str, emit: &mut dyn FnMut(DeviceEvent)) -> Vec<String> {
+        let (key, raw) = match line.split_once('=') {
+            Some((k, v)) => (k.trim(), v.trim()),
+            None => (line.trim(), ""),
+        };
+        // Valeur déséchappée, et ses éléments pour les commandes à plusieurs arguments (a;b;c)
+        let Ok(value) = unescape(raw) else {
+            return vec!["ERR=bad_escape".to_string()];
+        };
+        let Some(fields) = split_unescaped(raw, LIST_SEPARATOR)
+            .into_iter()
+            .map(|f| unescape(f).ok())
+            .collect::<Option<Vec<String>>>()
+        else {
+            return vec!["ERR=bad_escape".to_string()];
+        };
+
+        if line == FRAMED_NEGOTIATION_COMMAND && !self.text_only {
+            return vec![
+                format!("PROTO={}", FRAMED_NEGOTIATION_REPLY),
+                "OK".to_string(),
+            ];
+        }
+
+        let command = match key {
+            "uid" => UsbCommand::Uid,
+            "getdevice" => UsbCommand::GetDevice,
+            "version" => UsbCommand::Version,
+            "cmd_modify" => UsbCommand::ConfirmModification,
+            "enroll" => UsbCommand::Enroll,
+            "challenge" => UsbCommand::Challenge {
+                challenge: value.to_string(),
+            },
+            "signed_challenge" => {
+                let [context, challenge, session_id, expires_at, server_signature] = &fields[..]
+                else {
+                    return vec!["ERR=bad_signed_challenge".to_string()];
+                };
+                let Ok(expires_at) = expires_at.parse::<u64>() else {
+                    return vec!["ERR=bad_signed_challenge".to_string()];
+                };
+                UsbCommand::SignedChallenge {
+                    context: context.to_string(),
+                    challenge: challenge.to_string(),
+                    session_id: session_id.to_string(),
+                    expires_at,
+                    server_signature: server_signature.to_string(),
+                }
+            }
+            "factory_wipe" if raw.is_empty() => UsbCommand::FactoryWipe,
+            "factory_wipe" => {
+                let [context, challenge, session_id, expires_at, server_signature] = &fields[..]
+                else {
+                    return vec!["ERR=bad_factory_wipe".to_string()];
+                };
+                let Ok(expires_at) = expires_at.parse::<u64>() else {
+                    return vec!["ERR=bad_factory_wipe".to_string()];
+                };
+                UsbCommand::FactoryWipeAuthorized {
+                    context: context.to_string(),
+                    challenge: challenge.to_string(),
+                    session_id: session_id.to_string(),
+                    expires_at,
+                    server_signature: server_signature.to_string(),
+                }
+            }
+            "delete_volume" => UsbCommand::DeleteVolume {
+                volume_id: value.to_string(),
+            },
+            "action" if value == "init_format" => UsbCommand::InitFormat,
+            "fw_begin" => {
+                let [version, size, sha256] = &fields[..] else {
+                    return vec!["ERR=bad_fw_begin".to_string()];
+                };
+                let Ok(size) = size.parse::<u64>() else {
+                    return vec!["ERR=bad_fw_begin".to_string()];
+                };
+                UsbCommand::FirmwareBegin {
+                    version: version.to_string(),
+                    size,
+                    sha256: sha256.to_string(),
+                }
+            }
+            "fw_chunk" => {
+                let parsed = match &fields[..] {
+                    [offset, data] => offset.parse::<u64>().ok().zip(hex::decode(data).ok()),
+                    _ => None,
+                };
+                let Some((offset, data)) = parsed else {
+                    return vec!["ERR=bad_fw_chunk".to_string()];
+                };
+                UsbCommand::FirmwareChunk { offset, data }
+            }
+            "payload_sign" => UsbCommand::PayloadSign {
+                digest: value.to_string(),
+            },
+            "payload_unwrap" => {
+                let [ephemeral_pubkey, wrapped] = &fields[..] else {
+                    return vec!["ERR=bad_payload_unwrap".to_string()];
+                };
+                UsbCommand::PayloadUnwrap {
+                    ephemeral_pubkey: ephemeral_pubkey.to_string(),
+                    wrapped: wrapped.to_string(),
+                }
+            }
+            "selftest" => UsbCommand::SelfTest,
+            "fp_verify" => UsbCommand::FingerprintVerify,
+            "fp_list" => UsbCommand::FingerprintList,
+            "fp_enroll" => UsbCommand::FingerprintEnroll {
+                label: value.to_string(),
+            },
+            "fp_delete" => match value.parse::<u16>() {
+                Ok(slot) => UsbCommand::FingerprintDelete { slot },
+                Err(_) => return vec!["ERR=bad_slot".to_string()],
+            },
+            "inventory" => UsbCommand::Inventory,
+            "abort" => UsbCommand::Abort,
+            "fw_verify" => UsbCommand::FirmwareVerify,
+            "fw_commit" => UsbCommand::FirmwareCommit,
+            "fw_abort" => UsbCommand::FirmwareAbort,
+            "volume_name"
+            | "volume_id"
+            | "lba_start"
+            | "lba_end"
+            | "share_volume_id"
+            | "share_target_sn"
+            | "share_target_pubkey"
+            | "share_target_slot"
+            | "recv_share_slot"
+            | "recv_share_source_pubkey"
+            | "recv_share_wrapped" => {
+                self.pending.insert(key.to_string(), value);
+                match self.take_multiline_command() {
+                    Some(Ok(command)) => command,
+                    Some(Err(reason)) => return vec![format!("STATUS=ERR={}", reason)],
+                    None => return Vec::new(),
+                }
+            }
+            _ => return vec!["ERR=unknown_command".to_string()],
+        };
+
+        let status_style = matches!(
+            command,
+            UsbCommand::RegisterLbaRange { .. }
+                | UsbCommand::InstallShare { .. }
+                | UsbCommand::DeleteVolume { .. }
+                | UsbCommand::InitFormat
+                | UsbCommand::FactoryWipe
+                | UsbCommand::FactoryWipeAuthorized { .. }
+        );
+
+        match self.execute(command, emit) {
+            Ok(data) => text_reply(data, status_style),
+            Err(reason) if status_style => vec![format!("STATUS=ERR={}", reason)],
+            Err(reason) => vec![format!("ERR={}", reason)],
+        }
+    }
+
+    fn take_pending(&mut self, keys: &[&str]) -> Option<Vec<String>> {
+        if !keys.iter().all(|k| self.pending.contains_key(*k)) {
+            return None;
+        }
+        Some(
+            keys.iter()
+                .map(|k| self.pending.remove(*k).unwrap_or_default())
+                .collect(),
+        )
+    }
+
+    fn take_multiline_command(&mut self) -> Option<Result<UsbCommand, String>> {
+        if let Some(f) = self.take_pending(&["volume_name", "volume_id", "lba_start", "lba_end"]) {
+            let (Ok(lba_start), Ok(lba_end)) = (f[2].parse::<u64>(), f[3].parse::<u64>()) else {
+                return Some(Err("bad_lba".to_string()));
+            };
+            return Some(Ok(UsbCommand::RegisterLbaRange {
+                volume_name: f[0].clone(),
+                volume_id: f[1].clone(),
+                lba_start,
+                lba_end,
+            }));
+        }
+
+        if let Some(f) = self.take_pending(&[
+            "share_volume_id",
+            "share_target_sn",
+            "share_target_pubkey",
+            "share_target_slot",
+        ]) {
+            let Ok(target_slot) = f[3].parse::<u16>() else {
+                return Some(Err("bad_slot".to_string()));
+            };
+            return Some(Ok(UsbCommand::WrapShare {
+                volume_id: f[0].clone(),
+                target_sn: f[1].clone(),
+                target_pubkey: f[2].clone(),
+                target_slot,
+            }));
+        }
+
+        if let Some(f) = self.take_pending(&[
+            "recv_share_slot",
+            "recv_share_source_pubkey",
+            "recv_share_wrapped",
+        ]) {
+            let Ok(slot) = f[0].parse::<u16>() else {
+                return Some(Err("bad_slot".to_string()));
+            };
+            return Some(Ok(UsbCommand::InstallShare {
+                slot,
+                source_pubkey: f[1].clone(),
+                wrapped: f[2].clone(),
+            }));
+        }
+
+        None
+    }
+
+    // ==========================================
+    // TRANSPORT EN TRAMES
+    // ==========================================
+
+    fn handle_frame(&mut self, frame: Frame, out: &mut File) -> Vec<u8> {
+        let seq = frame.seq;
+        let mut emit = |event: DeviceEvent| {
+            if let Ok(event_frame) = Frame::new(MessageType::Event, seq, &event) {
+                let _ = out.write_all(&event_frame.encode());
+                let _ = out.flush();
+            }
+        };
+
+        let response = match frame.body::<UsbCommand>() {
+            Ok(command) => {
+                println!("[EMU] << [#{}] {:?}", frame.seq, command);
+                match self.execute(command, &mut emit) {
+                    Ok(data) => UsbResponse::Success(data),
+                    Err(reason) => UsbResponse::Error(reason),
+                }
+            }
+            Err(e) => UsbResponse::Error(format!("bad_payload: {}", e)),
+        };
+        println!("[EMU] >> [#{}] {:?}", frame.seq, response);
+
+        let message_type = match response {
+            UsbResponse::Success(_) => MessageType::Response,
+            UsbResponse::Error(_) => MessageType::Error,
+        };
+        Frame::new(message_type, frame.seq, &response)
+            .map(|f| f.encode())
+            .unwrap_or_default()
+    }
+}
+
+// Valeurs échappées (voir text_protocol.rs) : un nom peut contenir ';', ':' ou un saut de ligne
+fn text_reply(data: SuccessData, status_style: bool) -> Vec<String> {
+    let field = |key: &str, value: &str| format!("{}={}", key, escape(value));
+    let mut lines = match data {
+        SuccessData::Uid { sn } => vec![field("SN", &sn)],
+        SuccessData::DeviceInfo { device_name, .. } => vec![field("DN", &device_name)],
+        SuccessData::Signature { signature } => vec![field("SIG", &signature)],
+        SuccessData::EnrollmentInfo {
+            sn,
+            pub_sign,
+            pub_ecdh,
+        } => vec![
+            field("SN", &sn),
+            field("PUB_SIGN", &pub_sign),
+            field("PUB_ECDH", &pub_ecdh),
+        ],
+        SuccessData::WrappedShare { source_sn, wrapped } => {
+            vec![field("SN", &source_sn), field("WRAPPED", &wrapped)]
+        }
+        SuccessData::SignedWrappedShare {
+            source_sn,
+            wrapped,
+            signature,
+        } => vec![
+            field("SN", &source_sn),
+            field("WRAPPED", &wrapped),
+            field("SIG", &signature),
+        ],
+        // Une ligne par ID (forme répétée)
+        SuccessData::FormatInitialized { to_delete } => {
+            to_delete.iter().map(|id| field("TO_DEL", id)).collect()
+        }
+        SuccessData::FirmwareInfo {
+            version,
+            capabilities,
+        } => vec![
+            field("FW", &version),
+            field("CAPS", &capabilities.join(",")),
+        ],
+        SuccessData::FirmwareProgress { offset } => vec![format!("FW_OFFSET={}", offset)],
+        SuccessData::FirmwareHash { sha256 } => vec![field("FW_SHA256", &sha256)],
+        SuccessData::PayloadSignature {
+            signature,
+            pub_sign,
+        } => vec![field("SIG", &signature), field("PUB_SIGN", &pub_sign)],
+        SuccessData::PayloadKey { key } => vec![field("PAYLOAD_KEY", &key)],
+        SuccessData::SelfTest {
+            secure_element,
+            fingerprint_sensor,
+            slave_link,
+            medium,
+            share_slots_free,
+            share_slots_total,
+        } => vec![
+            field("SE", &secure_element),
+            field("FP_SENSOR", &fingerprint_sensor),
+            field("SLAVE_LINK", &slave_link),
+            field("MEDIUM", &medium),
+            format!("SHARE_SLOTS={}/{}", share_slots_free, share_slots_total),
+        ],
+        SuccessData::FingerprintMatch { slot } => vec![format!("FP_MATCH={}", slot)],
+        SuccessData::FingerprintSlots { slots, capacity } => vec![
+            packed_list(
+                "FP_SLOTS",
+                slots
+                    .iter()
+                    .map(|s| format!("{}:{}", s.slot, escape(&s.label))),
+            ),
+            format!("FP_CAPACITY={}", capacity),
+        ],
+        SuccessData::Inventory { volumes, shares } => vec![
+            packed_list(
+                "INV_VOLUMES",
+                volumes.iter().map(|v| {
+                    format!(
+                        "{}:{}:{}:{}",
+                        escape(&v.volume_id),
+                        escape(&v.name),
+                        v.lba_start,
+                        v.lba_end
+                    )
+                }),
+            ),
+            packed_list(
+                "INV_SHARES",
+                shares
+                    .iter()
+                    .map(|s| format!("{}:{}", s.slot, escape(&s.volume_id))),
+            ),
+        ],
+        SuccessData::Ack | SuccessData::VolumeCreated { .. } => Vec::new(),
+    };
+
+    lines.push(if status_style { "STATUS=OK" } else { "OK" }.to_string());
+    lines
+}
+
+// Événement en texte, lu côté client par DeviceEvent::from_text_line
+fn event_text_line(event: &DeviceEvent) -> String {
+    match event {
+        DeviceEvent::Prompt(prompt) => format!("PROMPT={}", prompt),
+        DeviceEvent::Progress { current, total } => format!("PROGRESS={}/{}", current, total),
+        DeviceEvent::FingerprintQuality(quality) => format!("FP_QUALITY={}", quality),
+    }
+}
+
+// Éléments déjà échappés, séparés par ';' sur une seule ligne (présente même si vide)
+fn packed_list(key: &str, items: impl Iterator<Item = String>) -> String {
+    format!(
+        "{}={}",
+        key,
+        items.collect::<Vec<_>>().join(&LIST_SEPARATOR.to_string())
+    )
+}
+
+// Une ligne "abort" ou une trame Abort parmi les octets reçus pendant une attente
+fn is_abort(bytes: &[u8]) -> bool {
+    if bytes.first() == Some(&FRAME_MAGIC) {
+        return matches!(
+            Frame::decode(bytes),
+            Ok(Some((frame, _))) if matches!(frame.body::<UsbCommand>(), Ok(UsbCommand::Abort))
+        );
+    }
+    String::from_utf8_lossy(bytes)
+        .lines()
+        .any(|line| line.trim() == "abort")
+}
+
+fn arg_value(args: &[String], name: &str) -> Option<String> {
+    args.iter()
+        .position(|a| a == name)
+        .and_then(|i| args.get(i + 1).cloned())
+}
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let sn = arg_value(&args, "--sn").unwrap_or_else(|| "BK-EMU-0001".to_string());
+    let link = arg_value(&args, "--link");
+    let text_only = args.iter().any(|a| a == "--text-only");
+    let firmware = match arg_value(&args, "--firmware").as_deref() {
+        Some("legacy") => None,
+        Some(version) => Some(version.to_string()),
+        None => Some(EMULATED_FIRMWARE.to_string()),
+    };
+    let fault = arg_value(&args, "--fault");
+
+    let pty = openpty(None, None)?;
+
+    // Mode brut : pas d'écho ni de conversion CR/LF sur la ligne émulée
+    let mut termios = tcgetattr(&pty.slave)?;
+    cfmakeraw(&mut termios);
+    tcsetattr(&pty.slave, SetArg::TCSANOW, &termios)?;
+
+    let slave_path = nix::unistd::ttyname(&pty.slave)?;
+    println!("[EMU] BindKey émulée {} sur {}", sn, slave_path.display());
+
+    if let Some(link) = &link {
+        let _ = std::fs::remove_file(link);
+        std::os::unix::fs::symlink(&slave_path, link)?;
+        println!("[EMU] Lien créé : {} -> {}", link, slave_path.display());
+    }
+
+    // On garde l'esclave ouvert : sinon le maître lit EIO à chaque fermeture côté client
+    let _slave = pty.slave;
+    let mut master = File::from(pty.master);
+    let mut writer = master.try_clone()?;
+    let mut device = EmulatedBindKey::new(sn, text_only, firmware, fault);
+    device.input = Some(master.try_clone()?);
+    if let Some(org_key) = arg_value(&args, "--org-key") {
+        let bytes = hex::decode(org_key.trim())?;
+        device.org_key = Some(VerifyingKey::from_sec1_bytes(&bytes)?);
+        println!("[EMU] Clé d'organisation épinglée : challenges non signés refusés");
+    }
+
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut chunk = [0u8; 256];
+
+    loop {
+        let n = master.read(&mut chunk)?;
+        if n == 0 {
+            continue;
+        }
+        buffer.extend_from_slice(&chunk[..n]);
+
+        loop {
+            // Une trame commence toujours par FRAME_MAGIC, jamais présent dans une ligne texte
+            if buffer.first() == Some(&FRAME_MAGIC) && !device.text_only {
+                match Frame::decode(&buffer) {
+                    Ok(Some((frame, consumed))) => {
+                        buffer.drain(..consumed);
+                        let reply = device.handle_frame(frame, &mut writer);
+                        master.write_all(&reply)?;
+                        master.flush()?;
+                        continue;
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        println!("[EMU] Trame rejetée : {}", e);
+                        buffer.drain(..1);
+                        continue;
+                    }
+                }
+            }
+
+            let Some(end) = buffer.iter().position(|&b| b == b'\n') else {
+                break;
+            };
+            let raw: Vec<u8> = buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&raw).trim().to_string();
+            if line.is_empty() {
+                continue;
+            }
+            println!("[EMU] << {}", line);
+
+            let mut emit = |event: DeviceEvent| {
+                let event_line = event_text_line(&event);
+                println!("[EMU] >> {}", event_line);
+                let _ = writer.write_all(format!("{}\n", event_line).as_bytes());
+                let _ = writer.flush();
+            };
+            for reply in device.handle_line(&line, &mut emit) {
+                println!("[EMU] >> {}", reply);
+                master.write_all(format!("{}\n", reply).as_bytes())?;
+            }
+            master.flush()?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_written_as_the_client_reads_them() {
+        let events = [
+            DeviceEvent::Prompt("place_finger".to_string()),
+            DeviceEvent::Progress {
+                current: 2,
+                total: 3,
+            },
+            DeviceEvent::FingerprintQuality("low".to_string()),
+        ];
+        for event in events {
+            let line = event_text_line(&event);
+            let (key, value) = line.split_once('=').unwrap();
+            assert_eq!(DeviceEvent::from_text_line(key, value), Some(event));
+        }
+        assert_eq!(
+            event_text_line(&DeviceEvent::Prompt("place_finger".to_string())),
+            "PROMPT=place_finger"
+        );
+    }
+}
@@ -0,0 +1,57 @@
+// Signe une image de firmware de l'ESP32 maître pour la mise à jour par l'application.
+// La clé privée (scalaire P-256 en hex) reste sur le poste de publication ; sa clé publique
+// doit être celle de FIRMWARE_PUBLIC_KEY (src/firmware.rs).
+//
+// Usage : cargo run --bin bindkey_sign_firmware -- --key signing_key.hex --version 1.3.0
+//         --image build/bindkey.bin [--out bindkey-1.3.0.bkfw]
+
+#[allow(dead_code)]
+#[path = "../firmware.rs"]
+mod firmware;
+
+use anyhow::{Context, bail};
+use p256::ecdsa::SigningKey;
+
+fn arg_value(args: &[String], name: &str) -> Option<String> {
+    args.iter()
+        .position(|a| a == name)
+        .and_then(|i| args.get(i + 1).cloned())
+}
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let (Some(key_path), Some(version), Some(image_path)) = (
+        arg_value(&args, "--key"),
+        arg_value(&args, "--version"),
+        arg_value(&args, "--image"),
+    ) else {
+        bail!(
+            "usage : --key <clé.hex> --version <x.y.z> --image <firmware.bin> [--out <fichier.bkfw>]"
+        );
+    };
+    let out_path = arg_value(&args, "--out").unwrap_or_else(|| format!("bindkey-{}.bkfw", version));
+
+    let key_hex =
+        std::fs::read_to_string(&key_path).with_context(|| format!("lecture de {}", key_path))?;
+    let key_bytes = hex::decode(key_hex.trim()).context("clé privée : hex invalide")?;
+    let key = SigningKey::from_slice(&key_bytes).context("clé privée P-256 invalide")?;
+
+    let public_key = hex::encode(key.verifying_key().to_encoded_point(false).as_bytes());
+    if public_key != firmware::FIRMWARE_PUBLIC_KEY {
+        println!(
+            "Attention : cette clé ne correspond pas à FIRMWARE_PUBLIC_KEY ({})",
+            public_key
+        );
+    }
+
+    let image = std::fs::read(&image_path).with_context(|| format!("lecture de {}", image_path))?;
+    let size = image.len();
+    let signed = firmware::sign_image(&version, image, &key).map_err(anyhow::Error::msg)?;
+    std::fs::write(&out_path, &signed).with_context(|| format!("écriture de {}", out_path))?;
+
+    println!(
+        "{} : firmware {} signé ({} octets)",
+        out_path, version, size
+    );
+    Ok(())
+}
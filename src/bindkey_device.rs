@@ -0,0 +1,975 @@
+use crate::firmware::{Feature, FirmwareInfo};
+use crate::protocol::share_protocol::{
+    DeviceEvent, FRAMED_NEGOTIATION_COMMAND, FRAMED_NEGOTIATION_REPLY, FingerprintSlot,
+    InventoryShare, InventoryVolume, SuccessData, UsbCommand,
+};
+use crate::protocol::text_protocol::{MalformedLine, TextReply, escape, split_record};
+use crate::server_challenge::ServerChallenge;
+use crate::transcript::{REPLAY_PORT_PREFIX, ReplayPort};
+use crate::usb_service::{
+    Interrupts, abort_framed_command, abort_text_command, command_timeout, send_framed_command,
+    send_text_command,
+};
+use serialport::SerialPort;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+pub const BINDKEY_BAUD_RATE: u32 = 115_200;
+
+// Délai de lecture du port : send_text_command boucle tant que le timeout global n'est pas atteint
+const PORT_READ_TIMEOUT: Duration = Duration::from_millis(500);
+// Temps laissé à l'ESP32 après la montée de DTR/RTS
+const SETTLE_DELAY: Duration = Duration::from_millis(500);
+// Un firmware texte-seul peut ignorer la ligne de négociation : on n'attend pas longtemps
+const NEGOTIATION_TIMEOUT: Duration = Duration::from_secs(2);
+// Taille du nom d'empreinte stocké par le firmware
+const FINGERPRINT_LABEL_MAX: usize = 32;
+// Granularité de `pause` : délai maximal avant de voir une annulation
+const PAUSE_STEP: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeviceError {
+    NotConnected,
+    PortOpen(String),
+    Io(String),
+    Timeout,
+    Rejected(String),
+    MissingField(&'static str),
+    UnexpectedResponse(String),
+    Cancelled,
+    Disconnected,
+    Unsupported(String),
+    MalformedLine(String),
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceError::NotConnected => write!(f, "Aucune BindKey détectée"),
+            DeviceError::PortOpen(e) => write!(f, "Impossible d'ouvrir le port : {}", e),
+            DeviceError::Io(e) => write!(f, "Erreur I/O : {}", e),
+            DeviceError::Timeout => write!(f, "Timeout : pas de réponse finale de la clé"),
+            DeviceError::Rejected(e) => write!(f, "Erreur Clé : {}", e),
+            DeviceError::MissingField(field) => {
+                write!(f, "Réponse incomplète ({} manquant)", field)
+            }
+            DeviceError::UnexpectedResponse(e) => write!(f, "Réponse inattendue : {}", e),
+            DeviceError::Cancelled => write!(f, "Opération annulée"),
+            DeviceError::Disconnected => write!(f, "BindKey débranchée pendant l'opération"),
+            DeviceError::Unsupported(reason) => write!(f, "{}", reason),
+            DeviceError::MalformedLine(e) => write!(f, "Ligne illisible de la clé : {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DeviceError {}
+
+impl From<MalformedLine> for DeviceError {
+    fn from(e: MalformedLine) -> Self {
+        DeviceError::MalformedLine(e.to_string())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnrollmentKeys {
+    pub sn: String,
+    pub pub_sign: String,
+    pub pub_ecdh: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WrappedShare {
+    pub source_sn: String,
+    pub wrapped: String,
+    // Signature des métadonnées du partage par cette clé (share_signature.rs)
+    pub signature: String,
+}
+
+// États bruts renvoyés par l'autotest de la clé ("ok", "ok:<détail>" ou code d'erreur)
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelfTestReport {
+    pub secure_element: String,
+    pub fingerprint_sensor: String,
+    pub slave_link: String,
+    pub medium: String,
+    pub share_slots_free: u16,
+    pub share_slots_total: u16,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FingerprintList {
+    pub slots: Vec<FingerprintSlot>,
+    pub capacity: u16,
+}
+
+// Contenu réel de la clé, confronté à la table de partitions et au serveur
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceInventory {
+    pub volumes: Vec<InventoryVolume>,
+    pub shares: Vec<InventoryShare>,
+}
+
+// Transport négocié à l'ouverture du port
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WireProtocol {
+    Text,
+    Framed,
+}
+
+pub type DeviceEventSink = Box<dyn FnMut(DeviceEvent) + Send>;
+
+pub struct BindKeyDevice {
+    port: Box<dyn SerialPort>,
+    protocol: WireProtocol,
+    next_seq: u16,
+    // Destinataire des PROMPT / PROGRESS / FP_QUALITY de la commande en cours
+    event_sink: Option<DeviceEventSink>,
+    // Levé par la détection hotplug : la commande en cours s'arrête aussitôt
+    detached: Arc<AtomicBool>,
+    // Interrogé une seule fois, au premier besoin
+    firmware: Option<FirmwareInfo>,
+    // Annulation demandée par l'interface pour le job en cours (posé par le propriétaire du port)
+    cancel: Arc<AtomicBool>,
+    // false après une commande interrompue que la clé n'a pas acquittée : le port doit être
+    // rouvert, une réponse tardive décalerait toutes les suivantes
+    in_sync: bool,
+}
+
+impl BindKeyDevice {
+    pub fn open(port_name: &str) -> Result<Self, DeviceError> {
+        if port_name.is_empty() {
+            return Err(DeviceError::NotConnected);
+        }
+
+        if let Some(path) = port_name.strip_prefix(REPLAY_PORT_PREFIX) {
+            let replay =
+                ReplayPort::open(path).map_err(|e| DeviceError::PortOpen(e.to_string()))?;
+            return Ok(Self::from_port(Box::new(replay)));
+        }
+
+        let mut port = serialport::new(port_name, BINDKEY_BAUD_RATE)
+            .timeout(PORT_READ_TIMEOUT)
+            .open()
+            .map_err(|e| DeviceError::PortOpen(e.to_string()))?;
+
+        let _ = port.write_data_terminal_ready(true);
+        let _ = port.write_request_to_send(true);
+        std::thread::sleep(SETTLE_DELAY);
+
+        Ok(Self::from_port(port))
+    }
+
+    // Port déjà ouvert (série réel ou rejeu d'un transcript) : on négocie le transport
+    pub fn from_port(mut port: Box<dyn SerialPort>) -> Self {
+        let protocol = negotiate_protocol(&mut *port);
+
+        BindKeyDevice {
+            port,
+            protocol,
+            next_seq: 1,
+            event_sink: None,
+            detached: Arc::new(AtomicBool::new(false)),
+            firmware: None,
+            cancel: Arc::new(AtomicBool::new(false)),
+            in_sync: true,
+        }
+    }
+
+    pub fn wire_protocol(&self) -> WireProtocol {
+        self.protocol
+    }
+
+    pub fn detach_flag(&self) -> Arc<AtomicBool> {
+        self.detached.clone()
+    }
+
+    pub fn set_event_sink(&mut self, sink: Option<DeviceEventSink>) {
+        self.event_sink = sink;
+    }
+
+    pub fn set_cancel_flag(&mut self, cancel: Arc<AtomicBool>) {
+        self.cancel = cancel;
+    }
+
+    pub fn in_sync(&self) -> bool {
+        self.in_sync
+    }
+
+    // Attente entre deux tentatives, interrompue par une annulation ou un débranchement
+    pub fn pause(&self, duration: Duration) -> Result<(), DeviceError> {
+        let interrupts = Interrupts {
+            detached: &self.detached,
+            cancel: &self.cancel,
+        };
+        let start = Instant::now();
+        while start.elapsed() < duration {
+            interrupts.check()?;
+            std::thread::sleep(PAUSE_STEP);
+        }
+        interrupts.check()
+    }
+
+    fn exchange(&mut self, command: UsbCommand) -> Result<SuccessData, DeviceError> {
+        // Job annulé : les commandes suivantes ne partent même pas
+        if self.cancel.load(Ordering::SeqCst) {
+            return Err(DeviceError::Cancelled);
+        }
+        let sink = &mut self.event_sink;
+        let mut on_event = |event: DeviceEvent| {
+            if let Some(sink) = sink.as_mut() {
+                sink(event);
+            }
+        };
+        let interrupts = Interrupts {
+            detached: &self.detached,
+            cancel: &self.cancel,
+        };
+        let timeout = command_timeout(&command);
+
+        let (result, seq) = match self.protocol {
+            WireProtocol::Framed => {
+                let seq = self.next_seq;
+                self.next_seq = self.next_seq.wrapping_add(1);
+                let result = send_framed_command(
+                    &mut *self.port,
+                    seq,
+                    &command,
+                    timeout,
+                    &interrupts,
+                    &mut on_event,
+                );
+                (result, seq)
+            }
+            WireProtocol::Text => {
+                let result = send_text_command(
+                    &mut *self.port,
+                    &text_command(&command),
+                    timeout,
+                    &interrupts,
+                    &mut on_event,
+                )
+                .and_then(|reply| parse_text_reply(&command, &reply));
+                (result, 0)
+            }
+        };
+
+        if matches!(result, Err(DeviceError::Cancelled | DeviceError::Timeout)) {
+            self.abort_pending(seq);
+        }
+        result
+    }
+
+    // La commande interrompue tourne peut-être encore sur la clé : on lui demande d'abandonner
+    fn abort_pending(&mut self, seq: u16) {
+        let abortable = self
+            .firmware
+            .as_ref()
+            .is_some_and(|info| info.check(Feature::CommandAbort).is_ok());
+        let acknowledged = abortable
+            && match self.protocol {
+                WireProtocol::Framed => abort_framed_command(&mut *self.port, seq, &self.detached),
+                WireProtocol::Text => abort_text_command(&mut *self.port, &self.detached),
+            };
+        if !acknowledged {
+            println!("[PORT] Commande interrompue sans acquittement : le port sera rouvert");
+        }
+        self.in_sync = acknowledged;
+    }
+
+    pub fn read_uid(&mut self) -> Result<String, DeviceError> {
+        match self.exchange(UsbCommand::Uid)? {
+            SuccessData::Uid { sn } => Ok(sn),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    // Un firmware non versionné répond ERR=unknown_command
+    pub fn firmware_info(&mut self) -> Result<FirmwareInfo, DeviceError> {
+        if let Some(info) = &self.firmware {
+            return Ok(info.clone());
+        }
+
+        let info = match self.exchange(UsbCommand::Version) {
+            Ok(SuccessData::FirmwareInfo {
+                version,
+                capabilities,
+            }) => FirmwareInfo {
+                version: Some(version.parse().map_err(DeviceError::UnexpectedResponse)?),
+                capabilities,
+            },
+            Ok(other) => return Err(unexpected(other)),
+            Err(DeviceError::Rejected(reason)) if reason.contains("unknown_command") => {
+                FirmwareInfo::legacy()
+            }
+            Err(e) => return Err(e),
+        };
+        self.firmware = Some(info.clone());
+        Ok(info)
+    }
+
+    // Refuse les opérations d'écriture qu'un firmware trop ancien ne gère pas correctement
+    fn require(&mut self, feature: Feature) -> Result<(), DeviceError> {
+        self.firmware_info()?
+            .check(feature)
+            .map_err(DeviceError::Unsupported)
+    }
+
+    // ==========================================
+    // MISE À JOUR DU FIRMWARE
+    // ==========================================
+
+    // Renvoie l'offset déjà reçu par la clé pour cette image (0 pour une nouvelle image)
+    pub fn firmware_begin(
+        &mut self,
+        version: &str,
+        size: u64,
+        sha256: &str,
+    ) -> Result<u64, DeviceError> {
+        self.require(Feature::FirmwareUpdate)?;
+        let command = UsbCommand::FirmwareBegin {
+            version: version.to_string(),
+            size,
+            sha256: sha256.to_string(),
+        };
+        match self.exchange(command)? {
+            SuccessData::FirmwareProgress { offset } => Ok(offset),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    // La clé répond avec le prochain offset attendu, même si ce bloc n'était pas le bon
+    pub fn firmware_chunk(&mut self, offset: u64, data: Vec<u8>) -> Result<u64, DeviceError> {
+        match self.exchange(UsbCommand::FirmwareChunk { offset, data })? {
+            SuccessData::FirmwareProgress { offset } => Ok(offset),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    pub fn firmware_hash(&mut self) -> Result<String, DeviceError> {
+        match self.exchange(UsbCommand::FirmwareVerify)? {
+            SuccessData::FirmwareHash { sha256 } => Ok(sha256),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    // La clé redémarre sur la nouvelle image : la version en cache n'est plus valable
+    pub fn firmware_commit(&mut self) -> Result<(), DeviceError> {
+        self.exchange(UsbCommand::FirmwareCommit)
+            .and_then(expect_ack)?;
+        self.firmware = None;
+        Ok(())
+    }
+
+    pub fn firmware_abort(&mut self) -> Result<(), DeviceError> {
+        self.exchange(UsbCommand::FirmwareAbort)
+            .and_then(expect_ack)
+    }
+
+    pub fn read_device_name(&mut self) -> Result<String, DeviceError> {
+        match self.exchange(UsbCommand::GetDevice)? {
+            SuccessData::DeviceInfo { device_name, .. } => Ok(device_name),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    // Le client a déjà vérifié le challenge ; la clé le revérifie avec sa propre clé
+    // d'organisation épinglée avant de demander le doigt. Un firmware qui ne sait pas le
+    // faire signerait n'importe quoi : il est refusé.
+    pub fn sign_server_challenge(
+        &mut self,
+        challenge: &ServerChallenge,
+    ) -> Result<String, DeviceError> {
+        self.require(Feature::SignedChallenge)?;
+        let command = UsbCommand::SignedChallenge {
+            context: challenge.context.clone(),
+            challenge: challenge.challenge.clone(),
+            session_id: challenge.session_id.clone(),
+            expires_at: challenge.expires_at,
+            server_signature: challenge.signature.clone(),
+        };
+        match self.exchange(command)? {
+            SuccessData::Signature { signature } => Ok(signature),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    // Efface l'identité de la clé avant sa réattribution. Sans ordre d'administrateur,
+    // la clé demande le doigt d'une empreinte enregistrée ; avec, elle vérifie la signature
+    // de l'organisation (contexte bindkey-wipe-v1) comme pour un challenge de connexion.
+    pub fn factory_wipe(
+        &mut self,
+        admin_order: Option<&ServerChallenge>,
+    ) -> Result<(), DeviceError> {
+        self.require(Feature::FactoryWipe)?;
+        let command = match admin_order {
+            None => UsbCommand::FactoryWipe,
+            Some(order) => UsbCommand::FactoryWipeAuthorized {
+                context: order.context.clone(),
+                challenge: order.challenge.clone(),
+                session_id: order.session_id.clone(),
+                expires_at: order.expires_at,
+                server_signature: order.signature.clone(),
+            },
+        };
+        self.exchange(command).and_then(expect_ack)
+    }
+
+    // ==========================================
+    // PAQUETS HORS LIGNE
+    // ==========================================
+
+    // Renvoie (signature, clé publique de signature) ; la clé demande le doigt de l'émetteur
+    pub fn sign_payload(&mut self, digest: &str) -> Result<(String, String), DeviceError> {
+        self.require(Feature::PayloadTransfer)?;
+        let command = UsbCommand::PayloadSign {
+            digest: digest.to_string(),
+        };
+        match self.exchange(command)? {
+            SuccessData::PayloadSignature {
+                signature,
+                pub_sign,
+            } => Ok((signature, pub_sign)),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    // Clé AES du contenu, déballée avec la clé ECDH de cette BindKey
+    pub fn unwrap_payload_key(
+        &mut self,
+        ephemeral_pubkey: &str,
+        wrapped: &str,
+    ) -> Result<[u8; 32], DeviceError> {
+        self.require(Feature::PayloadTransfer)?;
+        let command = UsbCommand::PayloadUnwrap {
+            ephemeral_pubkey: ephemeral_pubkey.to_string(),
+            wrapped: wrapped.to_string(),
+        };
+        match self.exchange(command)? {
+            SuccessData::PayloadKey { key } => hex::decode(&key)
+                .ok()
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or_else(|| DeviceError::UnexpectedResponse("PAYLOAD_KEY".to_string())),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    // ==========================================
+    // DIAGNOSTIC
+    // ==========================================
+
+    pub fn self_test(&mut self) -> Result<SelfTestReport, DeviceError> {
+        self.require(Feature::SelfTest)?;
+        match self.exchange(UsbCommand::SelfTest)? {
+            SuccessData::SelfTest {
+                secure_element,
+                fingerprint_sensor,
+                slave_link,
+                medium,
+                share_slots_free,
+                share_slots_total,
+            } => Ok(SelfTestReport {
+                secure_element,
+                fingerprint_sensor,
+                slave_link,
+                medium,
+                share_slots_free,
+                share_slots_total,
+            }),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    // ==========================================
+    // EMPREINTES DIGITALES
+    // ==========================================
+    //
+    // La clé refuse (ERR=not_authorized) toute commande de gestion qui ne suit pas
+    // immédiatement un `verify_fingerprint` réussi : chaque étape redemande un doigt connu.
+
+    // Renvoie le slot de l'empreinte reconnue ; ERR=no_match si le doigt est inconnu
+    pub fn verify_fingerprint(&mut self) -> Result<u16, DeviceError> {
+        self.require(Feature::FingerprintManagement)?;
+        match self.exchange(UsbCommand::FingerprintVerify)? {
+            SuccessData::FingerprintMatch { slot } => Ok(slot),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    pub fn list_fingerprints(&mut self) -> Result<FingerprintList, DeviceError> {
+        self.exchange(UsbCommand::FingerprintList)
+            .and_then(fingerprint_list)
+    }
+
+    // Le firmware choisit le slot libre et renvoie la nouvelle liste
+    pub fn enroll_fingerprint(&mut self, label: &str) -> Result<FingerprintList, DeviceError> {
+        let label: String = label
+            .chars()
+            .filter(|c| !c.is_control())
+            .take(FINGERPRINT_LABEL_MAX)
+            .collect();
+        self.exchange(UsbCommand::FingerprintEnroll {
+            label: label.trim().to_string(),
+        })
+        .and_then(fingerprint_list)
+    }
+
+    pub fn delete_fingerprint(&mut self, slot: u16) -> Result<FingerprintList, DeviceError> {
+        self.exchange(UsbCommand::FingerprintDelete { slot })
+            .and_then(fingerprint_list)
+    }
+
+    pub fn enroll(&mut self) -> Result<EnrollmentKeys, DeviceError> {
+        match self.exchange(UsbCommand::Enroll)? {
+            SuccessData::EnrollmentInfo {
+                sn,
+                pub_sign,
+                pub_ecdh,
+            } => Ok(EnrollmentKeys {
+                sn,
+                pub_sign,
+                pub_ecdh,
+            }),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    pub fn confirm_modification(&mut self) -> Result<(), DeviceError> {
+        self.exchange(UsbCommand::ConfirmModification)
+            .and_then(expect_ack)
+    }
+
+    pub fn register_lba_range(
+        &mut self,
+        volume_name: &str,
+        volume_id: &str,
+        lba_start: u64,
+        lba_end: u64,
+    ) -> Result<(), DeviceError> {
+        self.require(Feature::LbaRegistration)?;
+        self.exchange(UsbCommand::RegisterLbaRange {
+            volume_name: volume_name.to_string(),
+            volume_id: volume_id.to_string(),
+            lba_start,
+            lba_end,
+        })
+        .and_then(expect_ack)
+    }
+
+    pub fn wrap_share(
+        &mut self,
+        volume_id: &str,
+        target_sn: &str,
+        target_pubkey: &str,
+        target_slot: u16,
+    ) -> Result<WrappedShare, DeviceError> {
+        self.require(Feature::Sharing)?;
+        // Un partage non signé serait refusé par le destinataire
+        self.require(Feature::ShareSignature)?;
+        let command = UsbCommand::WrapShare {
+            volume_id: volume_id.to_string(),
+            target_sn: target_sn.to_string(),
+            target_pubkey: target_pubkey.to_string(),
+            target_slot,
+        };
+        match self.exchange(command)? {
+            SuccessData::SignedWrappedShare {
+                source_sn,
+                wrapped,
+                signature,
+            } => Ok(WrappedShare {
+                source_sn,
+                wrapped,
+                signature,
+            }),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    pub fn install_share(
+        &mut self,
+        slot: u16,
+        source_pubkey: &str,
+        wrapped: &str,
+    ) -> Result<(), DeviceError> {
+        self.require(Feature::Sharing)?;
+        self.exchange(UsbCommand::InstallShare {
+            slot,
+            source_pubkey: source_pubkey.to_string(),
+            wrapped: wrapped.to_string(),
+        })
+        .and_then(expect_ack)
+    }
+
+    pub fn delete_volume(&mut self, volume_id: &str) -> Result<(), DeviceError> {
+        self.exchange(UsbCommand::DeleteVolume {
+            volume_id: volume_id.to_string(),
+        })
+        .and_then(expect_ack)
+    }
+
+    pub fn inventory(&mut self) -> Result<DeviceInventory, DeviceError> {
+        self.require(Feature::Inventory)?;
+        match self.exchange(UsbCommand::Inventory)? {
+            SuccessData::Inventory { volumes, shares } => Ok(DeviceInventory { volumes, shares }),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    // Renvoie les IDs de volumes que la clé vient d'oublier (à purger côté serveur)
+    pub fn init_format(&mut self) -> Result<Vec<String>, DeviceError> {
+        self.require(Feature::Format)?;
+        match self.exchange(UsbCommand::InitFormat)? {
+            SuccessData::FormatInitialized { to_delete } => Ok(to_delete),
+            other => Err(unexpected(other)),
+        }
+    }
+}
+
+// Un firmware ancien répond ERR=unknown_command (ou rien) : on reste alors en texte
+fn negotiate_protocol(port: &mut dyn SerialPort) -> WireProtocol {
+    let interrupts = Interrupts {
+        detached: &AtomicBool::new(false),
+        cancel: &AtomicBool::new(false),
+    };
+    match send_text_command(
+        port,
+        FRAMED_NEGOTIATION_COMMAND,
+        NEGOTIATION_TIMEOUT,
+        &interrupts,
+        &mut |_| {},
+    ) {
+        Ok(reply)
+            if reply.value("PROTO").ok().flatten().as_deref() == Some(FRAMED_NEGOTIATION_REPLY) =>
+        {
+            WireProtocol::Framed
+        }
+        _ => WireProtocol::Text,
+    }
+}
+
+// ==========================================
+// PROTOCOLE TEXTE (firmwares sans transport en trames)
+// ==========================================
+
+// Toute valeur passe par `escape` : un nom contenant '\n', '=' ou ';' ne casse plus la ligne
+fn text_command(command: &UsbCommand) -> String {
+    match command {
+        UsbCommand::Uid => "uid".to_string(),
+        UsbCommand::GetDevice => "getdevice".to_string(),
+        UsbCommand::Challenge { challenge } => format!("challenge={}", escape(challenge)),
+        UsbCommand::Enroll => "enroll".to_string(),
+        UsbCommand::ConfirmModification => "cmd_modify".to_string(),
+        UsbCommand::RegisterLbaRange {
+            volume_name,
+            volume_id,
+            lba_start,
+            lba_end,
+        } => format!(
+            "volume_name={}\nvolume_id={}\nlba_start={}\nlba_end={}\n",
+            escape(volume_name),
+            escape(volume_id),
+            lba_start,
+            lba_end
+        ),
+        UsbCommand::WrapShare {
+            volume_id,
+            target_sn,
+            target_pubkey,
+            target_slot,
+        } => format!(
+            "share_volume_id={}\nshare_target_sn={}\nshare_target_pubkey={}\nshare_target_slot={}\n",
+            escape(volume_id),
+            escape(target_sn),
+            escape(target_pubkey),
+            target_slot
+        ),
+        UsbCommand::InstallShare {
+            slot,
+            source_pubkey,
+            wrapped,
+        } => format!(
+            "recv_share_slot={}\nrecv_share_source_pubkey={}\nrecv_share_wrapped={}\n",
+            slot,
+            escape(source_pubkey),
+            escape(wrapped)
+        ),
+        UsbCommand::DeleteVolume { volume_id } => format!("delete_volume={}", escape(volume_id)),
+        UsbCommand::InitFormat => "action=init_format".to_string(),
+        UsbCommand::Version => "version".to_string(),
+        UsbCommand::FirmwareBegin {
+            version,
+            size,
+            sha256,
+        } => format!("fw_begin={};{};{}", escape(version), size, escape(sha256)),
+        UsbCommand::FirmwareChunk { offset, data } => {
+            format!("fw_chunk={};{}", offset, hex::encode(data))
+        }
+        UsbCommand::FirmwareVerify => "fw_verify".to_string(),
+        UsbCommand::FirmwareCommit => "fw_commit".to_string(),
+        UsbCommand::FirmwareAbort => "fw_abort".to_string(),
+        UsbCommand::PayloadSign { digest } => format!("payload_sign={}", escape(digest)),
+        UsbCommand::PayloadUnwrap {
+            ephemeral_pubkey,
+            wrapped,
+        } => format!(
+            "payload_unwrap={};{}",
+            escape(ephemeral_pubkey),
+            escape(wrapped)
+        ),
+        UsbCommand::SelfTest => "selftest".to_string(),
+        UsbCommand::FingerprintVerify => "fp_verify".to_string(),
+        UsbCommand::FingerprintList => "fp_list".to_string(),
+        UsbCommand::FingerprintEnroll { label } => format!("fp_enroll={}", escape(label)),
+        UsbCommand::FingerprintDelete { slot } => format!("fp_delete={}", slot),
+        UsbCommand::Inventory => "inventory".to_string(),
+        UsbCommand::Abort => "abort".to_string(),
+        UsbCommand::SignedChallenge {
+            context,
+            challenge,
+            session_id,
+            expires_at,
+            server_signature,
+        } => format!(
+            "signed_challenge={};{};{};{};{}",
+            escape(context),
+            escape(challenge),
+            escape(session_id),
+            expires_at,
+            escape(server_signature)
+        ),
+        UsbCommand::FactoryWipe => "factory_wipe".to_string(),
+        UsbCommand::FactoryWipeAuthorized {
+            context,
+            challenge,
+            session_id,
+            expires_at,
+            server_signature,
+        } => format!(
+            "factory_wipe={};{};{};{};{}",
+            escape(context),
+            escape(challenge),
+            escape(session_id),
+            expires_at,
+            escape(server_signature)
+        ),
+    }
+}
+
+fn parse_text_reply(command: &UsbCommand, reply: &TextReply) -> Result<SuccessData, DeviceError> {
+    match command {
+        UsbCommand::Uid => Ok(SuccessData::Uid {
+            sn: take_field(reply, "SN")?,
+        }),
+        UsbCommand::GetDevice => Ok(SuccessData::DeviceInfo {
+            device_name: take_field(reply, "DN")?,
+            device_size: 0.0,
+            device_available_size: 0.0,
+        }),
+        UsbCommand::Challenge { .. } | UsbCommand::SignedChallenge { .. } => {
+            Ok(SuccessData::Signature {
+                signature: take_field(reply, "SIG")?,
+            })
+        }
+        UsbCommand::Enroll => Ok(SuccessData::EnrollmentInfo {
+            sn: take_field(reply, "SN")?,
+            pub_sign: take_field(reply, "PUB_SIGN")?,
+            pub_ecdh: take_field(reply, "PUB_ECDH")?,
+        }),
+        UsbCommand::ConfirmModification | UsbCommand::DeleteVolume { .. } => Ok(SuccessData::Ack),
+        UsbCommand::RegisterLbaRange { .. } => {
+            expect_status_ok(reply)?;
+            Ok(SuccessData::Ack)
+        }
+        UsbCommand::WrapShare { .. } => {
+            let source_sn = take_field(reply, "SN")?;
+            let wrapped = take_field(reply, "WRAPPED")?;
+            match reply.value("SIG")? {
+                Some(signature) => Ok(SuccessData::SignedWrappedShare {
+                    source_sn,
+                    wrapped,
+                    signature,
+                }),
+                None => Ok(SuccessData::WrappedShare { source_sn, wrapped }),
+            }
+        }
+        UsbCommand::InstallShare { .. } => {
+            // Sans ligne STATUS, c'est le "OK" final qui a validé l'échange
+            if reply.contains("STATUS") {
+                expect_status_ok(reply)?;
+            }
+            Ok(SuccessData::Ack)
+        }
+        // Seul un STATUS=OK explicite atteste que l'identité a bien été effacée
+        UsbCommand::FactoryWipe | UsbCommand::FactoryWipeAuthorized { .. } => {
+            expect_status_ok(reply)?;
+            Ok(SuccessData::Ack)
+        }
+        UsbCommand::InitFormat => {
+            expect_status_ok(reply)?;
+            // Une ligne TO_DEL par ID, ou tous les IDs séparés par ';'
+            Ok(SuccessData::FormatInitialized {
+                to_delete: reply.list("TO_DEL")?,
+            })
+        }
+        UsbCommand::Version => Ok(SuccessData::FirmwareInfo {
+            version: take_field(reply, "FW")?,
+            capabilities: reply
+                .list("CAPS")?
+                .iter()
+                .flat_map(|caps| caps.split(','))
+                .map(|c| c.trim().to_string())
+                .filter(|c| !c.is_empty())
+                .collect(),
+        }),
+        UsbCommand::FirmwareBegin { .. } | UsbCommand::FirmwareChunk { .. } => {
+            let offset = take_field(reply, "FW_OFFSET")?;
+            Ok(SuccessData::FirmwareProgress {
+                offset: offset.parse().map_err(|_| {
+                    DeviceError::UnexpectedResponse(format!("FW_OFFSET={}", offset))
+                })?,
+            })
+        }
+        UsbCommand::FirmwareVerify => Ok(SuccessData::FirmwareHash {
+            sha256: take_field(reply, "FW_SHA256")?,
+        }),
+        UsbCommand::FirmwareCommit | UsbCommand::FirmwareAbort | UsbCommand::Abort => {
+            Ok(SuccessData::Ack)
+        }
+        UsbCommand::PayloadSign { .. } => Ok(SuccessData::PayloadSignature {
+            signature: take_field(reply, "SIG")?,
+            pub_sign: take_field(reply, "PUB_SIGN")?,
+        }),
+        UsbCommand::PayloadUnwrap { .. } => Ok(SuccessData::PayloadKey {
+            key: take_field(reply, "PAYLOAD_KEY")?,
+        }),
+        UsbCommand::SelfTest => {
+            // SHARE_SLOTS=libres/total
+            let slots = take_field(reply, "SHARE_SLOTS")?;
+            let (free, total) = slots
+                .split_once('/')
+                .and_then(|(free, total)| {
+                    Some((free.trim().parse().ok()?, total.trim().parse().ok()?))
+                })
+                .ok_or_else(|| DeviceError::UnexpectedResponse(format!("SHARE_SLOTS={}", slots)))?;
+            Ok(SuccessData::SelfTest {
+                secure_element: take_field(reply, "SE")?,
+                fingerprint_sensor: take_field(reply, "FP_SENSOR")?,
+                slave_link: take_field(reply, "SLAVE_LINK")?,
+                medium: take_field(reply, "MEDIUM")?,
+                share_slots_free: free,
+                share_slots_total: total,
+            })
+        }
+        UsbCommand::FingerprintVerify => {
+            let slot = take_field(reply, "FP_MATCH")?;
+            Ok(SuccessData::FingerprintMatch {
+                slot: slot
+                    .parse()
+                    .map_err(|_| DeviceError::UnexpectedResponse(format!("FP_MATCH={}", slot)))?,
+            })
+        }
+        UsbCommand::FingerprintList
+        | UsbCommand::FingerprintEnroll { .. }
+        | UsbCommand::FingerprintDelete { .. } => {
+            // FP_SLOTS=0:Index droit;2:Pouce gauche (vide si aucune empreinte)
+            let slots = take_records(reply, "FP_SLOTS", 2)?
+                .into_iter()
+                .map(|fields| {
+                    Ok(FingerprintSlot {
+                        slot: parse_number("FP_SLOTS", &fields[0])?,
+                        label: fields[1].clone(),
+                    })
+                })
+                .collect::<Result<Vec<_>, DeviceError>>()?;
+            let capacity = take_field(reply, "FP_CAPACITY")?;
+            Ok(SuccessData::FingerprintSlots {
+                slots,
+                capacity: parse_number("FP_CAPACITY", &capacity)?,
+            })
+        }
+        UsbCommand::Inventory => {
+            // INV_VOLUMES=id:nom:début:fin;... et INV_SHARES=slot:id;... (vides si rien)
+            let volumes = take_records(reply, "INV_VOLUMES", 4)?
+                .into_iter()
+                .map(|fields| {
+                    Ok(InventoryVolume {
+                        volume_id: fields[0].clone(),
+                        name: fields[1].clone(),
+                        lba_start: parse_number("INV_VOLUMES", &fields[2])?,
+                        lba_end: parse_number("INV_VOLUMES", &fields[3])?,
+                    })
+                })
+                .collect::<Result<Vec<_>, DeviceError>>()?;
+            let shares = take_records(reply, "INV_SHARES", 2)?
+                .into_iter()
+                .map(|fields| {
+                    Ok(InventoryShare {
+                        slot: parse_number("INV_SHARES", &fields[0])?,
+                        volume_id: fields[1].clone(),
+                    })
+                })
+                .collect::<Result<Vec<_>, DeviceError>>()?;
+            Ok(SuccessData::Inventory { volumes, shares })
+        }
+    }
+}
+
+fn unexpected(data: SuccessData) -> DeviceError {
+    DeviceError::UnexpectedResponse(format!("{:?}", data))
+}
+
+fn fingerprint_list(data: SuccessData) -> Result<FingerprintList, DeviceError> {
+    match data {
+        SuccessData::FingerprintSlots { slots, capacity } => {
+            Ok(FingerprintList { slots, capacity })
+        }
+        other => Err(unexpected(other)),
+    }
+}
+
+fn expect_ack(data: SuccessData) -> Result<(), DeviceError> {
+    match data {
+        SuccessData::Ack => Ok(()),
+        other => Err(unexpected(other)),
+    }
+}
+
+fn take_field(reply: &TextReply, key: &'static str) -> Result<String, DeviceError> {
+    reply.value(key)?.ok_or(DeviceError::MissingField(key))
+}
+
+// Liste obligatoire (éventuellement vide) dont chaque élément compte `fields` sous-champs
+fn take_records(
+    reply: &TextReply,
+    key: &'static str,
+    fields: usize,
+) -> Result<Vec<Vec<String>>, DeviceError> {
+    if !reply.contains(key) {
+        return Err(DeviceError::MissingField(key));
+    }
+    reply
+        .raw_items(key)
+        .into_iter()
+        .map(|item| split_record(item, fields).map_err(DeviceError::from))
+        .collect()
+}
+
+fn parse_number<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, DeviceError> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| DeviceError::UnexpectedResponse(format!("{}={}", key, value)))
+}
+
+fn expect_status_ok(reply: &TextReply) -> Result<(), DeviceError> {
+    match reply.value("STATUS")? {
+        Some(status) if status.contains("OK") => Ok(()),
+        Some(status) => Err(DeviceError::UnexpectedResponse(format!(
+            "STATUS={}",
+            status
+        ))),
+        None => Err(DeviceError::MissingField("STATUS")),
+    }
+}
@@ -1,22 +1,139 @@
+use crate::auto_lock::LockPolicy;
+use crate::server_endpoint::ServerConfig;
 use serde::{Deserialize, Serialize};
 use std::fs;
+use std::io::ErrorKind;
+
+const CONFIG_FILE: &str = "bindkey_config.toml";
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AppConfig {
-    pub api_url: String,
+    // Adresse du serveur, confiance TLS et découverte sur le réseau local
+    #[serde(default)]
+    pub server: ServerConfig,
+    // Ancienne URL de l'API, remplacée par [server] : reprise dans host et port si la
+    // section [server] est absente, ignorée sinon
+    #[serde(default, rename = "api_url", skip_serializing)]
+    pub legacy_api_url: Option<String>,
+    // Fichier illisible ou invalide, api_url inutilisable : l'application refuse de démarrer
+    // plutôt que de viser le serveur par défaut sans la configuration de l'utilisateur
+    #[serde(skip)]
+    pub startup_error: Option<String>,
+    // Chemin du port série à utiliser à la place de la détection USB (ex: émulateur PTY)
+    #[serde(default)]
+    pub device_port: Option<String>,
+    // Disque associé au port imposé (émulateur, rejeu) : sysfs ne peut pas faire le lien
+    #[serde(default)]
+    pub device_disk: Option<String>,
+    // Enregistrement du trafic série (JSON Lines), désactivé par défaut
+    #[serde(default)]
+    pub transcript_path: Option<String>,
+    // Masque SIG, WRAPPED, PUB_* et challenges dans le transcript
+    #[serde(default = "default_true")]
+    pub transcript_redact: bool,
+    // Rejoue un transcript à la place de la clé physique
+    #[serde(default)]
+    pub replay_transcript: Option<String>,
+    // Empreintes (ou clés publiques) des BindKeys dont les paquets hors ligne sont acceptés
+    #[serde(default)]
+    pub trusted_payload_signers: Vec<String>,
+    // Clé publique de l'organisation (SEC1, hexadécimal) qui signe les challenges de connexion.
+    // La même clé est épinglée dans chaque BindKey ; sans elle, aucune connexion par clé.
+    #[serde(default)]
+    pub organization_key: Option<String>,
+    // Verrouillage automatique : clé débranchée, inactivité, écran verrouillé
+    #[serde(default)]
+    pub auto_lock: LockPolicy,
+}
+
+fn default_true() -> bool {
+    true
 }
 
 impl AppConfig {
     pub fn load() -> Self {
-        match fs::read_to_string("bindkey_config.toml") {
-            Ok(content) => toml::from_str(&content).unwrap_or_else(|_| Self::default()),
-            Err(_) => Self::default(),
+        match fs::read_to_string(CONFIG_FILE) {
+            Ok(content) => Self::parse(&content),
+            Err(e) if e.kind() == ErrorKind::NotFound => Self::default(),
+            Err(e) => Self::invalid(format!("{} illisible : {}", CONFIG_FILE, e)),
+        }
+    }
+
+    fn parse(content: &str) -> Self {
+        let mut config = match toml::from_str::<Self>(content) {
+            Ok(config) => config,
+            Err(e) => return Self::invalid(format!("{} invalide : {}", CONFIG_FILE, e)),
+        };
+        let has_server = content
+            .parse::<toml::Table>()
+            .is_ok_and(|table| table.contains_key("server"));
+        if let Err(e) = config.migrate_legacy_url(has_server) {
+            eprintln!("⚠️ {}", e);
+            config.startup_error = Some(e);
+        }
+        config
+    }
+
+    fn invalid(error: String) -> Self {
+        eprintln!("⚠️ {}", error);
+        Self {
+            startup_error: Some(error),
+            ..Self::default()
+        }
+    }
+
+    // Ancien format : api_url = "https://hôte:port", sans section [server]
+    fn migrate_legacy_url(&mut self, has_server: bool) -> Result<(), String> {
+        let Some(url) = &self.legacy_api_url else {
+            return Ok(());
+        };
+        if has_server {
+            eprintln!(
+                "⚠️ api_url = \"{}\" est ignoré : la section [server] le remplace",
+                url
+            );
+            return Ok(());
+        }
+
+        let unusable = |reason: String| {
+            format!(
+                "api_url = \"{}\" inutilisable ({}) : renseignez host et port dans la section [server] de bindkey_config.toml",
+                url, reason
+            )
+        };
+        let parsed = reqwest::Url::parse(url).map_err(|e| unusable(e.to_string()))?;
+        if parsed.scheme() != "https" {
+            return Err(unusable("seul https est accepté".to_string()));
         }
+        let host = parsed
+            .host_str()
+            .ok_or_else(|| unusable("hôte absent".to_string()))?;
+        self.server.host = host.to_string();
+        self.server.port = parsed.port_or_known_default().unwrap_or(self.server.port);
+        eprintln!(
+            "⚠️ api_url = \"{}\" repris dans [server] (host = \"{}\", port = {}) : mettez à jour bindkey_config.toml",
+            url, self.server.host, self.server.port
+        );
+        Ok(())
+    }
+
+    pub fn api_url(&self) -> String {
+        self.server.api_url()
     }
 
     pub fn default() -> Self {
         Self {
-            api_url: "https://api.bindkey.local".to_string(),
+            server: ServerConfig::default(),
+            legacy_api_url: None,
+            startup_error: None,
+            device_port: None,
+            device_disk: None,
+            transcript_path: None,
+            transcript_redact: true,
+            replay_transcript: None,
+            trusted_payload_signers: Vec::new(),
+            organization_key: None,
+            auto_lock: LockPolicy::default(),
         }
     }
 }
@@ -28,15 +145,73 @@ mod tests {
     #[test]
     fn test_default_config() {
         let config = AppConfig::default();
-        assert_eq!(config.api_url, "https://api.bindkey.local");
+        assert_eq!(config.api_url(), "https://api.bindkey.local:31278");
     }
 
     #[test]
     fn test_config_serialization() {
         let config = AppConfig {
-            api_url: "https://test.local".to_string(),
+            server: ServerConfig {
+                host: "test.local".to_string(),
+                ..ServerConfig::default()
+            },
+            legacy_api_url: Some("https://ancien.local".to_string()),
+            startup_error: None,
+            device_port: Some("/tmp/bindkey0".to_string()),
+            device_disk: Some("/dev/loop0".to_string()),
+            transcript_path: None,
+            transcript_redact: true,
+            replay_transcript: None,
+            trusted_payload_signers: vec!["3f2a9c0d5e7b1a64".to_string()],
+            organization_key: None,
+            auto_lock: LockPolicy::default(),
         };
         let toml_string = toml::to_string(&config).unwrap();
-        assert!(toml_string.contains("https://test.local"));
+        assert!(toml_string.contains("[server]"));
+        assert!(toml_string.contains("test.local"));
+        assert!(!toml_string.contains("ancien.local"));
+        assert!(toml_string.contains("/tmp/bindkey0"));
+    }
+
+    #[test]
+    fn test_config_without_device_port() {
+        let config: AppConfig = toml::from_str("api_url = \"https://test.local\"").unwrap();
+        assert_eq!(config.device_port, None);
+        assert!(config.transcript_redact);
+        assert_eq!(config.legacy_api_url.as_deref(), Some("https://test.local"));
+        assert_eq!(config.server, ServerConfig::default());
+    }
+
+    #[test]
+    fn test_legacy_api_url_migration() {
+        let config = AppConfig::parse("api_url = \"https://ancien.local:8443\"");
+        assert_eq!(config.api_url(), "https://ancien.local:8443");
+        assert_eq!(config.startup_error, None);
+
+        // Une section [server] l'emporte sur l'ancienne URL
+        let config = AppConfig::parse("api_url = \"https://ancien.local\"\n[server]\nport = 9000");
+        assert_eq!(config.api_url(), "https://api.bindkey.local:9000");
+
+        let config = AppConfig::parse("api_url = \"http://ancien.local\"");
+        assert!(config.startup_error.is_some());
+        assert_eq!(config.server, ServerConfig::default());
+    }
+
+    #[test]
+    fn test_invalid_file_is_reported() {
+        let config = AppConfig::parse("organization_key = \"04ab\"\n[server\nhost = \"x\"");
+        let error = config.startup_error.unwrap();
+        assert!(error.starts_with("bindkey_config.toml invalide"));
+    }
+
+    #[test]
+    fn test_server_section() {
+        let config: AppConfig = toml::from_str(
+            "[server]\nhost = \"api.example.org\"\nport = 8443\naddress = \"10.0.0.5\"\nca_bundle = \"ca.pem\"",
+        )
+        .unwrap();
+        assert_eq!(config.api_url(), "https://api.example.org:8443");
+        assert_eq!(config.server.address, Some("10.0.0.5".parse().unwrap()));
+        assert!(!config.server.discovery && config.server.spki_pins.is_empty());
     }
 }
@@ -0,0 +1,345 @@
+use crate::bindkey_device::{BindKeyDevice, DeviceError};
+use crate::protocol::share_protocol::DeviceEvent;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+
+// ==========================================
+// PROPRIÉTAIRE UNIQUE DU PORT SÉRIE
+// ==========================================
+//
+// Un seul thread ouvre les ports des BindKeys et exécute les commandes une par une,
+// dans l'ordre où l'interface les a demandées. Les pages ne touchent plus jamais
+// au port directement : elles choisissent une clé (`DeviceHandle::target`) puis
+// passent par le `DeviceTarget` obtenu.
+
+// Ce que le panneau latéral affiche : commande en cours et taille de la file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DeviceActivity {
+    pub current: Option<String>,
+    pub queued: usize,
+}
+
+impl DeviceActivity {
+    pub fn is_busy(&self) -> bool {
+        self.current.is_some() || self.queued > 0
+    }
+}
+
+// Annulation d'un job, partagée entre l'interface et le thread du port : la commande en cours
+// est interrompue (Abort envoyé à la clé) et les suivantes du même job ne partent pas
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+// Reçoit la clé ouverte (ou l'erreur d'ouverture / d'annulation).
+// Renvoie false si le port doit être rouvert avant la prochaine commande.
+type JobFn = Box<dyn FnOnce(Result<&mut BindKeyDevice, DeviceError>) -> bool + Send>;
+
+struct Job {
+    label: String,
+    port_name: String,
+    epoch: u64,
+    cancel: CancelToken,
+    run: JobFn,
+}
+
+enum OwnerMessage {
+    Job(Job),
+    SetPorts(Vec<String>),
+}
+
+#[derive(Clone)]
+pub struct DeviceHandle {
+    sender: Sender<OwnerMessage>,
+    activity: Arc<Mutex<DeviceActivity>>,
+    // Tout job soumis avant le dernier `cancel_pending` est abandonné sans être exécuté
+    epoch: Arc<AtomicU64>,
+    // Drapeau de débranchement de chaque port ouvert, levé sans attendre le thread du port
+    detach_flags: DetachFlags,
+    // Jeton du job en cours d'exécution
+    current_cancel: Arc<Mutex<Option<CancelToken>>>,
+}
+
+type DetachFlags = Arc<Mutex<HashMap<String, Arc<AtomicBool>>>>;
+
+impl DeviceHandle {
+    pub fn spawn() -> Self {
+        let (sender, receiver) = channel();
+        let activity = Arc::new(Mutex::new(DeviceActivity::default()));
+        let epoch = Arc::new(AtomicU64::new(0));
+        let detach_flags: DetachFlags = Arc::new(Mutex::new(HashMap::new()));
+        let current_cancel = Arc::new(Mutex::new(None));
+
+        let shared = OwnerShared {
+            activity: activity.clone(),
+            epoch: epoch.clone(),
+            detach_flags: detach_flags.clone(),
+            current_cancel: current_cancel.clone(),
+        };
+        std::thread::Builder::new()
+            .name("bindkey-owner".to_string())
+            .spawn(move || owner_loop(receiver, shared))
+            .expect("Impossible de lancer le thread du port BindKey");
+
+        DeviceHandle {
+            sender,
+            activity,
+            epoch,
+            detach_flags,
+            current_cancel,
+        }
+    }
+
+    // Appelé par la détection USB avec tous les ports BindKey présents ;
+    // le port d'une clé débranchée est fermé
+    pub fn set_ports(&self, port_names: Vec<String>) {
+        // La commande en cours sur une clé retirée échoue tout de suite (DeviceError::Disconnected)
+        if let Ok(flags) = self.detach_flags.lock() {
+            for (port, flag) in flags.iter() {
+                if !port_names.contains(port) {
+                    flag.store(true, Ordering::SeqCst);
+                }
+            }
+        }
+        let _ = self.sender.send(OwnerMessage::SetPorts(port_names));
+    }
+
+    // Les commandes passent toujours par une clé désignée explicitement
+    pub fn target(&self, port_name: &str) -> DeviceTarget {
+        DeviceTarget {
+            handle: self.clone(),
+            port_name: port_name.to_string(),
+            cancel: None,
+        }
+    }
+
+    pub fn activity(&self) -> DeviceActivity {
+        self.activity.lock().map(|a| a.clone()).unwrap_or_default()
+    }
+
+    // Abandonne les commandes en attente ; celle en cours va jusqu'au bout
+    pub fn cancel_pending(&self) {
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+    }
+
+    // Interrompt la commande en cours (la clé reçoit un Abort) sans toucher à la file
+    pub fn cancel_current(&self) {
+        if let Ok(current) = self.current_cancel.lock()
+            && let Some(token) = current.as_ref()
+        {
+            token.cancel();
+        }
+    }
+
+    // Bouton "Annuler" : plus rien ne part vers la clé et le port est libéré
+    pub fn cancel_all(&self) {
+        self.cancel_pending();
+        self.cancel_current();
+    }
+
+    fn submit<T, F>(
+        &self,
+        port_name: &str,
+        label: &str,
+        cancel: CancelToken,
+        f: F,
+    ) -> oneshot::Receiver<Result<T, DeviceError>>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut BindKeyDevice) -> Result<T, DeviceError> + Send + 'static,
+    {
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        let job = Job {
+            label: label.to_string(),
+            port_name: port_name.to_string(),
+            epoch: self.epoch.load(Ordering::SeqCst),
+            cancel,
+            run: Box::new(move |device| {
+                let result = device.and_then(f);
+                let port_healthy =
+                    !matches!(result, Err(DeviceError::Io(_) | DeviceError::Disconnected));
+                let _ = reply_tx.send(result);
+                port_healthy
+            }),
+        };
+
+        if let Ok(mut activity) = self.activity.lock() {
+            activity.queued += 1;
+        }
+        let _ = self.sender.send(OwnerMessage::Job(job));
+        reply_rx
+    }
+}
+
+// Une BindKey précise parmi celles branchées, identifiée par son port
+#[derive(Clone)]
+pub struct DeviceTarget {
+    handle: DeviceHandle,
+    port_name: String,
+    // Jeton partagé par tous les jobs de cette cible ; sinon un jeton neuf par job
+    cancel: Option<CancelToken>,
+}
+
+impl DeviceTarget {
+    pub fn port_name(&self) -> &str {
+        &self.port_name
+    }
+
+    // Pour une page qui propose son propre bouton "Annuler"
+    pub fn with_cancel(mut self, token: CancelToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    fn job_token(&self) -> CancelToken {
+        self.cancel.clone().unwrap_or_default()
+    }
+
+    pub async fn run<T, F>(&self, label: &str, f: F) -> Result<T, DeviceError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut BindKeyDevice) -> Result<T, DeviceError> + Send + 'static,
+    {
+        self.handle
+            .submit(&self.port_name, label, self.job_token(), f)
+            .await
+            .unwrap_or_else(|_| Err(owner_stopped()))
+    }
+
+    // Comme `run`, mais les PROMPT / PROGRESS / FP_QUALITY émis pendant la commande
+    // sont transmis à `on_event` (depuis le thread du port)
+    pub async fn run_with_events<T, F, E>(
+        &self,
+        label: &str,
+        on_event: E,
+        f: F,
+    ) -> Result<T, DeviceError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut BindKeyDevice) -> Result<T, DeviceError> + Send + 'static,
+        E: FnMut(DeviceEvent) + Send + 'static,
+    {
+        self.run(label, move |d| {
+            d.set_event_sink(Some(Box::new(on_event)));
+            let result = f(d);
+            d.set_event_sink(None);
+            result
+        })
+        .await
+    }
+
+    // Pour le code déjà synchrone (spawn_blocking) : ne jamais appeler depuis une tâche tokio
+    pub fn run_blocking<T, F>(&self, label: &str, f: F) -> Result<T, DeviceError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut BindKeyDevice) -> Result<T, DeviceError> + Send + 'static,
+    {
+        self.handle
+            .submit(&self.port_name, label, self.job_token(), f)
+            .blocking_recv()
+            .unwrap_or_else(|_| Err(owner_stopped()))
+    }
+}
+
+fn owner_stopped() -> DeviceError {
+    DeviceError::Io("Le thread du port BindKey est arrêté".to_string())
+}
+
+// État du `DeviceHandle` consulté par le thread du port
+struct OwnerShared {
+    activity: Arc<Mutex<DeviceActivity>>,
+    epoch: Arc<AtomicU64>,
+    detach_flags: DetachFlags,
+    current_cancel: Arc<Mutex<Option<CancelToken>>>,
+}
+
+fn owner_loop(receiver: Receiver<OwnerMessage>, shared: OwnerShared) {
+    let OwnerShared {
+        activity,
+        epoch,
+        detach_flags,
+        current_cancel,
+    } = shared;
+    let mut port_names: Vec<String> = Vec::new();
+    let mut devices: HashMap<String, BindKeyDevice> = HashMap::new();
+
+    for message in receiver {
+        let job = match message {
+            OwnerMessage::SetPorts(new_ports) => {
+                // Clés débranchées : on relâche leur port
+                devices.retain(|port, _| new_ports.contains(port));
+                if let Ok(mut flags) = detach_flags.lock() {
+                    flags.retain(|port, _| new_ports.contains(port));
+                }
+                port_names = new_ports;
+                continue;
+            }
+            OwnerMessage::Job(job) => job,
+        };
+
+        if let Ok(mut a) = activity.lock() {
+            a.queued = a.queued.saturating_sub(1);
+            a.current = Some(job.label.clone());
+        }
+
+        if job.epoch < epoch.load(Ordering::SeqCst) || job.cancel.is_cancelled() {
+            println!("[PORT] Commande annulée avant exécution : {}", job.label);
+            (job.run)(Err(DeviceError::Cancelled));
+        } else if !port_names.contains(&job.port_name) {
+            (job.run)(Err(DeviceError::NotConnected));
+        } else {
+            if !devices.contains_key(&job.port_name) {
+                match BindKeyDevice::open(&job.port_name) {
+                    Ok(opened) => {
+                        if let Ok(mut flags) = detach_flags.lock() {
+                            flags.insert(job.port_name.clone(), opened.detach_flag());
+                        }
+                        devices.insert(job.port_name.clone(), opened);
+                    }
+                    Err(e) => {
+                        (job.run)(Err(e));
+                        finish(&activity);
+                        continue;
+                    }
+                }
+            }
+
+            println!("[PORT] Exécution sur {} : {}", job.port_name, job.label);
+            if let Some(open_device) = devices.get_mut(&job.port_name) {
+                open_device.set_cancel_flag(job.cancel.0.clone());
+                if let Ok(mut current) = current_cancel.lock() {
+                    *current = Some(job.cancel.clone());
+                }
+                let healthy = (job.run)(Ok(&mut *open_device));
+                if let Ok(mut current) = current_cancel.lock() {
+                    *current = None;
+                }
+                // Commande interrompue sans réponse de la clé : on repart d'un port neuf
+                if !healthy || !open_device.in_sync() {
+                    devices.remove(&job.port_name);
+                }
+            }
+        }
+
+        finish(&activity);
+    }
+}
+
+fn finish(activity: &Arc<Mutex<DeviceActivity>>) {
+    if let Ok(mut a) = activity.lock() {
+        a.current = None;
+    }
+}
@@ -0,0 +1,437 @@
+use crate::bindkey_device::{BindKeyDevice, DeviceError, SelfTestReport, WireProtocol};
+use crate::firmware::FirmwareInfo;
+use crate::pages::payload::format_utc;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+// ==========================================
+// DIAGNOSTIC DE LA CLÉ
+// ==========================================
+//
+// Les tests tournent d'un bloc sur le thread du port : rien ne s'intercale entre deux
+// allers-retours. Le rapport exporté est joint aux tickets de support ; il ne contient
+// aucun secret (SN, versions et états des composants seulement).
+
+const UART_ROUND_TRIPS: u32 = 3;
+// Au-delà, la liaison marche mais quelque chose la ralentit (hub, câble, firmware occupé)
+const SLOW_ROUND_TRIP: Duration = Duration::from_millis(300);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheckStatus {
+    Ok,
+    Warning,
+    Failed,
+    Skipped,
+}
+
+impl CheckStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            CheckStatus::Ok => "OK",
+            CheckStatus::Warning => "ATTENTION",
+            CheckStatus::Failed => "ÉCHEC",
+            CheckStatus::Skipped => "NON TESTÉ",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DiagnosticCheck {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+impl DiagnosticCheck {
+    fn new(name: &'static str, status: CheckStatus, detail: impl Into<String>) -> Self {
+        DiagnosticCheck {
+            name,
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DiagnosticReport {
+    pub created_at: u64,
+    pub hostname: String,
+    pub os: String,
+    pub port_name: String,
+    pub sn: Option<String>,
+    pub firmware: Option<FirmwareInfo>,
+    pub transport: Option<WireProtocol>,
+    // Disque relié à la clé côté hôte (sysfs)
+    pub host_disk: Option<String>,
+    // Transcript du port série à joindre au ticket, s'il est activé
+    pub transcript_path: Option<String>,
+    pub checks: Vec<DiagnosticCheck>,
+}
+
+impl DiagnosticReport {
+    pub fn new(port_name: &str, host_disk: Option<String>, created_at: u64) -> Self {
+        DiagnosticReport {
+            created_at,
+            hostname: sysinfo::System::host_name().unwrap_or_default(),
+            os: sysinfo::System::long_os_version()
+                .unwrap_or_else(|| std::env::consts::OS.to_string()),
+            port_name: port_name.to_string(),
+            sn: None,
+            firmware: None,
+            transport: None,
+            host_disk,
+            transcript_path: None,
+            checks: Vec::new(),
+        }
+    }
+
+    pub fn worst_status(&self) -> CheckStatus {
+        let has = |status| self.checks.iter().any(|c| c.status == status);
+        if has(CheckStatus::Failed) {
+            CheckStatus::Failed
+        } else if has(CheckStatus::Warning) {
+            CheckStatus::Warning
+        } else {
+            CheckStatus::Ok
+        }
+    }
+
+    // Le port n'a même pas pu être ouvert (ou la commande a été annulée)
+    pub fn unreachable(&mut self, error: &DeviceError) {
+        self.checks.push(uart_failure(error));
+        self.skip_device_checks("Pas de liaison avec la clé");
+    }
+
+    fn skip_device_checks(&mut self, reason: &str) {
+        for name in COMPONENT_CHECKS {
+            self.checks
+                .push(DiagnosticCheck::new(name, CheckStatus::Skipped, reason));
+        }
+    }
+
+    pub fn file_name(&self) -> String {
+        let date = format_utc(self.created_at);
+        let stamp: String = date.chars().filter(|c| c.is_ascii_digit()).collect();
+        format!(
+            "diagnostic_{}_{}.txt",
+            self.sn.as_deref().unwrap_or("cle-inconnue"),
+            stamp
+        )
+    }
+
+    // Texte brut à joindre à un ticket
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "Rapport de diagnostic BindKey");
+        let _ = writeln!(out, "=============================");
+        let _ = writeln!(out, "Date        : {}", format_utc(self.created_at));
+        let _ = writeln!(
+            out,
+            "Application : {} {}",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        );
+        let _ = writeln!(out, "Système     : {} ({})", self.os, self.hostname);
+        let _ = writeln!(out, "Port        : {}", self.port_name);
+        let _ = writeln!(
+            out,
+            "Clé         : {}",
+            self.sn.as_deref().unwrap_or("non identifiée")
+        );
+        let firmware = match &self.firmware {
+            Some(info) if info.capabilities.is_empty() => info.display_version(),
+            Some(info) => format!(
+                "{} ({})",
+                info.display_version(),
+                info.capabilities.join(", ")
+            ),
+            None => "inconnu".to_string(),
+        };
+        let _ = writeln!(out, "Firmware    : {}", firmware);
+        let transport = match self.transport {
+            Some(WireProtocol::Framed) => "trames",
+            Some(WireProtocol::Text) => "texte",
+            None => "inconnu",
+        };
+        let _ = writeln!(out, "Transport   : {}", transport);
+        let _ = writeln!(
+            out,
+            "Disque hôte : {}",
+            self.host_disk.as_deref().unwrap_or("aucun")
+        );
+        if let Some(path) = &self.transcript_path {
+            let _ = writeln!(out, "Transcript  : {} (à joindre aussi)", path);
+        }
+        let _ = writeln!(out);
+
+        for check in &self.checks {
+            let _ = writeln!(
+                out,
+                "[{:<9}] {} : {}",
+                check.status.label(),
+                check.name,
+                check.detail
+            );
+        }
+        let _ = writeln!(out);
+        let skipped = self
+            .checks
+            .iter()
+            .filter(|c| c.status == CheckStatus::Skipped)
+            .count();
+        match skipped {
+            0 => {
+                let _ = writeln!(out, "Bilan : {}", self.worst_status().label());
+            }
+            n => {
+                let _ = writeln!(
+                    out,
+                    "Bilan : {} ({} test(s) non exécuté(s))",
+                    self.worst_status().label(),
+                    n
+                );
+            }
+        }
+        out
+    }
+}
+
+const CHECK_UART: &str = "Liaison UART";
+const CHECK_SECURE_ELEMENT: &str = "Élément sécurisé";
+const CHECK_FINGERPRINT: &str = "Capteur d'empreintes";
+const CHECK_SLAVE: &str = "Liaison ESP32 esclave";
+const CHECK_MEDIUM: &str = "Support de stockage";
+const CHECK_SLOTS: &str = "Slots de partage";
+
+const COMPONENT_CHECKS: [&str; 5] = [
+    CHECK_SECURE_ELEMENT,
+    CHECK_FINGERPRINT,
+    CHECK_SLAVE,
+    CHECK_MEDIUM,
+    CHECK_SLOTS,
+];
+
+// Exécuté sur le thread du port, clé déjà ouverte
+pub fn run_checks(device: &mut BindKeyDevice, report: &mut DiagnosticReport) {
+    report.transport = Some(device.wire_protocol());
+
+    let uart = check_uart(device, report);
+    let uart_ok = uart.status != CheckStatus::Failed;
+    report.checks.push(uart);
+    if !uart_ok {
+        report.skip_device_checks("La clé ne répond pas sur l'UART");
+        return;
+    }
+
+    match device.firmware_info() {
+        Ok(info) => report.firmware = Some(info),
+        Err(e) => println!("[DIAG] Version du firmware illisible : {}", e),
+    }
+
+    match device.self_test() {
+        Ok(self_test) => {
+            let checks = component_checks(&self_test, report.host_disk.as_deref());
+            report.checks.extend(checks);
+        }
+        Err(DeviceError::Unsupported(reason)) => {
+            report.skip_device_checks(&reason);
+            // Le disque reste vérifiable côté hôte
+            if let Some(medium) = report.checks.iter_mut().find(|c| c.name == CHECK_MEDIUM) {
+                *medium = medium_check(None, report.host_disk.as_deref());
+            }
+        }
+        Err(e) => {
+            report.skip_device_checks(&format!("Autotest en échec : {}", e));
+        }
+    }
+}
+
+fn check_uart(device: &mut BindKeyDevice, report: &mut DiagnosticReport) -> DiagnosticCheck {
+    let mut slowest = Duration::ZERO;
+    let mut total = Duration::ZERO;
+
+    for _ in 0..UART_ROUND_TRIPS {
+        let started = Instant::now();
+        let sn = match device.read_uid() {
+            Ok(sn) => sn,
+            Err(e) => return uart_failure(&e),
+        };
+        let elapsed = started.elapsed();
+        total += elapsed;
+        slowest = slowest.max(elapsed);
+
+        if report.sn.as_ref().is_some_and(|known| known != &sn) {
+            return DiagnosticCheck::new(
+                CHECK_UART,
+                CheckStatus::Failed,
+                format!(
+                    "réponses incohérentes (SN {} puis {})",
+                    report.sn.as_deref().unwrap_or_default(),
+                    sn
+                ),
+            );
+        }
+        report.sn = Some(sn);
+    }
+
+    let detail = format!(
+        "{} allers-retours, {:.1} ms en moyenne, {:.1} ms au plus",
+        UART_ROUND_TRIPS,
+        (total / UART_ROUND_TRIPS).as_secs_f64() * 1000.0,
+        slowest.as_secs_f64() * 1000.0
+    );
+    if slowest > SLOW_ROUND_TRIP {
+        DiagnosticCheck::new(
+            CHECK_UART,
+            CheckStatus::Warning,
+            format!("{} (lent : câble, hub ou clé occupée ?)", detail),
+        )
+    } else {
+        DiagnosticCheck::new(CHECK_UART, CheckStatus::Ok, detail)
+    }
+}
+
+fn uart_failure(error: &DeviceError) -> DiagnosticCheck {
+    let hint = match error {
+        DeviceError::PortOpen(_) => {
+            " — port occupé par un autre programme ou droits insuffisants (groupe dialout)"
+        }
+        DeviceError::Timeout => " — débrancher et rebrancher la clé, essayer un autre câble",
+        DeviceError::NotConnected | DeviceError::Disconnected => " — vérifier le branchement",
+        _ => "",
+    };
+    DiagnosticCheck::new(
+        CHECK_UART,
+        CheckStatus::Failed,
+        format!("{}{}", error, hint),
+    )
+}
+
+fn component_checks(self_test: &SelfTestReport, host_disk: Option<&str>) -> Vec<DiagnosticCheck> {
+    let free = self_test.share_slots_free;
+    let total = self_test.share_slots_total;
+    let slots = if total == 0 {
+        DiagnosticCheck::new(
+            CHECK_SLOTS,
+            CheckStatus::Failed,
+            "aucun slot annoncé par la clé",
+        )
+    } else if free == 0 {
+        DiagnosticCheck::new(
+            CHECK_SLOTS,
+            CheckStatus::Warning,
+            format!(
+                "0 / {} libre : les prochains partages vers cette clé seront refusés",
+                total
+            ),
+        )
+    } else {
+        DiagnosticCheck::new(
+            CHECK_SLOTS,
+            CheckStatus::Ok,
+            format!("{} / {} libres", free, total),
+        )
+    };
+
+    vec![
+        component_check(CHECK_SECURE_ELEMENT, &self_test.secure_element),
+        component_check(CHECK_FINGERPRINT, &self_test.fingerprint_sensor),
+        component_check(CHECK_SLAVE, &self_test.slave_link),
+        medium_check(Some(&self_test.medium), host_disk),
+        slots,
+    ]
+}
+
+// "ok", "ok:<détail>" ou code d'erreur du firmware
+fn component_check(name: &'static str, raw: &str) -> DiagnosticCheck {
+    match raw.strip_prefix("ok") {
+        Some("") => DiagnosticCheck::new(name, CheckStatus::Ok, "fonctionnel"),
+        Some(detail) if detail.starts_with(':') => {
+            DiagnosticCheck::new(name, CheckStatus::Ok, &detail[1..])
+        }
+        _ => DiagnosticCheck::new(name, CheckStatus::Failed, explain_code(raw)),
+    }
+}
+
+// État annoncé par la clé (None : autotest indisponible) croisé avec le disque vu par l'hôte
+fn medium_check(device_status: Option<&str>, host_disk: Option<&str>) -> DiagnosticCheck {
+    let host = match host_disk {
+        Some(disk) => format!("disque hôte {}", disk),
+        None => "aucun disque relié côté hôte".to_string(),
+    };
+
+    match device_status {
+        None => match host_disk {
+            Some(_) => DiagnosticCheck::new(
+                CHECK_MEDIUM,
+                CheckStatus::Ok,
+                format!("{} (état côté clé inconnu)", host),
+            ),
+            None => DiagnosticCheck::new(CHECK_MEDIUM, CheckStatus::Warning, host),
+        },
+        Some("none") => DiagnosticCheck::new(
+            CHECK_MEDIUM,
+            CheckStatus::Failed,
+            format!("aucun support détecté par la clé ; {}", host),
+        ),
+        Some(status) => {
+            let mut check = component_check(CHECK_MEDIUM, status);
+            if check.status == CheckStatus::Ok && host_disk.is_none() {
+                // La clé voit son support mais l'hôte ne l'a pas (câble de charge seule, udev...)
+                check.status = CheckStatus::Warning;
+            }
+            check.detail = format!("{} ; {}", check.detail, host);
+            check
+        }
+    }
+}
+
+fn explain_code(code: &str) -> String {
+    let explanation = match code {
+        "absent" => "non détecté",
+        "no_response" => "ne répond pas",
+        "timeout" => "délai dépassé",
+        "bad_crc" => "erreurs de transmission",
+        "not_calibrated" => "non calibré",
+        "locked" => "verrouillé",
+        "io_error" => "erreur d'entrée/sortie",
+        _ => return format!("erreur ({})", code),
+    };
+    format!("{} ({})", explanation, code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_self_test_report_checks() {
+        let self_test = SelfTestReport {
+            secure_element: "ok:ATECC608B".to_string(),
+            fingerprint_sensor: "no_response".to_string(),
+            slave_link: "ok".to_string(),
+            medium: "ok:sd 32 Go".to_string(),
+            share_slots_free: 0,
+            share_slots_total: 8,
+        };
+
+        let checks = component_checks(&self_test, None);
+        let status = |name: &str| checks.iter().find(|c| c.name == name).unwrap().status;
+        assert_eq!(status(CHECK_SECURE_ELEMENT), CheckStatus::Ok);
+        assert_eq!(status(CHECK_FINGERPRINT), CheckStatus::Failed);
+        assert_eq!(status(CHECK_SLAVE), CheckStatus::Ok);
+        // Support vu par la clé mais pas par l'hôte
+        assert_eq!(status(CHECK_MEDIUM), CheckStatus::Warning);
+        assert_eq!(status(CHECK_SLOTS), CheckStatus::Warning);
+
+        let mut report = DiagnosticReport::new("/dev/ttyUSB0", None, 0);
+        report.sn = Some("BK-0001".to_string());
+        report.checks = checks;
+        assert_eq!(report.worst_status(), CheckStatus::Failed);
+        assert_eq!(report.file_name(), "diagnostic_BK-0001_197001010000.txt");
+
+        let text = report.to_text();
+        assert!(text.contains("[ÉCHEC    ] Capteur d'empreintes : ne répond pas (no_response)"));
+        assert!(text.contains("Bilan : ÉCHEC"));
+    }
+}
@@ -0,0 +1,199 @@
+use crate::config::AppConfig;
+use crate::transcript::REPLAY_PORT_PREFIX;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// ==========================================
+// CORRÉLATION PORT SÉRIE <-> DISQUE DE LA BINDKEY
+// ==========================================
+//
+// La BindKey expose son UART (CP210x) et son stockage de masse sur le même bus USB.
+// On remonte l'arborescence sysfs des deux périphériques jusqu'à leur périphérique USB :
+// c'est la même clé s'ils partagent ce périphérique ou le même numéro de série USB.
+// Un hub commun ne suffit pas : une clé USB étrangère branchée sur le même hub (station
+// d'accueil, hub externe) serait prise pour le disque de la BindKey. Plusieurs disques
+// reliés au même port : aucun n'est retenu.
+
+const SYS_ROOT: &str = "/sys";
+
+// Vrai si `disk_path` (disque ou partition, ex. /dev/sdb1) est le seul disque derrière la clé de `port_name`
+pub fn disk_behind_port(port_name: &str, disk_path: &str, config: &AppConfig) -> bool {
+    let sys_root = Path::new(SYS_ROOT);
+    let linked = match forced_disk(port_name, config) {
+        Some(disk) => Some(disk),
+        None => find_disk_in(sys_root, &dev_name(port_name)).ok().flatten(),
+    };
+    linked
+        .is_some_and(|disk| base_disk_name(sys_root, &disk) == base_disk_name(sys_root, disk_path))
+}
+
+// Le disque entier (ex. /dev/sdb) derrière la clé de `port_name`, s'il est branché.
+// Erreur si plusieurs disques s'y rattachent : impossible de savoir lequel est le bon.
+pub fn find_disk_for_port(port_name: &str, config: &AppConfig) -> Result<Option<String>, String> {
+    if let Some(disk) = forced_disk(port_name, config) {
+        return Ok(Some(disk));
+    }
+    find_disk_in(Path::new(SYS_ROOT), &dev_name(port_name))
+}
+
+fn find_disk_in(sys_root: &Path, tty_name: &str) -> Result<Option<String>, String> {
+    let Ok(entries) = fs::read_dir(sys_root.join("class/block")) else {
+        return Ok(None);
+    };
+    let mut disks: Vec<String> = entries
+        .flatten()
+        .filter(|e| !e.path().join("partition").exists())
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .filter(|block| correlated_in(sys_root, tty_name, block))
+        .map(|block| format!("/dev/{}", block))
+        .collect();
+    disks.sort();
+    match disks.len() {
+        0 | 1 => Ok(disks.pop()),
+        _ => Err(format!(
+            "Plusieurs disques semblent reliés à {} ({}) : débranchez les autres périphériques USB",
+            tty_name,
+            disks.join(", ")
+        )),
+    }
+}
+
+// Port imposé (émulateur, rejeu) : udev ne connaît pas le lien, la configuration le donne
+fn forced_disk(port_name: &str, config: &AppConfig) -> Option<String> {
+    let forced = config.device_port.as_deref() == Some(port_name)
+        || port_name.starts_with(REPLAY_PORT_PREFIX);
+    if forced {
+        config.device_disk.clone()
+    } else {
+        None
+    }
+}
+
+fn dev_name(path: &str) -> String {
+    path.rsplit('/').next().unwrap_or(path).to_string()
+}
+
+// /dev/sdb1 -> sdb ; /dev/nvme0n1p2 -> nvme0n1
+fn base_disk_name(sys_root: &Path, path: &str) -> String {
+    let name = dev_name(path);
+    let entry = sys_root.join("class/block").join(&name);
+    if entry.join("partition").exists()
+        && let Ok(real) = fs::canonicalize(&entry)
+        && let Some(parent) = real.parent().and_then(|p| p.file_name())
+    {
+        return parent.to_string_lossy().to_string();
+    }
+    name
+}
+
+fn correlated_in(sys_root: &Path, tty_name: &str, block_name: &str) -> bool {
+    let Some(tty_usb) = usb_device_of(&sys_root.join("class/tty").join(tty_name)) else {
+        return false;
+    };
+    let Some(disk_usb) = usb_device_of(&sys_root.join("class/block").join(block_name)) else {
+        return false;
+    };
+
+    if tty_usb == disk_usb {
+        return true;
+    }
+
+    let serial = |dir: &Path| {
+        fs::read_to_string(dir.join("serial"))
+            .map(|s| s.trim().to_string())
+            .unwrap_or_default()
+    };
+    let tty_serial = serial(&tty_usb);
+    !tty_serial.is_empty() && tty_serial == serial(&disk_usb)
+}
+
+// Premier ancêtre qui est un périphérique USB (il porte idVendor)
+fn usb_device_of(class_entry: &Path) -> Option<PathBuf> {
+    let mut dir = fs::canonicalize(class_entry).ok()?;
+    loop {
+        if is_usb_device(&dir) {
+            return Some(dir);
+        }
+        if !dir.pop() {
+            return None;
+        }
+    }
+}
+
+fn is_usb_device(dir: &Path) -> bool {
+    dir.join("idVendor").exists()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    // /sys/devices/usb1/1-2 (hub) -> 1-2.1 (UART de la clé), 1-2.2 (stockage de la clé, même
+    // numéro de série) et 1-2.3 (clé USB étrangère sur le même hub) ; 1-3 : autre clé USB
+    fn fake_sysfs() -> PathBuf {
+        let root = std::env::temp_dir().join(format!("bindkey-sysfs-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let usb = root.join("devices/usb1");
+
+        let usb_devices = [
+            ("", ""),
+            ("/1-2", ""),
+            ("/1-2/1-2.1", "BK1"),
+            ("/1-2/1-2.2", "BK1"),
+            ("/1-2/1-2.3", "FOREIGN"),
+        ];
+        for (dir, serial) in usb_devices.into_iter().chain([("/1-3", "XYZ")]) {
+            let dir = usb.join(dir.trim_start_matches('/'));
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("idVendor"), "10c4").unwrap();
+            fs::write(dir.join("serial"), serial).unwrap();
+        }
+
+        let tty = usb.join("1-2/1-2.1/1-2.1:1.0/ttyUSB0/tty/ttyUSB0");
+        let bk_disk = usb.join("1-2/1-2.2/1-2.2:1.0/host3/block/sdb");
+        let other_disk = usb.join("1-3/1-3:1.0/host4/block/sdc");
+        let hub_disk = usb.join("1-2/1-2.3/1-2.3:1.0/host5/block/sdd");
+        for dir in [
+            &tty,
+            &bk_disk,
+            &bk_disk.join("sdb1"),
+            &other_disk,
+            &hub_disk,
+        ] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        fs::write(bk_disk.join("sdb1/partition"), "1").unwrap();
+
+        fs::create_dir_all(root.join("class/tty")).unwrap();
+        fs::create_dir_all(root.join("class/block")).unwrap();
+        symlink(&tty, root.join("class/tty/ttyUSB0")).unwrap();
+        symlink(&bk_disk, root.join("class/block/sdb")).unwrap();
+        symlink(bk_disk.join("sdb1"), root.join("class/block/sdb1")).unwrap();
+        symlink(&other_disk, root.join("class/block/sdc")).unwrap();
+        symlink(&hub_disk, root.join("class/block/sdd")).unwrap();
+        root
+    }
+
+    #[test]
+    fn test_disk_correlation_ignores_shared_hub() {
+        let root = fake_sysfs();
+
+        assert!(correlated_in(&root, "ttyUSB0", "sdb"));
+        assert!(correlated_in(&root, "ttyUSB0", "sdb1"));
+        assert!(!correlated_in(&root, "ttyUSB0", "sdc"));
+        assert!(!correlated_in(&root, "ttyUSB0", "sdd"));
+        assert!(!correlated_in(&root, "ttyUSB9", "sdb"));
+        assert_eq!(base_disk_name(&root, "/dev/sdb1"), "sdb");
+        assert_eq!(
+            find_disk_in(&root, "ttyUSB0"),
+            Ok(Some("/dev/sdb".to_string()))
+        );
+
+        // Un second disque au même numéro de série : aucun n'est retenu
+        fs::write(root.join("devices/usb1/1-2/1-2.3/serial"), "BK1").unwrap();
+        assert!(find_disk_in(&root, "ttyUSB0").is_err());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
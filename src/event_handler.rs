@@ -1,14 +1,21 @@
+use crate::bindkey_device::DeviceError;
 use crate::protocol::protocol::{
-    ApiMessage, LoginSuccessResponse, ModifyPayload, Page, RegisterPayload, Role,
+    ApiMessage, LogOut, LoginSuccessResponse, ModifyPayload, Page, RegisterPayload, Role,
     StatusBindkey::ACTIVE, User, VolumeCreatedInfo,
 };
-use crate::protocol::protocol::{StatusBindkey, UserWithBindKey};
-use crate::protocol::share_protocol::{SuccessData, UsbResponse};
-use crate::usb_service::send_text_command;
-use crate::{BindKeyApp, pages::enrollment::hash_password_with_salt};
+use crate::disk_link;
+use crate::firmware_update;
+use crate::auto_lock::{self, LockReason};
+use crate::hotplug::HotplugEvent;
+use crate::protocol::protocol::{AttachedBindKey, StatusBindkey, UserWithBindKey};
+use crate::transcript::REPLAY_PORT_PREFIX;
+use crate::protocol::share_protocol::{DeviceEvent, SuccessData, UsbResponse};
+use crate::pages::reassign::AuditEntry;
+use crate::srp;
+use crate::{BindKeyApp, pages};
 use serde_json::json;
 use std::process::Command;
-use std::time::Duration;
+use std::time::Instant;
 pub fn handle_api_message(app: &mut BindKeyApp, message: ApiMessage) {
     match message {
         ApiMessage::EnrollmentSuccess(texte) => {
@@ -25,34 +32,55 @@ pub fn handle_api_message(app: &mut BindKeyApp, message: ApiMessage) {
                     let clone_firstname = app.enroll_firstname.clone();
                     let clone_lastname = app.enroll_lastname.clone();
                     let clone_email = app.enroll_email.clone();
-                    let hash_password = hash_password_with_salt(&app.enroll_password);
+                    let clone_password = app.enroll_password.clone();
                     let clone_user_role = app.enroll_role.clone();
-                    let clone_auth_token = app.server_token.clone();
+                    let clone_session = app.session.clone();
                     let clone_bk_pub_sign = pub_sign;
                     let clone_bk_pub_ecdh = pub_ecdh;
+                    // Clé tout juste réinitialisée : l'enrôlement clôt son dossier de réattribution
+                    let reassignment_id = app.reassign.pending_for(&sn);
                     let clone_bk_sn = sn;
-                    let clone_url = app.config.api_url.clone();
+                    let clone_url = app.config.api_url();
                     let clone_api_client = app.api_client.clone();
 
                     tokio::spawn(async move {
+                        // PBKDF2 : calcul bloquant
+                        let email = clone_email.clone();
+                        let credentials = match tokio::task::spawn_blocking(move || {
+                            srp::new_verifier(&email, &clone_password)
+                        })
+                        .await
+                        {
+                            Ok(credentials) => credentials,
+                            Err(e) => {
+                                let _ = clone_sender.send(ApiMessage::EnrollmentError(format!(
+                                    " Erreur calcul du vérificateur : {}",
+                                    e
+                                )));
+                                return;
+                            }
+                        };
                         let payload = RegisterPayload {
                             first_name: clone_firstname,
                             last_name: clone_lastname,
                             email: clone_email,
-                            password: hash_password,
+                            credentials,
                             user_role: clone_user_role,
                             bindkey_status: ACTIVE,
                             pub_sign: clone_bk_pub_sign,
                             sn: clone_bk_sn,
                             pub_ecdh: clone_bk_pub_ecdh,
+                            reassignment_id: reassignment_id.clone(),
                         };
                         println!("{:?}", payload);
                         let url = format!("{}/auth/register", clone_url);
-                        let resultat = clone_api_client
-                            .post(&url)
-                            .json(&payload)
-                            .bearer_auth(clone_auth_token)
-                            .send()
+                        let resultat = clone_session
+                            .send(|token| {
+                                clone_api_client
+                                    .post(&url)
+                                    .json(&payload)
+                                    .bearer_auth(token)
+                            })
                             .await;
 
                         match resultat {
@@ -61,6 +89,10 @@ pub fn handle_api_message(app: &mut BindKeyApp, message: ApiMessage) {
                                     let _ = clone_sender.send(ApiMessage::EnrollmentSuccess(
                                         " Enrolé (API OK) !".to_string(),
                                     ));
+                                    if reassignment_id.is_some() {
+                                        let _ = clone_sender
+                                            .send(ApiMessage::ReassignEnrolled(payload.email.clone()));
+                                    }
                                 } else {
                                     let _ = clone_sender.send(ApiMessage::EnrollmentError(
                                         " Refus serveur (API KO)".to_string(),
@@ -90,8 +122,8 @@ pub fn handle_api_message(app: &mut BindKeyApp, message: ApiMessage) {
                 let clone_sender = app.sender.clone();
                 let clone_email = app.enroll_email.clone();
                 let clone_user_role = app.enroll_role.clone();
-                let clone_auth_token = app.server_token.clone();
-                let clone_url = app.config.api_url.clone();
+                let clone_session = app.session.clone();
+                let clone_url = app.config.api_url();
                 let clone_api_client = app.api_client.clone();
 
                 tokio::spawn(async move {
@@ -100,11 +132,13 @@ pub fn handle_api_message(app: &mut BindKeyApp, message: ApiMessage) {
                         user_role: clone_user_role,
                     };
                     let url = format!("{}/users/modify", clone_url);
-                    let resultat = clone_api_client
-                        .post(&url)
-                        .json(&payload)
-                        .bearer_auth(clone_auth_token)
-                        .send()
+                    let resultat = clone_session
+                        .send(|token| {
+                            clone_api_client
+                                .post(&url)
+                                .json(&payload)
+                                .bearer_auth(token)
+                        })
                         .await;
                     match resultat {
                         Ok(response) => {
@@ -153,56 +187,74 @@ pub fn handle_api_message(app: &mut BindKeyApp, message: ApiMessage) {
 
         ApiMessage::ReceivedChallenge(le_challenge, session_id, bindkey_uid) => {
             app.login_status =
-                "Challenge reçue, communication avec la bindkey en cours".to_string();
+                "Challenge signé par l'organisation reçu, vérification par la bindkey".to_string();
             app.is_loading = true;
             let clone_sender = app.sender.clone();
-            let clone_port_name = app.current_port_name.clone();
+            // La clé qui vient de donner son UID signe le challenge, même si une autre est branchée
+            let Some(device) = app
+                .attached_bindkeys
+                .iter()
+                .find(|k| k.sn.as_ref() == Some(&bindkey_uid))
+                .map(|k| app.device.target(&k.port_name))
+            else {
+                app.login_status = "Clé non détectée".to_string();
+                app.is_loading = false;
+                return;
+            };
+            pages::device_feedback::reset_device_feedback(app);
+            let on_event = pages::device_feedback::forward_device_events(app);
             tokio::spawn(async move {
-                if !clone_port_name.is_empty() {
-                    match serialport::new(&clone_port_name, 115200)
-                        .timeout(Duration::from_secs(15))
-                        .open()
+                let _ = clone_sender.send(ApiMessage::LoginError("Scannez votre doigt".to_string()));
+                let sign_result = device
+                    .run_with_events("Signature du challenge", on_event, move |d| {
+                        d.sign_server_challenge(&le_challenge)
+                    })
+                    .await;
+                let _ = clone_sender.send(ApiMessage::DeviceFeedbackReset);
+
+                match sign_result {
+                    Ok(sig) => {
+                        println!("🔍 DEBUG SIGNATURE: {} caractères", sig.len());
+                        let _ = clone_sender.send(ApiMessage::SignedChallenge(
+                            sig,
+                            session_id,
+                            bindkey_uid,
+                        ));
+                    }
+                    Err(DeviceError::MissingField(_)) => {
+                        let _ = clone_sender.send(ApiMessage::LoginError(
+                            "La clé a répondu mais sans SIG".to_string(),
+                        ));
+                    }
+                    Err(DeviceError::NotConnected) => {
+                        let _ =
+                            clone_sender.send(ApiMessage::LoginError("Clé non détectée".to_string()));
+                    }
+                    // Codes du firmware quand il refuse le challenge avant de demander le doigt
+                    Err(DeviceError::Rejected(code))
+                        if matches!(
+                            code.as_str(),
+                            "no_org_key" | "bad_org_signature" | "expired" | "wrong_context"
+                        ) =>
                     {
-                        Ok(mut port) => {
-                            let _ = port.write_data_terminal_ready(true);
-                            std::thread::sleep(Duration::from_millis(100));
-
-                            let cmd = format!("challenge={}", le_challenge);
-                            let _ = clone_sender
-                                .send(ApiMessage::LoginError("Scannez votre doigt".to_string()));
-                            match send_text_command(&mut *port, &cmd) {
-                                Ok(map) => {
-                                    if let Some(sig) = map.get("SIG") {
-                                        println!("🔍 DEBUG SIGNATURE: '{}, {}'", sig, sig.len());
-                                        let _ = clone_sender.send(ApiMessage::SignedChallenge(
-                                            sig.clone(),
-                                            session_id,
-                                            bindkey_uid,
-                                        ));
-                                    } else {
-                                        let _ = clone_sender.send(ApiMessage::LoginError(
-                                            "La clé a répondu mais sans SIG".to_string(),
-                                        ));
-                                    }
-                                }
-                                Err(e) => {
-                                    let _ = clone_sender.send(ApiMessage::LoginError(format!(
-                                        "Erreur Com USB: {}",
-                                        e
-                                    )));
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            let _ = clone_sender.send(ApiMessage::LoginError(format!(
-                                "Impossible d'ouvrir le port: {}",
-                                e
-                            )));
-                        }
+                        let _ = clone_sender.send(ApiMessage::LoginError(format!(
+                            "La BindKey refuse ce challenge ({}) : serveur non reconnu par l'organisation ou challenge périmé",
+                            code
+                        )));
+                    }
+                    Err(e @ DeviceError::Unsupported(_)) => {
+                        let _ = clone_sender.send(ApiMessage::LoginError(format!(
+                            "{} : mettez à jour le firmware de la BindKey pour vous connecter",
+                            e
+                        )));
+                    }
+                    Err(e @ (DeviceError::PortOpen(_) | DeviceError::Cancelled | DeviceError::Disconnected)) => {
+                        let _ = clone_sender.send(ApiMessage::LoginError(e.to_string()));
+                    }
+                    Err(e) => {
+                        let _ = clone_sender
+                            .send(ApiMessage::LoginError(format!("Erreur Com USB: {}", e)));
                     }
-                } else {
-                    let _ =
-                        clone_sender.send(ApiMessage::LoginError("Clé non détectée".to_string()));
                 }
             });
         }
@@ -213,7 +265,7 @@ pub fn handle_api_message(app: &mut BindKeyApp, message: ApiMessage) {
             let clone_session_id = session_id.clone();
             let clone_signature = signature.clone();
             let clone_sender = app.sender.clone();
-            let clone_url = app.config.api_url.clone();
+            let clone_url = app.config.api_url();
             let clone_api_client = app.api_client.clone();
 
             tokio::spawn(async move {
@@ -233,7 +285,7 @@ pub fn handle_api_message(app: &mut BindKeyApp, message: ApiMessage) {
                                 Ok(response) => {
                                     let _ = clone_sender.send(ApiMessage::LoginSuccess(
                                         response.role,
-                                        response.server_token,
+                                        response.tokens,
                                         response.first_name,
                                         response.local_token,
                                         bindkey_uid,
@@ -265,9 +317,10 @@ pub fn handle_api_message(app: &mut BindKeyApp, message: ApiMessage) {
                 }
             });
         }
-        ApiMessage::LoginSuccess(role, token, first_name, local_token, bindkey_uid) => {
+        ApiMessage::LoginSuccess(role, tokens, first_name, local_token, bindkey_uid) => {
             app.role_user = role;
-            app.server_token = token;
+            app.session.begin(tokens);
+            app.last_activity = Instant::now();
             app.first_name_user = first_name;
             app.local_token = local_token;
 
@@ -285,13 +338,12 @@ pub fn handle_api_message(app: &mut BindKeyApp, message: ApiMessage) {
                     partition_number,
                 }) => {
                     let clone_sender = app.sender.clone();
-                    let clone_auth_token = app.server_token.clone();
+                    let clone_session = app.session.clone();
                     let clone_volume_name = app.volume_created_name.trim().to_uppercase();
                     let clone_volume_size = app.volume_created_size;
                     let clone_device_name = app.device_name.clone();
-                    let clone_url = app.config.api_url.clone();
+                    let clone_url = app.config.api_url();
                     let clone_api_client = app.api_client.clone();
-                    let clone_port = app.current_port_name.clone();
 
                     tokio::spawn(async move {
                         let payload = VolumeCreatedInfo {
@@ -300,11 +352,13 @@ pub fn handle_api_message(app: &mut BindKeyApp, message: ApiMessage) {
                             id: volume_id.clone(),
                         };
                         let url = format!("{}/volumes", clone_url);
-                        let resultat = clone_api_client
-                            .post(&url)
-                            .json(&payload)
-                            .bearer_auth(clone_auth_token)
-                            .send()
+                        let resultat = clone_session
+                            .send(|token| {
+                                clone_api_client
+                                    .post(&url)
+                                    .json(&payload)
+                                    .bearer_auth(token)
+                            })
                             .await;
                         match resultat {
                             Ok(response) => {
@@ -323,7 +377,7 @@ pub fn handle_api_message(app: &mut BindKeyApp, message: ApiMessage) {
                                                                             rollback_physical_volume(
                                                                                 &device_path,
                                                                                 &partition_number,
-                                                                                &clone_port,
+                                                                                &device,
                                                                                 &volume_id,
                                                                             );
 
@@ -344,7 +398,7 @@ pub fn handle_api_message(app: &mut BindKeyApp, message: ApiMessage) {
                                                                 rollback_physical_volume(
                                                                     &device_path,
                                                                     &partition_number,
-                                                                    &clone_port,
+                                                                    &device,
                                                                     &volume_id,
                                                                 );
                                 */
@@ -395,16 +449,18 @@ pub fn handle_api_message(app: &mut BindKeyApp, message: ApiMessage) {
         }
         ApiMessage::FetchUsers => {
             let clone_sender = app.sender.clone();
-            let url = app.config.api_url.clone();
-            let clone_auth_token = app.server_token.clone();
+            let url = app.config.api_url();
+            let clone_session = app.session.clone();
             let clone_api_client = app.api_client.clone();
 
             tokio::spawn(async move {
                 let url = format!("{}/admin/users", url);
-                let resultat = clone_api_client
-                    .get(url)
-                    .bearer_auth(clone_auth_token)
-                    .send()
+                let resultat = clone_session
+                    .send(|token| {
+                        clone_api_client
+                            .get(&url)
+                            .bearer_auth(token)
+                    })
                     .await;
 
                 match resultat {
@@ -450,15 +506,17 @@ pub fn handle_api_message(app: &mut BindKeyApp, message: ApiMessage) {
         }
         ApiMessage::DeleteUser(user_id) => {
             let clone_sender = app.sender.clone();
-            let url = format!("{}/admin/users/{}", app.config.api_url, user_id);
-            let clone_auth_token = app.server_token.clone();
+            let url = format!("{}/admin/users/{}", app.config.api_url(), user_id);
+            let clone_session = app.session.clone();
             let clone_api_client = app.api_client.clone();
 
             tokio::spawn(async move {
-                let resultat = clone_api_client
-                    .delete(url)
-                    .bearer_auth(clone_auth_token)
-                    .send()
+                let resultat = clone_session
+                    .send(|token| {
+                        clone_api_client
+                            .delete(&url)
+                            .bearer_auth(token)
+                    })
                     .await;
 
                 match resultat {
@@ -498,8 +556,8 @@ pub fn handle_api_message(app: &mut BindKeyApp, message: ApiMessage) {
         }
         ApiMessage::SearchUserByEmail(email) => {
             let clone_sender = app.sender.clone();
-            let url = app.config.api_url.clone();
-            let clone_auth_token = app.server_token.clone();
+            let url = app.config.api_url();
+            let clone_session = app.session.clone();
             let clone_api_client = app.api_client.clone();
 
             app.enroll_status = "Recherche en cours...".to_string();
@@ -507,10 +565,12 @@ pub fn handle_api_message(app: &mut BindKeyApp, message: ApiMessage) {
             tokio::spawn(async move {
                 let url = format!("{}/admin/users/search?email={}", url, email);
 
-                let resultat = clone_api_client
-                    .get(&url)
-                    .bearer_auth(clone_auth_token)
-                    .send()
+                let resultat = clone_session
+                    .send(|token| {
+                        clone_api_client
+                            .get(&url)
+                            .bearer_auth(token)
+                    })
                     .await;
 
                 match resultat {
@@ -549,8 +609,8 @@ pub fn handle_api_message(app: &mut BindKeyApp, message: ApiMessage) {
         }
         ApiMessage::UpdateBindKeyStatus(serial, new_status) => {
             let clone_sender = app.sender.clone();
-            let url = app.config.api_url.clone();
-            let clone_auth_token = app.server_token.clone();
+            let url = app.config.api_url();
+            let clone_session = app.session.clone();
             let clone_api_client = app.api_client.clone();
 
             app.enroll_status = "⏳ Mise à jour du statut de la clé en cours...".to_string();
@@ -563,17 +623,20 @@ pub fn handle_api_message(app: &mut BindKeyApp, message: ApiMessage) {
                     StatusBindkey::RESET => "RESET",
                     StatusBindkey::LOST => "LOST",
                     StatusBindkey::BROKEN => "BROKEN",
+                    StatusBindkey::REVOKED => "REVOKED",
                 };
 
                 let payload = serde_json::json!({
                     "status": status_str
                 });
 
-                let resultat = clone_api_client
-                    .patch(&url)
-                    .json(&payload)
-                    .bearer_auth(clone_auth_token)
-                    .send()
+                let resultat = clone_session
+                    .send(|token| {
+                        clone_api_client
+                            .patch(&url)
+                            .json(&payload)
+                            .bearer_auth(token)
+                    })
                     .await;
 
                 match resultat {
@@ -605,116 +668,103 @@ pub fn handle_api_message(app: &mut BindKeyApp, message: ApiMessage) {
             app.enroll_status = format!("Échec de la mise à jour : {}", e);
         }
         ApiMessage::StartFormatBindKey {
+            bindkey_port,
             device_path,
             partitions,
-            port_name,
             volume_names: _,
         } => {
             let clone_sender = app.sender.clone();
             app.formatage_status = "Initialisation du formatage...".to_string();
 
-            let clone_url = app.config.api_url.clone();
-            let clone_token = app.server_token.clone();
+            let clone_url = app.config.api_url();
+            let clone_session = app.session.clone();
             let clone_api_client = app.api_client.clone();
+            let Some(device) = app.selected_device(&Some(bindkey_port)) else {
+                app.formatage_status = "La BindKey choisie n'est plus branchée".to_string();
+                return;
+            };
+            if !disk_link::disk_behind_port(device.port_name(), &device_path, &app.config) {
+                app.formatage_status = format!(
+                    "Erreur : refus de formater {}, ce disque n'est pas derrière la BindKey choisie",
+                    device_path
+                );
+                app.is_loading = false;
+                return;
+            }
 
             tokio::spawn(async move {
-                // 1. Ouverture du port série
-                match serialport::new(&port_name, 115200)
-                    .timeout(std::time::Duration::from_secs(10))
-                    .open()
-                {
-                    Ok(mut port) => {
-                        let _ = port.write_data_terminal_ready(true);
-                        let _ = port.write_request_to_send(true);
-                        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                let _ = clone_sender.send(ApiMessage::FormatStatus(
+                    "Récupération des IDs sur la BindKey...".to_string(),
+                ));
+
+                let init_result = device
+                    .run("Initialisation du formatage", |d| d.init_format())
+                    .await;
 
-                        let cmd_init_format = "action=init_format\n".to_string();
+                let ids = match init_result {
+                    Ok(ids) => ids,
+                    Err(DeviceError::PortOpen(e)) => {
+                        let _ = clone_sender.send(ApiMessage::FormatStatus(format!(
+                            "Impossible d'ouvrir le port USB : {}",
+                            e
+                        )));
+                        return;
+                    }
+                    Err(DeviceError::UnexpectedResponse(_)) | Err(DeviceError::MissingField(_)) => {
                         let _ = clone_sender.send(ApiMessage::FormatStatus(
-                            "Récupération des IDs sur la BindKey...".to_string(),
+                            "Erreur: La puce a refusé de s'initialiser".to_string(),
                         ));
+                        return;
+                    }
+                    Err(e) => {
+                        let _ = clone_sender.send(ApiMessage::FormatStatus(format!(
+                            "Échec de communication USB : {}",
+                            e
+                        )));
+                        return;
+                    }
+                };
 
-                        match crate::usb_service::send_text_command(&mut *port, &cmd_init_format) {
-                            Ok(map) => {
-                                if let Some(ids_str) = map.get("TO_DEL") {
-                                    let ids: Vec<&str> = ids_str.split(';').collect();
-                                    for id in ids {
-                                        let trimmed_id = id.trim();
-                                        if !trimmed_id.is_empty() {
-                                            let _ = clone_sender.send(ApiMessage::FormatStatus(
-                                                format!(
-                                                    "Suppression du volume {} sur le serveur...",
-                                                    trimmed_id
-                                                ),
-                                            ));
-                                            let url_del = format!(
-                                                "{}/volumes/delete_id/{}",
-                                                clone_url, trimmed_id
-                                            );
-                                            let _ = clone_api_client
-                                                .delete(&url_del)
-                                                .bearer_auth(&clone_token)
-                                                .send()
-                                                .await;
-                                        }
-                                    }
-                                }
+                for id in ids {
+                    let _ = clone_sender.send(ApiMessage::FormatStatus(format!(
+                        "Suppression du volume {} sur le serveur...",
+                        id
+                    )));
+                    let url_del = format!("{}/volumes/delete_id/{}", clone_url, id);
+                    let _ = clone_session
+                        .send(|token| {
+                            clone_api_client
+                                .delete(&url_del)
+                                .bearer_auth(token)
+                        })
+                        .await;
+                }
 
-                                let is_ok = map
-                                    .get("STATUS")
-                                    .map(|val| val.contains("OK"))
-                                    .unwrap_or(false);
+                let _ = clone_sender.send(ApiMessage::FormatStatus(
+                    "BindKey prête. Démarrage du formatage Linux...".to_string(),
+                ));
 
-                                if is_ok {
-                                    let _ = clone_sender.send(ApiMessage::FormatStatus(
-                                        "BindKey prête. Démarrage du formatage Linux..."
-                                            .to_string(),
-                                    ));
+                let format_result = tokio::task::spawn_blocking(move || {
+                    crate::pages::volumes::force_format(&device_path, &partitions)
+                })
+                .await;
 
-                                    let format_result = tokio::task::spawn_blocking(move || {
-                                        crate::pages::volumes::force_format(
-                                            &device_path,
-                                            &partitions,
-                                        )
-                                    })
-                                    .await;
-
-                                    match format_result {
-                                        Ok(Ok(_)) => {
-                                            let _ = clone_sender.send(ApiMessage::FormatStatus(
-                                                "Succès : La clé est vide et réinitialiséé."
-                                                    .to_string(),
-                                            ));
-                                            let _ =
-                                                clone_sender.send(ApiMessage::RequestVolumeRefresh);
-                                        }
-                                        Ok(Err(e)) => {
-                                            let _ = clone_sender.send(ApiMessage::FormatStatus(
-                                                format!("Erreur système lors du formatage : {}", e),
-                                            ));
-                                        }
-                                        Err(e) => {
-                                            let _ = clone_sender.send(ApiMessage::FormatStatus(
-                                                format!("Erreur fatale du thread : {}", e),
-                                            ));
-                                        }
-                                    }
-                                } else {
-                                    let _ = clone_sender.send(ApiMessage::FormatStatus(
-                                        "Erreur: La puce a refusé de s'initialiser".to_string(),
-                                    ));
-                                }
-                            }
-                            Err(e) => {
-                                let _ = clone_sender.send(ApiMessage::FormatStatus(format!(
-                                    "Échec de communication USB : {}",
-                                    e
-                                )));
-                            }
-                        }
+                match format_result {
+                    Ok(Ok(_)) => {
+                        let _ = clone_sender.send(ApiMessage::FormatStatus(
+                            "Succès : La clé est vide et réinitialiséé.".to_string(),
+                        ));
+                        let _ = clone_sender.send(ApiMessage::RequestVolumeRefresh);
+                    }
+                    Ok(Err(e)) => {
+                        let _ = clone_sender.send(ApiMessage::FormatStatus(format!(
+                            "Erreur système lors du formatage : {}",
+                            e
+                        )));
                     }
                     Err(e) => {
                         let _ = clone_sender.send(ApiMessage::FormatStatus(format!(
-                            "Impossible d'ouvrir le port USB : {}",
+                            "Erreur fatale du thread : {}",
                             e
                         )));
                     }
@@ -814,29 +864,72 @@ pub fn handle_api_message(app: &mut BindKeyApp, message: ApiMessage) {
             app.dashboard_volumes = volumes;
         }
         ApiMessage::LogOutSuccess => {
-            app.current_page = Page::Login;
-            app.role_user = Role::NONE;
-
-            app.server_token.clear();
-            app.local_token.clear();
-            app.login_password.clear();
-
-            app.first_name_user.clear();
-            app.enroll_firstname.clear();
-            app.enroll_lastname.clear();
-            app.enroll_email.clear();
-            app.enroll_status.clear();
-
-            app.device_available_space = 0.0;
-            app.device_name.clear();
-            app.device_size = 0.0;
-            app.volume_created_name.clear();
-            app.volume_created_size = 0;
-            app.volume_status.clear();
-            app.users_list.clear();
-
+            clear_session(app);
             app.login_status = " Déconnexion réussie.".to_string();
         }
+        ApiMessage::Lock(reason) => lock_session(app, reason),
+        ApiMessage::ReassignAudit(entry) => {
+            println!(
+                "[RÉATTRIBUTION] {} {} : {:?}",
+                entry.at, entry.step, entry.outcome
+            );
+            app.reassign.audit.push(entry);
+        }
+        ApiMessage::ReassignStarted(id, previous_owner) => {
+            app.reassign.reassignment_id = Some(id);
+            app.reassign.previous_owner = previous_owner;
+            app.reassign.status = "Remise à zéro de la clé...".to_string();
+        }
+        ApiMessage::ReassignWiped(result) => {
+            app.reassign.running = false;
+            match result {
+                Ok(port) => {
+                    // Directement vers l'enrôlement, sur cette clé
+                    app.reassign.awaiting_enrollment = true;
+                    app.enroll_port = Some(port);
+                    app.enroll_status = format!(
+                        "Clé {} réinitialisée : renseignez son nouveau propriétaire puis enrôlez-le.",
+                        app.reassign.sn
+                    );
+                    app.reassign.status =
+                        "En attente de l'enrôlement du nouveau propriétaire.".to_string();
+                }
+                Err(e) => {
+                    app.reassign.status = format!("❌ {}", e);
+                }
+            }
+        }
+        ApiMessage::ReassignEnrolled(email) => {
+            app.reassign.audit.push(AuditEntry::new(
+                "Enrôlement du nouveau propriétaire",
+                Ok(email),
+            ));
+            app.reassign.awaiting_enrollment = false;
+            app.reassign.status = format!("✔ Clé {} réattribuée.", app.reassign.sn);
+        }
+        ApiMessage::RestorationProgress(text) => {
+            app.restoration.status = text;
+        }
+        ApiMessage::RestorationFinished(result) => {
+            app.restoration.running = false;
+            match result {
+                Ok(report) => {
+                    println!(
+                        "[RESTAURATION] {} remplace {} ({} partage(s) à re-chiffrer)",
+                        report.new_sn,
+                        report.old_sn,
+                        report.rewraps.len()
+                    );
+                    app.restoration.status =
+                        "✔ Restauration terminée : connectez-vous avec votre nouvelle BindKey."
+                            .to_string();
+                    app.restoration.report = Some(report);
+                }
+                Err(e) => {
+                    app.restoration.status = format!("❌ {}", e);
+                }
+            }
+        }
         ApiMessage::LogOutError(e) => {
             println!("{}", e);
         }
@@ -849,18 +942,20 @@ pub fn handle_api_message(app: &mut BindKeyApp, message: ApiMessage) {
         ApiMessage::StartVolumeDeletion(name, device_path) => {
             app.dashboard_status = format!("Recherche de l'ID pour le volume {}...", name);
             let clone_sender = app.sender.clone();
-            let clone_url = app.config.api_url.clone();
-            let clone_token = app.server_token.clone();
+            let clone_url = app.config.api_url();
+            let clone_session = app.session.clone();
             let clone_api_client = app.api_client.clone();
             let clone_name = name.clone();
             let clone_path = device_path.clone();
 
             tokio::spawn(async move {
                 let url = format!("{}/volumes/find_id?name={}", clone_url, clone_name);
-                let res = clone_api_client
-                    .get(&url)
-                    .bearer_auth(&clone_token)
-                    .send()
+                let res = clone_session
+                    .send(|token| {
+                        clone_api_client
+                            .get(&url)
+                            .bearer_auth(token)
+                    })
                     .await;
 
                 match res {
@@ -890,93 +985,99 @@ pub fn handle_api_message(app: &mut BindKeyApp, message: ApiMessage) {
         ApiMessage::VolumeIdReceivedForDeletion(name, id, device_path) => {
             app.dashboard_status = format!("Suppression du volume {} sur la BindKey...", name);
             let clone_sender = app.sender.clone();
-            let clone_port = app.current_port_name.clone();
+            let Some(device) = app.session_device() else {
+                app.dashboard_status = "Branchez la BindKey de votre session".to_string();
+                return;
+            };
+            if !disk_link::disk_behind_port(device.port_name(), &device_path, &app.config) {
+                app.dashboard_status = format!(
+                    "Erreur : refus de supprimer {}, ce disque n'est pas derrière votre BindKey",
+                    device_path
+                );
+                return;
+            }
             let clone_id = id.clone();
             let clone_path = device_path.clone();
 
             tokio::spawn(async move {
-                if !clone_port.is_empty() {
-                    match serialport::new(&clone_port, 115200)
-                        .timeout(Duration::from_secs(5))
-                        .open()
-                    {
-                        Ok(mut port) => {
-                            let _ = port.write_data_terminal_ready(true);
-                            let cmd = format!("delete_volume={}", clone_id);
-                            match send_text_command(&mut *port, &cmd) {
-                                Ok(_) => {
-                                    let _ = clone_sender.send(ApiMessage::UpdateStatus(
-                                        "Suppression physique de la partition...".to_string(),
-                                    ));
+                let hw_id = clone_id.clone();
+                let delete_result = device
+                    .run("Suppression du volume", move |d| d.delete_volume(&hw_id))
+                    .await;
 
-                                    let _ = Command::new("/usr/bin/udisksctl")
-                                        .args(["unmount", "-b", &clone_path])
-                                        .output();
+                match delete_result {
+                    Ok(()) => {
+                        let _ = clone_sender.send(ApiMessage::UpdateStatus(
+                            "Suppression physique de la partition...".to_string(),
+                        ));
 
-                                    let (disk, part_num) = if clone_path.contains("nvme") {
-                                        if let Some(pos) = clone_path.rfind('p') {
-                                            (&clone_path[..pos], &clone_path[pos + 1..])
-                                        } else {
-                                            ("", "")
-                                        }
-                                    } else {
-                                        let last_digit_idx =
-                                            clone_path.rfind(|c: char| c.is_ascii_digit());
-                                        if let Some(idx) = last_digit_idx {
-                                            (&clone_path[..idx], &clone_path[idx..])
-                                        } else {
-                                            ("", "")
-                                        }
-                                    };
+                        let _ = Command::new("/usr/bin/udisksctl")
+                            .args(["unmount", "-b", &clone_path])
+                            .output();
 
-                                    if !disk.is_empty() && !part_num.is_empty() {
-                                        let _ = Command::new("/usr/bin/pkexec")
-                                            .args(["/usr/sbin/parted", "-s", disk, "rm", part_num])
-                                            .output();
+                        let (disk, part_num) = if clone_path.contains("nvme") {
+                            if let Some(pos) = clone_path.rfind('p') {
+                                (&clone_path[..pos], &clone_path[pos + 1..])
+                            } else {
+                                ("", "")
+                            }
+                        } else {
+                            let last_digit_idx = clone_path.rfind(|c: char| c.is_ascii_digit());
+                            if let Some(idx) = last_digit_idx {
+                                (&clone_path[..idx], &clone_path[idx..])
+                            } else {
+                                ("", "")
+                            }
+                        };
 
-                                        let _ = Command::new("/usr/bin/pkexec")
-                                            .args(["/usr/bin/udevadm", "settle"])
-                                            .output();
-                                    }
+                        if !disk.is_empty() && !part_num.is_empty() {
+                            let _ = Command::new("/usr/bin/pkexec")
+                                .args(["/usr/sbin/parted", "-s", disk, "rm", part_num])
+                                .output();
 
-                                    let _ = clone_sender
-                                        .send(ApiMessage::VolumeDeletedOnServer(clone_id));
-                                }
-                                Err(e) => {
-                                    let _ = clone_sender.send(ApiMessage::VolumeDeletionError(
-                                        format!("Erreur USB : {}", e),
-                                    ));
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            let _ = clone_sender.send(ApiMessage::VolumeDeletionError(format!(
-                                "Port USB indisponible : {}",
-                                e
-                            )));
+                            let _ = Command::new("/usr/bin/pkexec")
+                                .args(["/usr/bin/udevadm", "settle"])
+                                .output();
                         }
+
+                        let _ = clone_sender.send(ApiMessage::VolumeDeletedOnServer(clone_id));
+                    }
+                    Err(DeviceError::NotConnected) => {
+                        let _ = clone_sender.send(ApiMessage::VolumeDeletionError(
+                            "BindKey non connectée pour la suppression".to_string(),
+                        ));
+                    }
+                    Err(DeviceError::PortOpen(e)) => {
+                        let _ = clone_sender.send(ApiMessage::VolumeDeletionError(format!(
+                            "Port USB indisponible : {}",
+                            e
+                        )));
+                    }
+                    Err(e) => {
+                        let _ = clone_sender.send(ApiMessage::VolumeDeletionError(format!(
+                            "Erreur USB : {}",
+                            e
+                        )));
                     }
-                } else {
-                    let _ = clone_sender.send(ApiMessage::VolumeDeletionError(
-                        "BindKey non connectée pour la suppression".to_string(),
-                    ));
                 }
             });
         }
         ApiMessage::VolumeDeletedOnServer(id) => {
             app.dashboard_status = format!("Suppression du volume {} sur le serveur...", id);
             let clone_sender = app.sender.clone();
-            let clone_url = app.config.api_url.clone();
-            let clone_token = app.server_token.clone();
+            let clone_url = app.config.api_url();
+            let clone_session = app.session.clone();
             let clone_api_client = app.api_client.clone();
             let clone_id = id.clone();
 
             tokio::spawn(async move {
                 let url = format!("{}/volumes/{}", clone_url, clone_id);
-                let res = clone_api_client
-                    .delete(&url)
-                    .bearer_auth(&clone_token)
-                    .send()
+                let res = clone_session
+                    .send(|token| {
+                        clone_api_client
+                            .delete(&url)
+                            .bearer_auth(token)
+                    })
                     .await;
 
                 match res {
@@ -999,5 +1100,375 @@ pub fn handle_api_message(app: &mut BindKeyApp, message: ApiMessage) {
             app.dashboard_status = format!("❌ Erreur : {}", err);
             app.is_loading = false;
         }
+        ApiMessage::DeviceEvent(event) => match event {
+            DeviceEvent::Prompt(code) => {
+                app.device_prompt = Some(code);
+            }
+            DeviceEvent::Progress { current, total } => {
+                app.device_progress = Some((current, total));
+            }
+            DeviceEvent::FingerprintQuality(quality) => {
+                app.device_fp_quality = Some(quality);
+            }
+        },
+        ApiMessage::DeviceFeedbackReset => {
+            pages::device_feedback::reset_device_feedback(app);
+        }
+        ApiMessage::Hotplug(event) => match event {
+            HotplugEvent::BindKeyAttached(port_name) => {
+                if app.attached_bindkeys.iter().any(|k| k.port_name == port_name) {
+                    return;
+                }
+                // Première clé branchée : on rafraîchit les volumes
+                if app.attached_bindkeys.is_empty() {
+                    let _ = app.sender.send(ApiMessage::RequestVolumeRefresh);
+                }
+                let mut key = AttachedBindKey {
+                    port_name: port_name.clone(),
+                    sn: None,
+                    disk_path: None,
+                    disk_conflict: None,
+                    firmware: None,
+                };
+                key.set_disk(disk_link::find_disk_for_port(&port_name, &app.config));
+                app.attached_bindkeys.push(key);
+                app.device.set_ports(app.attached_ports());
+                app.usb_connected = true;
+
+                // Un transcript rejoué ne contient que les commandes enregistrées :
+                // son SN sera connu à la lecture de l'UID de la connexion
+                if port_name.starts_with(REPLAY_PORT_PREFIX) {
+                    return;
+                }
+
+                identify_bindkey(app, port_name);
+            }
+            HotplugEvent::BindKeyDetached(port_name) => {
+                let session_key = app.attached_bindkeys.iter().any(|k| {
+                    k.port_name == port_name && k.sn.is_some() && k.sn == app.local_bindkey_sn
+                });
+                if session_key && app.config.auto_lock.on_unplug {
+                    // Avant de l'oublier : son disque fait partie des volumes à démonter
+                    lock_session(app, LockReason::KeyUnplugged);
+                }
+                app.attached_bindkeys.retain(|k| k.port_name != port_name);
+                // Interrompt aussitôt la commande en cours sur cette clé
+                app.device.set_ports(app.attached_ports());
+                app.usb_connected = !app.attached_bindkeys.is_empty();
+            }
+            HotplugEvent::DiskAttached(_) => {
+                app.relink_disks();
+                let _ = app.sender.send(ApiMessage::RequestVolumeRefresh);
+            }
+            HotplugEvent::DiskDetached(devnode) => {
+                app.relink_disks();
+                let before = app.available_devices.len();
+                app.available_devices.retain(|d| d.path != devnode);
+                if app.available_devices.len() != before {
+                    // Le disque analysé a disparu : il faudra relancer l'analyse
+                    app.device_name.clear();
+                    app.device_size = 0.0;
+                    app.device_available_space = 0.0;
+                    app.volume_status = format!("Disque {} retiré", devnode);
+                }
+                let _ = app.sender.send(ApiMessage::RequestVolumeRefresh);
+            }
+        },
+        ApiMessage::BindKeyIdentified {
+            port_name,
+            sn,
+            firmware,
+        } => {
+            println!("[USB] BindKey {} sur {}", sn, port_name);
+            if let Some(key) = app
+                .attached_bindkeys
+                .iter_mut()
+                .find(|k| k.port_name == port_name)
+            {
+                key.sn = Some(sn.clone());
+                if let Some(firmware) = firmware {
+                    println!("[USB] Firmware {}", firmware.display_version());
+                    key.firmware = Some(firmware);
+                }
+            }
+
+            // Mise à jour du firmware interrompue par un débranchement : on reprend sur cette clé
+            let resume = app
+                .firmware_update
+                .as_ref()
+                .is_some_and(|u| u.interrupted && !u.running && u.sn == sn);
+            if resume {
+                println!("[FIRMWARE] Clé {} rebranchée, reprise de la mise à jour", sn);
+                app.firmware_status = "Clé rebranchée, reprise de la mise à jour...".to_string();
+                let device = app.device.target(&port_name);
+                firmware_update::start(app, device);
+            }
+        }
+        ApiMessage::PayloadStatus(text) => {
+            app.payload.status = text;
+        }
+        ApiMessage::PayloadFinished(result) => {
+            app.payload.busy = false;
+            app.payload.status = match result {
+                Ok(text) => text,
+                Err(e) => format!("❌ {}", e),
+            };
+        }
+        ApiMessage::FingerprintsUpdated(result) => {
+            app.fingerprints.busy = false;
+            match result {
+                Ok((list, text)) => {
+                    app.fingerprints.list = Some(list);
+                    app.fingerprints.new_label.clear();
+                    app.fingerprints.status = text;
+                }
+                Err(e) => {
+                    app.fingerprints.status = format!("❌ {}", e);
+                }
+            }
+        }
+        ApiMessage::ReconciliationStatus(text) => {
+            app.reconcile.status = text;
+        }
+        ApiMessage::ReconciliationFinished(result) => {
+            app.reconcile.running = false;
+            match result {
+                Ok(report) => {
+                    println!(
+                        "[COHÉRENCE] {} anomalie(s) sur {}",
+                        report.findings.len(),
+                        report.disk_path
+                    );
+                    app.reconcile.report = Some(report);
+                }
+                Err(e) => {
+                    app.reconcile.report = None;
+                    app.reconcile.status = format!("❌ {}", e);
+                }
+            }
+        }
+        ApiMessage::ReauthRequired => {
+            println!("[SESSION] Jeton expiré : nouvelle vérification d'empreinte demandée");
+            app.reauth = Default::default();
+        }
+        ApiMessage::ReauthFinished(result) => {
+            app.reauth.running = false;
+            match result {
+                Ok(tokens) => {
+                    app.session.begin(tokens);
+                    app.reauth.status.clear();
+                    println!("[SESSION] Réauthentification réussie : reprise des requêtes");
+                }
+                Err(e) => {
+                    app.reauth.status = format!("❌ {}", e);
+                }
+            }
+        }
+        ApiMessage::IncomingSharesChecked(result) => {
+            app.incoming_shares.running = false;
+            match result {
+                Ok(shares) if shares.is_empty() => {
+                    app.incoming_shares.shares.clear();
+                    app.incoming_shares.status = "Aucun nouveau partage en attente.".to_string();
+                }
+                Ok(shares) => {
+                    let refused = shares.iter().filter(|s| s.verified.is_err()).count();
+                    app.incoming_shares.status = format!(
+                        "{} partage(s) en attente, dont {} refusé(s) : vérifiez l'émetteur avant d'installer",
+                        shares.len(),
+                        refused
+                    );
+                    app.incoming_shares.shares = shares;
+                }
+                Err(e) => {
+                    app.incoming_shares.status = format!("❌ {}", e);
+                }
+            }
+        }
+        ApiMessage::IncomingShareInstalled(share_id, result) => {
+            app.incoming_shares.running = false;
+            match result {
+                Ok(()) => {
+                    app.incoming_shares
+                        .shares
+                        .retain(|s| s.share.share_id != share_id);
+                    app.incoming_shares.status = "Partage installé ! REDÉMARREZ votre BindKey avec le disque pour l'activer.".to_string();
+                }
+                Err(e) => {
+                    app.incoming_shares.status = format!("❌ {}", e);
+                }
+            }
+        }
+        ApiMessage::DiagnosticsFinished(report) => {
+            app.diagnostics.running = false;
+            println!(
+                "[DIAG] Diagnostic de {} terminé : {}",
+                report.port_name,
+                report.worst_status().label()
+            );
+            app.diagnostics.export_path = report.file_name();
+            app.diagnostics.report = Some(report);
+        }
+        ApiMessage::FirmwareUpdateProgress { sent, total } => {
+            if let Some(update) = app.firmware_update.as_mut() {
+                update.sent = sent;
+            }
+            app.firmware_status = format!("Envoi du firmware : {} / {} octets", sent, total);
+        }
+        ApiMessage::FirmwareUpdateFinished {
+            result,
+            interrupted,
+        } => {
+            let Some(mut update) = app.firmware_update.take() else {
+                return;
+            };
+            update.running = false;
+
+            match result {
+                Ok(()) => {
+                    app.firmware_status = format!(
+                        "Firmware {} installé, la clé redémarre.",
+                        update.firmware.version
+                    );
+                    // Nouvelle version et nouvelles capacités à relire
+                    let port = app
+                        .attached_bindkeys
+                        .iter()
+                        .find(|k| k.sn.as_ref() == Some(&update.sn))
+                        .map(|k| k.port_name.clone());
+                    if let Some(port_name) = port {
+                        identify_bindkey(app, port_name);
+                    }
+                }
+                Err(e) if interrupted => {
+                    app.firmware_status = format!(
+                        "❌ Mise à jour interrompue ({}) : rebranchez la clé {} pour reprendre.",
+                        e, update.sn
+                    );
+                    update.interrupted = true;
+                    app.firmware_update = Some(update);
+                }
+                Err(e) => {
+                    app.firmware_status = format!("❌ Échec de la mise à jour du firmware : {}", e);
+                }
+            }
+        }
     }
 }
+
+// Lit le SN et la version du firmware d'une clé qui vient d'apparaître
+fn identify_bindkey(app: &BindKeyApp, port_name: String) {
+    let device = app.device.target(&port_name);
+    let clone_sender = app.sender.clone();
+    tokio::spawn(async move {
+        match device
+            .run("Identification de la clé", |d| {
+                let sn = d.read_uid()?;
+                Ok((sn, d.firmware_info().ok()))
+            })
+            .await
+        {
+            Ok((sn, firmware)) => {
+                let _ = clone_sender.send(ApiMessage::BindKeyIdentified {
+                    port_name,
+                    sn,
+                    firmware,
+                });
+            }
+            Err(e) => println!("[USB] Clé sur {} non identifiée : {}", port_name, e),
+        }
+    });
+}
+
+// Retour à la connexion : rien de la session précédente ne reste en mémoire
+fn clear_session(app: &mut BindKeyApp) {
+    app.current_page = Page::Login;
+    app.role_user = Role::NONE;
+
+    app.session.end();
+    app.local_token.clear();
+    app.login_email.clear();
+    app.login_password.clear();
+    app.local_bindkey_sn = None;
+
+    app.first_name_user.clear();
+    app.enroll_firstname.clear();
+    app.enroll_lastname.clear();
+    app.enroll_email.clear();
+    app.enroll_password.clear();
+    app.enroll_status.clear();
+
+    app.search_result = None;
+    app.share_target_name = None;
+    app.share_target_email = None;
+    app.share_target_role = None;
+    app.sharing_active_volume = None;
+
+    app.device_available_space = 0.0;
+    app.device_name.clear();
+    app.device_size = 0.0;
+    app.volume_created_name.clear();
+    app.volume_created_size = 0;
+    app.volume_status.clear();
+    app.users_list.clear();
+    app.fingerprints = Default::default();
+    app.reconcile = Default::default();
+    app.incoming_shares = Default::default();
+    app.reauth = Default::default();
+    app.reassign = Default::default();
+    app.payload = Default::default();
+    app.restoration = Default::default();
+    app.diagnostics = Default::default();
+    app.firmware_update = None;
+    app.firmware_status.clear();
+    app.dashboard_volumes.clear();
+}
+
+// Verrouillage automatique : comme une déconnexion, mais les volumes BindKey sont démontés
+// et les opérations en cours sur la clé interrompues
+fn lock_session(app: &mut BindKeyApp, reason: LockReason) {
+    if !app.is_logged_in() {
+        return;
+    }
+    println!("[VERROUILLAGE] Session verrouillée : {}", reason);
+
+    let mut volumes: Vec<String> = app
+        .dashboard_volumes
+        .iter()
+        .filter(|v| v.is_mounted)
+        .map(|v| v.device_path.clone())
+        .collect();
+    // Le disque derrière la clé de session, même si ses volumes ne sont pas listés
+    volumes.extend(
+        app.attached_bindkeys
+            .iter()
+            .filter(|k| k.sn.is_some() && k.sn == app.local_bindkey_sn)
+            .filter_map(|k| k.disk_path.clone()),
+    );
+    tokio::task::spawn_blocking(move || auto_lock::unmount_volumes(&volumes));
+
+    // Le serveur révoque les deux jetons si on le joint ; sinon ils expireront d'eux-mêmes
+    let token = app.session.token();
+    let refresh_token = app.session.refresh_token();
+    let url = format!("{}/sessions/logout", app.config.api_url());
+    let api_client = app.api_client.clone();
+    tokio::spawn(async move {
+        let payload = LogOut {
+            server_token: token.clone(),
+            refresh_token,
+        };
+        if let Err(e) = api_client
+            .post(url)
+            .json(&payload)
+            .bearer_auth(token)
+            .send()
+            .await
+        {
+            println!("[VERROUILLAGE] Jeton non révoqué par le serveur : {}", e);
+        }
+    });
+
+    app.device.cancel_all();
+    clear_session(app);
+    app.login_status = format!("🔒 Session verrouillée ({}) : reconnectez-vous.", reason);
+}
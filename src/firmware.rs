@@ -0,0 +1,379 @@
+use p256::ecdsa::signature::{Signer, Verifier};
+use p256::ecdsa::{Signature, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+
+// ==========================================
+// VERSION DU FIRMWARE ET COMPATIBILITÉ
+// ==========================================
+//
+// La clé annonce sa version et ses capacités (commande "version"). Un firmware qui
+// ne connaît pas cette commande est antérieur au versionnage : on le considère trop
+// ancien pour toute opération qui écrit sur la clé.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FirmwareVersion {
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+}
+
+impl FirmwareVersion {
+    pub const fn new(major: u16, minor: u16, patch: u16) -> Self {
+        FirmwareVersion {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+impl FromStr for FirmwareVersion {
+    type Err = String;
+
+    // Accepte "1.2.0", "v1.2" ou "1.2.0-rc1"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let core = s.trim().trim_start_matches('v');
+        let core = core.split(['-', '+']).next().unwrap_or(core);
+        let mut parts = core.split('.').map(|p| p.parse::<u16>());
+
+        let mut next = || match parts.next() {
+            None => Ok(0),
+            Some(Ok(n)) => Ok(n),
+            Some(Err(_)) => Err(format!("Version de firmware illisible : {}", s)),
+        };
+        let version = FirmwareVersion::new(next()?, next()?, next()?);
+        if parts.next().is_some() {
+            return Err(format!("Version de firmware illisible : {}", s));
+        }
+        Ok(version)
+    }
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Feature {
+    LbaRegistration,
+    Sharing,
+    Format,
+    FirmwareUpdate,
+    PayloadTransfer,
+    SelfTest,
+    FingerprintManagement,
+    Inventory,
+    CommandAbort,
+    SignedChallenge,
+    ShareSignature,
+    FactoryWipe,
+}
+
+struct Requirement {
+    feature: Feature,
+    min_version: FirmwareVersion,
+    // Capacité annoncée par la clé (CAPS=...) ; ignorée si la clé n'en annonce aucune
+    capability: &'static str,
+    label: &'static str,
+}
+
+// Table de compatibilité : version minimale de chaque fonctionnalité
+const COMPATIBILITY: [Requirement; 12] = [
+    Requirement {
+        feature: Feature::LbaRegistration,
+        min_version: FirmwareVersion::new(1, 1, 0),
+        capability: "lba",
+        label: "Création de volume",
+    },
+    Requirement {
+        feature: Feature::Sharing,
+        min_version: FirmwareVersion::new(1, 2, 0),
+        capability: "share",
+        label: "Partage de volume",
+    },
+    Requirement {
+        feature: Feature::Format,
+        min_version: FirmwareVersion::new(1, 1, 0),
+        capability: "format",
+        label: "Formatage",
+    },
+    Requirement {
+        feature: Feature::FirmwareUpdate,
+        min_version: FirmwareVersion::new(1, 2, 0),
+        capability: "ota",
+        label: "Mise à jour du firmware par USB",
+    },
+    Requirement {
+        feature: Feature::PayloadTransfer,
+        min_version: FirmwareVersion::new(1, 2, 0),
+        capability: "payload",
+        label: "Paquets hors ligne",
+    },
+    Requirement {
+        feature: Feature::SelfTest,
+        min_version: FirmwareVersion::new(1, 2, 0),
+        capability: "diag",
+        label: "Autotest de la clé",
+    },
+    Requirement {
+        feature: Feature::FingerprintManagement,
+        min_version: FirmwareVersion::new(1, 2, 0),
+        capability: "fp_mgmt",
+        label: "Gestion des empreintes",
+    },
+    Requirement {
+        feature: Feature::Inventory,
+        min_version: FirmwareVersion::new(1, 2, 0),
+        capability: "inventory",
+        label: "Inventaire des volumes",
+    },
+    Requirement {
+        feature: Feature::CommandAbort,
+        min_version: FirmwareVersion::new(1, 2, 0),
+        capability: "abort",
+        label: "Annulation d'une commande en cours",
+    },
+    Requirement {
+        feature: Feature::SignedChallenge,
+        min_version: FirmwareVersion::new(1, 2, 0),
+        capability: "signed_challenge",
+        label: "Vérification du serveur avant signature",
+    },
+    Requirement {
+        feature: Feature::ShareSignature,
+        min_version: FirmwareVersion::new(1, 2, 0),
+        capability: "share_sig",
+        label: "Signature des partages",
+    },
+    Requirement {
+        feature: Feature::FactoryWipe,
+        min_version: FirmwareVersion::new(1, 2, 0),
+        capability: "factory_wipe",
+        label: "Remise à zéro d'usine",
+    },
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FirmwareInfo {
+    // None : firmware antérieur à la commande "version"
+    pub version: Option<FirmwareVersion>,
+    pub capabilities: Vec<String>,
+}
+
+impl FirmwareInfo {
+    pub fn legacy() -> Self {
+        FirmwareInfo {
+            version: None,
+            capabilities: Vec::new(),
+        }
+    }
+
+    pub fn display_version(&self) -> String {
+        match self.version {
+            Some(version) => version.to_string(),
+            None => "antérieur à v1.1 (non versionné)".to_string(),
+        }
+    }
+
+    // Ok si la fonctionnalité est utilisable, sinon l'explication à afficher
+    pub fn check(&self, feature: Feature) -> Result<(), String> {
+        let Some(requirement) = COMPATIBILITY.iter().find(|r| r.feature == feature) else {
+            return Ok(());
+        };
+
+        let Some(version) = self.version else {
+            return Err(format!(
+                "{} indisponible : firmware trop ancien, mettez la clé à jour (minimum {})",
+                requirement.label, requirement.min_version
+            ));
+        };
+        if version < requirement.min_version {
+            return Err(format!(
+                "{} indisponible : firmware {} trop ancien (minimum {})",
+                requirement.label, version, requirement.min_version
+            ));
+        }
+        if !self.capabilities.is_empty()
+            && !self
+                .capabilities
+                .iter()
+                .any(|c| c == requirement.capability)
+        {
+            return Err(format!(
+                "{} indisponible : non prise en charge par ce firmware ({})",
+                requirement.label, version
+            ));
+        }
+        Ok(())
+    }
+}
+
+// ==========================================
+// IMAGE DE FIRMWARE SIGNÉE
+// ==========================================
+//
+// Fichier .bkfw : "BKFW" suivi d'un SignedImage encodé en postcard. La signature ECDSA P-256
+// couvre le contexte, la version et le SHA-256 de l'image : une image ne peut être ni
+// modifiée ni présentée sous une autre version.
+
+pub const FIRMWARE_IMAGE_MAGIC: &[u8; 4] = b"BKFW";
+const FIRMWARE_SIGNING_CONTEXT: &[u8] = b"bindkey-firmware-v1";
+
+// Clé publique de signature des firmwares de l'ESP32 maître (SEC1 non compressée)
+pub const FIRMWARE_PUBLIC_KEY: &str = "0447d0a41b519772314cf975004b5c8c71328098681ef874d5979caa2748c197f027b4ff4672e5067608b66c97196d406ffa152006fae0ce2c225bd60174d954ca";
+
+#[derive(Serialize, Deserialize)]
+struct SignedImage {
+    version: String,
+    image: Vec<u8>,
+    // r || s, 64 octets
+    signature: Vec<u8>,
+}
+
+// Image dont la signature a été vérifiée : seule forme acceptée pour l'envoi à la clé
+#[derive(Debug, Clone)]
+pub struct VerifiedFirmware {
+    pub version: FirmwareVersion,
+    pub image: Vec<u8>,
+    pub sha256: String,
+}
+
+impl VerifiedFirmware {
+    // Une image plus ancienne que le firmware installé rouvrirait les failles corrigées
+    // depuis : refusée sauf si l'utilisateur force explicitement le retour en arrière
+    pub fn check_downgrade(
+        &self,
+        installed: &FirmwareInfo,
+        allow_downgrade: bool,
+    ) -> Result<(), String> {
+        match installed.version {
+            Some(current) if self.version < current && !allow_downgrade => Err(format!(
+                "Image {} plus ancienne que le firmware installé ({}) : retour en arrière refusé",
+                self.version, current
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn signed_message(version: &str, digest: &[u8]) -> Vec<u8> {
+    let mut message = FIRMWARE_SIGNING_CONTEXT.to_vec();
+    message.extend_from_slice(version.as_bytes());
+    message.push(0);
+    message.extend_from_slice(digest);
+    message
+}
+
+// Utilisée par l'outil de publication (src/bin/bindkey_sign_firmware.rs)
+#[allow(dead_code)]
+pub fn sign_image(version: &str, image: Vec<u8>, key: &SigningKey) -> Result<Vec<u8>, String> {
+    version.parse::<FirmwareVersion>()?;
+    let digest = Sha256::digest(&image);
+    let signature: Signature = key.sign(&signed_message(version, &digest));
+
+    let signed = SignedImage {
+        version: version.to_string(),
+        image,
+        signature: signature.to_bytes().to_vec(),
+    };
+    let mut out = FIRMWARE_IMAGE_MAGIC.to_vec();
+    out.extend(postcard::to_stdvec(&signed).map_err(|e| format!("Encodage de l'image : {}", e))?);
+    Ok(out)
+}
+
+pub fn verify_image(bytes: &[u8]) -> Result<VerifiedFirmware, String> {
+    let key = hex::decode(FIRMWARE_PUBLIC_KEY)
+        .ok()
+        .and_then(|k| VerifyingKey::from_sec1_bytes(&k).ok())
+        .ok_or("Clé publique de firmware embarquée invalide")?;
+    verify_image_with(bytes, &key)
+}
+
+fn verify_image_with(bytes: &[u8], key: &VerifyingKey) -> Result<VerifiedFirmware, String> {
+    let payload = bytes
+        .strip_prefix(FIRMWARE_IMAGE_MAGIC)
+        .ok_or("Ce fichier n'est pas une image de firmware BindKey")?;
+    let signed: SignedImage = postcard::from_bytes(payload)
+        .map_err(|e| format!("Image de firmware illisible : {}", e))?;
+
+    let digest = Sha256::digest(&signed.image);
+    let signature = Signature::from_slice(&signed.signature)
+        .map_err(|_| "Signature de l'image illisible".to_string())?;
+    key.verify(&signed_message(&signed.version, &digest), &signature)
+        .map_err(|_| "Signature de l'image invalide : firmware refusé".to_string())?;
+
+    Ok(VerifiedFirmware {
+        version: signed.version.parse()?,
+        sha256: hex::encode(digest),
+        image: signed.image,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compatibility_gating() {
+        assert_eq!(
+            "v1.2.0-rc1".parse::<FirmwareVersion>(),
+            Ok(FirmwareVersion::new(1, 2, 0))
+        );
+        assert!("1.x".parse::<FirmwareVersion>().is_err());
+
+        let legacy = FirmwareInfo::legacy();
+        assert!(legacy.check(Feature::LbaRegistration).is_err());
+
+        let old = FirmwareInfo {
+            version: Some(FirmwareVersion::new(1, 1, 3)),
+            capabilities: Vec::new(),
+        };
+        assert!(old.check(Feature::LbaRegistration).is_ok());
+        assert!(old.check(Feature::Sharing).is_err());
+
+        let no_share = FirmwareInfo {
+            version: Some(FirmwareVersion::new(1, 3, 0)),
+            capabilities: vec!["lba".to_string(), "format".to_string()],
+        };
+        assert!(no_share.check(Feature::Format).is_ok());
+        assert!(no_share.check(Feature::Sharing).is_err());
+    }
+
+    #[test]
+    fn test_signed_image_verification() {
+        let key = SigningKey::random(&mut rand_core::OsRng);
+        let image = vec![0xE9, 0x03, 0x02, 0x20, 0x42];
+        let signed = sign_image("1.3.0", image.clone(), &key).unwrap();
+
+        let verified = verify_image_with(&signed, key.verifying_key()).unwrap();
+        assert_eq!(verified.version, FirmwareVersion::new(1, 3, 0));
+        assert_eq!(verified.image, image);
+
+        // Dernier octet de l'image modifié
+        let mut tampered = signed.clone();
+        let index = tampered.len() - 66;
+        tampered[index] ^= 0x01;
+        assert!(verify_image_with(&tampered, key.verifying_key()).is_err());
+
+        let other = SigningKey::random(&mut rand_core::OsRng);
+        assert!(verify_image_with(&signed, other.verifying_key()).is_err());
+        assert!(verify_image(&signed).is_err());
+
+        let installed = FirmwareInfo {
+            version: Some(FirmwareVersion::new(1, 4, 0)),
+            capabilities: Vec::new(),
+        };
+        assert!(verified.check_downgrade(&installed, false).is_err());
+        assert!(verified.check_downgrade(&installed, true).is_ok());
+        assert!(
+            verified
+                .check_downgrade(&FirmwareInfo::legacy(), false)
+                .is_ok()
+        );
+    }
+}
@@ -0,0 +1,130 @@
+use crate::BindKeyApp;
+use crate::bindkey_device::DeviceError;
+use crate::device_owner::DeviceTarget;
+use crate::firmware::VerifiedFirmware;
+use crate::protocol::protocol::ApiMessage;
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+
+// ==========================================
+// MISE À JOUR DU FIRMWARE PAR L'UART
+// ==========================================
+//
+// L'image est vérifiée (signature) avant tout envoi. Chaque bloc est acquitté par la clé
+// avec le prochain offset attendu ; après un débranchement, FirmwareBegin renvoie l'offset
+// déjà reçu et l'envoi reprend là. L'empreinte calculée par la clé doit être celle de l'image
+// signée avant de lui demander d'installer.
+
+pub const FIRMWARE_CHUNK_SIZE: usize = 1024;
+
+pub struct FirmwareUpdate {
+    pub firmware: Arc<VerifiedFirmware>,
+    // Clé visée : la reprise ne se fait que sur elle
+    pub sn: String,
+    pub sent: u64,
+    pub running: bool,
+    // Interrompue par un débranchement : reprend à la prochaine identification de la clé
+    pub interrupted: bool,
+    // Retour à une version antérieure demandé explicitement par l'utilisateur
+    pub allow_downgrade: bool,
+}
+
+// La clé a disparu en cours de route, pas de refus de sa part
+pub fn is_interruption(error: &DeviceError) -> bool {
+    matches!(
+        error,
+        DeviceError::Disconnected
+            | DeviceError::NotConnected
+            | DeviceError::Io(_)
+            | DeviceError::Timeout
+    )
+}
+
+pub fn start(app: &mut BindKeyApp, device: DeviceTarget) {
+    let Some(update) = app.firmware_update.as_mut() else {
+        return;
+    };
+    if update.running {
+        return;
+    }
+    update.running = true;
+    update.interrupted = false;
+
+    let firmware = update.firmware.clone();
+    let allow_downgrade = update.allow_downgrade;
+    let sender = app.sender.clone();
+    tokio::spawn(async move {
+        let result = push_firmware(&device, firmware, allow_downgrade, &sender).await;
+        let _ = sender.send(ApiMessage::FirmwareUpdateFinished {
+            interrupted: result.as_ref().is_err_and(is_interruption),
+            result: result.map_err(|e| e.to_string()),
+        });
+    });
+}
+
+async fn push_firmware(
+    device: &DeviceTarget,
+    firmware: Arc<VerifiedFirmware>,
+    allow_downgrade: bool,
+    sender: &Sender<ApiMessage>,
+) -> Result<(), DeviceError> {
+    // Contrôle sur la version annoncée par la clé elle-même, pas sur celle affichée
+    let installed = device
+        .run("Lecture de la version", |d| d.firmware_info())
+        .await?;
+    firmware
+        .check_downgrade(&installed, allow_downgrade)
+        .map_err(DeviceError::Unsupported)?;
+
+    let total = firmware.image.len() as u64;
+    let version = firmware.version.to_string();
+    let sha256 = firmware.sha256.clone();
+
+    let mut offset = device
+        .run("Mise à jour du firmware", move |d| {
+            d.firmware_begin(&version, total, &sha256)
+        })
+        .await?;
+    if offset > 0 {
+        println!("[FIRMWARE] Reprise de l'envoi à l'octet {}", offset);
+    }
+
+    while offset < total {
+        let start = offset as usize;
+        let end = (start + FIRMWARE_CHUNK_SIZE).min(firmware.image.len());
+        let data = firmware.image[start..end].to_vec();
+
+        let next = device
+            .run("Envoi du firmware", move |d| d.firmware_chunk(offset, data))
+            .await?;
+        if next > total {
+            return Err(DeviceError::UnexpectedResponse(format!(
+                "offset {} au-delà de l'image ({} octets)",
+                next, total
+            )));
+        }
+        offset = next;
+        let _ = sender.send(ApiMessage::FirmwareUpdateProgress {
+            sent: offset,
+            total,
+        });
+    }
+
+    let reported = device
+        .run("Vérification du firmware", |d| d.firmware_hash())
+        .await?;
+    if !reported.eq_ignore_ascii_case(&firmware.sha256) {
+        // Image corrompue pendant le transfert : la clé la jette, on repartira de zéro
+        let _ = device
+            .run("Abandon de la mise à jour", |d| d.firmware_abort())
+            .await;
+        return Err(DeviceError::UnexpectedResponse(format!(
+            "empreinte du firmware reçu différente (clé : {}, image : {})",
+            reported, firmware.sha256
+        )));
+    }
+
+    device
+        .run("Installation du firmware", |d| d.firmware_commit())
+        .await
+}
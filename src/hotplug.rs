@@ -0,0 +1,188 @@
+use crate::config::AppConfig;
+use crate::protocol::protocol::ApiMessage;
+use crate::transcript::REPLAY_PORT_PREFIX;
+use eframe::egui;
+use nix::poll::{PollFd, PollFlags, PollTimeout, poll};
+use serialport::SerialPortType;
+use std::os::fd::AsFd;
+use std::path::Path;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+// ==========================================
+// DÉTECTION DES BRANCHEMENTS (udev / netlink)
+// ==========================================
+//
+// Un thread écoute les événements udev du port série de la BindKey (sous-système tty)
+// et des disques USB (block/disk), puis prévient l'interface par un ApiMessage.
+// Sans udev (conteneur, port imposé par la configuration), il retombe sur un sondage
+// en arrière-plan : le rendu de l'interface ne liste plus jamais les ports lui-même.
+
+// Pont USB-UART CP210x de la BindKey
+pub const BINDKEY_USB_VID: u16 = 0x10c4;
+pub const BINDKEY_USB_PID: u16 = 0xea60;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HotplugEvent {
+    BindKeyAttached(String),
+    BindKeyDetached(String),
+    DiskAttached(String),
+    DiskDetached(String),
+}
+
+pub fn spawn_watcher(config: &AppConfig, sender: Sender<ApiMessage>, ctx: egui::Context) {
+    let replay = config
+        .replay_transcript
+        .as_ref()
+        .map(|path| format!("{}{}", REPLAY_PORT_PREFIX, path));
+    let forced_port = config.device_port.clone();
+
+    let notify = move |event: HotplugEvent| {
+        println!("[HOTPLUG] {:?}", event);
+        let _ = sender.send(ApiMessage::Hotplug(event));
+        ctx.request_repaint();
+    };
+
+    let spawned = std::thread::Builder::new()
+        .name("bindkey-hotplug".to_string())
+        .spawn(move || {
+            if let Some(port) = replay {
+                // Un transcript rejoué est « branché » pour toute la session
+                notify(HotplugEvent::BindKeyAttached(port));
+            } else if let Some(port) = forced_port {
+                // Lien PTY de l'émulateur : udev ne le voit pas
+                poll_ports(|| forced_port_present(&port), notify);
+            } else if let Err(e) = watch_udev(&notify) {
+                eprintln!(
+                    "[HOTPLUG] udev indisponible ({}), sondage des ports série",
+                    e
+                );
+                poll_ports(scan_serial_ports, notify);
+            }
+        });
+
+    if let Err(e) = spawned {
+        eprintln!(
+            "[HOTPLUG] Impossible de lancer le thread de détection : {}",
+            e
+        );
+    }
+}
+
+fn watch_udev(notify: &impl Fn(HotplugEvent)) -> std::io::Result<()> {
+    // On s'abonne avant l'énumération pour ne rater aucun branchement entre les deux
+    let socket = udev::MonitorBuilder::new()?
+        .match_subsystem("tty")?
+        .match_subsystem_devtype("block", "disk")?
+        .listen()?;
+
+    let mut enumerator = udev::Enumerator::new()?;
+    for device in enumerator.scan_devices()? {
+        if let Some(event) = classify(&device, true) {
+            notify(event);
+        }
+    }
+
+    loop {
+        let mut fds = [PollFd::new(socket.as_fd(), PollFlags::POLLIN)];
+        match poll(&mut fds, PollTimeout::NONE) {
+            Ok(_) => {}
+            Err(nix::errno::Errno::EINTR) => continue,
+            Err(e) => return Err(e.into()),
+        }
+
+        for event in socket.iter() {
+            let attached = match event.event_type() {
+                udev::EventType::Add => true,
+                udev::EventType::Remove => false,
+                _ => continue,
+            };
+            if let Some(hotplug) = classify(&event.device(), attached) {
+                notify(hotplug);
+            }
+        }
+    }
+}
+
+// Port série de la BindKey ou disque de masse USB ; tout le reste est ignoré
+fn classify(device: &udev::Device, attached: bool) -> Option<HotplugEvent> {
+    let devnode = device.devnode()?.to_string_lossy().to_string();
+    let property = |key: &str| {
+        device
+            .property_value(key)
+            .map(|v| v.to_string_lossy().to_string())
+    };
+
+    match device.subsystem()?.to_str()? {
+        "tty" => {
+            let vid = u16::from_str_radix(&property("ID_VENDOR_ID")?, 16).ok()?;
+            let pid = u16::from_str_radix(&property("ID_MODEL_ID")?, 16).ok()?;
+            if (vid, pid) != (BINDKEY_USB_VID, BINDKEY_USB_PID) {
+                return None;
+            }
+            Some(if attached {
+                HotplugEvent::BindKeyAttached(devnode)
+            } else {
+                HotplugEvent::BindKeyDetached(devnode)
+            })
+        }
+        "block" => {
+            if device.devtype()? != "disk" || property("ID_BUS").as_deref() != Some("usb") {
+                return None;
+            }
+            Some(if attached {
+                HotplugEvent::DiskAttached(devnode)
+            } else {
+                HotplugEvent::DiskDetached(devnode)
+            })
+        }
+        _ => None,
+    }
+}
+
+// ==========================================
+// REPLI : SONDAGE EN ARRIÈRE-PLAN
+// ==========================================
+
+fn poll_ports(scan: impl Fn() -> Vec<String>, notify: impl Fn(HotplugEvent)) {
+    let mut known: Vec<String> = Vec::new();
+
+    loop {
+        let found = scan();
+
+        for port in known.iter().filter(|p| !found.contains(p)) {
+            notify(HotplugEvent::BindKeyDetached(port.clone()));
+        }
+        for port in found.iter().filter(|p| !known.contains(p)) {
+            notify(HotplugEvent::BindKeyAttached(port.clone()));
+        }
+
+        known = found;
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn forced_port_present(port: &str) -> Vec<String> {
+    if Path::new(port).exists() {
+        vec![port.to_string()]
+    } else {
+        Vec::new()
+    }
+}
+
+fn scan_serial_ports() -> Vec<String> {
+    serialport::available_ports()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|p| {
+            matches!(
+                &p.port_type,
+                SerialPortType::UsbPort(info)
+                    if info.vid == BINDKEY_USB_VID && info.pid == BINDKEY_USB_PID
+            )
+        })
+        .map(|p| p.port_name)
+        .collect()
+}
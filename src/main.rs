@@ -1,18 +1,46 @@
 use eframe::egui;
-use serialport::SerialPortType;
 use std::sync::mpsc::{Receiver, Sender, channel};
 use std::time::{Duration, Instant};
+mod auto_lock;
+mod bindkey_device;
 mod config;
+mod device_owner;
+mod diagnostics;
+mod disk_link;
+mod firmware;
+mod firmware_update;
 mod pages;
+mod partition_table;
+mod payload_bundle;
 mod protocol;
+mod reconcile;
+mod server_challenge;
+mod server_endpoint;
+mod session;
+mod share_signature;
+mod srp;
 use crate::protocol::protocol::{
-    ApiMessage, BindKeyInfo, LogOut, Page, Role, UsbDevice, User, UserWithBindKey, VolumeInfo,
-    VolumeTab, create_secure_client,
+    ApiMessage, AttachedBindKey, BindKeyInfo, LogOut, Page, Role, UsbDevice, User, UserWithBindKey, VolumeInfo,
+    VolumeTab,
 };
+mod transcript;
 mod usb_service;
 use crate::config::AppConfig;
+use crate::device_owner::{DeviceHandle, DeviceTarget};
+use crate::firmware::Feature;
+use crate::firmware_update::FirmwareUpdate;
+use crate::pages::diagnostics::DiagnosticsState;
+use crate::pages::fingerprints::FingerprintState;
+use crate::pages::payload::PayloadState;
+use crate::pages::incoming_shares::IncomingSharesState;
+use crate::pages::login::ReauthState;
+use crate::pages::reassign::ReassignState;
+use crate::pages::reconcile::ReconcileState;
+use crate::pages::restoration::RestorationState;
+use crate::session::ServerSession;
 use validator::Validate;
 mod event_handler;
+mod hotplug;
 pub const UPDATE_PUBLIC_KEY: &str = "RWSJeF+oi2P6KH0F+FjnPr3NuWxaRv2DNisbPUBQpq2E6oB87JFQAqcX";
 
 #[derive(Validate)]
@@ -44,13 +72,18 @@ struct BindKeyApp {
     pub login_email: String,
     pub login_password: String,
     pub is_admin_mode: bool,
-    pub server_token: String,
+    pub session: ServerSession,
     pub local_token: String,
     pub config: AppConfig,
     pub usb_connected: bool,
-    pub last_usb_check: Instant,
     pub users_list: Vec<User>,
-    pub current_port_name: String,
+    // Toutes les BindKeys branchées, dans l'ordre de détection
+    pub attached_bindkeys: Vec<AttachedBindKey>,
+    pub device: DeviceHandle,
+    // Clé choisie pour chaque opération qui ne vise pas forcément la clé de session
+    pub login_port: Option<String>,
+    pub enroll_port: Option<String>,
+    pub format_port: Option<String>,
     pub api_client: reqwest::Client,
     pub available_devices: Vec<UsbDevice>,
     pub active_tab: VolumeTab,
@@ -75,6 +108,30 @@ struct BindKeyApp {
     pub needs_volume_refresh: bool,
 
     pub local_bindkey_sn: Option<String>,
+
+    // Retour en direct de la clé pendant une commande longue (PROMPT / PROGRESS / FP_QUALITY)
+    pub device_prompt: Option<String>,
+    pub device_progress: Option<(u32, u32)>,
+    pub device_fp_quality: Option<String>,
+
+    // Mise à jour du firmware de la clé (image signée choisie, envoi en cours ou interrompu)
+    pub firmware_image_path: String,
+    pub firmware_update: Option<FirmwareUpdate>,
+    pub firmware_status: String,
+
+    pub payload: PayloadState,
+    pub diagnostics: DiagnosticsState,
+    pub reconcile: ReconcileState,
+    pub incoming_shares: IncomingSharesState,
+    pub reauth: ReauthState,
+    pub restoration: RestorationState,
+    pub reassign: ReassignState,
+    // Dernière interaction (clavier, souris) ou opération sur la clé : verrouillage sur inactivité
+    pub last_activity: Instant,
+    pub fingerprints: FingerprintState,
+    // Configuration serveur inutilisable (confiance TLS absente...) : l'application reste bloquée
+    pub startup_error: Option<String>,
+    pub egui_ctx: egui::Context,
 }
 
 impl BindKeyApp {
@@ -107,16 +164,23 @@ impl BindKeyApp {
 
         cc.egui_ctx.set_visuals(visuals);
 
-        let client = match create_secure_client() {
-            Ok(c) => c,
+        let (tx, rx) = channel();
+        let config = AppConfig::load();
+        let client = match &config.startup_error {
+            Some(e) => Err(e.clone()),
+            None => server_endpoint::create_secure_client(&config.server),
+        };
+        let (client, startup_error) = match client {
+            Ok(c) => (c, None),
             Err(e) => {
                 eprintln!("ERREUR FATALE CLIENT HTTP : {}", e);
-                reqwest::Client::new()
+                (server_endpoint::untrusted_client(), Some(e))
             }
         };
-
-        let (tx, rx) = channel();
-        let config = AppConfig::load();
+        transcript::init(&config);
+        hotplug::spawn_watcher(&config, tx.clone(), cc.egui_ctx.clone());
+        auto_lock::spawn_screen_lock_watcher(&config.auto_lock, tx.clone(), cc.egui_ctx.clone());
+        let session = ServerSession::new(client.clone(), config.api_url(), tx.clone());
         BindKeyApp {
             is_loading: false,
             current_page: Page::Login,
@@ -142,13 +206,16 @@ impl BindKeyApp {
             login_email: String::new(),
             login_password: String::new(),
             is_admin_mode: false,
-            server_token: String::new(),
+            session,
             local_token: String::new(),
             config,
             usb_connected: false,
-            last_usb_check: Instant::now(),
             users_list: Vec::new(),
-            current_port_name: String::new(),
+            attached_bindkeys: Vec::new(),
+            device: DeviceHandle::spawn(),
+            login_port: None,
+            enroll_port: None,
+            format_port: None,
             api_client: client,
             available_devices: Vec::new(),
             active_tab: VolumeTab::Gestion,
@@ -168,8 +235,92 @@ impl BindKeyApp {
             share_pipeline_status: String::new(),
             needs_volume_refresh: false,
             local_bindkey_sn: None,
+            device_prompt: None,
+            device_progress: None,
+            device_fp_quality: None,
+            firmware_image_path: String::new(),
+            firmware_update: None,
+            firmware_status: String::new(),
+            payload: PayloadState::default(),
+            diagnostics: DiagnosticsState::default(),
+            reconcile: ReconcileState::default(),
+            incoming_shares: IncomingSharesState::default(),
+            reauth: ReauthState::default(),
+            restoration: RestorationState::default(),
+            reassign: ReassignState::default(),
+            last_activity: Instant::now(),
+            fingerprints: FingerprintState::default(),
+            startup_error,
+            egui_ctx: cc.egui_ctx.clone(),
         }
     }
+
+    // La clé de la session en cours, retrouvée par son SN quel que soit son port
+    pub fn session_device(&self) -> Option<DeviceTarget> {
+        let sn = self.local_bindkey_sn.as_ref()?;
+        self.attached_bindkeys
+            .iter()
+            .find(|k| k.sn.as_ref() == Some(sn))
+            .map(|k| self.device.target(&k.port_name))
+    }
+
+    // La clé choisie dans un sélecteur, si elle est toujours branchée
+    pub fn selected_device(&self, port_name: &Option<String>) -> Option<DeviceTarget> {
+        let port_name = port_name.as_ref()?;
+        self.attached_bindkeys
+            .iter()
+            .find(|k| &k.port_name == port_name)
+            .map(|k| self.device.target(&k.port_name))
+    }
+
+    // Disque de stockage relié (sysfs) à la clé de session
+    pub fn session_disk(&self) -> Option<String> {
+        let sn = self.local_bindkey_sn.as_ref()?;
+        self.attached_bindkeys
+            .iter()
+            .find(|k| k.sn.as_ref() == Some(sn))
+            .and_then(|k| k.disk_path.clone())
+    }
+
+    // À refaire à chaque branchement de disque : le disque arrive souvent après l'UART
+    pub fn relink_disks(&mut self) {
+        for key in &mut self.attached_bindkeys {
+            key.set_disk(disk_link::find_disk_for_port(&key.port_name, &self.config));
+        }
+    }
+
+    // Tant que la clé n'est pas identifiée, seule la clé elle-même tranche (refus à l'exécution)
+    pub fn feature_for_port(&self, port_name: Option<&str>, feature: Feature) -> Result<(), String> {
+        let key = self
+            .attached_bindkeys
+            .iter()
+            .find(|k| Some(k.port_name.as_str()) == port_name);
+        match key.and_then(|k| k.firmware.as_ref()) {
+            Some(firmware) => firmware.check(feature),
+            None => Ok(()),
+        }
+    }
+
+    pub fn session_feature(&self, feature: Feature) -> Result<(), String> {
+        let sn = self.local_bindkey_sn.as_ref();
+        let port = self
+            .attached_bindkeys
+            .iter()
+            .find(|k| sn.is_some() && k.sn.as_ref() == sn)
+            .map(|k| k.port_name.as_str());
+        self.feature_for_port(port, feature)
+    }
+
+    pub fn is_logged_in(&self) -> bool {
+        self.session.is_active()
+    }
+
+    pub fn attached_ports(&self) -> Vec<String> {
+        self.attached_bindkeys
+            .iter()
+            .map(|k| k.port_name.clone())
+            .collect()
+    }
 }
 
 impl eframe::App for BindKeyApp {
@@ -177,51 +328,77 @@ impl eframe::App for BindKeyApp {
         egui_extras::install_image_loaders(ctx);
         configurer_theme_bindkey(ctx);
 
-        if self.last_usb_check.elapsed() > Duration::from_secs(1) {
-            self.last_usb_check = Instant::now();
-
-            let mut found_port = String::new();
-
-            if let Ok(ports) = serialport::available_ports() {
-                for p in ports {
-                    match p.port_type {
-                        SerialPortType::UsbPort(info) => {
-                            if info.vid == 0x10c4 && info.pid == 0xea60 {
-                                found_port = p.port_name;
-                                break;
-                            }
-                        }
-                        _ => {}
-                    }
-                }
-            }
-
-            // Si on vient de brancher la clé (passage de false à true)
-            if !self.usb_connected && !found_port.is_empty() {
-                let _ = self.sender.send(ApiMessage::RequestVolumeRefresh);
-            }
-
-            self.usb_connected = !found_port.is_empty();
-            self.current_port_name = found_port;
+        if let Some(error) = &self.startup_error {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                pages::login::show_startup_error(ui, error);
+            });
+            return;
         }
 
         ctx.request_repaint_after(Duration::from_secs(1));
 
-        if let Ok(message) = self.receiver.try_recv() {
+        // On vide toute la file : les événements de la clé arrivent par rafales
+        while let Ok(message) = self.receiver.try_recv() {
             event_handler::handle_api_message(self, message);
         }
 
-        if self.current_page != Page::Login {
+        if ctx.input(|i| !i.events.is_empty() || i.pointer.is_moving())
+            || self.device.activity().is_busy()
+        {
+            self.last_activity = Instant::now();
+        }
+        let policy = &self.config.auto_lock;
+        if self.is_logged_in() && policy.idle_expired(self.last_activity, Instant::now()) {
+            let reason = auto_lock::LockReason::Idle(policy.idle_minutes);
+            event_handler::handle_api_message(self, ApiMessage::Lock(reason));
+        }
+
+        // Les pages paquets et diagnostic sont aussi ouvertes hors connexion : pas de menu
+        if self.current_page != Page::Login && self.is_logged_in() {
             egui::SidePanel::left("menu").show(ctx, |ui| {
                 ui.add_space(10.0);
 
                 ui.horizontal(|ui| {
-                    if self.usb_connected {
+                    if self.session_device().is_some() {
                         ui.colored_label(egui::Color32::GREEN, "BindKey Connectée");
                     } else {
                         ui.colored_label(egui::Color32::RED, "BindKey Déconnectée");
                     }
                 });
+                // Les autres clés branchées (enrôlement, réinitialisation...)
+                for key in &self.attached_bindkeys {
+                    if key.sn.is_some() && key.sn == self.local_bindkey_sn {
+                        continue;
+                    }
+                    ui.label(egui::RichText::new(key.display_name()).size(14.0).weak());
+                }
+
+                let activity = self.device.activity();
+                if activity.is_busy() {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label(
+                            egui::RichText::new(
+                                activity.current.as_deref().unwrap_or("En attente..."),
+                            )
+                            .size(14.0),
+                        );
+                    });
+                    ui.horizontal(|ui| {
+                        if activity.queued > 0 {
+                            ui.label(
+                                egui::RichText::new(format!("{} en file", activity.queued))
+                                    .size(14.0),
+                            );
+                        }
+                        // Interrompt aussi la commande en cours : la clé abandonne et le port est libéré
+                        if ui.small_button("Annuler").clicked() {
+                            self.device.cancel_all();
+                        }
+                    });
+                } else {
+                    ui.label(egui::RichText::new("Clé inactive").size(14.0).weak());
+                }
                 ui.add_space(20.0);
 
                 if ui.button("Accueil").clicked() {
@@ -240,17 +417,29 @@ impl eframe::App for BindKeyApp {
                     self.current_page = Page::Volume;
                 };
                 ui.add_space(10.0);
+
+                if ui.button("Paquets").clicked() {
+                    self.current_page = Page::Payload;
+                };
+                ui.add_space(10.0);
+
+                if ui.button("Diagnostic").clicked() {
+                    self.current_page = Page::Diagnostics;
+                };
+                ui.add_space(10.0);
                 ui.separator();
                 ui.add_space(10.0);
                 if ui.button("Déconnexion").clicked() {
-                    let url = self.config.api_url.clone();
-                    let clone_auth_token = self.server_token.clone();
+                    let url = self.config.api_url();
+                    let clone_auth_token = self.session.token();
+                    let clone_refresh_token = self.session.refresh_token();
                     let clone_sender = self.sender.clone();
                     let clone_api_client = self.api_client.clone();
 
                     tokio::spawn(async move {
                         let payload = LogOut {
                             server_token: clone_auth_token.clone(),
+                            refresh_token: clone_refresh_token,
                         };
 
                         let result = clone_api_client
@@ -298,7 +487,19 @@ impl eframe::App for BindKeyApp {
             Page::Volume => {
                 pages::volumes::show_volumes_page(self, ui);
             }
+            Page::Payload => {
+                pages::payload::show_payload_page(self, ui);
+            }
+            Page::Diagnostics => {
+                pages::diagnostics::show_diagnostics_page(self, ui);
+            }
+            Page::Restoration => {
+                pages::restoration::show_restoration_page(self, ui);
+            }
         });
+
+        // Par-dessus la page courante : son travail reprend après la réauthentification
+        pages::login::show_reauth_window(self, ctx);
     }
 }
 
@@ -0,0 +1,73 @@
+use crate::BindKeyApp;
+use crate::protocol::protocol::ApiMessage;
+use crate::protocol::share_protocol::DeviceEvent;
+use eframe::egui;
+
+// =========================================================
+// RETOUR EN DIRECT DE LA CLÉ (PROMPT / PROGRESS / FP_QUALITY)
+// =========================================================
+
+pub fn reset_device_feedback(app: &mut BindKeyApp) {
+    app.device_prompt = None;
+    app.device_progress = None;
+    app.device_fp_quality = None;
+}
+
+// À passer à `DeviceHandle::run_with_events` : relaie chaque événement vers l'interface
+pub fn forward_device_events(app: &BindKeyApp) -> impl FnMut(DeviceEvent) + Send + 'static {
+    let sender = app.sender.clone();
+    let ctx = app.egui_ctx.clone();
+    move |event| {
+        let _ = sender.send(ApiMessage::DeviceEvent(event));
+        ctx.request_repaint();
+    }
+}
+
+fn prompt_text(code: &str) -> String {
+    match code {
+        "place_finger" => "👆 Placez votre doigt sur le capteur".to_string(),
+        "lift_finger" => "Retirez votre doigt".to_string(),
+        "place_again" => "👆 Replacez le même doigt".to_string(),
+        "place_enrolled_finger" => "👆 Posez un doigt déjà enregistré".to_string(),
+        "processing" => "Traitement de l'empreinte...".to_string(),
+        other => other.replace('_', " "),
+    }
+}
+
+pub fn show_device_feedback(app: &BindKeyApp, ui: &mut egui::Ui) {
+    if let Some(code) = &app.device_prompt {
+        ui.label(
+            egui::RichText::new(prompt_text(code))
+                .size(18.0)
+                .color(egui::Color32::from_rgb(100, 200, 255)),
+        );
+    }
+
+    if let Some((current, total)) = app.device_progress
+        && total > 0
+    {
+        ui.add(
+            egui::ProgressBar::new(current.min(total) as f32 / total as f32)
+                .text(format!("{}/{}", current, total))
+                .desired_width(300.0),
+        );
+    }
+
+    if let Some(quality) = &app.device_fp_quality {
+        let (text, color) = match quality.as_str() {
+            "low" | "bad" => (
+                "Qualité d'empreinte faible, réessayez".to_string(),
+                egui::Color32::from_rgb(255, 165, 0),
+            ),
+            "good" | "high" => (
+                "Empreinte de bonne qualité".to_string(),
+                egui::Color32::GREEN,
+            ),
+            other => (
+                format!("Qualité d'empreinte : {}", other),
+                egui::Color32::GRAY,
+            ),
+        };
+        ui.colored_label(color, text);
+    }
+}
@@ -0,0 +1,55 @@
+use crate::protocol::protocol::AttachedBindKey;
+use eframe::egui;
+
+// =========================================================
+// CHOIX DE LA BINDKEY CIBLE (plusieurs clés branchées)
+// =========================================================
+
+// Garde `selected` sur une clé encore branchée ; propose une liste dès qu'il y a le choix
+pub fn show_device_picker(
+    ui: &mut egui::Ui,
+    id_salt: &str,
+    attached: &[AttachedBindKey],
+    session_sn: Option<&str>,
+    selected: &mut Option<String>,
+) {
+    let still_attached = selected
+        .as_ref()
+        .is_some_and(|port| attached.iter().any(|k| &k.port_name == port));
+    if !still_attached {
+        *selected = attached.first().map(|k| k.port_name.clone());
+    }
+
+    let display = |key: &AttachedBindKey| {
+        if session_sn.is_some() && key.sn.as_deref() == session_sn {
+            format!("{} — votre clé", key.display_name())
+        } else {
+            key.display_name()
+        }
+    };
+
+    match attached {
+        [] => {
+            ui.colored_label(egui::Color32::RED, "Aucune BindKey branchée");
+        }
+        [only] => {
+            ui.label(egui::RichText::new(display(only)).weak());
+        }
+        _ => {
+            let current = attached
+                .iter()
+                .find(|k| Some(&k.port_name) == selected.as_ref())
+                .map(display)
+                .unwrap_or_default();
+
+            egui::ComboBox::from_id_salt(id_salt)
+                .selected_text(current)
+                .width(ui.available_width().min(400.0))
+                .show_ui(ui, |ui| {
+                    for key in attached {
+                        ui.selectable_value(selected, Some(key.port_name.clone()), display(key));
+                    }
+                });
+        }
+    }
+}
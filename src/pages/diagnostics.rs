@@ -0,0 +1,170 @@
+use crate::BindKeyApp;
+use crate::device_owner::DeviceTarget;
+use crate::diagnostics::{self, CheckStatus, DiagnosticReport};
+use crate::pages::device_picker::show_device_picker;
+use crate::protocol::protocol::{ApiMessage, Page};
+use eframe::egui;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// =========================================================
+// DIAGNOSTIC DE LA CLÉ
+// =========================================================
+//
+// Accessible hors connexion : c'est souvent la connexion elle-même qui échoue.
+
+#[derive(Default)]
+pub struct DiagnosticsState {
+    pub port: Option<String>,
+    pub running: bool,
+    pub report: Option<DiagnosticReport>,
+    pub export_path: String,
+    pub status: String,
+}
+
+pub fn show_diagnostics_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        ui.heading("🩺 Diagnostic de la clé");
+        ui.label("Teste la liaison et les composants de la BindKey, puis exporte un rapport pour le support.");
+        ui.add_space(10.0);
+
+        if !app.is_logged_in() && ui.button("⬅ Retour à la connexion").clicked() {
+            app.current_page = Page::Login;
+        }
+        ui.add_space(10.0);
+
+        show_device_picker(
+            ui,
+            "diagnostics_device",
+            &app.attached_bindkeys,
+            app.local_bindkey_sn.as_deref(),
+            &mut app.diagnostics.port,
+        );
+        ui.add_space(10.0);
+
+        let device = app.selected_device(&app.diagnostics.port);
+        ui.horizontal(|ui| {
+            let enabled = device.is_some() && !app.diagnostics.running;
+            if ui
+                .add_enabled(enabled, egui::Button::new("▶ Lancer le diagnostic"))
+                .clicked()
+                && let Some(device) = device
+            {
+                start_diagnostics(app, device);
+            }
+            if app.diagnostics.running {
+                ui.spinner();
+            }
+        });
+
+        if let Some(report) = &app.diagnostics.report {
+            ui.add_space(15.0);
+            show_report(ui, report);
+        }
+
+        if app.diagnostics.report.is_some() {
+            ui.add_space(15.0);
+            ui.horizontal(|ui| {
+                ui.label("Exporter vers :");
+                ui.text_edit_singleline(&mut app.diagnostics.export_path);
+                if ui.button("💾 Exporter le rapport").clicked() {
+                    export_report(app);
+                }
+            });
+        }
+
+        if !app.diagnostics.status.is_empty() {
+            let color = if app.diagnostics.status.contains("❌") {
+                egui::Color32::RED
+            } else {
+                egui::Color32::GREEN
+            };
+            ui.colored_label(color, &app.diagnostics.status);
+        }
+    });
+}
+
+fn show_report(ui: &mut egui::Ui, report: &DiagnosticReport) {
+    egui::Frame::group(ui.style()).show(ui, |ui| {
+        ui.set_width(ui.available_width());
+        ui.label(format!(
+            "Clé : {} sur {}",
+            report.sn.as_deref().unwrap_or("non identifiée"),
+            report.port_name
+        ));
+        if let Some(firmware) = &report.firmware {
+            ui.label(format!("Firmware : {}", firmware.display_version()));
+        }
+        ui.add_space(10.0);
+
+        egui::Grid::new("diagnostics_checks")
+            .num_columns(3)
+            .spacing([20.0, 8.0])
+            .show(ui, |ui| {
+                for check in &report.checks {
+                    ui.colored_label(status_color(check.status), check.status.label());
+                    ui.label(check.name);
+                    ui.label(egui::RichText::new(&check.detail).size(16.0));
+                    ui.end_row();
+                }
+            });
+    });
+}
+
+fn status_color(status: CheckStatus) -> egui::Color32 {
+    match status {
+        CheckStatus::Ok => egui::Color32::GREEN,
+        CheckStatus::Warning => egui::Color32::from_rgb(220, 150, 0),
+        CheckStatus::Failed => egui::Color32::RED,
+        CheckStatus::Skipped => egui::Color32::GRAY,
+    }
+}
+
+fn start_diagnostics(app: &mut BindKeyApp, device: DeviceTarget) {
+    let host_disk = app
+        .attached_bindkeys
+        .iter()
+        .find(|k| k.port_name == device.port_name())
+        .and_then(|k| k.disk_path.clone());
+    let created_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let mut report = DiagnosticReport::new(device.port_name(), host_disk, created_at);
+    report.transcript_path = app.config.transcript_path.clone();
+
+    app.diagnostics.running = true;
+    app.diagnostics.report = None;
+    app.diagnostics.status.clear();
+    let sender = app.sender.clone();
+
+    tokio::spawn(async move {
+        let fallback = report.clone();
+        let result = device
+            .run("Diagnostic de la clé", move |d| {
+                diagnostics::run_checks(d, &mut report);
+                Ok(report)
+            })
+            .await;
+        let report = result.unwrap_or_else(|e| {
+            let mut report = fallback;
+            report.unreachable(&e);
+            report
+        });
+        let _ = sender.send(ApiMessage::DiagnosticsFinished(report));
+    });
+}
+
+fn export_report(app: &mut BindKeyApp) {
+    let Some(report) = &app.diagnostics.report else {
+        return;
+    };
+    let path = match app.diagnostics.export_path.trim() {
+        "" => report.file_name(),
+        path => path.to_string(),
+    };
+
+    app.diagnostics.status = match std::fs::write(&path, report.to_text()) {
+        Ok(()) => format!("Rapport écrit : {} (à joindre au ticket de support)", path),
+        Err(e) => format!("❌ Écriture de {} impossible : {}", path, e),
+    };
+}
@@ -1,8 +1,13 @@
 use crate::BindKeyApp;
+use crate::pages::device_feedback::{
+    forward_device_events, reset_device_feedback, show_device_feedback,
+};
+use crate::pages::device_picker::show_device_picker;
+use crate::pages::reassign::show_reassign_card;
+use crate::bindkey_device::DeviceError;
 use crate::protocol::protocol::{ApiMessage, Role, StatusBindkey};
 use crate::protocol::share_protocol::{SuccessData, UsbResponse};
 use eframe::egui;
-use sha2::{Digest, Sha256};
 use validator::{self, ValidateEmail, ValidateLength};
 
 pub fn show_enrollment_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
@@ -94,6 +99,18 @@ pub fn show_enrollment_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
                     ui.separator();
                     ui.add_space(10.0);
 
+                    ui.label("BindKey à enrôler :");
+                    show_device_picker(
+                        ui,
+                        "enroll_device",
+                        &app.attached_bindkeys,
+                        app.local_bindkey_sn.as_deref(),
+                        &mut app.enroll_port,
+                    );
+                    let enroll_device = app.selected_device(&app.enroll_port);
+                    let session_device = app.session_device();
+                    ui.add_space(10.0);
+
                     let formulaire_valide = !app.enroll_firstname.is_empty()
                         && !app.enroll_lastname.is_empty()
                         && !app.enroll_email.is_empty()
@@ -101,7 +118,7 @@ pub fn show_enrollment_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
                         && !app.enroll_password.is_empty()
                         && app.enroll_password.validate_length(Some(14), None, None)
                         && app.enroll_role != Role::NONE
-                        && app.usb_connected;
+                        && enroll_device.is_some();
 
                     let modif_valid = !app.enroll_email.is_empty()
                         && app.enroll_role != Role::NONE
@@ -109,51 +126,40 @@ pub fn show_enrollment_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
                         && app.enroll_firstname.is_empty()
                         && app.enroll_lastname.is_empty()
                         && app.enroll_password.is_empty()
-                        && app.usb_connected;
+                        && session_device.is_some();
 
                     ui.vertical_centered(|ui| {
                         if formulaire_valide {
                             if ui.add(egui::Button::new("➕ Enrôler le nouvel utilisateur").min_size(egui::vec2(ui.available_width(), 40.0))).clicked() {
                                 let sender = app.sender.clone();
-                                let port_name = app.current_port_name.clone();
+                                let device = enroll_device.clone().expect("clé vérifiée par formulaire_valide");
+                                reset_device_feedback(app);
+                                let on_event = forward_device_events(app);
 
                                 tokio::spawn(async move {
-                                    let _ = sender.send(ApiMessage::EnrollmentError("Attente de la clé USB...".to_string()));
+                                    let _ = sender.send(ApiMessage::EnrollmentError("👆 Veuillez placer votre doigt 2 fois sur le capteur...".to_string()));
 
-                                    if port_name.is_empty() {
-                                        let _ = sender.send(ApiMessage::EnrollmentError("Erreur : Aucune clé détectée".to_string()));
-                                        return;
-                                    }
+                                    let enroll_result = device.run_with_events("Enrôlement", on_event, |d| d.enroll()).await;
+                                    let _ = sender.send(ApiMessage::DeviceFeedbackReset);
 
-                                    match serialport::new(&port_name, 115200).timeout(std::time::Duration::from_secs(45)).open() {
-                                        Ok(mut port) => {
-                                            let _ = port.write_data_terminal_ready(true);
-                                            std::thread::sleep(std::time::Duration::from_millis(100));
-
-                                            let _ = sender.send(ApiMessage::EnrollmentError("👆 Veuillez placer votre doigt 2 fois sur le capteur...".to_string()));
-                                            let cmd = "enroll".to_string(); 
-
-                                            match crate::usb_service::send_text_command(&mut *port, &cmd) {
-                                                Ok(map) => {
-                                                    if let (Some(sn), Some(pub_sign), Some(pub_ecdh)) = (map.get("SN"), map.get("PUB_SIGN"), map.get("PUB_ECDH")) {
-                                                        let data = UsbResponse::Success(SuccessData::EnrollmentInfo {
-                                                            sn: sn.clone(),
-                                                            pub_sign: pub_sign.clone(),
-                                                            pub_ecdh: pub_ecdh.clone(),
-                                                        });
-                                                        // On envoie le succès à l'Event Handler qui fera l'appel API !
-                                                        let _ = sender.send(ApiMessage::EnrollmentUsbSuccess(data));
-                                                    } else {
-                                                        let _ = sender.send(ApiMessage::EnrollmentError("Erreur: SN, PUB_SIGN ou PUB_ECDH manquant dans la réponse".to_string()));
-                                                    }
-                                                }
-                                                Err(e) => {
-                                                    let _ = sender.send(ApiMessage::EnrollmentError(format!("Erreur communication USB : {}", e)));
-                                                }
-                                            }
+                                    match enroll_result {
+                                        Ok(keys) => {
+                                            let data = UsbResponse::Success(SuccessData::EnrollmentInfo {
+                                                sn: keys.sn,
+                                                pub_sign: keys.pub_sign,
+                                                pub_ecdh: keys.pub_ecdh,
+                                            });
+                                            // On envoie le succès à l'Event Handler qui fera l'appel API !
+                                            let _ = sender.send(ApiMessage::EnrollmentUsbSuccess(data));
+                                        }
+                                        Err(DeviceError::MissingField(_)) => {
+                                            let _ = sender.send(ApiMessage::EnrollmentError("Erreur: SN, PUB_SIGN ou PUB_ECDH manquant dans la réponse".to_string()));
+                                        }
+                                        Err(e @ (DeviceError::NotConnected | DeviceError::PortOpen(_) | DeviceError::Cancelled | DeviceError::Disconnected)) => {
+                                            let _ = sender.send(ApiMessage::EnrollmentError(e.to_string()));
                                         }
                                         Err(e) => {
-                                            let _ = sender.send(ApiMessage::EnrollmentError(format!("Impossible d'ouvrir le port : {}", e)));
+                                            let _ = sender.send(ApiMessage::EnrollmentError(format!("Erreur communication USB : {}", e)));
                                         }
                                     }
                                 });
@@ -162,37 +168,23 @@ pub fn show_enrollment_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
                         } else if modif_valid {
                             if ui.add(egui::Button::new("✏️ Modifier les droits (Email + Rôle)").min_size(egui::vec2(ui.available_width(), 40.0))).clicked() {
                                 let sender = app.sender.clone();
-                                let port_name = app.current_port_name.clone();
+                                // Les droits sont confirmés sur la clé de l'enrôleur connecté
+                                let device = session_device.clone().expect("clé vérifiée par modif_valid");
 
                                 tokio::spawn(async move {
                                     let _ = sender.send(ApiMessage::EnrollmentError("Modification sur la clé USB en cours...".to_string()));
 
-                                    if port_name.is_empty() {
-                                        let _ = sender.send(ApiMessage::EnrollmentError("Erreur : Aucune clé détectée".to_string()));
-                                        return;
-                                    }
-
-                                    match serialport::new(&port_name, 115200).timeout(std::time::Duration::from_secs(15)).open() {
-                                        Ok(mut port) => {
-                                            let _ = port.write_data_terminal_ready(true);
-                                            std::thread::sleep(std::time::Duration::from_millis(100));
-
-                                            // /!\ IMPORTANT : Mets ici la vraie commande de modif /!\
-                                            let cmd = "cmd_modify".to_string(); 
-
-                                            match crate::usb_service::send_text_command(&mut *port, &cmd) {
-                                                Ok(_) => {
-                                                    // La clé a dit OK, on lance la requête API
-                                                    let data = UsbResponse::Success(SuccessData::Ack);
-                                                    let _ = sender.send(ApiMessage::ModificationUsbSuccess(data));
-                                                }
-                                                Err(e) => {
-                                                    let _ = sender.send(ApiMessage::EnrollmentError(format!("Erreur communication USB : {}", e)));
-                                                }
-                                            }
+                                    match device.run("Confirmation des droits", |d| d.confirm_modification()).await {
+                                        Ok(()) => {
+                                            // La clé a dit OK, on lance la requête API
+                                            let data = UsbResponse::Success(SuccessData::Ack);
+                                            let _ = sender.send(ApiMessage::ModificationUsbSuccess(data));
+                                        }
+                                        Err(e @ (DeviceError::NotConnected | DeviceError::PortOpen(_) | DeviceError::Cancelled | DeviceError::Disconnected)) => {
+                                            let _ = sender.send(ApiMessage::EnrollmentError(e.to_string()));
                                         }
                                         Err(e) => {
-                                            let _ = sender.send(ApiMessage::EnrollmentError(format!("Impossible d'ouvrir le port : {}", e)));
+                                            let _ = sender.send(ApiMessage::EnrollmentError(format!("Erreur communication USB : {}", e)));
                                         }
                                     }
                                 });
@@ -313,12 +305,18 @@ pub fn show_enrollment_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
                                                             bk.status.clone(),
                                                         ));
                                                     }
+                                                    // Pré-remplit la carte de réattribution
+                                                    let reassign_idle = !app.reassign.running && !app.reassign.awaiting_enrollment;
+                                                    if ui.add_enabled(reassign_idle, egui::Button::new(egui::RichText::new("♻️ Réattribuer").size(20.0))).clicked() {
+                                                        app.reassign.sn = bk.serial_number.clone();
+                                                    }
 
                                                     let current_status_text = match bk.status {
                                                         StatusBindkey::ACTIVE => "Actif",
                                                         StatusBindkey::RESET => "Révoquée",
                                                         StatusBindkey::LOST => "Perdue",
                                                         StatusBindkey::BROKEN => "Cassée",
+                                                        StatusBindkey::REVOKED => "Révoquée (remplacée)",
                                                     };
 
                                                     egui::ComboBox::from_id_salt("status_combo")
@@ -328,6 +326,7 @@ pub fn show_enrollment_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
                                                             ui.selectable_value(&mut bk.status, StatusBindkey::RESET, "Révoquée");
                                                             ui.selectable_value(&mut bk.status, StatusBindkey::LOST, "Perdue");
                                                             ui.selectable_value(&mut bk.status, StatusBindkey::BROKEN, "Cassée");
+                                                            ui.selectable_value(&mut bk.status, StatusBindkey::REVOKED, "Révoquée (remplacée)");
                                                         });
 
                                                     ui.label(egui::RichText::new("Statut :").size(20.0));
@@ -385,6 +384,9 @@ pub fn show_enrollment_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
 
                 });
 
+                ui.add_space(20.0);
+                card_frame.show(ui, |ui| show_reassign_card(app, ui));
+
                 } else {
                     // =========================================================
                     // VUE STANDARD : On bloque l'interface
@@ -407,6 +409,10 @@ pub fn show_enrollment_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
                 // =========================================================
                 // AFFICHAGE DU STATUT DES ACTIONS (Pour tout le monde)
                 // =========================================================
+                ui.vertical_centered(|ui| {
+                    show_device_feedback(app, ui);
+                });
+
                 ui.centered_and_justified(|ui| {
                     if !app.enroll_status.is_empty() {
                         ui.add_space(10.0);
@@ -422,11 +428,3 @@ pub fn show_enrollment_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
         });
     });
 }
-
-pub fn hash_password_with_salt(password: &str) -> String {
-    let salt = "bindkey.com";
-    let combined = format!("{}{}", password, salt);
-    let mut hasher = Sha256::new();
-    hasher.update(combined);
-    format!("{:x}", hasher.finalize())
-}
@@ -0,0 +1,211 @@
+use crate::BindKeyApp;
+use crate::bindkey_device::{DeviceError, FingerprintList};
+use crate::device_owner::{CancelToken, DeviceTarget};
+use crate::firmware::Feature;
+use crate::pages::device_feedback::{
+    forward_device_events, reset_device_feedback, show_device_feedback,
+};
+use crate::protocol::protocol::ApiMessage;
+use eframe::egui;
+
+// =========================================================
+// GESTION DES EMPREINTES DIGITALES (carte de l'accueil)
+// =========================================================
+//
+// Chaque opération commence par la reconnaissance d'un doigt déjà enregistré, dans le même
+// job que l'opération elle-même : aucune autre commande ne peut s'intercaler.
+
+#[derive(Default)]
+pub struct FingerprintState {
+    // None tant que l'utilisateur n'a pas posé le doigt pour afficher la liste
+    pub list: Option<FingerprintList>,
+    pub new_label: String,
+    // Slot dont la suppression attend confirmation
+    pub confirm_delete: Option<u16>,
+    pub busy: bool,
+    // Interrompt l'opération en cours, même quand la clé attend un doigt
+    pub cancel: CancelToken,
+    pub status: String,
+}
+
+enum FingerprintAction {
+    List,
+    Enroll(String),
+    Delete(u16),
+}
+
+pub fn show_fingerprint_section(app: &mut BindKeyApp, ui: &mut egui::Ui) {
+    ui.heading("Empreintes digitales");
+    ui.separator();
+
+    if let Err(reason) = app.session_feature(Feature::FingerprintManagement) {
+        ui.colored_label(egui::Color32::from_rgb(220, 150, 0), reason);
+        return;
+    }
+    let Some(device) = app.session_device() else {
+        ui.colored_label(
+            egui::Color32::RED,
+            "Branchez votre BindKey pour gérer vos empreintes.",
+        );
+        return;
+    };
+    ui.label(
+        egui::RichText::new("Chaque opération demande d'abord un doigt déjà enregistré.")
+            .size(14.0)
+            .weak(),
+    );
+    ui.add_space(5.0);
+
+    let state = &mut app.fingerprints;
+    let mut action = None;
+
+    match &state.list {
+        None => {
+            let button = egui::Button::new("🔍 Afficher mes empreintes");
+            if ui.add_enabled(!state.busy, button).clicked() {
+                action = Some(FingerprintAction::List);
+            }
+        }
+        Some(list) => {
+            ui.label(format!(
+                "{} / {} empreinte(s) enregistrée(s)",
+                list.slots.len(),
+                list.capacity
+            ));
+            let last_one = list.slots.len() <= 1;
+
+            for slot in &list.slots {
+                ui.horizontal(|ui| {
+                    let label = if slot.label.is_empty() {
+                        format!("Slot {}", slot.slot)
+                    } else {
+                        format!("{} (slot {})", slot.label, slot.slot)
+                    };
+                    ui.label(format!("• {}", label));
+
+                    if state.confirm_delete == Some(slot.slot) {
+                        if ui.button("Confirmer").clicked() {
+                            action = Some(FingerprintAction::Delete(slot.slot));
+                        }
+                        if ui.button("Annuler").clicked() {
+                            state.confirm_delete = None;
+                        }
+                    } else {
+                        let delete = ui
+                            .add_enabled(!state.busy && !last_one, egui::Button::new("🗑"))
+                            .on_disabled_hover_text(
+                                "La dernière empreinte ne peut pas être supprimée",
+                            );
+                        if delete.clicked() {
+                            state.confirm_delete = Some(slot.slot);
+                        }
+                    }
+                });
+            }
+
+            ui.add_space(5.0);
+            let full = list.slots.len() >= list.capacity as usize;
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::TextEdit::singleline(&mut state.new_label)
+                        .hint_text("Nom du doigt (ex. index gauche)")
+                        .desired_width(220.0),
+                );
+                let add = ui
+                    .add_enabled(
+                        !state.busy && !full,
+                        egui::Button::new("➕ Ajouter un doigt"),
+                    )
+                    .on_disabled_hover_text("Tous les slots d'empreinte sont occupés");
+                if add.clicked() {
+                    action = Some(FingerprintAction::Enroll(
+                        state.new_label.trim().to_string(),
+                    ));
+                }
+            });
+        }
+    }
+
+    if state.busy {
+        ui.horizontal(|ui| {
+            ui.spinner();
+            if ui.button("Annuler").clicked() {
+                state.cancel.cancel();
+            }
+        });
+    }
+    if !state.status.is_empty() {
+        let color = if state.status.contains("❌") {
+            egui::Color32::RED
+        } else {
+            egui::Color32::GREEN
+        };
+        ui.colored_label(color, &state.status);
+    }
+    if app.fingerprints.busy {
+        show_device_feedback(app, ui);
+    }
+
+    if let Some(action) = action {
+        start_action(app, device, action);
+    }
+}
+
+fn start_action(app: &mut BindKeyApp, device: DeviceTarget, action: FingerprintAction) {
+    app.fingerprints.busy = true;
+    app.fingerprints.confirm_delete = None;
+    app.fingerprints.cancel = CancelToken::default();
+    let device = device.with_cancel(app.fingerprints.cancel.clone());
+    app.fingerprints.status =
+        "👆 Posez un doigt déjà enregistré pour autoriser l'opération".to_string();
+    reset_device_feedback(app);
+    let on_event = forward_device_events(app);
+    let sender = app.sender.clone();
+
+    let label = match &action {
+        FingerprintAction::List => "Lecture des empreintes",
+        FingerprintAction::Enroll(_) => "Ajout d'une empreinte",
+        FingerprintAction::Delete(_) => "Suppression d'une empreinte",
+    };
+
+    tokio::spawn(async move {
+        let result = device
+            .run_with_events(label, on_event, move |d| {
+                let matched = d.verify_fingerprint()?;
+                println!("[EMPREINTE] Doigt du slot {} reconnu", matched);
+                match action {
+                    FingerprintAction::List => d
+                        .list_fingerprints()
+                        .map(|list| (list, "Empreintes lues".to_string())),
+                    FingerprintAction::Enroll(label) => d
+                        .enroll_fingerprint(&label)
+                        .map(|list| (list, "✔ Nouvelle empreinte enregistrée".to_string())),
+                    FingerprintAction::Delete(slot) => d
+                        .delete_fingerprint(slot)
+                        .map(|list| (list, format!("Empreinte du slot {} supprimée", slot))),
+                }
+            })
+            .await;
+        let _ = sender.send(ApiMessage::DeviceFeedbackReset);
+        let _ = sender.send(ApiMessage::FingerprintsUpdated(
+            result.map_err(|e| describe_error(&e)),
+        ));
+    });
+}
+
+// Codes renvoyés par le firmware pour la gestion des empreintes
+fn describe_error(error: &DeviceError) -> String {
+    match error {
+        DeviceError::Rejected(code) => match code.as_str() {
+            "no_match" => "Empreinte non reconnue : opération refusée".to_string(),
+            "not_authorized" => "La clé exige d'abord un doigt reconnu".to_string(),
+            "no_free_slot" => "Plus aucun slot d'empreinte libre sur la clé".to_string(),
+            "last_template" => {
+                "La dernière empreinte de la clé ne peut pas être supprimée".to_string()
+            }
+            "unknown_slot" => "Cette empreinte n'existe plus sur la clé".to_string(),
+            _ => error.to_string(),
+        },
+        other => other.to_string(),
+    }
+}
@@ -3,6 +3,9 @@ use crate::{
     protocol::protocol::{ApiMessage, Page, VolumeTab},
     protocol::updater::{UPDATE_PUBLIC_KEY, update_application},
 };
+use crate::firmware::{self, Feature};
+use crate::firmware_update::{self, FirmwareUpdate};
+use crate::pages::fingerprints::show_fingerprint_section;
 use eframe::egui;
 
 pub fn show_home_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
@@ -51,11 +54,18 @@ pub fn show_home_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
             ui.set_width(ui.available_width());
             ui.heading("Statut de la clé");
             ui.separator();
-            if app.usb_connected {
+            if app.session_device().is_some() {
                 ui.label("BindKey détectée et prête.");
             } else {
                 ui.colored_label(egui::Color32::RED, "Veuillez brancher votre BindKey.");
             }
+            let others = app
+                .attached_bindkeys
+                .iter()
+                .filter(|k| k.sn.is_none() || k.sn != app.local_bindkey_sn);
+            for key in others {
+                ui.label(egui::RichText::new(format!("Autre clé : {}", key.display_name())).weak());
+            }
         });
 
         cols[0].add_space(15.0);
@@ -65,7 +75,31 @@ pub fn show_home_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
             ui.heading("Sécurité");
             ui.separator();
             ui.label(format!("Volumes chiffrés actifs : {}", app.dashboard_volumes.len()));
-            ui.label("Firmware : v1.0.4");
+            let session_key = app
+                .attached_bindkeys
+                .iter()
+                .find(|k| k.sn.is_some() && k.sn == app.local_bindkey_sn);
+            match session_key.and_then(|k| k.firmware.as_ref()) {
+                Some(firmware) => {
+                    ui.label(format!("Firmware : {}", firmware.display_version()));
+                    // Fonctionnalités bloquées par un firmware trop ancien
+                    for feature in [Feature::LbaRegistration, Feature::Sharing, Feature::Format] {
+                        if let Err(reason) = firmware.check(feature) {
+                            ui.colored_label(egui::Color32::from_rgb(220, 150, 0), reason);
+                        }
+                    }
+                }
+                None => {
+                    ui.label("Firmware : inconnu");
+                }
+            }
+        });
+
+        cols[0].add_space(15.0);
+
+        frame_style.show(&mut cols[0], |ui| {
+            ui.set_width(ui.available_width());
+            show_fingerprint_section(app, ui);
         });
 
         // ==========================================
@@ -171,6 +205,103 @@ pub fn show_home_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
             let color = if app.update_status.contains("❌") { egui::Color32::RED } else { egui::Color32::GREEN };
             ui.colored_label(color, &app.update_status);
         }
+
+        ui.add_space(15.0);
+        ui.separator();
+        ui.label(egui::RichText::new("Firmware de la BindKey").strong());
+        ui.add_space(5.0);
+
+        let running = app.firmware_update.as_ref().is_some_and(|u| u.running);
+        ui.add_enabled_ui(!running, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Image signée (.bkfw) :");
+                ui.text_edit_singleline(&mut app.firmware_image_path);
+                if ui.button("Vérifier l'image").clicked() {
+                    check_firmware_image(app);
+                }
+            });
+        });
+
+        let installed = app
+            .attached_bindkeys
+            .iter()
+            .find(|k| k.sn.is_some() && k.sn == app.local_bindkey_sn)
+            .and_then(|k| k.firmware.clone());
+        let gate = app.session_feature(Feature::FirmwareUpdate);
+        let session_device = app.session_device();
+
+        let mut install_on = None;
+        if let Some(update) = app.firmware_update.as_mut() {
+            ui.label(format!(
+                "Image {} ({} octets) pour la clé {}",
+                update.firmware.version,
+                update.firmware.image.len(),
+                update.sn
+            ));
+
+            if update.running || update.interrupted {
+                let total = update.firmware.image.len().max(1) as f32;
+                ui.add(egui::ProgressBar::new(update.sent as f32 / total).show_percentage());
+            }
+
+            if !update.running && !update.interrupted {
+                if let Err(reason) = &gate {
+                    ui.colored_label(egui::Color32::from_rgb(220, 150, 0), reason);
+                }
+                // La clé revérifie sa version avant l'envoi ; ici on prévient et on demande confirmation
+                let downgrade = installed
+                    .as_ref()
+                    .map_or(Ok(()), |firmware| update.firmware.check_downgrade(firmware, false));
+                if let Err(reason) = &downgrade {
+                    ui.colored_label(egui::Color32::from_rgb(220, 150, 0), reason);
+                    ui.checkbox(&mut update.allow_downgrade, "Forcer l'installation de cette version antérieure");
+                }
+                let allowed = downgrade.is_ok() || update.allow_downgrade;
+                let install = egui::Button::new(format!("⬆ Installer {}", update.firmware.version));
+                if ui.add_enabled(gate.is_ok() && allowed && session_device.is_some(), install).clicked() {
+                    install_on = session_device;
+                }
+            }
+        }
+        if let Some(device) = install_on {
+            app.firmware_status = "Envoi du firmware à la clé...".to_string();
+            firmware_update::start(app, device);
+        }
+
+        if !app.firmware_status.is_empty() {
+            let color = if app.firmware_status.contains("❌") { egui::Color32::RED } else { egui::Color32::GREEN };
+            ui.colored_label(color, &app.firmware_status);
+        }
     });
         });
 }
+
+// La signature est vérifiée ici, avant que l'image ne puisse être envoyée à la clé
+fn check_firmware_image(app: &mut BindKeyApp) {
+    let Some(sn) = app.local_bindkey_sn.clone() else {
+        app.firmware_status = "❌ Connectez-vous avec la clé à mettre à jour.".to_string();
+        return;
+    };
+
+    let verified = std::fs::read(app.firmware_image_path.trim())
+        .map_err(|e| format!("Lecture de {} impossible : {}", app.firmware_image_path, e))
+        .and_then(|bytes| firmware::verify_image(&bytes));
+
+    match verified {
+        Ok(image) => {
+            app.firmware_status = format!("Image {} authentique (SHA-256 {})", image.version, image.sha256);
+            app.firmware_update = Some(FirmwareUpdate {
+                firmware: std::sync::Arc::new(image),
+                sn,
+                sent: 0,
+                running: false,
+                interrupted: false,
+                allow_downgrade: false,
+            });
+        }
+        Err(e) => {
+            app.firmware_status = format!("❌ {}", e);
+            app.firmware_update = None;
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use crate::BindKeyApp;
+use crate::firmware::Feature;
+use crate::payload_bundle::signer_fingerprint;
+use crate::protocol::protocol::{ApiMessage, PendingShare, ShareAckPayload};
+use crate::share_signature;
+use eframe::egui;
+
+// =========================================================
+// PARTAGES ENTRANTS (onglet des volumes)
+// =========================================================
+//
+// Chaque partage en attente est vérifié (signature de la BindKey source) puis affiché
+// avec son émetteur : rien n'est confié à la clé sans confirmation, partage par partage.
+
+#[derive(Debug, Clone)]
+pub struct IncomingShare {
+    pub share: PendingShare,
+    // Empreinte de la clé de signature de l'émetteur, ou raison du refus
+    pub verified: Result<String, String>,
+}
+
+#[derive(Default)]
+pub struct IncomingSharesState {
+    pub running: bool,
+    pub shares: Vec<IncomingShare>,
+    pub status: String,
+}
+
+// `local_sn` : la BindKey de la session, seule destinataire légitime du partage
+pub fn verify_share(share: &PendingShare, local_sn: &str) -> Result<String, String> {
+    let source_pubkey = share
+        .source_pubkey_sign
+        .as_deref()
+        .ok_or("Le serveur ne fournit pas la clé de signature de l'émetteur")?;
+    let signature = share
+        .signature
+        .as_deref()
+        .ok_or("Partage non signé par la BindKey source")?;
+    let message = share_signature::signed_message(
+        &share.volume_id,
+        &share.source_sn,
+        &share.source_pubkey_ecdh,
+        local_sn,
+        share.slot,
+        &share.wrapped,
+    );
+    share_signature::verify(&message, signature, source_pubkey)?;
+    Ok(signer_fingerprint(source_pubkey))
+}
+
+pub fn show_incoming_shares(app: &mut BindKeyApp, ui: &mut egui::Ui) {
+    let share_gate = app.session_feature(Feature::Sharing);
+    if let Err(reason) = &share_gate {
+        ui.colored_label(egui::Color32::from_rgb(220, 150, 0), reason);
+    }
+    ui.horizontal(|ui| {
+        let button = egui::Button::new("Vérifier les partages entrant");
+        if ui
+            .add_enabled(share_gate.is_ok() && !app.incoming_shares.running, button)
+            .clicked()
+        {
+            fetch(app);
+        }
+        if app.incoming_shares.running {
+            ui.spinner();
+        }
+    });
+
+    let mut install = None;
+    let mut dismiss = None;
+    for (index, incoming) in app.incoming_shares.shares.iter().enumerate() {
+        let share = &incoming.share;
+        egui::Frame::group(ui.style()).show(ui, |ui| {
+            ui.set_width(ui.available_width());
+            let sender = match &share.source_owner {
+                Some(owner) => format!("{} (BindKey {})", owner, share.source_sn),
+                None => format!("BindKey {}", share.source_sn),
+            };
+            ui.label(egui::RichText::new(format!("De : {}", sender)).size(16.0));
+            ui.label(format!("Volume {} → slot {}", share.volume_id, share.slot));
+
+            match &incoming.verified {
+                Ok(fingerprint) => {
+                    ui.colored_label(
+                        egui::Color32::GREEN,
+                        format!(
+                            "✔ Signé par la BindKey {} (empreinte {})",
+                            share.source_sn, fingerprint
+                        ),
+                    );
+                }
+                Err(reason) => {
+                    ui.colored_label(
+                        egui::Color32::RED,
+                        format!("❌ {} : ce partage ne sera pas installé", reason),
+                    );
+                }
+            }
+
+            ui.horizontal(|ui| {
+                let enabled = incoming.verified.is_ok() && !app.incoming_shares.running;
+                if ui
+                    .add_enabled(enabled, egui::Button::new("Installer sur ma BindKey"))
+                    .clicked()
+                {
+                    install = Some(index);
+                }
+                if ui.button("Ignorer").clicked() {
+                    dismiss = Some(index);
+                }
+            });
+        });
+        ui.add_space(5.0);
+    }
+
+    if let Some(index) = install {
+        start_install(app, index);
+    }
+    if let Some(index) = dismiss {
+        app.incoming_shares.shares.remove(index);
+    }
+
+    if !app.incoming_shares.status.is_empty() {
+        let color = if app.incoming_shares.status.contains("❌") {
+            egui::Color32::RED
+        } else {
+            egui::Color32::from_rgb(100, 200, 255)
+        };
+        ui.colored_label(color, &app.incoming_shares.status);
+    }
+}
+
+fn fetch(app: &mut BindKeyApp) {
+    let Some(local_sn) = app.local_bindkey_sn.clone() else {
+        app.incoming_shares.status = "❌ Veuillez vous enrôler/connecter d'abord.".to_string();
+        return;
+    };
+    app.incoming_shares.running = true;
+    app.incoming_shares.status = "Recherche de partage en cours...".to_string();
+    let sender = app.sender.clone();
+    let api_client = app.api_client.clone();
+    let url = format!(
+        "{}/shares/pending?target_sn={}",
+        app.config.api_url(), local_sn
+    );
+    let session = app.session.clone();
+
+    tokio::spawn(async move {
+        let result = match session
+            .send(|token| api_client.get(&url).bearer_auth(token))
+            .await
+        {
+            Ok(resp) if resp.status().is_success() => resp
+                .json::<Vec<PendingShare>>()
+                .await
+                .map_err(|e| format!("Liste des partages illisible : {}", e)),
+            Ok(resp) => Err(format!(
+                "Erreur de récupération des partages ({})",
+                resp.status()
+            )),
+            Err(e) => Err(format!("Serveur injoignable : {}", e)),
+        };
+        let checked = result.map(|shares| {
+            shares
+                .into_iter()
+                .map(|share| {
+                    let verified = verify_share(&share, &local_sn);
+                    if let Err(e) = &verified {
+                        println!("[PARTAGE] {} refusé : {}", share.share_id, e);
+                    }
+                    IncomingShare { share, verified }
+                })
+                .collect()
+        });
+        let _ = sender.send(ApiMessage::IncomingSharesChecked(checked));
+    });
+}
+
+fn start_install(app: &mut BindKeyApp, index: usize) {
+    let Some(device) = app.session_device() else {
+        app.incoming_shares.status =
+            "❌ Branchez la BindKey de votre session pour installer les partages.".to_string();
+        return;
+    };
+    let share = app.incoming_shares.shares[index].share.clone();
+    app.incoming_shares.running = true;
+    app.incoming_shares.status = format!("Installation du partage de {}...", share.source_sn);
+    let sender = app.sender.clone();
+    let api_client = app.api_client.clone();
+    let api_url = app.config.api_url();
+    let session = app.session.clone();
+
+    tokio::spawn(async move {
+        let share_id = share.share_id.clone();
+        let installed = device
+            .run("Installation d'un partage", move |d| {
+                d.install_share(share.slot, &share.source_pubkey_ecdh, &share.wrapped)
+            })
+            .await
+            .map_err(|e| format!("Refus matériel : {}", e));
+
+        let result = match installed {
+            Ok(()) => {
+                let ack = ShareAckPayload {
+                    share_id: share_id.clone(),
+                };
+                match session
+                    .send(|token| {
+                        api_client
+                            .post(format!("{}/share_acknowledged", api_url))
+                            .bearer_auth(token)
+                            .json(&ack)
+                    })
+                    .await
+                {
+                    Ok(resp) if resp.status().is_success() => Ok(()),
+                    Ok(resp) => Err(format!(
+                        "Partage installé mais non acquitté par le serveur ({})",
+                        resp.status()
+                    )),
+                    Err(e) => Err(format!(
+                        "Partage installé mais non acquitté par le serveur : {}",
+                        e
+                    )),
+                }
+            }
+            Err(e) => Err(e),
+        };
+        let _ = sender.send(ApiMessage::IncomingShareInstalled(share_id, result));
+    });
+}
@@ -1,10 +1,17 @@
-use std::time::Duration;
-
-use crate::protocol::protocol::{ApiMessage, ChallengeResponse, LoginSuccessResponse, Page, Role};
-use crate::usb_service::send_text_command;
-use crate::{BindKeyApp, pages::enrollment::hash_password_with_salt};
+use crate::BindKeyApp;
+use crate::bindkey_device::DeviceError;
+use crate::pages::device_feedback::{
+    forward_device_events, reset_device_feedback, show_device_feedback,
+};
+use crate::pages::device_picker::show_device_picker;
+use crate::protocol::protocol::{
+    ApiMessage, ChallengeResponse, LoginSuccessResponse, Page, Role, SrpStartResponse,
+};
+use crate::server_challenge::{self, ServerChallenge, unix_now};
+use crate::srp::{self, ClientProof, SrpAccounts};
 use eframe::egui;
-use serde_json::json;
+use p256::ecdsa::VerifyingKey;
+use serde_json::{Map, Value, json};
 use validator::ValidateEmail;
 
 pub fn show_login_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
@@ -47,6 +54,18 @@ pub fn show_login_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
 
                     ui.checkbox(&mut app.is_admin_mode, "Mode administrateur (Sans USB)");
 
+                    if !app.is_admin_mode {
+                        ui.add_space(15.0);
+                        ui.label("BindKey :");
+                        show_device_picker(
+                            ui,
+                            "login_device",
+                            &app.attached_bindkeys,
+                            None,
+                            &mut app.login_port,
+                        );
+                    }
+
                     ui.add_space(30.0);
 
                     ui.vertical_centered(|ui| {
@@ -68,6 +87,20 @@ pub fn show_login_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
 
                         ui.add_space(20.0);
 
+                        show_device_feedback(app, ui);
+
+                        // Machine isolée : ouverture d'un paquet sans passer par le serveur
+                        if ui.button("📦 Ouvrir un paquet hors ligne").clicked() {
+                            app.current_page = Page::Payload;
+                        }
+                        if ui.button("🩺 Diagnostic de la clé").clicked() {
+                            app.current_page = Page::Diagnostics;
+                        }
+                        // Clé perdue ou cassée : impossible de se connecter sans elle
+                        if ui.button("🛟 Restaurer avec un code de secours").clicked() {
+                            app.current_page = Page::Restoration;
+                        }
+
                         if !app.login_status.is_empty() {
                             if app.is_loading {
                                 ui.horizontal(|ui| {
@@ -108,25 +141,39 @@ fn handle_admin_login(app: &mut BindKeyApp) {
 
     let clone_sender = app.sender.clone();
     let clone_email = app.login_email.clone();
-    let clone_pass = hash_password_with_salt(&app.login_password);
-    let clone_url = app.config.api_url.clone();
+    let clone_pass = app.login_password.clone();
+    let clone_url = app.config.api_url();
     let clone_api_client = app.api_client.clone();
 
     tokio::spawn(async move {
         let url = format!("{}/sessions/test", clone_url);
-        let payload = json!({
-            "email": clone_email,
-            "password": clone_pass
-        });
+        let (payload, proof) =
+            match password_credentials(&clone_api_client, &clone_url, &clone_email, clone_pass)
+                .await
+            {
+                Ok(credentials) => credentials,
+                Err(e) => {
+                    let _ = clone_sender.send(ApiMessage::LoginError(e));
+                    return;
+                }
+            };
 
         match clone_api_client.post(&url).json(&payload).send().await {
             Ok(response) => {
                 if response.status().is_success() {
                     if let Ok(data) = response.json::<LoginSuccessResponse>().await {
+                        if let Err(e) = check_server_proof(&proof, data.server_proof.as_deref()) {
+                            let _ = clone_sender.send(ApiMessage::LoginError(e));
+                            return;
+                        }
+                        if let Err(e) = remember_srp_account(&clone_email) {
+                            let _ = clone_sender.send(ApiMessage::LoginError(e));
+                            return;
+                        }
                         let _ = clone_sender.send(ApiMessage::LoginSuccess(
                             data.role,
+                            data.tokens,
                             data.first_name,
-                            data.server_token,
                             data.local_token,
                             String::new(),
                         ));
@@ -158,18 +205,24 @@ fn handle_login(app: &mut BindKeyApp, ctx: egui::Context) {
         app.login_status = " Champs invalides".to_string();
         return;
     }
-    if !app.usb_connected {
+    let Some(device) = app.selected_device(&app.login_port) else {
         app.login_status = " Veuillez brancher votre BindKey".to_string();
         return;
-    }
+    };
+    let organization_key = match organization_key(app) {
+        Ok(key) => key,
+        Err(e) => {
+            app.login_status = format!(" {}", e);
+            return;
+        }
+    };
     app.is_loading = true;
     app.login_status = " Lecture de la BindKey...".to_string();
 
     let clone_sender = app.sender.clone();
     let clone_email = app.login_email.clone();
-    let clone_pass = hash_password_with_salt(&app.login_password);
-    let clone_url = app.config.api_url.clone();
-    let clone_port_name = app.current_port_name.clone();
+    let clone_pass = app.login_password.clone();
+    let clone_url = app.config.api_url();
     let clone_api_client = app.api_client.clone();
     let bypass_usb = false;
 
@@ -180,53 +233,46 @@ fn handle_login(app: &mut BindKeyApp, ctx: egui::Context) {
             tokio::time::sleep(std::time::Duration::from_millis(300)).await;
             bindkey_uid = "SIMULATED-BK-UID-999".to_string();
         } else {
-            if !clone_port_name.is_empty() {
-                match serialport::new(&clone_port_name, 115200)
-                    .timeout(Duration::from_secs(2))
-                    .open()
-                {
-                    Ok(mut port) => {
-                        let _ = port.write_data_terminal_ready(true);
-                        std::thread::sleep(Duration::from_millis(100));
-
-                        match send_text_command(&mut *port, "uid") {
-                            Ok(map) => {
-                                if let Some(sn) = map.get("SN") {
-                                    bindkey_uid = sn.clone();
-                                    let _ = clone_sender.send(ApiMessage::LoginError(
-                                        "UID récupéré, envoi au serveur...".to_string(),
-                                    ));
-                                } else {
-                                    let _ = clone_sender.send(ApiMessage::LoginError(
-                                        "Clé muette (SN manquant)".into(),
-                                    ));
-                                    return;
-                                }
-                            }
-                            Err(e) => {
-                                let _ = clone_sender.send(ApiMessage::LoginError(format!(
-                                    "Erreur lecture Clé: {}",
-                                    e
-                                )));
-                                return;
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        let _ = clone_sender
-                            .send(ApiMessage::LoginError(format!("Erreur Lecture Clé: {}", e)));
-                    }
+            match device.run("Lecture de l'UID", |d| d.read_uid()).await {
+                Ok(sn) => {
+                    // Le challenge sera signé par la clé portant ce SN
+                    let _ = clone_sender.send(ApiMessage::BindKeyIdentified {
+                        port_name: device.port_name().to_string(),
+                        sn: sn.clone(),
+                        firmware: None,
+                    });
+                    bindkey_uid = sn;
+                    let _ = clone_sender.send(ApiMessage::LoginError(
+                        "UID récupéré, envoi au serveur...".to_string(),
+                    ));
+                }
+                Err(DeviceError::MissingField(_)) => {
+                    let _ = clone_sender
+                        .send(ApiMessage::LoginError("Clé muette (SN manquant)".into()));
+                    return;
+                }
+                Err(DeviceError::NotConnected) => {
+                    let _ = clone_sender.send(ApiMessage::LoginError("Port introuvable".to_string()));
+                }
+                Err(e) => {
+                    let _ = clone_sender
+                        .send(ApiMessage::LoginError(format!("Erreur lecture Clé: {}", e)));
+                    return;
                 }
-            } else {
-                let _ = clone_sender.send(ApiMessage::LoginError("Port introuvable".to_string()));
             }
         }
 
-        let payload = json!( {
-            "email": clone_email,
-            "password": clone_pass,
-            "bindkey_id": bindkey_uid,
-        });
+        let (mut payload, proof) =
+            match password_credentials(&clone_api_client, &clone_url, &clone_email, clone_pass)
+                .await
+            {
+                Ok(credentials) => credentials,
+                Err(e) => {
+                    let _ = clone_sender.send(ApiMessage::LoginError(e));
+                    return;
+                }
+            };
+        payload.insert("bindkey_id".to_string(), json!(bindkey_uid));
 
         let url = format!("{}/sessions/login", clone_url);
 
@@ -234,11 +280,27 @@ fn handle_login(app: &mut BindKeyApp, ctx: egui::Context) {
             Ok(response) => {
                 if response.status().is_success() {
                     if let Ok(chall) = response.json::<ChallengeResponse>().await {
-                        let _ = clone_sender.send(ApiMessage::ReceivedChallenge(
-                            chall.auth_challenge,
-                            chall.session_id,
-                            bindkey_uid,
-                        ));
+                        // Pas de doigt demandé pour un serveur qui n'a pas prouvé son identité
+                        if let Err(e) = check_server_proof(&proof, chall.server_proof.as_deref()) {
+                            let _ = clone_sender.send(ApiMessage::LoginError(e));
+                            return;
+                        }
+                        if let Err(e) = remember_srp_account(&clone_email) {
+                            let _ = clone_sender.send(ApiMessage::LoginError(e));
+                            return;
+                        }
+                        match verified_challenge(&chall, &bindkey_uid, &organization_key) {
+                            Ok(challenge) => {
+                                let _ = clone_sender.send(ApiMessage::ReceivedChallenge(
+                                    challenge,
+                                    chall.session_id,
+                                    bindkey_uid,
+                                ));
+                            }
+                            Err(e) => {
+                                let _ = clone_sender.send(ApiMessage::LoginError(e));
+                            }
+                        }
                     }
                 } else {
                     let _ = clone_sender.send(ApiMessage::LoginError(format!(
@@ -254,3 +316,280 @@ fn handle_login(app: &mut BindKeyApp, ctx: egui::Context) {
     });
     app.login_password.clear();
 }
+
+// ==========================================
+// MOT DE PASSE : SRP-6a, OU MIGRATION D'UN ANCIEN COMPTE
+// ==========================================
+//
+// Renvoie les champs à envoyer à /sessions/login ou /sessions/test, et la preuve SRP
+// à confronter à celle du serveur. Un compte créé avec l'ancien hash (SHA-256 du mot de
+// passe salé par "bindkey.com") l'envoie une dernière fois avec le vérificateur SRP qui le
+// remplace : le serveur les enregistre ensemble et ne propose plus que SRP ensuite.
+// Ce poste refuse cette migration pour un compte qui y a déjà réussi SRP (SrpAccounts).
+enum PasswordProof {
+    Srp(ClientProof),
+    // Seul cas où le serveur ne prouve pas connaître le vérificateur
+    LegacyMigration,
+}
+
+async fn password_credentials(
+    client: &reqwest::Client,
+    api_url: &str,
+    email: &str,
+    password: String,
+) -> Result<(Map<String, Value>, PasswordProof), String> {
+    let url = format!("{}/sessions/srp/start", api_url);
+    let response = client
+        .post(&url)
+        .json(&json!({ "email": email }))
+        .send()
+        .await
+        .map_err(|e| format!("Erreur Réseau: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("Refus Serveur: {}", response.status()));
+    }
+    let start = response
+        .json::<SrpStartResponse>()
+        .await
+        .map_err(|_| "Erreur format réponse serveur".to_string())?;
+
+    let mut fields = Map::new();
+    fields.insert("email".to_string(), json!(email));
+
+    // PBKDF2 : calcul bloquant, hors du runtime tokio
+    let email = email.to_string();
+    let proof = match start {
+        SrpStartResponse::Srp6a {
+            srp_session,
+            srp_salt,
+            kdf_iterations,
+            server_public,
+        } => {
+            let proof = tokio::task::spawn_blocking(move || {
+                srp::client_proof(&email, &password, &srp_salt, kdf_iterations, &server_public)
+            })
+            .await
+            .map_err(|e| e.to_string())??;
+            fields.insert("srp_session".to_string(), json!(srp_session));
+            fields.insert("client_public".to_string(), json!(proof.client_public));
+            fields.insert("client_proof".to_string(), json!(proof.client_proof));
+            PasswordProof::Srp(proof)
+        }
+        SrpStartResponse::Legacy => {
+            // Sans la liste des comptes déjà passés à SRP, rien n'écarte un faux serveur
+            let known = SrpAccounts::local()
+                .and_then(|accounts| accounts.contains(&email))
+                .map_err(|e| format!("Comptes SRP de ce poste illisibles : {}", e))?;
+            if known {
+                return Err(
+                    "Le serveur demande l'ancien mot de passe haché pour un compte déjà passé à SRP : connexion refusée (serveur non authentique ?)"
+                        .to_string(),
+                );
+            }
+            println!("[AUTH] Compte sur l'ancien hash : migration vers SRP à cette connexion");
+            let legacy = srp::legacy_password_hash(&password);
+            let upgrade = tokio::task::spawn_blocking(move || srp::new_verifier(&email, &password))
+                .await
+                .map_err(|e| e.to_string())?;
+            fields.insert("password".to_string(), json!(legacy));
+            fields.insert("srp_upgrade".to_string(), json!(upgrade));
+            PasswordProof::LegacyMigration
+        }
+    };
+    Ok((fields, proof))
+}
+
+fn check_server_proof(proof: &PasswordProof, server_proof: Option<&str>) -> Result<(), String> {
+    match proof {
+        PasswordProof::Srp(proof) => proof.verify_server(server_proof),
+        PasswordProof::LegacyMigration => Ok(()),
+    }
+}
+
+// Après un échange SRP prouvé, ou une migration acceptée : ce compte est désormais sur SRP
+// Non mémorisé, le compte resterait exposé à une nouvelle demande de migration : connexion refusée
+fn remember_srp_account(email: &str) -> Result<(), String> {
+    SrpAccounts::local()
+        .and_then(|accounts| accounts.insert(email))
+        .map_err(|e| format!("Compte SRP non mémorisé sur ce poste ({}) : connexion refusée", e))
+}
+
+// Sans clé d'organisation, impossible de savoir si un challenge vient du vrai serveur
+pub fn organization_key(app: &BindKeyApp) -> Result<VerifyingKey, String> {
+    app.config
+        .organization_key
+        .as_deref()
+        .ok_or_else(|| "Clé publique de l'organisation absente de la configuration".to_string())
+        .and_then(server_challenge::verifying_key)
+}
+
+// Ni le doigt ni la clé ne signent un challenge que l'organisation n'a pas émis
+fn verified_challenge(
+    response: &ChallengeResponse,
+    sn: &str,
+    organization_key: &VerifyingKey,
+) -> Result<ServerChallenge, String> {
+    let challenge = response.server_challenge(sn)?;
+    challenge.verify(organization_key, sn, unix_now())?;
+    Ok(challenge)
+}
+
+// ==========================================
+// RÉAUTHENTIFICATION (jeton expiré en cours de session)
+// ==========================================
+//
+// Le serveur émet un challenge signé pour la BindKey de la session (/sessions/reauth,
+// avec le jeton expiré) ; la clé le vérifie et le signe comme à la connexion. Les requêtes
+// suspendues par ServerSession reprennent avec le nouveau jeton.
+
+#[derive(Default)]
+pub struct ReauthState {
+    pub running: bool,
+    pub status: String,
+}
+
+pub fn show_reauth_window(app: &mut BindKeyApp, ctx: &egui::Context) {
+    if !app.session.reauth_required() {
+        return;
+    }
+    egui::Window::new("Session expirée")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+        .show(ctx, |ui| {
+            ui.set_max_width(400.0);
+            ui.label(
+                "Votre session a expiré. Les opérations en cours sont suspendues : elles reprendront dès que votre empreinte aura été vérifiée.",
+            );
+            ui.add_space(10.0);
+            if app.reauth.running {
+                show_device_feedback(app, ui);
+            }
+            ui.horizontal(|ui| {
+                let button = egui::Button::new("🔐 Vérifier mon empreinte");
+                if ui.add_enabled(!app.reauth.running, button).clicked() {
+                    start_reauthentication(app);
+                }
+                if app.reauth.running {
+                    ui.spinner();
+                }
+                if ui.button("Se déconnecter").clicked() {
+                    let _ = app.sender.send(ApiMessage::LogOutSuccess);
+                }
+            });
+            if !app.reauth.status.is_empty() {
+                let color = if app.reauth.status.contains("❌") {
+                    egui::Color32::RED
+                } else {
+                    egui::Color32::from_rgb(100, 200, 255)
+                };
+                ui.colored_label(color, &app.reauth.status);
+            }
+        });
+}
+
+fn start_reauthentication(app: &mut BindKeyApp) {
+    let Some(sn) = app.local_bindkey_sn.clone() else {
+        app.reauth.status =
+            "❌ Session ouverte sans BindKey : déconnectez-vous puis reconnectez-vous".to_string();
+        return;
+    };
+    let Some(device) = app.session_device() else {
+        app.reauth.status = "❌ Branchez la BindKey de votre session".to_string();
+        return;
+    };
+    let organization_key = match organization_key(app) {
+        Ok(key) => key,
+        Err(e) => {
+            app.reauth.status = format!("❌ {}", e);
+            return;
+        }
+    };
+    app.reauth.running = true;
+    app.reauth.status = "Scannez votre doigt".to_string();
+    reset_device_feedback(app);
+    let on_event = forward_device_events(app);
+    let sender = app.sender.clone();
+    let api_client = app.api_client.clone();
+    let api_url = app.config.api_url();
+    let expired_token = app.session.token();
+
+    tokio::spawn(async move {
+        let result = async {
+            let response = api_client
+                .post(format!("{}/sessions/reauth", api_url))
+                .bearer_auth(&expired_token)
+                .json(&json!({ "bindkey_id": sn }))
+                .send()
+                .await
+                .map_err(|e| format!("Erreur Réseau: {}", e))?;
+            if !response.status().is_success() {
+                return Err(format!(
+                    "Réauthentification refusée par le serveur ({})",
+                    response.status()
+                ));
+            }
+            let chall = response
+                .json::<ChallengeResponse>()
+                .await
+                .map_err(|e| format!("Challenge illisible : {}", e))?;
+            let challenge = verified_challenge(&chall, &sn, &organization_key)?;
+
+            let signature = device
+                .run_with_events("Réauthentification", on_event, move |d| {
+                    d.sign_server_challenge(&challenge)
+                })
+                .await
+                .map_err(|e| e.to_string())?;
+
+            let response = api_client
+                .post(format!("{}/sessions/verify", api_url))
+                .json(&json!({
+                    "session_id": chall.session_id,
+                    "signature": signature,
+                }))
+                .send()
+                .await
+                .map_err(|e| format!("Erreur Réseau: {}", e))?;
+            if !response.status().is_success() {
+                return Err("Signature refusée par le serveur".to_string());
+            }
+            response
+                .json::<LoginSuccessResponse>()
+                .await
+                .map(|data| data.tokens)
+                .map_err(|e| e.to_string())
+        }
+        .await;
+        let _ = sender.send(ApiMessage::DeviceFeedbackReset);
+        let _ = sender.send(ApiMessage::ReauthFinished(result));
+    });
+}
+
+// ==========================================
+// CONFIGURATION SERVEUR INUTILISABLE
+// ==========================================
+
+// Affiché à la place de toute l'application : sans confiance TLS, rien ne part vers le serveur
+pub fn show_startup_error(ui: &mut egui::Ui, error: &str) {
+    ui.vertical_centered(|ui| {
+        ui.add_space(60.0);
+        ui.heading("⛔ Connexion au serveur impossible");
+        ui.add_space(20.0);
+        ui.colored_label(egui::Color32::RED, error);
+        ui.add_space(20.0);
+        ui.label("Corrigez bindkey_config.toml, puis relancez l'application. Section [server] attendue :");
+        ui.add_space(10.0);
+        ui.label(
+            egui::RichText::new(
+                "[server]\nhost = \"api.bindkey.local\"\nport = 31278\nca_bundle = \"bindkey_cert.pem\"\n# ou : spki_pins = [\"sha256/...\"]",
+            )
+            .monospace()
+            .size(16.0),
+        );
+        ui.add_space(20.0);
+        if ui.button("Quitter").clicked() {
+            ui.ctx().send_viewport_cmd(egui::ViewportCommand::Close);
+        }
+    });
+}
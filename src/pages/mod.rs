@@ -1,4 +1,13 @@
+pub mod device_feedback;
+pub mod diagnostics;
+pub mod device_picker;
 pub mod enrollment;
+pub mod fingerprints;
 pub mod home;
+pub mod incoming_shares;
 pub mod login;
+pub mod payload;
+pub mod reassign;
+pub mod reconcile;
+pub mod restoration;
 pub mod volumes;
@@ -0,0 +1,509 @@
+use crate::BindKeyApp;
+use crate::firmware::Feature;
+use crate::pages::device_feedback::{
+    forward_device_events, reset_device_feedback, show_device_feedback,
+};
+use crate::pages::device_picker::show_device_picker;
+use crate::payload_bundle::{self, BUNDLE_EXTENSION, OpenedBundle, Origin, PayloadFile};
+use crate::protocol::protocol::{ApiMessage, Page, PayloadRecipientResponse};
+use eframe::egui;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// =========================================================
+// PAQUETS HORS LIGNE (MAINTENANCE AIR-GAPPED)
+// =========================================================
+//
+// Préparer : la clé de session signe le paquet, éventuellement chiffré pour la BindKey d'un
+// autre utilisateur. Ouvrir : vérification puis extraction sur la machine isolée, sans
+// connexion au serveur (accessible depuis la page de connexion).
+
+#[derive(Default)]
+pub struct PayloadState {
+    // Un chemin par ligne
+    pub files_input: String,
+    pub description: String,
+    // Vide : paquet signé mais non chiffré
+    pub recipient_email: String,
+    pub output_path: String,
+
+    pub bundle_path: String,
+    pub extract_dir: String,
+    pub opened: Option<Arc<OpenedBundle>>,
+    pub unwrap_port: Option<String>,
+
+    pub busy: bool,
+    pub status: String,
+}
+
+pub fn show_payload_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        ui.heading("📦 Paquets hors ligne");
+        ui.label("Transport signé (et chiffré si besoin) de fichiers vers des machines isolées.");
+        ui.add_space(10.0);
+
+        let logged_in = app.is_logged_in();
+        if !logged_in && ui.button("⬅ Retour à la connexion").clicked() {
+            app.current_page = Page::Login;
+        }
+
+        if logged_in {
+            ui.add_space(10.0);
+            egui::Frame::group(ui.style()).show(ui, |ui| {
+                ui.set_width(ui.available_width());
+                show_prepare_section(app, ui);
+            });
+        }
+
+        ui.add_space(15.0);
+        egui::Frame::group(ui.style()).show(ui, |ui| {
+            ui.set_width(ui.available_width());
+            show_open_section(app, ui);
+        });
+
+        ui.add_space(10.0);
+        show_device_feedback(app, ui);
+        if app.payload.busy {
+            ui.spinner();
+        }
+        if !app.payload.status.is_empty() {
+            let color = if app.payload.status.contains("❌") {
+                egui::Color32::RED
+            } else {
+                egui::Color32::GREEN
+            };
+            ui.colored_label(color, &app.payload.status);
+        }
+    });
+}
+
+// ==========================================
+// PRÉPARATION
+// ==========================================
+
+fn show_prepare_section(app: &mut BindKeyApp, ui: &mut egui::Ui) {
+    ui.heading("Préparer un paquet");
+    ui.add_space(5.0);
+
+    ui.label("Fichiers (un chemin par ligne) :");
+    ui.add(egui::TextEdit::multiline(&mut app.payload.files_input).desired_rows(3));
+    ui.horizontal(|ui| {
+        ui.label("Description :");
+        ui.add(
+            egui::TextEdit::singleline(&mut app.payload.description)
+                .hint_text("Mise à jour, configuration, script..."),
+        );
+    });
+    ui.horizontal(|ui| {
+        ui.label("Chiffrer pour (email) :");
+        ui.add(
+            egui::TextEdit::singleline(&mut app.payload.recipient_email)
+                .hint_text("vide : non chiffré"),
+        );
+    });
+    ui.horizontal(|ui| {
+        ui.label("Fichier de sortie :");
+        ui.add(
+            egui::TextEdit::singleline(&mut app.payload.output_path)
+                .hint_text(format!("paquet.{}", BUNDLE_EXTENSION)),
+        );
+    });
+    ui.add_space(10.0);
+
+    let gate = app.session_feature(Feature::PayloadTransfer);
+    if let Err(reason) = &gate {
+        ui.colored_label(egui::Color32::from_rgb(220, 150, 0), reason);
+    }
+    let session_device = app.session_device();
+    if session_device.is_none() {
+        ui.colored_label(
+            egui::Color32::RED,
+            "Branchez la BindKey de votre session pour signer.",
+        );
+    }
+
+    let enabled = gate.is_ok() && !app.payload.busy && !app.payload.files_input.trim().is_empty();
+    let button = egui::Button::new("✍ Signer et écrire le paquet");
+    if !ui.add_enabled(enabled, button).clicked() {
+        return;
+    }
+    let Some(device) = session_device else {
+        return;
+    };
+
+    let paths: Vec<String> = app
+        .payload
+        .files_input
+        .lines()
+        .map(|l| l.trim().to_string())
+        .filter(|l| !l.is_empty())
+        .collect();
+    let output = match app.payload.output_path.trim() {
+        "" => format!("paquet.{}", BUNDLE_EXTENSION),
+        path => path.to_string(),
+    };
+    let description = app.payload.description.trim().to_string();
+    let recipient_email = app.payload.recipient_email.trim().to_string();
+    let origin = Origin {
+        sn: app.local_bindkey_sn.clone().unwrap_or_default(),
+        user_email: app.login_email.clone(),
+        hostname: sysinfo::System::host_name().unwrap_or_default(),
+        created_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+    };
+
+    let sender = app.sender.clone();
+    let api_client = app.api_client.clone();
+    let url = app.config.api_url();
+    let session = app.session.clone();
+    app.payload.busy = true;
+    app.payload.status = "Préparation du paquet...".to_string();
+    reset_device_feedback(app);
+    let on_event = forward_device_events(app);
+
+    tokio::spawn(async move {
+        let mut files = Vec::new();
+        for path in &paths {
+            let name = Path::new(path)
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| path.clone());
+            match tokio::fs::read(path).await {
+                Ok(data) => files.push(PayloadFile { name, data }),
+                Err(e) => {
+                    let _ = sender.send(ApiMessage::PayloadFinished(Err(format!(
+                        "Lecture de {} impossible : {}",
+                        path, e
+                    ))));
+                    return;
+                }
+            }
+        }
+
+        // Clé ECDH de la BindKey du destinataire, connue du serveur
+        let recipient = if recipient_email.is_empty() {
+            None
+        } else {
+            let _ = sender.send(ApiMessage::PayloadStatus(format!(
+                "Recherche de la BindKey de {}...",
+                recipient_email
+            )));
+            let response = session
+                .send(|token| {
+                    api_client
+                        .get(format!("{}/payload_recipient", url))
+                        .query(&[("email", &recipient_email)])
+                        .bearer_auth(token)
+                })
+                .await;
+            match response {
+                Ok(resp) if resp.status().is_success() => {
+                    match resp.json::<PayloadRecipientResponse>().await {
+                        Ok(target) => Some((target.target_sn, target.target_pubkey_ecdh)),
+                        Err(e) => {
+                            let _ = sender.send(ApiMessage::PayloadFinished(Err(format!(
+                                "Réponse serveur illisible : {}",
+                                e
+                            ))));
+                            return;
+                        }
+                    }
+                }
+                Ok(resp) => {
+                    let _ = sender.send(ApiMessage::PayloadFinished(Err(format!(
+                        "Destinataire introuvable ({})",
+                        resp.status()
+                    ))));
+                    return;
+                }
+                Err(e) => {
+                    let _ = sender.send(ApiMessage::PayloadFinished(Err(format!(
+                        "Erreur réseau : {}",
+                        e
+                    ))));
+                    return;
+                }
+            }
+        };
+        let recipient_sn = recipient.as_ref().map(|(sn, _)| sn.clone());
+        let count = files.len();
+
+        let prepared = match payload_bundle::prepare(files, &description, origin, recipient) {
+            Ok(prepared) => prepared,
+            Err(e) => {
+                let _ = sender.send(ApiMessage::PayloadFinished(Err(e)));
+                return;
+            }
+        };
+
+        let _ = sender.send(ApiMessage::PayloadStatus(
+            "👆 Signature par votre BindKey...".to_string(),
+        ));
+        let digest = prepared.digest();
+        let signed = device
+            .run_with_events("Signature du paquet", on_event, move |d| {
+                d.sign_payload(&digest)
+            })
+            .await;
+        let _ = sender.send(ApiMessage::DeviceFeedbackReset);
+
+        let result = signed
+            .map_err(|e| format!("Signature refusée : {}", e))
+            .and_then(|(signature, pub_sign)| prepared.finish(&signature, &pub_sign))
+            .and_then(|bytes| {
+                std::fs::write(&output, bytes)
+                    .map_err(|e| format!("Écriture de {} impossible : {}", output, e))
+            })
+            .map(|()| match recipient_sn {
+                Some(sn) => format!(
+                    "Paquet écrit : {} ({} fichier(s), chiffré pour la BindKey {})",
+                    output, count, sn
+                ),
+                None => format!(
+                    "Paquet écrit : {} ({} fichier(s), signé, non chiffré)",
+                    output, count
+                ),
+            });
+        let _ = sender.send(ApiMessage::PayloadFinished(result));
+    });
+}
+
+// ==========================================
+// OUVERTURE (MACHINE CIBLE)
+// ==========================================
+
+fn show_open_section(app: &mut BindKeyApp, ui: &mut egui::Ui) {
+    ui.heading("Ouvrir un paquet");
+    ui.add_space(5.0);
+
+    ui.horizontal(|ui| {
+        ui.label("Paquet :");
+        ui.text_edit_singleline(&mut app.payload.bundle_path);
+        if ui
+            .add_enabled(!app.payload.busy, egui::Button::new("Vérifier"))
+            .clicked()
+        {
+            verify_bundle(app);
+        }
+    });
+
+    let Some(opened) = app.payload.opened.clone() else {
+        return;
+    };
+    let manifest = &opened.manifest;
+
+    ui.add_space(10.0);
+    ui.label(egui::RichText::new("Manifeste").strong());
+    if !manifest.description.is_empty() {
+        ui.label(format!("Description : {}", manifest.description));
+    }
+    ui.label(format!(
+        "Origine : BindKey {} ({}) sur {}, le {}",
+        manifest.origin.sn,
+        manifest.origin.user_email,
+        manifest.origin.hostname,
+        format_utc(manifest.origin.created_at)
+    ));
+
+    let fingerprint = opened.signer_fingerprint();
+    let trusted = signer_trusted(app, &opened);
+    match trusted {
+        Some(true) => {
+            ui.colored_label(
+                egui::Color32::GREEN,
+                format!("✔ Signataire de confiance ({})", fingerprint),
+            );
+        }
+        Some(false) => {
+            ui.colored_label(
+                egui::Color32::RED,
+                format!(
+                    "❌ Signataire absent de la liste de confiance ({})",
+                    fingerprint
+                ),
+            );
+        }
+        None => {
+            ui.colored_label(
+                egui::Color32::from_rgb(220, 150, 0),
+                format!("Signature valide, empreinte du signataire : {} (aucune liste de confiance configurée)", fingerprint),
+            );
+        }
+    }
+
+    for file in &manifest.files {
+        ui.label(
+            egui::RichText::new(format!(
+                "• {} — {} octets — SHA-256 {}…",
+                file.name,
+                file.size,
+                &file.sha256[..16.min(file.sha256.len())]
+            ))
+            .size(14.0),
+        );
+    }
+
+    if let Some(recipient) = &manifest.recipient {
+        ui.label(format!("🔒 Chiffré pour la BindKey {}", recipient.sn));
+        show_device_picker(
+            ui,
+            "payload_unwrap_device",
+            &app.attached_bindkeys,
+            Some(recipient.sn.as_str()),
+            &mut app.payload.unwrap_port,
+        );
+    }
+
+    ui.add_space(10.0);
+    ui.horizontal(|ui| {
+        ui.label("Extraire dans :");
+        ui.add(
+            egui::TextEdit::singleline(&mut app.payload.extract_dir)
+                .hint_text("dossier de destination"),
+        );
+    });
+
+    let unwrap_device = app.selected_device(&app.payload.unwrap_port);
+    let gate = if opened.is_encrypted() {
+        app.feature_for_port(
+            unwrap_device.as_ref().map(|d| d.port_name()),
+            Feature::PayloadTransfer,
+        )
+    } else {
+        Ok(())
+    };
+    if let Err(reason) = &gate {
+        ui.colored_label(egui::Color32::from_rgb(220, 150, 0), reason);
+    }
+
+    let enabled = trusted != Some(false)
+        && gate.is_ok()
+        && !app.payload.busy
+        && !app.payload.extract_dir.trim().is_empty()
+        && (!opened.is_encrypted() || unwrap_device.is_some());
+    if !ui
+        .add_enabled(enabled, egui::Button::new("📂 Extraire"))
+        .clicked()
+    {
+        return;
+    }
+
+    let dir = app.payload.extract_dir.trim().to_string();
+    let sender = app.sender.clone();
+    app.payload.busy = true;
+    reset_device_feedback(app);
+
+    let Some(recipient) = manifest.recipient.clone() else {
+        let result = opened
+            .extract(None)
+            .and_then(|files| write_extracted(&files, &dir));
+        let _ = sender.send(ApiMessage::PayloadFinished(result));
+        return;
+    };
+    let Some(device) = unwrap_device else {
+        return;
+    };
+
+    app.payload.status = "👆 Déchiffrement par la BindKey du destinataire...".to_string();
+    let on_event = forward_device_events(app);
+    tokio::spawn(async move {
+        let key = device
+            .run_with_events("Ouverture du paquet", on_event, move |d| {
+                d.unwrap_payload_key(&recipient.ephemeral_pubkey, &recipient.wrapped_key)
+            })
+            .await;
+        let _ = sender.send(ApiMessage::DeviceFeedbackReset);
+
+        let result = key
+            .map_err(|e| format!("Déchiffrement refusé par la clé : {}", e))
+            .and_then(|key| opened.extract(Some(key)))
+            .and_then(|files| write_extracted(&files, &dir));
+        let _ = sender.send(ApiMessage::PayloadFinished(result));
+    });
+}
+
+fn verify_bundle(app: &mut BindKeyApp) {
+    let path = app.payload.bundle_path.trim().to_string();
+    let opened = std::fs::read(&path)
+        .map_err(|e| format!("Lecture de {} impossible : {}", path, e))
+        .and_then(|bytes| payload_bundle::open(&bytes));
+
+    match opened {
+        Ok(opened) => {
+            app.payload.status = format!(
+                "Paquet authentique : {} fichier(s)",
+                opened.manifest.files.len()
+            );
+            // La clé du destinataire est proposée d'office si elle est branchée
+            if let Some(recipient) = &opened.manifest.recipient
+                && let Some(key) = app
+                    .attached_bindkeys
+                    .iter()
+                    .find(|k| k.sn.as_ref() == Some(&recipient.sn))
+            {
+                app.payload.unwrap_port = Some(key.port_name.clone());
+            }
+            if app.payload.extract_dir.is_empty() {
+                let stem = Path::new(&path)
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                app.payload.extract_dir = format!("{}_extrait", stem);
+            }
+            app.payload.opened = Some(Arc::new(opened));
+        }
+        Err(e) => {
+            app.payload.status = format!("❌ {}", e);
+            app.payload.opened = None;
+        }
+    }
+}
+
+// None : pas de liste configurée, la décision revient à l'utilisateur
+fn signer_trusted(app: &BindKeyApp, opened: &OpenedBundle) -> Option<bool> {
+    let trusted = &app.config.trusted_payload_signers;
+    if trusted.is_empty() {
+        return None;
+    }
+    let fingerprint = opened.signer_fingerprint();
+    Some(trusted.iter().any(|t| {
+        t.eq_ignore_ascii_case(&fingerprint) || t.eq_ignore_ascii_case(&opened.signer_pubkey)
+    }))
+}
+
+fn write_extracted(files: &[PayloadFile], dir: &str) -> Result<String, String> {
+    let written = payload_bundle::write_files(files, Path::new(dir))?;
+    Ok(format!(
+        "{} fichier(s) vérifié(s) et extrait(s) dans {}",
+        written.len(),
+        dir
+    ))
+}
+
+// AAAA-MM-JJ HH:MM UTC (jours civils depuis l'époque Unix)
+pub fn format_utc(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let minutes = (secs % 86_400) / 60;
+
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        minutes / 60,
+        minutes % 60
+    )
+}
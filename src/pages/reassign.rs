@@ -0,0 +1,339 @@
+use crate::BindKeyApp;
+use crate::firmware::Feature;
+use crate::pages::device_feedback::{
+    forward_device_events, reset_device_feedback, show_device_feedback,
+};
+use crate::pages::login::organization_key;
+use crate::protocol::protocol::{
+    ApiMessage, AuditEventPayload, ReassignmentCleanup, ReassignmentStartPayload,
+    ReassignmentStartResponse, WipeMethod,
+};
+use crate::server_challenge::{WIPE_CHALLENGE_CONTEXT, unix_now};
+use eframe::egui;
+
+// =========================================================
+// RÉATTRIBUTION D'UNE BINDKEY RENDUE (administrateur)
+// =========================================================
+//
+// 1. /admin/reassignments ouvre un dossier d'audit pour le SN (et fournit l'ordre de
+//    remise à zéro signé par l'organisation si l'ancien propriétaire n'est pas là) ;
+// 2. la clé est remise à zéro (doigt ou ordre signé) et l'issue est tracée dans le dossier ;
+// 3. le serveur supprime les partages et volumes rattachés à l'ancien SN ;
+// 4. l'enrôlement du nouveau propriétaire reprend ce dossier (reassignment_id).
+// Chaque étape serveur n'a lieu que si la précédente est tracée.
+
+#[derive(Default)]
+pub struct ReassignState {
+    pub sn: String,
+    pub method: WipeMethod,
+    pub running: bool,
+    pub reassignment_id: Option<String>,
+    pub previous_owner: Option<String>,
+    // Clé effacée et nettoyée côté serveur : l'enrôlement en cours la rattache au dossier
+    pub awaiting_enrollment: bool,
+    pub audit: Vec<AuditEntry>,
+    pub status: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    // Secondes Unix
+    pub at: u64,
+    pub step: String,
+    pub outcome: Result<String, String>,
+}
+
+impl AuditEntry {
+    pub fn new(step: &str, outcome: Result<String, String>) -> Self {
+        AuditEntry {
+            at: unix_now(),
+            step: step.to_string(),
+            outcome,
+        }
+    }
+}
+
+impl ReassignState {
+    // Dossier à joindre à l'enrôlement de cette clé, s'il y en a un
+    pub fn pending_for(&self, sn: &str) -> Option<String> {
+        if self.awaiting_enrollment && self.sn == sn {
+            self.reassignment_id.clone()
+        } else {
+            None
+        }
+    }
+}
+
+pub fn show_reassign_card(app: &mut BindKeyApp, ui: &mut egui::Ui) {
+    ui.heading("♻️ Réattribution d'une BindKey");
+    ui.separator();
+    ui.add_space(10.0);
+    ui.label("Efface l'identité d'une clé rendue, supprime ses partages et volumes, puis l'enrôle pour un nouveau propriétaire.");
+    ui.add_space(10.0);
+
+    let locked = app.reassign.running || app.reassign.awaiting_enrollment;
+    ui.horizontal(|ui| {
+        ui.label("Numéro de série :");
+        ui.add_enabled(
+            !locked,
+            egui::TextEdit::singleline(&mut app.reassign.sn).hint_text("ex: BK-0001"),
+        );
+    });
+    ui.horizontal(|ui| {
+        ui.label("Confirmation :");
+        ui.add_enabled_ui(!locked, |ui| {
+            ui.radio_value(
+                &mut app.reassign.method,
+                WipeMethod::Fingerprint,
+                "Empreinte de l'ancien propriétaire",
+            );
+            ui.radio_value(
+                &mut app.reassign.method,
+                WipeMethod::AdminOrder,
+                "Ordre d'administrateur signé",
+            );
+        });
+    });
+
+    let sn = app.reassign.sn.trim().to_string();
+    let port = app
+        .attached_bindkeys
+        .iter()
+        .find(|k| !sn.is_empty() && k.sn.as_deref() == Some(sn.as_str()))
+        .map(|k| k.port_name.clone());
+    if !sn.is_empty() && port.is_none() && !app.reassign.awaiting_enrollment {
+        ui.colored_label(
+            egui::Color32::from_rgb(220, 150, 0),
+            format!("Branchez la BindKey {} pour la réinitialiser", sn),
+        );
+    } else if app.local_bindkey_sn.as_deref() == Some(sn.as_str()) {
+        ui.colored_label(
+            egui::Color32::RED,
+            "Impossible de réattribuer la clé de votre propre session",
+        );
+    }
+    let gate = app.feature_for_port(port.as_deref(), Feature::FactoryWipe);
+    if let (Some(_), Err(reason)) = (&port, &gate) {
+        ui.colored_label(egui::Color32::from_rgb(220, 150, 0), reason);
+    }
+    ui.add_space(10.0);
+
+    ui.horizontal(|ui| {
+        let enabled =
+            !locked && gate.is_ok() && app.local_bindkey_sn.as_deref() != Some(sn.as_str());
+        if let Some(port) = port.filter(|_| enabled) {
+            if ui.button("🧨 Réinitialiser la clé").clicked() {
+                start_reassignment(app, sn.clone(), port);
+            }
+        } else {
+            ui.add_enabled(false, egui::Button::new("🧨 Réinitialiser la clé"));
+        }
+        if app.reassign.running {
+            ui.spinner();
+        }
+        if app.reassign.awaiting_enrollment && ui.button("Abandonner").clicked() {
+            // Le dossier reste ouvert côté serveur : la clé effacée peut être enrôlée plus tard
+            app.reassign.awaiting_enrollment = false;
+            app.reassign.status =
+                "Enrôlement abandonné : la clé reste effacée et non attribuée.".to_string();
+        }
+    });
+    if app.reassign.running {
+        show_device_feedback(app, ui);
+    }
+
+    if !app.reassign.audit.is_empty() {
+        ui.add_space(10.0);
+        let title = match &app.reassign.reassignment_id {
+            Some(id) => format!("Journal du dossier {}", id),
+            None => "Journal".to_string(),
+        };
+        ui.label(egui::RichText::new(title).strong());
+        egui::Grid::new("reassign_audit")
+            .num_columns(3)
+            .spacing([15.0, 6.0])
+            .show(ui, |ui| {
+                for entry in &app.reassign.audit {
+                    ui.label(egui::RichText::new(entry.at.to_string()).monospace().weak());
+                    ui.label(&entry.step);
+                    match &entry.outcome {
+                        Ok(detail) => {
+                            ui.colored_label(egui::Color32::GREEN, format!("✔ {}", detail))
+                        }
+                        Err(e) => ui.colored_label(egui::Color32::RED, format!("❌ {}", e)),
+                    };
+                    ui.end_row();
+                }
+            });
+    }
+
+    if !app.reassign.status.is_empty() {
+        let color = if app.reassign.status.contains("❌") {
+            egui::Color32::RED
+        } else {
+            egui::Color32::from_rgb(100, 200, 255)
+        };
+        ui.colored_label(color, &app.reassign.status);
+    }
+}
+
+fn start_reassignment(app: &mut BindKeyApp, sn: String, port: String) {
+    let method = app.reassign.method;
+    // L'ordre signé est vérifié avant d'être confié à la clé, comme un challenge de connexion
+    let org_key = match method {
+        WipeMethod::Fingerprint => None,
+        WipeMethod::AdminOrder => match organization_key(app) {
+            Ok(key) => Some(key),
+            Err(e) => {
+                app.reassign.status = format!("❌ {}", e);
+                return;
+            }
+        },
+    };
+    app.reassign.sn = sn.clone();
+    app.reassign.running = true;
+    app.reassign.reassignment_id = None;
+    app.reassign.previous_owner = None;
+    app.reassign.audit.clear();
+    app.reassign.status = "Ouverture du dossier de réattribution...".to_string();
+    reset_device_feedback(app);
+    let on_event = forward_device_events(app);
+    let device = app.device.target(&port);
+    let sender = app.sender.clone();
+    let api_client = app.api_client.clone();
+    let api_url = app.config.api_url();
+    let session = app.session.clone();
+
+    tokio::spawn(async move {
+        let audit = |step: &str, outcome: Result<String, String>| {
+            let _ = sender.send(ApiMessage::ReassignAudit(AuditEntry::new(step, outcome)));
+        };
+
+        let result = async {
+            // 1. Dossier d'audit
+            let payload = ReassignmentStartPayload {
+                sn: sn.clone(),
+                wipe_method: method,
+            };
+            let start = match session
+                .send(|token| {
+                    api_client
+                        .post(format!("{}/admin/reassignments", api_url))
+                        .bearer_auth(token)
+                        .json(&payload)
+                })
+                .await
+            {
+                Ok(resp) if resp.status().is_success() => resp
+                    .json::<ReassignmentStartResponse>()
+                    .await
+                    .map_err(|e| format!("Réponse illisible : {}", e))?,
+                Ok(resp) => return Err(format!("Refus serveur ({})", resp.status())),
+                Err(e) => return Err(format!("Erreur Réseau : {}", e)),
+            };
+            let id = start.reassignment_id.clone();
+            audit(
+                "Ouverture du dossier",
+                Ok(match &start.previous_owner {
+                    Some(owner) => format!("clé {} de {}", sn, owner),
+                    None => format!("clé {}", sn),
+                }),
+            );
+            let _ = sender.send(ApiMessage::ReassignStarted(
+                id.clone(),
+                start.previous_owner,
+            ));
+
+            let order = match org_key {
+                None => None,
+                Some(key) => {
+                    let order = start
+                        .wipe_order
+                        .ok_or("Le serveur n'a pas fourni d'ordre de remise à zéro signé")?
+                        .server_challenge(&sn);
+                    order.verify_for(WIPE_CHALLENGE_CONTEXT, &key, &sn, unix_now())?;
+                    Some(order)
+                }
+            };
+
+            // 2. Remise à zéro de la clé, puis sa trace dans le dossier
+            let wiped = device
+                .run_with_events("Remise à zéro d'usine", on_event, move |d| {
+                    d.factory_wipe(order.as_ref())
+                })
+                .await
+                .map_err(|e| e.to_string());
+            let _ = sender.send(ApiMessage::DeviceFeedbackReset);
+            let event = AuditEventPayload {
+                step: "device_wipe".to_string(),
+                success: wiped.is_ok(),
+                detail: match (&wiped, method) {
+                    (Ok(()), WipeMethod::Fingerprint) => "confirmée par empreinte".to_string(),
+                    (Ok(()), WipeMethod::AdminOrder) => "confirmée par ordre signé".to_string(),
+                    (Err(e), _) => e.clone(),
+                },
+                at: unix_now(),
+            };
+            audit(
+                "Remise à zéro de la clé",
+                wiped.clone().map(|()| event.detail.clone()),
+            );
+            let recorded = session
+                .send(|token| {
+                    api_client
+                        .post(format!("{}/admin/reassignments/{}/events", api_url, id))
+                        .bearer_auth(token)
+                        .json(&event)
+                })
+                .await;
+            wiped.map_err(|e| format!("Remise à zéro refusée par la clé : {}", e))?;
+            match recorded {
+                Ok(resp) if resp.status().is_success() => {}
+                Ok(resp) => {
+                    return Err(format!(
+                        "Clé effacée mais remise à zéro non tracée ({}) : nettoyage suspendu",
+                        resp.status()
+                    ));
+                }
+                Err(e) => {
+                    return Err(format!(
+                        "Clé effacée mais remise à zéro non tracée ({}) : nettoyage suspendu",
+                        e
+                    ));
+                }
+            }
+
+            // 3. Partages et volumes de l'ancien SN
+            let cleanup = match session
+                .send(|token| {
+                    api_client
+                        .post(format!("{}/admin/reassignments/{}/cleanup", api_url, id))
+                        .bearer_auth(token)
+                })
+                .await
+            {
+                Ok(resp) if resp.status().is_success() => resp
+                    .json::<ReassignmentCleanup>()
+                    .await
+                    .map_err(|e| format!("Réponse illisible : {}", e))?,
+                Ok(resp) => return Err(format!("Nettoyage refusé ({})", resp.status())),
+                Err(e) => return Err(format!("Erreur Réseau : {}", e)),
+            };
+            audit(
+                "Nettoyage serveur",
+                Ok(format!(
+                    "{} partage(s) et {} volume(s) supprimés",
+                    cleanup.shares_removed, cleanup.volumes_removed
+                )),
+            );
+            Ok(port)
+        }
+        .await;
+
+        if let Err(e) = &result {
+            audit("Interruption", Err(e.clone()));
+        }
+        let _ = sender.send(ApiMessage::DeviceFeedbackReset);
+        let _ = sender.send(ApiMessage::ReassignWiped(result));
+    });
+}
@@ -0,0 +1,270 @@
+use crate::BindKeyApp;
+use crate::device_owner::DeviceTarget;
+use crate::disk_link;
+use crate::firmware::Feature;
+use crate::partition_table::{self, PartitionTable};
+use crate::protocol::protocol::{ApiMessage, VolumeCreatedInfo};
+use crate::reconcile::{self, ReconciliationReport, RepairStep};
+use crate::session::ServerSession;
+use eframe::egui;
+
+// =========================================================
+// COHÉRENCE CLÉ / PARTITIONS / SERVEUR (onglet des volumes)
+// =========================================================
+//
+// Chaque réparation est confirmée une par une, puis l'analyse est relancée :
+// le rapport affiché reflète toujours l'état réel des trois sources.
+
+#[derive(Default)]
+pub struct ReconcileState {
+    pub running: bool,
+    pub report: Option<ReconciliationReport>,
+    // Index de l'anomalie dont la réparation attend confirmation
+    pub confirm: Option<usize>,
+    pub status: String,
+}
+
+// Ce qu'il faut pour interroger les trois sources depuis une tâche tokio
+#[derive(Clone)]
+struct ScanContext {
+    device: DeviceTarget,
+    disk_path: String,
+    api_client: reqwest::Client,
+    api_url: String,
+    session: ServerSession,
+}
+
+pub fn show_reconcile_tab(app: &mut BindKeyApp, ui: &mut egui::Ui) {
+    ui.label(
+        "Compare les volumes connus de la BindKey, la table de partitions du disque et le serveur.",
+    );
+    ui.add_space(10.0);
+
+    if let Err(reason) = app.session_feature(Feature::Inventory) {
+        ui.colored_label(egui::Color32::from_rgb(220, 150, 0), reason);
+        return;
+    }
+    let Some(device) = app.session_device() else {
+        ui.colored_label(egui::Color32::RED, "Branchez la BindKey de votre session.");
+        return;
+    };
+    let Some(disk_path) = app.session_disk() else {
+        ui.colored_label(
+            egui::Color32::RED,
+            "Aucun disque n'est relié au port série de votre BindKey.",
+        );
+        return;
+    };
+    let context = ScanContext {
+        device,
+        disk_path,
+        api_client: app.api_client.clone(),
+        api_url: app.config.api_url(),
+        session: app.session.clone(),
+    };
+
+    ui.horizontal(|ui| {
+        let button = egui::Button::new("🔎 Analyser la cohérence");
+        if ui.add_enabled(!app.reconcile.running, button).clicked() {
+            app.reconcile.status.clear();
+            start(app, context.clone(), Vec::new());
+        }
+        if app.reconcile.running {
+            ui.spinner();
+        }
+    });
+
+    let mut repair = None;
+    if let Some(report) = app.reconcile.report.clone() {
+        ui.add_space(10.0);
+        ui.label(format!(
+            "Clé : {} volume(s), {} part(s) reçue(s) | {} : {} partition(s) | Serveur : {} volume(s)",
+            report.inventory.volumes.len(),
+            report.inventory.shares.len(),
+            report.disk_path,
+            report.table.partitions.len(),
+            report.server.len()
+        ));
+        ui.add_space(10.0);
+
+        if report.findings.is_empty() {
+            ui.colored_label(egui::Color32::GREEN, "✔ Les trois sources concordent.");
+        }
+
+        for (index, finding) in report.findings.iter().enumerate() {
+            egui::Frame::group(ui.style()).show(ui, |ui| {
+                ui.set_width(ui.available_width());
+                ui.colored_label(egui::Color32::from_rgb(220, 150, 0), finding.title());
+                ui.label(egui::RichText::new(finding.detail()).size(16.0));
+
+                let steps = finding.repair();
+                if steps.is_empty() {
+                    ui.label(egui::RichText::new(finding.manual_advice()).weak());
+                } else if app.reconcile.confirm == Some(index) {
+                    ui.label("La réparation va :");
+                    for step in &steps {
+                        ui.label(format!("• {}", step.describe()));
+                    }
+                    ui.horizontal(|ui| {
+                        if ui.button("Confirmer").clicked() {
+                            repair = Some(steps.clone());
+                        }
+                        if ui.button("Annuler").clicked() {
+                            app.reconcile.confirm = None;
+                        }
+                    });
+                } else if ui
+                    .add_enabled(!app.reconcile.running, egui::Button::new("🛠 Réparer"))
+                    .clicked()
+                {
+                    app.reconcile.confirm = Some(index);
+                }
+            });
+            ui.add_space(5.0);
+        }
+    }
+
+    if let Some(steps) = repair {
+        // Même garde-fou que la suppression d'un volume : jamais de parted sur un autre disque
+        let removes_partition = steps
+            .iter()
+            .any(|s| matches!(s, RepairStep::RemovePartition(_)));
+        if removes_partition
+            && !disk_link::disk_behind_port(
+                context.device.port_name(),
+                &context.disk_path,
+                &app.config,
+            )
+        {
+            app.reconcile.status = format!(
+                "❌ Refus de modifier {} : ce disque n'est pas derrière votre BindKey",
+                context.disk_path
+            );
+        } else {
+            app.reconcile.status.clear();
+            start(app, context, steps);
+        }
+    }
+
+    if !app.reconcile.status.is_empty() {
+        let color = if app.reconcile.status.contains("❌") {
+            egui::Color32::RED
+        } else {
+            egui::Color32::GREEN
+        };
+        ui.add_space(10.0);
+        ui.colored_label(color, &app.reconcile.status);
+    }
+}
+
+// Applique les réparations demandées (aucune pour une simple analyse) puis relit les trois sources
+fn start(app: &mut BindKeyApp, context: ScanContext, steps: Vec<RepairStep>) {
+    app.reconcile.running = true;
+    app.reconcile.confirm = None;
+    let sender = app.sender.clone();
+
+    tokio::spawn(async move {
+        if !steps.is_empty() {
+            let status = match apply_repair(&context, &steps).await {
+                Ok(()) => "✔ Réparation appliquée".to_string(),
+                Err(e) => format!("❌ Réparation interrompue : {}", e),
+            };
+            let _ = sender.send(ApiMessage::ReconciliationStatus(status));
+            if steps
+                .iter()
+                .any(|s| matches!(s, RepairStep::RemovePartition(_)))
+            {
+                let _ = sender.send(ApiMessage::RequestVolumeRefresh);
+            }
+        }
+        let _ = sender.send(ApiMessage::ReconciliationFinished(scan(&context).await));
+    });
+}
+
+async fn scan(context: &ScanContext) -> Result<ReconciliationReport, String> {
+    let inventory = context
+        .device
+        .run("Inventaire de la clé", |d| d.inventory())
+        .await
+        .map_err(|e| format!("Inventaire de la clé impossible : {}", e))?;
+    let table = read_table(&context.disk_path).await?;
+    let server = fetch_server_volumes(context).await?;
+
+    let findings = reconcile::compare(&inventory, &table, &server);
+    Ok(ReconciliationReport {
+        disk_path: context.disk_path.clone(),
+        inventory,
+        table,
+        server,
+        findings,
+    })
+}
+
+async fn read_table(disk_path: &str) -> Result<PartitionTable, String> {
+    let disk_path = disk_path.to_string();
+    tokio::task::spawn_blocking(move || partition_table::read_partition_table(&disk_path))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+async fn fetch_server_volumes(context: &ScanContext) -> Result<Vec<VolumeCreatedInfo>, String> {
+    let url = format!("{}/volumes", context.api_url);
+    let response = context
+        .session
+        .send(|token| context.api_client.get(&url).bearer_auth(token))
+        .await
+        .map_err(|e| format!("Serveur injoignable : {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Liste des volumes refusée par le serveur ({})",
+            response.status()
+        ));
+    }
+    response
+        .json::<Vec<VolumeCreatedInfo>>()
+        .await
+        .map_err(|e| format!("Liste des volumes illisible : {}", e))
+}
+
+async fn apply_repair(context: &ScanContext, steps: &[RepairStep]) -> Result<(), String> {
+    for step in steps {
+        match step {
+            RepairStep::ForgetOnDevice(volume_id) => {
+                let volume_id = volume_id.clone();
+                context
+                    .device
+                    .run("Oubli d'un volume orphelin", move |d| {
+                        d.delete_volume(&volume_id)
+                    })
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+            RepairStep::RemovePartition(number) => {
+                let disk_path = context.disk_path.clone();
+                let number = *number;
+                tokio::task::spawn_blocking(move || {
+                    partition_table::remove_partition(&disk_path, number)
+                })
+                .await
+                .map_err(|e| e.to_string())??;
+            }
+            RepairStep::DeleteServerRecord(volume_id) => {
+                let url = format!("{}/volumes/{}", context.api_url, volume_id);
+                let response = context
+                    .session
+                    .send(|token| context.api_client.delete(&url).bearer_auth(token))
+                    .await
+                    .map_err(|e| format!("Serveur injoignable : {}", e))?;
+                if !response.status().is_success() {
+                    return Err(format!(
+                        "le serveur a refusé de retirer {} ({})",
+                        volume_id,
+                        response.status()
+                    ));
+                }
+            }
+        }
+        println!("[COHÉRENCE] Étape appliquée : {}", step.describe());
+    }
+    Ok(())
+}
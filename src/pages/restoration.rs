@@ -0,0 +1,337 @@
+use crate::BindKeyApp;
+use crate::device_owner::DeviceTarget;
+use crate::pages::device_feedback::{
+    forward_device_events, reset_device_feedback, show_device_feedback,
+};
+use crate::pages::device_picker::show_device_picker;
+use crate::protocol::protocol::{
+    ApiMessage, Page, RecoveryEnrollPayload, RecoveryStartPayload, RecoveryStartResponse,
+    RewrapRequestPayload,
+};
+use eframe::egui;
+use serde_json::json;
+
+// =========================================================
+// RESTAURATION PAR CODE DE SECOURS
+// =========================================================
+//
+// BindKey perdue ou cassée : le code de secours de l'utilisateur rattache une clé de
+// remplacement à son identité existante. Dans l'ordre :
+//   1. /recovery/start vérifie le code et renvoie un jeton de restauration, l'ancien SN
+//      et les partages de volumes que détenait l'ancienne clé ;
+//   2. la nouvelle clé est identifiée : ce ne doit pas être celle que l'on remplace ;
+//   3. elle est enrôlée (doigt) et ses PUB_SIGN / PUB_ECDH envoyées (/recovery/enroll) ;
+//   4. un re-chiffrement est demandé pour chaque partage ; les autres détenteurs du
+//      volume le réalisent, et il arrive dans les partages entrants de la nouvelle clé ;
+//   5. l'ancien SN est révoqué. Il ne l'est qu'une fois la nouvelle clé acceptée : un échec
+//      plus tôt laisse l'utilisateur avec sa clé actuelle et la restauration peut être reprise.
+//      Un échec de cette révocation est une erreur : la clé perdue a pu être volée.
+// Accessible hors connexion : sans sa clé, l'utilisateur ne peut pas se connecter.
+
+#[derive(Default)]
+pub struct RestorationState {
+    pub port: Option<String>,
+    pub email: String,
+    pub recovery_code: String,
+    pub running: bool,
+    pub status: String,
+    pub report: Option<RestorationReport>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RestorationReport {
+    pub old_sn: String,
+    pub new_sn: String,
+    // Nom du volume et issue de la demande de re-chiffrement
+    pub rewraps: Vec<(String, Result<(), String>)>,
+}
+
+// Les codes sont imprimés par groupes (ABCD-EFGH-...) : tirets, espaces et casse ignorés
+pub fn normalize_recovery_code(code: &str) -> Result<String, String> {
+    let normalized: String = code
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    if normalized.is_empty() {
+        return Err("Saisissez votre code de secours".to_string());
+    }
+    if !normalized.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err("Code de secours invalide : lettres et chiffres uniquement".to_string());
+    }
+    Ok(normalized)
+}
+
+pub fn show_restoration_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        ui.heading("🛟 Restauration par code de secours");
+        ui.label(
+            "Rattache une BindKey de remplacement à votre compte. L'ancienne clé est révoquée et l'accès à vos volumes vous est partagé à nouveau.",
+        );
+        ui.add_space(10.0);
+
+        if !app.is_logged_in() && ui.button("⬅ Retour à la connexion").clicked() {
+            app.current_page = Page::Login;
+        }
+        ui.add_space(10.0);
+
+        ui.label("Nouvelle BindKey :");
+        show_device_picker(
+            ui,
+            "restoration_device",
+            &app.attached_bindkeys,
+            None,
+            &mut app.restoration.port,
+        );
+        ui.add_space(10.0);
+
+        egui::Grid::new("restoration_form")
+            .num_columns(2)
+            .spacing([20.0, 8.0])
+            .show(ui, |ui| {
+                ui.label("Email :");
+                ui.text_edit_singleline(&mut app.restoration.email);
+                ui.end_row();
+                ui.label("Code de secours :");
+                ui.add(egui::TextEdit::singleline(&mut app.restoration.recovery_code).password(true));
+                ui.end_row();
+            });
+        ui.add_space(10.0);
+
+        let device = app.selected_device(&app.restoration.port);
+        ui.horizontal(|ui| {
+            let enabled = device.is_some()
+                && !app.restoration.running
+                && !app.restoration.email.trim().is_empty()
+                && !app.restoration.recovery_code.trim().is_empty();
+            if ui
+                .add_enabled(enabled, egui::Button::new("Restaurer sur cette BindKey"))
+                .clicked()
+                && let Some(device) = device
+            {
+                start_restoration(app, device);
+            }
+            if app.restoration.running {
+                ui.spinner();
+            }
+        });
+        if app.restoration.running {
+            show_device_feedback(app, ui);
+        }
+
+        if let Some(report) = &app.restoration.report {
+            ui.add_space(15.0);
+            show_report(ui, report);
+        }
+
+        if !app.restoration.status.is_empty() {
+            let color = if app.restoration.status.contains("❌") {
+                egui::Color32::RED
+            } else {
+                egui::Color32::from_rgb(100, 200, 255)
+            };
+            ui.colored_label(color, &app.restoration.status);
+        }
+    });
+}
+
+fn show_report(ui: &mut egui::Ui, report: &RestorationReport) {
+    egui::Frame::group(ui.style()).show(ui, |ui| {
+        ui.set_width(ui.available_width());
+        ui.label(
+            egui::RichText::new(format!(
+                "BindKey {} rattachée à votre compte",
+                report.new_sn
+            ))
+            .size(16.0),
+        );
+        ui.colored_label(
+            egui::Color32::GREEN,
+            format!("✔ Ancienne clé {} révoquée", report.old_sn),
+        );
+        ui.add_space(5.0);
+        if report.rewraps.is_empty() {
+            ui.label("Aucun partage de volume à restaurer.");
+        }
+        for (volume, result) in &report.rewraps {
+            match result {
+                Ok(()) => ui.colored_label(
+                    egui::Color32::GREEN,
+                    format!("✔ {} : re-chiffrement demandé", volume),
+                ),
+                Err(e) => ui.colored_label(egui::Color32::RED, format!("❌ {} : {}", volume, e)),
+            };
+        }
+        if report.rewraps.iter().any(|(_, r)| r.is_ok()) {
+            ui.label(
+                egui::RichText::new(
+                    "Les volumes apparaîtront dans vos partages entrants une fois re-chiffrés par leurs autres détenteurs.",
+                )
+                .weak(),
+            );
+        }
+    });
+}
+
+fn start_restoration(app: &mut BindKeyApp, device: DeviceTarget) {
+    let recovery_code = match normalize_recovery_code(&app.restoration.recovery_code) {
+        Ok(code) => code,
+        Err(e) => {
+            app.restoration.status = format!("❌ {}", e);
+            return;
+        }
+    };
+    // Le code ne reste pas affiché ni en mémoire au-delà de cet envoi
+    app.restoration.recovery_code.clear();
+    app.restoration.running = true;
+    app.restoration.report = None;
+    app.restoration.status = "Vérification du code de secours...".to_string();
+    reset_device_feedback(app);
+    let on_event = forward_device_events(app);
+    let sender = app.sender.clone();
+    let api_client = app.api_client.clone();
+    let api_url = app.config.api_url();
+    let payload = RecoveryStartPayload {
+        email: app.restoration.email.trim().to_string(),
+        recovery_code,
+    };
+
+    tokio::spawn(async move {
+        let result = async {
+            let response = api_client
+                .post(format!("{}/recovery/start", api_url))
+                .json(&payload)
+                .send()
+                .await
+                .map_err(|e| format!("Erreur Réseau: {}", e))?;
+            if !response.status().is_success() {
+                return Err(format!(
+                    "Code de secours refusé par le serveur ({})",
+                    response.status()
+                ));
+            }
+            let recovery = response
+                .json::<RecoveryStartResponse>()
+                .await
+                .map_err(|e| format!("Réponse illisible : {}", e))?;
+            // Vérifié avant d'écrire quoi que ce soit sur la clé
+            let new_sn = device
+                .run("Restauration", |d| d.read_uid())
+                .await
+                .map_err(|e| format!("Lecture de la nouvelle clé impossible : {}", e))?;
+            if new_sn == recovery.old_sn {
+                return Err("Cette BindKey est celle que vous remplacez".to_string());
+            }
+
+            let _ = sender.send(ApiMessage::RestorationProgress(
+                "👆 Enrôlement de la nouvelle clé : placez votre doigt 2 fois sur le capteur..."
+                    .to_string(),
+            ));
+            let keys = device
+                .run_with_events("Restauration", on_event, |d| d.enroll())
+                .await
+                .map_err(|e| format!("Enrôlement de la nouvelle clé impossible : {}", e))?;
+            if keys.sn != new_sn {
+                return Err(format!(
+                    "La clé enrôlée ({}) n'est pas celle identifiée ({})",
+                    keys.sn, new_sn
+                ));
+            }
+
+            let _ = sender.send(ApiMessage::RestorationProgress(
+                "Rattachement de la nouvelle clé à votre compte...".to_string(),
+            ));
+            let enroll = RecoveryEnrollPayload {
+                sn: keys.sn.clone(),
+                pub_sign: keys.pub_sign,
+                pub_ecdh: keys.pub_ecdh,
+            };
+            let response = api_client
+                .post(format!("{}/recovery/enroll", api_url))
+                .bearer_auth(&recovery.restore_token)
+                .json(&enroll)
+                .send()
+                .await
+                .map_err(|e| format!("Erreur Réseau: {}", e))?;
+            if !response.status().is_success() {
+                return Err(format!(
+                    "Nouvelle clé refusée par le serveur ({})",
+                    response.status()
+                ));
+            }
+
+            let mut rewraps = Vec::new();
+            for share in recovery.shares {
+                let request = RewrapRequestPayload {
+                    volume_id: share.volume_id,
+                    old_sn: recovery.old_sn.clone(),
+                    new_sn: keys.sn.clone(),
+                };
+                let result = match api_client
+                    .post(format!("{}/shares/rewrap_request", api_url))
+                    .bearer_auth(&recovery.restore_token)
+                    .json(&request)
+                    .send()
+                    .await
+                {
+                    Ok(resp) if resp.status().is_success() => Ok(()),
+                    Ok(resp) => Err(format!("refus du serveur ({})", resp.status())),
+                    Err(e) => Err(format!("erreur réseau : {}", e)),
+                };
+                rewraps.push((share.volume_name, result));
+            }
+
+            // La nouvelle clé est rattachée : l'ancienne ne doit plus rien ouvrir
+            let _ = sender.send(ApiMessage::RestorationProgress(
+                format!("Révocation de l'ancienne clé {}...", recovery.old_sn),
+            ));
+            match api_client
+                .post(format!("{}/recovery/revoke", api_url))
+                .bearer_auth(&recovery.restore_token)
+                .json(&json!({ "sn": recovery.old_sn }))
+                .send()
+                .await
+            {
+                Ok(resp) if resp.status().is_success() => {}
+                Ok(resp) => {
+                    return Err(format!(
+                        "Nouvelle clé {} rattachée, mais révocation de l'ancienne clé {} refusée ({}) : contactez un administrateur pour la révoquer",
+                        keys.sn,
+                        recovery.old_sn,
+                        resp.status()
+                    ));
+                }
+                Err(e) => {
+                    return Err(format!(
+                        "Nouvelle clé {} rattachée, mais révocation de l'ancienne clé {} impossible ({}) : contactez un administrateur pour la révoquer",
+                        keys.sn, recovery.old_sn, e
+                    ));
+                }
+            }
+
+            Ok(RestorationReport {
+                old_sn: recovery.old_sn,
+                new_sn: keys.sn,
+                rewraps,
+            })
+        }
+        .await;
+        let _ = sender.send(ApiMessage::DeviceFeedbackReset);
+        let _ = sender.send(ApiMessage::RestorationFinished(result));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recovery_codes_ignore_grouping_and_case() {
+        assert_eq!(
+            normalize_recovery_code(" abcd-efgh 1234 ").as_deref(),
+            Ok("ABCDEFGH1234")
+        );
+        assert!(normalize_recovery_code(" - ").is_err());
+        assert!(normalize_recovery_code("ABCD_EFGH").is_err());
+    }
+}
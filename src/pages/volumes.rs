@@ -8,17 +8,29 @@ use std::time::Duration;
 static SIMU_VOLUME_COUNTER: AtomicUsize = AtomicUsize::new(1);
 
 use crate::BindKeyApp;
+use crate::bindkey_device::DeviceError;
+use crate::device_owner::DeviceTarget;
+use crate::disk_link;
+use crate::firmware::Feature;
+use crate::pages::device_picker::show_device_picker;
+use crate::pages::incoming_shares::show_incoming_shares;
+use crate::pages::reconcile::show_reconcile_tab;
+use crate::partition_table;
 use crate::protocol::protocol::{
-    ApiMessage, FetchedUserInfo, LsblkOutput, PendingShare, ShareAckPayload, ShareCompletePayload,
+    ApiMessage, FetchedUserInfo, LsblkOutput, ShareCompletePayload,
     ShareRequestPayload, ShareRequestResponse, UsbDevice, VolumeInfo, VolumeInitInfo,
     VolumeInitResponse, VolumeTab,
 };
 use crate::protocol::share_protocol::{SuccessData, UsbResponse};
 use eframe::egui;
-use serialport::SerialPort;
 
 pub fn show_volumes_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
-    let usb_connected = app.usb_connected;
+    // Volumes et partages sont toujours liés à la clé de la session, pas à une autre clé branchée
+    let session_device = app.session_device();
+    // Fonctionnalités que le firmware de la clé de session ne prend pas en charge
+    let lba_gate = app.session_feature(Feature::LbaRegistration);
+    let share_gate = app.session_feature(Feature::Sharing);
+    let usb_connected = session_device.is_some();
 
     egui::ScrollArea::vertical().show(ui, |ui| {
         ui.vertical_centered(|ui| {
@@ -31,6 +43,7 @@ pub fn show_volumes_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
                 ui.selectable_value(&mut app.active_tab, VolumeTab::Dashboard, "Dashboard");
                 ui.selectable_value(&mut app.active_tab, VolumeTab::Gestion, "Gestion des Volumes");
                 ui.selectable_value(&mut app.active_tab, VolumeTab::Formatage, "Formatage clé USB");
+                ui.selectable_value(&mut app.active_tab, VolumeTab::Coherence, "Cohérence");
             });
             ui.separator();
             ui.add_space(10.0);
@@ -92,14 +105,14 @@ pub fn show_volumes_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
 
                                     let clone_email = app.share_input_email.trim().to_string();
                                     let clone_sender = app.sender.clone();
-                                    let clone_url = app.config.api_url.clone();
-                                    let clone_token = app.server_token.clone();
+                                    let clone_url = app.config.api_url();
+                                    let clone_session = app.session.clone();
                                     let clone_api_client = app.api_client.clone();
 
                                     tokio::spawn(async move {
                                         let url = format!("{}/users/search?email={}", clone_url, clone_email); 
 
-                                        match clone_api_client.get(&url).bearer_auth(clone_token).send().await {
+                                        match clone_session.send(|token| clone_api_client.get(&url).bearer_auth(token)).await {
                                             Ok(resp) => {
                                                 if resp.status().is_success() {
                                                     let text = resp.text().await.unwrap_or_default();
@@ -174,16 +187,19 @@ pub fn show_volumes_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
                                     ui.add_space(15.0);
 
                                     // On autorise plusieurs clics et on passe le nom en majuscules
-                                    if ui.button(egui::RichText::new(format!("🤝 Confirmer le partage à {}", name)).size(16.0)).clicked() {
+                                    if let Err(reason) = &share_gate {
+                                        ui.colored_label(egui::Color32::from_rgb(220, 150, 0), reason);
+                                    }
+                                    if ui.add_enabled(share_gate.is_ok(), egui::Button::new(egui::RichText::new(format!("🤝 Confirmer le partage à {}", name)).size(16.0))).clicked() {
                                         // 1. On indique que le partage est en cours pour le statut
                                         app.is_sharing_in_progress = true;
                                         app.share_pipeline_status = "⏳ Étape 1/3 : Récupération du certificat sécurisé...".to_string();
 
                                         let clone_sender = app.sender.clone();
                                         let clone_api_client = app.api_client.clone();
-                                        let clone_url = app.config.api_url.clone();
-                                        let clone_token = app.server_token.clone();
-                                        let port_name = app.current_port_name.clone();
+                                        let clone_url = app.config.api_url();
+                                        let clone_session = app.session.clone();
+                                        let device = session_device.clone();
 
                                         let local_volume_name = active_vol.name.trim().to_uppercase();
                                         let target_email = email.clone();
@@ -194,11 +210,13 @@ pub fn show_volumes_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
                                                     target_user_email: target_email,
                                                 };
 
-                                                let res_phase1 = clone_api_client
-                                                    .post(format!("{}/share_request", clone_url))
-                                                    .bearer_auth(&clone_token)
-                                                    .json(&req_payload)
-                                                    .send()
+                                                let res_phase1 = clone_session
+                                                    .send(|token| {
+                                                        clone_api_client
+                                                            .post(format!("{}/share_request", clone_url))
+                                                            .bearer_auth(token)
+                                                            .json(&req_payload)
+                                                    })
                                                     .await;
 
                                                 let target_info = match res_phase1 {
@@ -215,6 +233,10 @@ pub fn show_volumes_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
                                                     }
                                                 };
 
+                                                let Some(device) = device else {
+                                                    let _ = clone_sender.send(ApiMessage::SharePipelineStatus("Branchez la BindKey de votre session".to_string()));
+                                                    return;
+                                                };
                                                 let _ = clone_sender.send(ApiMessage::SharePipelineStatus(" Étape 2/3 : Chiffrement matériel (NE débranchez pas la clé)...".to_string()));
 
                                                 let target_sn = target_info.target_sn.clone();
@@ -223,15 +245,12 @@ pub fn show_volumes_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
                                                 let hw_target_pubkey = target_info.target_pubkey_ecdh;
                                                 let hw_target_slot = target_info.target_slot;
 
-                                                let phase2_result = tokio::task::spawn_blocking(move || {
-                                                    if port_name.is_empty() {return Err("Aucune clé connectée.".to_string());}
-                                                    let mut port = serialport::new(&port_name, 115200).timeout(std::time::Duration::from_secs(5)).open().map_err(|e| e.to_string())?;
-
-                                                    generate_hardware_share(&mut port, &volume_id, &target_sn, &hw_target_pubkey, hw_target_slot)
-                                                }).await.unwrap_or(Err("Crash du thread matériel".to_string()));
+                                                let phase2_result = device.run("Chiffrement du partage", move |d| {
+                                                    d.wrap_share(&volume_id, &target_sn, &hw_target_pubkey, hw_target_slot)
+                                                }).await;
 
-                                                let (bk_sn,wrapped_key) = match phase2_result {
-                                                    Ok(data) => data,
+                                                let (bk_sn,wrapped_key,share_signature) = match phase2_result {
+                                                    Ok(share) => (share.source_sn, share.wrapped, share.signature),
                                                     Err(e) => {
                                                         let _ = clone_sender.send(ApiMessage::SharePipelineStatus(format!("Refus matériel: {}", e)));
                                                         return;
@@ -245,13 +264,16 @@ pub fn show_volumes_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
                                                     target_sn: target_info.target_sn,
                                                     volume_id: target_info.volume_id,
                                                     wrapped: wrapped_key,
+                                                    signature: share_signature,
                                                 };
 
-                                                match clone_api_client
-                                                    .post(format!("{}/share_complete", clone_url))
-                                                    .bearer_auth(clone_token)
-                                                    .json(&complete_payload)
-                                                    .send()
+                                                match clone_session
+                                                    .send(|token| {
+                                                        clone_api_client
+                                                            .post(format!("{}/share_complete", clone_url))
+                                                            .bearer_auth(token)
+                                                            .json(&complete_payload)
+                                                    })
                                                     .await
                                                 {
                                                     Ok (resp) if resp.status().is_success() => {
@@ -448,81 +470,7 @@ pub fn show_volumes_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
                         }
                         ui.add_space(20.0);
 
-                        if ui.button("Vérifier les partages entrant").clicked() {
-                            let clone_sender = app.sender.clone();
-                            let clone_api_client = app.api_client.clone();
-                            let clone_url = app.config.api_url.clone();
-                            let clone_token = app.server_token.clone();
-                            let port_name = app.current_port_name.clone();
-
-                            let local_sn = app.local_bindkey_sn.clone().unwrap_or_default();
-
-                            if local_sn.is_empty() {
-                                let _ = clone_sender.send(ApiMessage::VolumeDashboardStatus("Veuillez vous enrôler/connecter d'abord.".to_string()));
-                            } else {
-                                let _ = clone_sender.send(ApiMessage::VolumeDashboardStatus("Recherche de partage en cours...".to_string()));
-
-                                tokio::spawn(async move {
-
-                                    let get_url = format!("{}/shares/pending?target_sn={}", clone_url, local_sn);
-
-                                    let pending_res = clone_api_client.get(&get_url).bearer_auth(&clone_token).send().await;
-                                    let pending_share: Vec<PendingShare> = match pending_res {
-                                        Ok(resp) if resp.status().is_success() => resp.json().await.unwrap_or_else(|_| vec![]),
-                                        _ => {
-                                            let _ = clone_sender.send(ApiMessage::VolumeDashboardStatus("Erreur de récupération du volume.".to_string()));
-                                            return;
-                                        }
-                                    };
-                                    if pending_share.is_empty() {
-                                        let _ = clone_sender.send(ApiMessage::VolumeDashboardStatus("Aucun nouveau partage en attente.".to_string()));
-                                        return;
-                                    }
-
-                                    let _ = clone_sender.send(ApiMessage::VolumeDashboardStatus(format!("Installation de {} partage", pending_share.len())));
-                                    let mut success_count = 0;
-
-                                    for share in pending_share{
-                                        let hw_pubkey = share.source_pubkey_ecdh.clone();
-                                        let hw_wrapped = share.wrapped.clone();
-                                        let hw_slot = share.slot.clone();
-                                        let port_name_clone = port_name.clone();
-
-                                        let hw_result = tokio::task::spawn_blocking(move || {
-                                            if port_name_clone.is_empty() {return Err("Clé Débranchée.".to_string()); }
-                                            let mut port = serialport::new(&port_name_clone, 115200).timeout(std::time::Duration::from_secs(3)).open().map_err(|e| e.to_string())?;
-                                            process_hardware_recv_share(&mut port, hw_slot, &hw_pubkey, &hw_wrapped)
-                                        }).await.unwrap_or(Err("Crash thread matériel".to_string()));
-
-                                        match hw_result {
-                                            Ok(_) => {
-                                                let ack_payload = ShareAckPayload {share_id: share.share_id.clone() };
-                                                let ack_res = clone_api_client
-                                                    .post(format!("{}/share_acknowledged", clone_url))
-                                                    .bearer_auth(&clone_token)
-                                                    .json(&ack_payload)
-                                                    .send()
-                                                    .await;
-
-                                                if let Ok(resp) = ack_res {
-                                                    if resp.status().is_success() {
-                                                        success_count += 1;
-                                                    }
-                                                }
-                                            },
-                                            Err(e) => {
-                                                println!("Erreur matérielle sur le share {}: {}", share.share_id, e);
-                                            }
-                                        }
-                                    }
-                                    if success_count > 0 {
-                                        let _ = clone_sender.send(ApiMessage::VolumeDashboardStatus(format!("{} partage(s) installé(s) ! REDÉMARREZ votre BindKey avec le disque pour les activer.", success_count)));
-                                    } else {
-                                        let _ = clone_sender.send(ApiMessage::VolumeDashboardStatus("Échecde l'installation matérielle des partages.".to_string()));
-                                    }
-                                });
-                            }
-                        }
+                        show_incoming_shares(app, ui);
 
                         if !app.dashboard_status.is_empty() {
                             let color = if app.dashboard_status.contains("Erreur") || app.dashboard_status.contains("Refus") || app.dashboard_status.contains("❌") {
@@ -568,6 +516,7 @@ pub fn show_volumes_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
                                 let ouput_str = String::from_utf8_lossy(&output.stdout);
                                 let parsed: LsblkOutput = serde_json::from_str(&ouput_str).unwrap_or(LsblkOutput { blockdevices: vec![] });
 
+                                let session_disk = app.session_disk();
                                 let mut devices = Vec::new();
                                 let mut target_total_gb = 0.0;
                                 let mut target_free_gb = 0.0;
@@ -603,9 +552,13 @@ pub fn show_volumes_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
                                                 disk.size.saturating_sub(used_bytes)
                                             };
 
-                                            target_total_gb = (disk.size as f64) / (1024.0 * 1024.0 * 1024.0);
-                                            target_free_gb = (free_bytes as f64) / (1024.0 * 1024.0 * 1024.0);
-                                            target_name = model.trim().to_string();
+                                            // Les chiffres affichés sont ceux du disque de la clé de session
+                                            let path = format!("/dev/{}", disk.name);
+                                            if session_disk.as_ref() == Some(&path) {
+                                                target_total_gb = (disk.size as f64) / (1024.0 * 1024.0 * 1024.0);
+                                                target_free_gb = (free_bytes as f64) / (1024.0 * 1024.0 * 1024.0);
+                                                target_name = model.trim().to_string();
+                                            }
 
                                             devices.push(UsbDevice {
                                                 path: format!("/dev/{}", disk.name),
@@ -622,9 +575,15 @@ pub fn show_volumes_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
                                     app.volume_status = "Aucune clé USB détectée par le système.".to_string();
                                     return;
                                 }
+                                if session_disk.is_none() {
+                                    app.volume_status = "❌ Aucun disque n'est relié au port série de votre BindKey.".to_string();
+                                    return;
+                                }
 
                                 let clone_sender = app.sender.clone();
-                                let clone_port_name = app.current_port_name.clone();
+                                let Some(device) = session_device.clone() else {
+                                    return;
+                                };
                                 let bypass_usb = true;
 
                                 let clone_total_gb = (target_total_gb * 100.0).round() / 100.0;
@@ -642,30 +601,19 @@ pub fn show_volumes_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
                                             device_available_size: clone_free_gb
                                         }));
                                     } else {
-                                        if !clone_port_name.is_empty() {
-                                            match serialport::new(&clone_port_name, 115200).timeout(Duration::from_secs(2)).open() {
-                                                Ok(mut port) => {
-                                                    let _ = port.write_data_terminal_ready(true);
-                                                    let _ = port.write_request_to_send(true);
-                                                    tokio::time::sleep(Duration::from_secs(2)).await;
-
-                                                    match crate::usb_service::send_text_command(&mut *port, "getdevice") {
-                                                        Ok(map) => {
-                                                            let name_str = map.get("DN").cloned().unwrap_or(clone_device_name);
-                                                            resultat_usb = Ok(UsbResponse::Success(SuccessData::DeviceInfo {
-                                                                device_name: name_str,
-                                                                device_size: clone_total_gb,
-                                                                device_available_size: clone_free_gb
-                                                            }));
-                                                        },
-                                                        Err(e) => resultat_usb = Err(format!("Echec communication série: {}", e))
-                                                    }
-                                                }
-                                                Err(e) => resultat_usb = Err(format!("Erreur d'ouverture port: {}", e))
+                                        resultat_usb = device.run("Lecture du nom de la clé", move |d| {
+                                            match d.read_device_name() {
+                                                Err(DeviceError::MissingField(_)) => Ok(clone_device_name),
+                                                other => other,
                                             }
-                                        } else {
-                                            resultat_usb = Err("Aucune Bindkey détectée".to_string());
-                                        }
+                                        })
+                                        .await
+                                        .map(|name_str| UsbResponse::Success(SuccessData::DeviceInfo {
+                                            device_name: name_str,
+                                            device_size: clone_total_gb,
+                                            device_available_size: clone_free_gb
+                                        }))
+                                        .map_err(|e| format!("Echec communication série: {}", e));
                                     }
 
                                     match resultat_usb {
@@ -689,7 +637,15 @@ pub fn show_volumes_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
                     ui.add_space(40.0);
                     ui.add_space(20.0);
 
-                    if app.available_devices.len() > 1 {
+                    let session_disk = app.session_disk();
+                    let linked_disk = app.available_devices.iter().position(|d| Some(&d.path) == session_disk.as_ref());
+                    let session_conflict = app
+                        .attached_bindkeys
+                        .iter()
+                        .find(|k| k.sn.is_some() && k.sn == app.local_bindkey_sn)
+                        .and_then(|k| k.disk_conflict.clone());
+
+                    if !app.available_devices.is_empty() && linked_disk.is_none() {
                         ui.add_space(20.0);
                         let frame_style = egui::Frame::none()
                             .fill(egui::Color32::from_rgba_unmultiplied(255, 0, 0, 20))
@@ -703,15 +659,19 @@ pub fn show_volumes_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
                                 ui.heading("Sécurité stricte activée");
                             });
                             ui.add_space(10.0);
-                            ui.label(
-                            egui::RichText::new("Plusieurs périphériques USB ont été détectés.")
-                                    .color(egui::Color32::RED)
-                                    .strong(),
-                            );
-                            ui.label("Pour éviter toute erreur de formatage ou de chiffrement, veuillez débrancher les autres clés et ne conserver que la BindKey cible, puis relancez l'analyse.");
+                            if let Some(conflict) = &session_conflict {
+                                ui.label(egui::RichText::new(conflict).color(egui::Color32::RED).strong());
+                            } else {
+                                ui.label(
+                                egui::RichText::new("Aucun des disques détectés n'est relié à votre BindKey.")
+                                        .color(egui::Color32::RED)
+                                        .strong(),
+                                );
+                                ui.label("Le disque doit se trouver derrière le même périphérique USB que le port série de la clé de session. Rebranchez votre BindKey puis relancez l'analyse.");
+                            }
                         });
                     }
-                    else if app.available_devices.len() == 1 {
+                    else if let Some(disk_index) = linked_disk {
                         if !app.device_name.is_empty() {
                             frame_style.show(ui, |ui| {
                                 ui.set_width(ui.available_width());
@@ -752,7 +712,7 @@ pub fn show_volumes_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
                         if !app.device_name.is_empty() && app.device_available_space == 0.0 && app.device_size > 0.0 {
                             ui.add_space(10.0);
 
-                            let has_partitions = !app.available_devices.is_empty() && !app.available_devices[0].partitions.is_empty();
+                            let has_partitions = !app.available_devices[disk_index].partitions.is_empty();
 
                             if !has_partitions {
                                 ui.label(
@@ -800,7 +760,10 @@ pub fn show_volumes_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
 
                                 ui.add_space(20.0);
 
-                                ui.add_enabled_ui(usb_connected, |ui| {
+                                if let Err(reason) = &lba_gate {
+                                    ui.colored_label(egui::Color32::from_rgb(220, 150, 0), reason);
+                                }
+                                ui.add_enabled_ui(usb_connected && lba_gate.is_ok(), |ui| {
                                     let btn_create = egui::Button::new(" Créer le volume chiffré")
                                         .min_size(egui::vec2(250.0, 45.0));
 
@@ -816,11 +779,17 @@ pub fn show_volumes_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
                                         let clone_sender = app.sender.clone();
                                         let clone_volume_name = app.volume_created_name.trim().to_uppercase();
                                         let clone_volume_size = app.volume_created_size;
-                                        let clone_url = app.config.api_url.clone();
-                                        let clone_auth_token = app.server_token.clone();
-                                        let clone_port_name = app.current_port_name.clone();
+                                        let clone_url = app.config.api_url();
+                                        let clone_session = app.session.clone();
+                                        let Some(device) = session_device.clone() else {
+                                            return;
+                                        };
                                         let clone_api_client = app.api_client.clone();
-                                        let clone_device_path = app.available_devices[0].path.clone();
+                                        let clone_device_path = app.available_devices[disk_index].path.clone();
+                                        if !disk_link::disk_behind_port(device.port_name(), &clone_device_path, &app.config) {
+                                            app.volume_status = format!("❌ Refus : {} n'est pas derrière votre BindKey", clone_device_path);
+                                            return;
+                                        }
 
                                         tokio::spawn(async move {
 
@@ -829,7 +798,7 @@ pub fn show_volumes_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
                                             name: clone_volume_name.clone(),
                                         };
 
-                                        let resultat = clone_api_client.post(url).json(&payload).bearer_auth(clone_auth_token).send().await;
+                                        let resultat = clone_session.send(|token| clone_api_client.post(&url).json(&payload).bearer_auth(token)).await;
 
                                         // On assigne le résultat à une variable et on attend un Uuid
                                         let mon_id_serveur: String = match resultat {
@@ -878,7 +847,7 @@ pub fn show_volumes_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
                                             let device_path_for_thread = clone_device_path.clone();
                                             let volume_name_for_thread = clone_volume_name.clone();
                                             let volume_id_for_thread = mon_id_serveur.clone();
-                                            let port_name_for_thread = clone_port_name.clone();
+                                            let device_for_thread = device.clone();
 
                                             // On utilise spawn_blocking car create_and_format_partition exécute des commandes OS synchrones
                                             let partition_result = tokio::task::spawn_blocking(move || {
@@ -887,7 +856,7 @@ pub fn show_volumes_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
                                                     clone_volume_size as f64,
                                                     &volume_name_for_thread,
                                                     &volume_id_for_thread,
-                                                    &port_name_for_thread
+                                                    &device_for_thread
                                                 )
                                             }).await.unwrap_or_else(|e| Err(format!("Erreur critique du thread OS : {}", e)));
 
@@ -940,9 +909,28 @@ pub fn show_volumes_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
                 VolumeTab::Formatage => {
                     ui.add_space(20.0);
 
-                    if app.available_devices.len() == 1 {
-                        let device = &app.available_devices[0];
-
+                    ui.horizontal(|ui| {
+                        ui.label("BindKey à réinitialiser : ");
+                        show_device_picker(
+                            ui,
+                            "format_device",
+                            &app.attached_bindkeys,
+                            app.local_bindkey_sn.as_deref(),
+                            &mut app.format_port,
+                        );
+                    });
+                    ui.add_space(10.0);
+
+                    // Seul le disque relié (sysfs) à la clé choisie peut être formaté
+                    let format_key = app
+                        .attached_bindkeys
+                        .iter()
+                        .find(|k| Some(&k.port_name) == app.format_port.as_ref());
+                    let format_disk = format_key.and_then(|k| k.disk_path.clone());
+                    let format_conflict = format_key.and_then(|k| k.disk_conflict.clone());
+                    let linked_device = app.available_devices.iter().find(|d| Some(&d.path) == format_disk.as_ref());
+
+                    if let Some(device) = linked_device {
                         ui.horizontal(|ui| {
                             ui.label("Périphérique cible : ");
                             ui.strong(&device.display_name);
@@ -950,21 +938,32 @@ pub fn show_volumes_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
 
                         ui.add_space(20.0);
 
+                        let bindkey_port = app.selected_device(&app.format_port).map(|d| d.port_name().to_string());
                         let format_button = egui::Button::new("Réinitialiser la clé à zéro");
+                        let format_gate = app.feature_for_port(bindkey_port.as_deref(), Feature::Format);
+                        if let Err(reason) = &format_gate {
+                            ui.colored_label(egui::Color32::from_rgb(220, 150, 0), reason);
+                        }
 
-                        if ui.add(format_button).clicked() {
+                        if ui.add_enabled(bindkey_port.is_some() && format_gate.is_ok(), format_button).clicked() {
                             app.is_loading = true;
                             let volume_names: Vec<String> = app.dashboard_volumes.iter().map(|v|v.name.clone()).collect();
                             let _ = app.sender.send(ApiMessage::StartFormatBindKey {
+                                bindkey_port: bindkey_port.unwrap_or_default(),
                                 device_path: device.path.clone(),
                                 partitions: device.partitions.clone(),
-                                port_name: app.current_port_name.clone(),
                                 volume_names,
                             });
                         }
                     }
-                    else if app.available_devices.len() > 1 {
-                        ui.colored_label(egui::Color32::RED, "Sécurité : Plusieurs clés détectées. Veuillez n'en brancher qu'une seule pour le formatage.");
+                    else if let Some(conflict) = format_conflict {
+                        ui.colored_label(egui::Color32::RED, format!("Sécurité : {}", conflict));
+                    }
+                    else if format_disk.is_none() && app.format_port.is_some() {
+                        ui.colored_label(egui::Color32::RED, "Sécurité : aucun disque n'est relié au port série de cette BindKey.");
+                    }
+                    else if !app.available_devices.is_empty() {
+                        ui.label("Le disque de cette BindKey n'a pas encore été analysé : lancez l'analyse dans l'onglet 'Gestion des volumes'.");
                     }
                     else {
                         ui.label("Branchez une BindKey et lancez l'analyse dans l'onglet 'Gestion des volumes' pour la formater");
@@ -982,6 +981,13 @@ pub fn show_volumes_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
                         ui.colored_label(color, &app.formatage_status);
                     }
                 },
+
+                // =================================================================
+                // ONGLET 4 : COHÉRENCE CLÉ / PARTITIONS / SERVEUR
+                // =================================================================
+                VolumeTab::Coherence => {
+                    show_reconcile_tab(app, ui);
+                },
             }
         });
     });
@@ -991,13 +997,13 @@ pub fn show_volumes_page(app: &mut BindKeyApp, ui: &mut egui::Ui) {
 // FONCTIONS UTILITAIRES SYSTÈME
 // =================================================================
 
-// ⚠️ N'oublie pas d'ajouter port_name dans les paramètres lors de l'appel !
+// Appelée depuis spawn_blocking : les échanges USB passent par run_blocking
 pub fn create_and_format_partition(
     device_path: &str,
     size_gb: f64,
     volume_name: &str,
     volume_id: &str,
-    port_name: &str,
+    device: &DeviceTarget,
 ) -> Result<(u64, u64, String), String> {
     // =========================================================
     // FIX 1 : FORCER LA LECTURE DU CACHE AVANT LE CALCUL (LBA)
@@ -1029,36 +1035,17 @@ pub fn create_and_format_partition(
         stdout
     );
 
-    let mut disk_size_sectors: u64 = 0;
-    let mut occupied: Vec<(u64, u64)> = Vec::new();
-
-    // A. On scanne la clé pour lister toutes les partitions existantes
-    for line in stdout.lines() {
-        let parts: Vec<&str> = line.split(':').collect();
-
-        // La ligne du disque (ex: /dev/sdb:15633408s:scsi:512:512:msdos:...)
-        if line.starts_with("/dev/") && parts.len() >= 2 {
-            if let Ok(size) = parts[1].trim_end_matches('s').parse::<u64>() {
-                disk_size_sectors = size;
-            }
-        }
-        // Les lignes de partitions (ex: 1:2048s:2099199s:...)
-        else if parts.len() >= 4 && parts[0].parse::<u32>().is_ok() {
-            let s = parts[1].trim_end_matches('s').parse::<u64>().unwrap_or(0);
-            let e = parts[2].trim_end_matches('s').parse::<u64>().unwrap_or(0);
-            occupied.push((s, e));
-        }
-    }
-
-    // B. On s'assure que les partitions sont triées par secteur de début
-    occupied.sort_by_key(|&(s, _)| s);
+    // A. On scanne la clé pour lister toutes les partitions existantes, triées par secteur de début
+    let table = partition_table::parse_parted_print(&stdout);
+    let disk_size_sectors = table.disk_size_sectors;
+    let occupied: Vec<(u64, u64)> = table.partitions.iter().map(|p| (p.start, p.end)).collect();
 
-    // C. Calcul de la taille cible en secteurs (alignée sur 1 Mo / 2048 secteurs)
+    // B. Calcul de la taille cible en secteurs (alignée sur 1 Mo / 2048 secteurs)
     let target_sectors = (size_gb * 1024.0 * 1024.0 * 1024.0 / 512.0) as u64;
     let mut final_target = target_sectors;
     final_target -= final_target % 2048;
 
-    // D. Recherche du premier trou (gap) disponible
+    // C. Recherche du premier trou (gap) disponible
     let mut start_sector: u64 = 0;
     let mut current_search_start: u64 = 2048; // On commence toujours à 2048 minimum
 
@@ -1078,7 +1065,7 @@ pub fn create_and_format_partition(
         }
     }
 
-    // E. Si on n'a pas trouvé de trou entre les partitions, on regarde après la dernière
+    // D. Si on n'a pas trouvé de trou entre les partitions, on regarde après la dernière
     if start_sector == 0 {
         if disk_size_sectors > current_search_start {
             let gap_after = disk_size_sectors - current_search_start;
@@ -1098,51 +1085,42 @@ pub fn create_and_format_partition(
     // 2. COMMUNICATION USB (LBA -> BindKey)
     // =========================================================
     {
-        println!("Ouverture du port USB pour envoyer les LBA...");
-        let mut port = serialport::new(port_name, 115200)
-            .timeout(Duration::from_secs(5))
-            .open()
-            .map_err(|e| format!("Impossible d'ouvrir le port USB : {}", e))?;
-
-        let _ = port.write_data_terminal_ready(true);
-        let _ = port.write_request_to_send(true);
-        thread::sleep(Duration::from_millis(500));
-
-        let cmd_sectors = format!(
-            "volume_name={}\nvolume_id={}\nlba_start={}\nlba_end={}\n",
-            volume_name, volume_id, start, end
-        );
-
-        let mut is_ready = false;
-        let mut tentatives = 0;
-
-        while !is_ready && tentatives < 5 {
-            match crate::usb_service::send_text_command(&mut *port, &cmd_sectors) {
-                Ok(map) => {
-                    if map
-                        .get("STATUS")
-                        .map(|val| val.contains("OK"))
-                        .unwrap_or(false)
-                    {
-                        is_ready = true;
-                    } else {
+        println!("Envoi des LBA à la BindKey...");
+        let lba_name = volume_name.to_string();
+        let lba_id = volume_id.to_string();
+
+        // Les 5 tentatives forment un seul job : aucune autre commande ne s'intercale.
+        // Un refus de la clé (occupée) se retente ; une clé muette a déjà épuisé son délai.
+        let is_ready = device.run_blocking("Enregistrement des secteurs LBA", move |d| {
+            let mut tentatives = 0;
+            loop {
+                match d.register_lba_range(&lba_name, &lba_id, start, end) {
+                    Ok(()) => return Ok(()),
+                    Err(e @ (DeviceError::Io(_) | DeviceError::Cancelled | DeviceError::Disconnected | DeviceError::Timeout)) => return Err(e),
+                    Err(e) if tentatives >= 4 => return Err(e),
+                    Err(_) => {
                         tentatives += 1;
-                        thread::sleep(Duration::from_millis(1000));
+                        d.pause(Duration::from_millis(1000))?;
                     }
                 }
-                Err(_) => {
-                    tentatives += 1;
-                    thread::sleep(Duration::from_millis(1000));
-                }
             }
-        }
+        });
 
-        if !is_ready {
-            return Err(
-                "La BindKey n'a pas confirmé l'enregistrement des secteurs LBA.".to_string(),
-            );
+        match is_ready {
+            Ok(()) => {}
+            Err(e @ (DeviceError::NotConnected | DeviceError::PortOpen(_))) => {
+                return Err(format!("Impossible d'ouvrir le port USB : {}", e));
+            }
+            Err(DeviceError::Cancelled) => {
+                return Err("Création annulée : aucun secteur n'a été enregistré.".to_string());
+            }
+            Err(_) => {
+                return Err(
+                    "La BindKey n'a pas confirmé l'enregistrement des secteurs LBA.".to_string(),
+                );
+            }
         }
-    } // Le port USB se ferme ici
+    }
 
     // =========================================================
     // 🛡️ LE BOUCLIER "MEDIUM NOT PRESENT"
@@ -1316,7 +1294,7 @@ pub fn force_format(device_path: &str, partitions: &Vec<String>) -> Result<(), S
 pub fn rollback_physical_volume(
     device_path: &str,
     partition_number: &str,
-    port_name: &str,
+    device: &DeviceTarget,
     volume_id: &str,
 ) {
     println!("DÉCLENCHEMENT DU ROLLBACK pour le volume {}", volume_id);
@@ -1334,16 +1312,8 @@ pub fn rollback_physical_volume(
         .output();
 
     // 2. Dire à la BindKey d'oublier les secteurs alloués pour cet ID
-    if !port_name.is_empty() {
-        if let Ok(mut port) = serialport::new(port_name, 115200)
-            .timeout(Duration::from_secs(2))
-            .open()
-        {
-            // TODO: Ajuste cette commande selon la syntaxe de ton firmware pour supprimer un volume
-            let cmd_delete = format!("delete_volume={}\n", volume_id);
-            let _ = crate::usb_service::send_text_command(&mut *port, &cmd_delete);
-        }
-    }
+    let rollback_id = volume_id.to_string();
+    let _ = device.run_blocking("Annulation du volume", move |d| d.delete_volume(&rollback_id));
 
     // 3. Demander à Linux de supprimer la partition de la table
     let _ = Command::new("/usr/bin/pkexec")
@@ -1363,62 +1333,6 @@ pub fn rollback_physical_volume(
     let _ = Command::new("/usr/bin/udevadm").arg("settle").output();
 }
 
-pub fn generate_hardware_share(
-    port: &mut Box<dyn SerialPort>,
-    volume_id: &str,
-    target_sn: &str,
-    target_pubkey: &str,
-    target_slot: u16,
-) -> Result<(String, String), String> {
-    let share_commands = format!(
-        "share_volume_id={}\nshare_target_sn={}\nshare_target_pubkey={}\nshare_target_slot={}\n",
-        volume_id, target_sn, target_pubkey, target_slot
-    );
-
-    match crate::usb_service::send_text_command(&mut **port, &share_commands) {
-        Ok(map) => {
-            if let (Some(sn), Some(wrapped)) = (map.get("SN"), map.get("WRAPPED")) {
-                Ok((sn.clone(), wrapped.clone()))
-            } else if let Some(err_reason) = map.get("ERR") {
-                Err(format!("Refus matériel: {}", err_reason))
-            } else {
-                Err("Réponse incomplète (SN ou WRAPPED manquant).".to_string())
-            }
-        }
-        Err(e) => Err(format!("Erreur de communication USB: {}", e)),
-    }
-}
-
-pub fn process_hardware_recv_share(
-    port: &mut Box<dyn SerialPort>,
-    slot: u16,
-    source_pubkey: &str,
-    wrapped: &str,
-) -> Result<(), String> {
-    let commands = format!(
-        "recv_share_slot={}\nrecv_share_source_pubkey={}\nrecv_share_wrapped={}\n",
-        slot, source_pubkey, wrapped
-    );
-
-    match crate::usb_service::send_text_command(&mut **port, &commands) {
-        Ok(map) => {
-            if let Some(status) = map.get("STATUS") {
-                if status == "OK" {
-                    return Ok(());
-                }
-            } else if let Some(err) = map.get("ERR") {
-                return Err(format!("Erreur puce ATTEC: {}", err));
-            }
-            if map.contains_key("OK") {
-                return Ok(());
-            }
-
-            Err("Réponse inattendue de la BindKey".to_string())
-        }
-        Err(e) => Err(format!("Erreur de communication USB: {}", e)),
-    }
-}
-
 /*
 // =========================================================
     // 3. CRÉATION PHYSIQUE (Maintenant que la puce écoute)
@@ -0,0 +1,137 @@
+use std::process::Command;
+
+// ==========================================
+// TABLE DE PARTITIONS (sortie machine de parted)
+// ==========================================
+//
+// `parted -s -m <disque> unit s print` : une ligne pour le disque
+// (/dev/sdb:15633408s:scsi:512:512:msdos:...) puis une ligne par partition
+// (1:2048s:2099199s:2097152s:fat32::lba;).
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Partition {
+    pub number: u32,
+    pub start: u64,
+    pub end: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PartitionTable {
+    pub disk_size_sectors: u64,
+    // Triées par secteur de début
+    pub partitions: Vec<Partition>,
+}
+
+pub fn parse_parted_print(stdout: &str) -> PartitionTable {
+    let mut table = PartitionTable::default();
+
+    for line in stdout.lines() {
+        let parts: Vec<&str> = line.split(':').collect();
+
+        if line.starts_with("/dev/") && parts.len() >= 2 {
+            if let Ok(size) = parts[1].trim_end_matches('s').parse::<u64>() {
+                table.disk_size_sectors = size;
+            }
+        } else if parts.len() >= 4 {
+            let Ok(number) = parts[0].parse::<u32>() else {
+                continue;
+            };
+            table.partitions.push(Partition {
+                number,
+                start: parts[1].trim_end_matches('s').parse().unwrap_or(0),
+                end: parts[2].trim_end_matches('s').parse().unwrap_or(0),
+            });
+        }
+    }
+
+    table.partitions.sort_by_key(|p| p.start);
+    table
+}
+
+// Bloquant (pkexec) : à appeler depuis spawn_blocking
+pub fn read_partition_table(device_path: &str) -> Result<PartitionTable, String> {
+    let output = Command::new("/usr/bin/pkexec")
+        .args(["parted", "-s", "-m", device_path, "unit", "s", "print"])
+        .output()
+        .map_err(|e| format!("Erreur parted: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "parted n'a pas pu lire {} : {}",
+            device_path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(parse_parted_print(&String::from_utf8_lossy(&output.stdout)))
+}
+
+// /dev/sdb + 2 -> /dev/sdb2 ; /dev/nvme0n1 + 2 -> /dev/nvme0n1p2
+pub fn partition_path(device_path: &str, number: u32) -> String {
+    if device_path.chars().last().unwrap_or('a').is_ascii_digit() {
+        format!("{}p{}", device_path, number)
+    } else {
+        format!("{}{}", device_path, number)
+    }
+}
+
+// Démonte puis retire la partition de la table ; bloquant
+pub fn remove_partition(device_path: &str, number: u32) -> Result<(), String> {
+    let _ = Command::new("/usr/bin/udisksctl")
+        .args(["unmount", "-f", "-b", &partition_path(device_path, number)])
+        .output();
+
+    let status = Command::new("/usr/bin/pkexec")
+        .args([
+            "/usr/sbin/parted",
+            "-s",
+            device_path,
+            "rm",
+            &number.to_string(),
+        ])
+        .status()
+        .map_err(|e| format!("Impossible de lancer parted: {}", e))?;
+    if !status.success() {
+        return Err(format!(
+            "parted a refusé de supprimer la partition {} de {}",
+            number, device_path
+        ));
+    }
+
+    let _ = Command::new("/usr/sbin/partprobe")
+        .arg(device_path)
+        .output();
+    let _ = Command::new("/usr/bin/udevadm").arg("settle").output();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_disk_and_partitions_in_order() {
+        let stdout = "BYT;\n\
+            /dev/sdb:15633408s:scsi:512:512:msdos:Generic Flash Disk:;\n\
+            2:4196352s:6293503s:2097152s:fat32::lba;\n\
+            1:2048s:2099199s:2097152s:fat32::lba;\n";
+        let table = parse_parted_print(stdout);
+
+        assert_eq!(table.disk_size_sectors, 15_633_408);
+        assert_eq!(
+            table.partitions,
+            vec![
+                Partition {
+                    number: 1,
+                    start: 2048,
+                    end: 2_099_199
+                },
+                Partition {
+                    number: 2,
+                    start: 4_196_352,
+                    end: 6_293_503
+                },
+            ]
+        );
+        assert_eq!(partition_path("/dev/nvme0n1", 2), "/dev/nvme0n1p2");
+    }
+}
@@ -0,0 +1,416 @@
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use p256::PublicKey;
+use p256::ecdh::EphemeralSecret;
+use p256::ecdsa::signature::Verifier;
+use p256::ecdsa::{Signature, VerifyingKey};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+// ==========================================
+// PAQUETS HORS LIGNE (MAINTENANCE AIR-GAPPED)
+// ==========================================
+//
+// Fichier .bkpkg : "BKPK" suivi d'un Bundle encodé en postcard. Le manifeste (JSON) décrit
+// l'origine et l'empreinte de chaque fichier ; la BindKey émettrice signe son SHA-256.
+// Chiffré pour un destinataire, le contenu est scellé par une clé AES aléatoire, elle-même
+// emballée pour la clé ECDH de la BindKey du destinataire (ECDH éphémère) : seule cette clé
+// peut la déballer, sur la machine cible, sans réseau.
+
+pub const BUNDLE_MAGIC: &[u8; 4] = b"BKPK";
+pub const BUNDLE_EXTENSION: &str = "bkpkg";
+const BUNDLE_FORMAT: u32 = 1;
+
+// Contextes partagés avec le firmware (PayloadSign / PayloadUnwrap)
+pub const PAYLOAD_SIGNING_CONTEXT: &[u8] = b"bindkey-payload-v1";
+pub const PAYLOAD_KEK_CONTEXT: &[u8] = b"bindkey-payload-kek-v1";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ManifestFile {
+    pub name: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Origin {
+    pub sn: String,
+    pub user_email: String,
+    pub hostname: String,
+    pub created_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Recipient {
+    pub sn: String,
+    pub ephemeral_pubkey: String,
+    // nonce || AES-GCM(clé du contenu), associé au SN du destinataire
+    pub wrapped_key: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PayloadManifest {
+    pub format: u32,
+    pub description: String,
+    pub origin: Origin,
+    pub files: Vec<ManifestFile>,
+    // Empreinte du contenu tel qu'il est stocké (chiffré le cas échéant)
+    pub content_sha256: String,
+    pub recipient: Option<Recipient>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Bundle {
+    // Texte exact dont l'empreinte est signée
+    manifest: String,
+    signature: String,
+    // Clé publique de signature de la BindKey émettrice (SEC1, hex), renvoyée avec la signature
+    signer_pubkey: String,
+    content: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PayloadFile {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+// Message signé par la clé : contexte || SHA-256 du manifeste
+pub fn signed_message(digest_hex: &str) -> Result<Vec<u8>, String> {
+    let digest = hex::decode(digest_hex).map_err(|_| "Empreinte invalide".to_string())?;
+    let mut message = PAYLOAD_SIGNING_CONTEXT.to_vec();
+    message.extend_from_slice(&digest);
+    Ok(message)
+}
+
+pub fn payload_kek(shared_secret: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(PAYLOAD_KEK_CONTEXT);
+    hasher.update(shared_secret);
+    hasher.finalize().into()
+}
+
+fn seal(key: &[u8; 32], plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, String> {
+    let mut nonce = [0u8; 12];
+    OsRng.fill_bytes(&mut nonce);
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(|e| e.to_string())?;
+    let ciphertext = cipher
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| "Chiffrement impossible".to_string())?;
+
+    let mut sealed = nonce.to_vec();
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+fn open_sealed(key: &[u8; 32], sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>, String> {
+    if sealed.len() < 12 {
+        return Err("Contenu chiffré tronqué".to_string());
+    }
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(|e| e.to_string())?;
+    cipher
+        .decrypt(
+            Nonce::from_slice(&sealed[..12]),
+            Payload {
+                msg: &sealed[12..],
+                aad,
+            },
+        )
+        .map_err(|_| "Déchiffrement refusé : contenu altéré ou mauvaise clé".to_string())
+}
+
+// ==========================================
+// CRÉATION
+// ==========================================
+
+// Paquet prêt à être signé par la BindKey émettrice
+pub struct PreparedBundle {
+    manifest: String,
+    content: Vec<u8>,
+}
+
+pub fn prepare(
+    files: Vec<PayloadFile>,
+    description: &str,
+    origin: Origin,
+    // (SN, clé publique ECDH) de la BindKey destinataire
+    recipient: Option<(String, String)>,
+) -> Result<PreparedBundle, String> {
+    if files.is_empty() {
+        return Err("Aucun fichier à empaqueter".to_string());
+    }
+
+    let listed: Vec<ManifestFile> = files
+        .iter()
+        .map(|f| ManifestFile {
+            name: f.name.clone(),
+            size: f.data.len() as u64,
+            sha256: sha256_hex(&f.data),
+        })
+        .collect();
+    let plain = postcard::to_stdvec(&files).map_err(|e| format!("Encodage du contenu : {}", e))?;
+
+    let (content, recipient) = match recipient {
+        None => (plain, None),
+        Some((sn, pub_ecdh)) => {
+            let peer = hex::decode(&pub_ecdh)
+                .ok()
+                .and_then(|bytes| PublicKey::from_sec1_bytes(&bytes).ok())
+                .ok_or("Clé publique du destinataire invalide")?;
+
+            let mut content_key = [0u8; 32];
+            OsRng.fill_bytes(&mut content_key);
+            let content = seal(&content_key, &plain, PAYLOAD_SIGNING_CONTEXT)?;
+
+            let ephemeral = EphemeralSecret::random(&mut OsRng);
+            let shared = ephemeral.diffie_hellman(&peer);
+            let kek = payload_kek(shared.raw_secret_bytes());
+            let wrapped = seal(&kek, &content_key, sn.as_bytes())?;
+
+            let recipient = Recipient {
+                sn,
+                ephemeral_pubkey: hex::encode(
+                    ephemeral.public_key().to_encoded_point(false).as_bytes(),
+                ),
+                wrapped_key: hex::encode(wrapped),
+            };
+            (content, Some(recipient))
+        }
+    };
+
+    let manifest = PayloadManifest {
+        format: BUNDLE_FORMAT,
+        description: description.to_string(),
+        origin,
+        files: listed,
+        content_sha256: sha256_hex(&content),
+        recipient,
+    };
+    let manifest = serde_json::to_string_pretty(&manifest)
+        .map_err(|e| format!("Encodage du manifeste : {}", e))?;
+
+    Ok(PreparedBundle { manifest, content })
+}
+
+impl PreparedBundle {
+    // Empreinte à faire signer par la clé (PayloadSign)
+    pub fn digest(&self) -> String {
+        sha256_hex(self.manifest.as_bytes())
+    }
+
+    pub fn finish(self, signature: &str, signer_pubkey: &str) -> Result<Vec<u8>, String> {
+        let bundle = Bundle {
+            manifest: self.manifest,
+            signature: signature.to_string(),
+            signer_pubkey: signer_pubkey.to_string(),
+            content: self.content,
+        };
+        let mut out = BUNDLE_MAGIC.to_vec();
+        out.extend(
+            postcard::to_stdvec(&bundle).map_err(|e| format!("Encodage du paquet : {}", e))?,
+        );
+        Ok(out)
+    }
+}
+
+// ==========================================
+// VÉRIFICATION ET EXTRACTION
+// ==========================================
+
+// Paquet dont la signature et l'empreinte du contenu ont été vérifiées
+#[derive(Debug)]
+pub struct OpenedBundle {
+    pub manifest: PayloadManifest,
+    pub signer_pubkey: String,
+    content: Vec<u8>,
+}
+
+pub fn open(bytes: &[u8]) -> Result<OpenedBundle, String> {
+    let payload = bytes
+        .strip_prefix(BUNDLE_MAGIC)
+        .ok_or("Ce fichier n'est pas un paquet BindKey")?;
+    let bundle: Bundle =
+        postcard::from_bytes(payload).map_err(|e| format!("Paquet illisible : {}", e))?;
+    let manifest: PayloadManifest = serde_json::from_str(&bundle.manifest)
+        .map_err(|e| format!("Manifeste illisible : {}", e))?;
+    if manifest.format != BUNDLE_FORMAT {
+        return Err(format!(
+            "Format de paquet {} non pris en charge",
+            manifest.format
+        ));
+    }
+
+    let signer = hex::decode(&bundle.signer_pubkey)
+        .ok()
+        .and_then(|bytes| VerifyingKey::from_sec1_bytes(&bytes).ok())
+        .ok_or("Clé de signature de l'émetteur illisible")?;
+    let signature = hex::decode(&bundle.signature)
+        .ok()
+        .and_then(|bytes| Signature::from_slice(&bytes).ok())
+        .ok_or("Signature du paquet illisible")?;
+    let message = signed_message(&sha256_hex(bundle.manifest.as_bytes()))?;
+    signer
+        .verify(&message, &signature)
+        .map_err(|_| "Signature du paquet invalide : paquet refusé".to_string())?;
+
+    if sha256_hex(&bundle.content) != manifest.content_sha256 {
+        return Err("Le contenu ne correspond pas au manifeste signé".to_string());
+    }
+
+    Ok(OpenedBundle {
+        manifest,
+        signer_pubkey: bundle.signer_pubkey,
+        content: bundle.content,
+    })
+}
+
+// Empreinte courte de la clé de l'émetteur, à comparer avec la liste de confiance
+pub fn signer_fingerprint(signer_pubkey: &str) -> String {
+    let bytes = hex::decode(signer_pubkey).unwrap_or_default();
+    sha256_hex(&bytes)[..16].to_string()
+}
+
+impl OpenedBundle {
+    pub fn signer_fingerprint(&self) -> String {
+        signer_fingerprint(&self.signer_pubkey)
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.manifest.recipient.is_some()
+    }
+
+    // `content_key` : clé déballée par la BindKey du destinataire (PayloadUnwrap)
+    pub fn extract(&self, content_key: Option<[u8; 32]>) -> Result<Vec<PayloadFile>, String> {
+        let plain = match (&self.manifest.recipient, content_key) {
+            (None, _) => self.content.clone(),
+            (Some(_), Some(key)) => open_sealed(&key, &self.content, PAYLOAD_SIGNING_CONTEXT)?,
+            (Some(recipient), None) => {
+                return Err(format!(
+                    "Paquet chiffré pour la BindKey {} : branchez-la pour l'ouvrir",
+                    recipient.sn
+                ));
+            }
+        };
+        let files: Vec<PayloadFile> =
+            postcard::from_bytes(&plain).map_err(|e| format!("Contenu illisible : {}", e))?;
+
+        let listed: Vec<(String, u64, String)> = self
+            .manifest
+            .files
+            .iter()
+            .map(|f| (f.name.clone(), f.size, f.sha256.clone()))
+            .collect();
+        let found: Vec<(String, u64, String)> = files
+            .iter()
+            .map(|f| (f.name.clone(), f.data.len() as u64, sha256_hex(&f.data)))
+            .collect();
+        if listed != found {
+            return Err("Les fichiers ne correspondent pas au manifeste".to_string());
+        }
+        Ok(files)
+    }
+}
+
+// Un nom venu du paquet ne doit jamais sortir du dossier de destination
+fn safe_file_name(name: &str) -> Option<&str> {
+    let name = Path::new(name).file_name()?.to_str()?;
+    if name.is_empty() || name.starts_with('.') {
+        return None;
+    }
+    Some(name)
+}
+
+pub fn write_files(files: &[PayloadFile], dir: &Path) -> Result<Vec<PathBuf>, String> {
+    std::fs::create_dir_all(dir)
+        .map_err(|e| format!("Création de {} impossible : {}", dir.display(), e))?;
+
+    let mut written = Vec::new();
+    for file in files {
+        let name = safe_file_name(&file.name)
+            .ok_or_else(|| format!("Nom de fichier refusé : {}", file.name))?;
+        let path = dir.join(name);
+        std::fs::write(&path, &file.data)
+            .map_err(|e| format!("Écriture de {} impossible : {}", path.display(), e))?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p256::SecretKey;
+    use p256::ecdsa::SigningKey;
+    use p256::ecdsa::signature::Signer;
+
+    // Ce que fait le firmware pour PayloadUnwrap
+    fn device_unwrap(ecdh: &SecretKey, sn: &str, recipient: &Recipient) -> [u8; 32] {
+        let peer =
+            PublicKey::from_sec1_bytes(&hex::decode(&recipient.ephemeral_pubkey).unwrap()).unwrap();
+        let shared = p256::ecdh::diffie_hellman(ecdh.to_nonzero_scalar(), peer.as_affine());
+        let kek = payload_kek(shared.raw_secret_bytes());
+        let wrapped = hex::decode(&recipient.wrapped_key).unwrap();
+        open_sealed(&kek, &wrapped, sn.as_bytes())
+            .unwrap()
+            .try_into()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_encrypted_bundle_round_trip() {
+        let signer = SigningKey::random(&mut OsRng);
+        let target = SecretKey::random(&mut OsRng);
+        let origin = Origin {
+            sn: "BK-SRC".to_string(),
+            user_email: "admin@bindkey.local".to_string(),
+            hostname: "poste-admin".to_string(),
+            created_at: 0,
+        };
+        let files = vec![PayloadFile {
+            name: "update.sh".to_string(),
+            data: b"#!/bin/sh\necho ok\n".to_vec(),
+        }];
+        let target_pub = hex::encode(target.public_key().to_encoded_point(false).as_bytes());
+
+        let prepared = prepare(
+            files.clone(),
+            "script",
+            origin,
+            Some(("BK-DST".to_string(), target_pub)),
+        )
+        .unwrap();
+        let signature: Signature = signer.sign(&signed_message(&prepared.digest()).unwrap());
+        let signer_pub = hex::encode(signer.verifying_key().to_encoded_point(false).as_bytes());
+        let bytes = prepared
+            .finish(&hex::encode(signature.to_bytes()), &signer_pub)
+            .unwrap();
+
+        let opened = open(&bytes).unwrap();
+        assert!(opened.extract(None).is_err());
+        let recipient = opened.manifest.recipient.clone().unwrap();
+        let key = device_unwrap(&target, "BK-DST", &recipient);
+        assert_eq!(opened.extract(Some(key)).unwrap(), files);
+
+        // Un octet du contenu modifié : refusé avant tout déchiffrement
+        let mut tampered = bytes.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 0x01;
+        assert!(open(&tampered).is_err());
+
+        assert_eq!(safe_file_name("../../etc/passwd"), Some("passwd"));
+        assert_eq!(safe_file_name(".bashrc"), None);
+    }
+}
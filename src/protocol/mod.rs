@@ -1,3 +1,4 @@
 pub mod protocol;
 pub mod share_protocol;
+pub mod text_protocol;
 pub mod updater;
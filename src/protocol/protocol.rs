@@ -1,13 +1,19 @@
-use crate::protocol::share_protocol::UsbResponse;
-use reqwest::{Certificate, Client};
+use crate::bindkey_device::FingerprintList;
+use crate::diagnostics::DiagnosticReport;
+use crate::firmware::FirmwareInfo;
+use crate::hotplug::HotplugEvent;
+use crate::pages::incoming_shares::IncomingShare;
+use crate::pages::reassign::AuditEntry;
+use crate::pages::restoration::RestorationReport;
+use crate::protocol::share_protocol::{DeviceEvent, UsbResponse};
+use crate::reconcile::ReconciliationReport;
+use crate::server_challenge::ServerChallenge;
+use crate::auto_lock::LockReason;
+use crate::session::SessionTokens;
+use crate::srp::SrpVerifier;
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
-use std::fs;
-use std::net::{IpAddr, SocketAddr};
-use std::path::Path;
 use std::str;
-use std::str::FromStr;
-use std::time::Duration;
 use uuid::Uuid;
 
 //----------------------------------------- ÉNUMÉRATION---------------------------------
@@ -18,6 +24,8 @@ pub enum StatusBindkey {
     RESET,
     LOST,
     BROKEN,
+    // Remplacée par restauration (code de secours) : définitivement hors service
+    REVOKED,
 }
 
 #[derive(PartialEq, Debug)]
@@ -26,6 +34,9 @@ pub enum Page {
     Home,
     Enrollment,
     Volume,
+    Payload,
+    Diagnostics,
+    Restoration,
 }
 
 #[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
@@ -41,6 +52,7 @@ pub enum VolumeTab {
     Dashboard,
     Gestion,
     Formatage,
+    Coherence,
 }
 
 pub enum ApiMessage {
@@ -49,9 +61,10 @@ pub enum ApiMessage {
     ModificationUsbSuccess(UsbResponse),
     LoginError(String),
     EnrollmentError(String),
-    ReceivedChallenge(String, Uuid, String),
+    ReceivedChallenge(ServerChallenge, Uuid, String),
     SignedChallenge(String, Uuid, String),
-    LoginSuccess(Role, String, String, String, String),
+    // Rôle, jetons, prénom, jeton local, SN de la BindKey de session
+    LoginSuccess(Role, SessionTokens, String, String, String),
     VolumeCreationSuccess(UsbResponse),
     VolumeCreationStatus(String),
     VolumeDashboardStatus(String),
@@ -72,9 +85,9 @@ pub enum ApiMessage {
     BindKeyStatusUpdated,
     UpdateBindKeyError(String),
     StartFormatBindKey {
+        bindkey_port: String,
         device_path: String,
         partitions: Vec<String>,
-        port_name: String,
         volume_names: Vec<String>,
     },
     FormatStatus(String),
@@ -86,24 +99,115 @@ pub enum ApiMessage {
     VolumeIdReceivedForDeletion(String, String, String),
     VolumeDeletedOnServer(String),
     VolumeDeletionError(String),
+    DeviceEvent(DeviceEvent),
+    DeviceFeedbackReset,
+    Hotplug(HotplugEvent),
+    BindKeyIdentified {
+        port_name: String,
+        sn: String,
+        firmware: Option<FirmwareInfo>,
+    },
+    FirmwareUpdateProgress {
+        sent: u64,
+        total: u64,
+    },
+    FirmwareUpdateFinished {
+        result: Result<(), String>,
+        interrupted: bool,
+    },
+    PayloadStatus(String),
+    PayloadFinished(Result<String, String>),
+    DiagnosticsFinished(DiagnosticReport),
+    // Liste à jour et message de succès
+    FingerprintsUpdated(Result<(FingerprintList, String), String>),
+    ReconciliationStatus(String),
+    ReconciliationFinished(Result<ReconciliationReport, String>),
+    IncomingSharesChecked(Result<Vec<IncomingShare>, String>),
+    // ID du partage et résultat de son installation
+    IncomingShareInstalled(String, Result<(), String>),
+    // Jeton expiré et non rafraîchissable : les requêtes attendent une nouvelle empreinte
+    ReauthRequired,
+    ReauthFinished(Result<SessionTokens, String>),
+    // Verrouillage automatique (clé débranchée, inactivité, écran verrouillé)
+    Lock(LockReason),
+    RestorationProgress(String),
+    RestorationFinished(Result<RestorationReport, String>),
+    ReassignAudit(AuditEntry),
+    // ID du dossier, ancien propriétaire
+    ReassignStarted(String, Option<String>),
+    // Port de la clé effacée, prête à être enrôlée
+    ReassignWiped(Result<String, String>),
+    // Nouveau propriétaire enrôlé sur la clé réattribuée (email)
+    ReassignEnrolled(String),
 }
 
 //--------------------------ÉNUMÉRATION (FIN)----------------------------
 
 //--------------------------STRUCT LOGIN (DÉBUT)----------------------------
 
+// Réponse de /sessions/srp/start : paramètres SRP du compte, ou ancien hash à migrer
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "scheme", rename_all = "snake_case")]
+pub enum SrpStartResponse {
+    Srp6a {
+        srp_session: Uuid,
+        srp_salt: String,
+        kdf_iterations: u32,
+        server_public: String,
+    },
+    Legacy,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ChallengeResponse {
     pub auth_challenge: String,
     pub session_id: Uuid,
+    // M2 : absent seulement pour la connexion qui migre un ancien compte
+    #[serde(default)]
+    pub server_proof: Option<String>,
+    // Signature de l'organisation sur (contexte, challenge, session, SN, expiration) ;
+    // absents chez un serveur qui ne signe pas encore : la connexion est alors refusée
+    #[serde(default)]
+    pub challenge_context: Option<String>,
+    #[serde(default)]
+    pub challenge_expires_at: Option<u64>,
+    #[serde(default)]
+    pub challenge_signature: Option<String>,
+}
+
+impl ChallengeResponse {
+    // `sn` : la BindKey qui a demandé la connexion
+    pub fn server_challenge(&self, sn: &str) -> Result<ServerChallenge, String> {
+        let (Some(context), Some(expires_at), Some(signature)) = (
+            &self.challenge_context,
+            self.challenge_expires_at,
+            &self.challenge_signature,
+        ) else {
+            return Err(
+                "Challenge non signé par le serveur : connexion refusée (serveur à mettre à jour ?)"
+                    .to_string(),
+            );
+        };
+        Ok(ServerChallenge {
+            context: context.clone(),
+            challenge: self.auth_challenge.clone(),
+            session_id: self.session_id.to_string(),
+            sn: sn.to_string(),
+            expires_at,
+            signature: signature.clone(),
+        })
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct LoginSuccessResponse {
-    pub server_token: String,
+    #[serde(flatten)]
+    pub tokens: SessionTokens,
     pub role: Role,
     pub first_name: String,
     pub local_token: String,
+    #[serde(default)]
+    pub server_proof: Option<String>,
 }
 
 //--------------------------STRUCT LOGIN (FIN)------------------------------
@@ -115,12 +219,17 @@ pub struct RegisterPayload {
     pub first_name: String,
     pub last_name: String,
     pub email: String,
-    pub password: String,
+    // Sel, vérificateur SRP et coût de dérivation : le mot de passe ne part jamais
+    #[serde(flatten)]
+    pub credentials: SrpVerifier,
     pub user_role: Role,
     pub sn: String,
     pub bindkey_status: StatusBindkey,
     pub pub_sign: String,
     pub pub_ecdh: String,
+    // Clé réattribuée après remise à zéro : rattache l'enrôlement au dossier d'audit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reassignment_id: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -128,6 +237,110 @@ pub struct ModifyPayload {
     pub email: String,
     pub user_role: Role,
 }
+
+// Restauration par code de secours : l'identité existante reçoit une nouvelle BindKey
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RecoveryStartPayload {
+    pub email: String,
+    pub recovery_code: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RecoveryStartResponse {
+    // Jeton limité à cette restauration (enrôlement, révocation, demandes de re-chiffrement)
+    pub restore_token: String,
+    pub old_sn: String,
+    // Partages de volumes que détenait l'ancienne clé
+    #[serde(default)]
+    pub shares: Vec<HeldShare>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HeldShare {
+    pub volume_id: String,
+    pub volume_name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RecoveryEnrollPayload {
+    pub sn: String,
+    pub pub_sign: String,
+    pub pub_ecdh: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RewrapRequestPayload {
+    pub volume_id: String,
+    pub old_sn: String,
+    pub new_sn: String,
+}
+
+// Réattribution d'une BindKey rendue : remise à zéro, nettoyage serveur, nouvel enrôlement
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum WipeMethod {
+    // Doigt d'une empreinte enregistrée sur la clé (l'ancien propriétaire est présent)
+    #[default]
+    Fingerprint,
+    // Ordre signé par l'organisation (clé rendue sans son propriétaire)
+    AdminOrder,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReassignmentStartPayload {
+    pub sn: String,
+    pub wipe_method: WipeMethod,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReassignmentStartResponse {
+    pub reassignment_id: String,
+    #[serde(default)]
+    pub previous_owner: Option<String>,
+    // Présent pour WipeMethod::AdminOrder
+    #[serde(default)]
+    pub wipe_order: Option<WipeOrder>,
+}
+
+// Signature de l'organisation sur (bindkey-wipe-v1, challenge, session, SN, expiration)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WipeOrder {
+    pub context: String,
+    pub challenge: String,
+    pub session_id: String,
+    pub expires_at: u64,
+    pub signature: String,
+}
+
+impl WipeOrder {
+    pub fn server_challenge(&self, sn: &str) -> ServerChallenge {
+        ServerChallenge {
+            context: self.context.clone(),
+            challenge: self.challenge.clone(),
+            session_id: self.session_id.clone(),
+            sn: sn.to_string(),
+            expires_at: self.expires_at,
+            signature: self.signature.clone(),
+        }
+    }
+}
+
+// Étape réalisée côté poste (la remise à zéro) : le serveur trace lui-même les siennes
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AuditEventPayload {
+    pub step: String,
+    pub success: bool,
+    pub detail: String,
+    pub at: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReassignmentCleanup {
+    #[serde(default)]
+    pub shares_removed: u32,
+    #[serde(default)]
+    pub volumes_removed: u32,
+}
 //--------------------------STRUCT ENRÔLEMENT (FIN)--------------------------------
 
 //--------------------------STRUCT VOLUME (DÉBUT)--------------------------------
@@ -153,10 +366,12 @@ pub struct VolumeInfo {
     pub mount_point: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+// Aussi renvoyé par GET /volumes (liste des volumes de l'utilisateur)
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct VolumeCreatedInfo {
     pub name: String,
     pub size_bytes: i64,
+    #[serde(alias = "volume_id")]
     pub id: String,
 }
 
@@ -167,6 +382,35 @@ pub struct UsbDevice {
     pub partitions: Vec<String>,
 }
 
+// Une BindKey branchée ; le SN est connu après la commande "uid"
+#[derive(Clone, PartialEq, Debug)]
+pub struct AttachedBindKey {
+    pub port_name: String,
+    pub sn: Option<String>,
+    // Disque de stockage derrière cette clé (corrélation sysfs), ex. /dev/sdb
+    pub disk_path: Option<String>,
+    // Plusieurs disques reliés à ce port : aucun n'est retenu, voir disk_link
+    pub disk_conflict: Option<String>,
+    // Version et capacités annoncées, lues à l'identification
+    pub firmware: Option<FirmwareInfo>,
+}
+
+impl AttachedBindKey {
+    pub fn set_disk(&mut self, found: Result<Option<String>, String>) {
+        (self.disk_path, self.disk_conflict) = match found {
+            Ok(disk) => (disk, None),
+            Err(e) => (None, Some(e)),
+        };
+    }
+
+    pub fn display_name(&self) -> String {
+        match &self.sn {
+            Some(sn) => format!("{} ({})", sn, self.port_name),
+            None => format!("Clé non identifiée ({})", self.port_name),
+        }
+    }
+}
+
 #[derive(Deserialize)]
 pub struct LsblkOutput {
     pub blockdevices: Vec<BlockDeviceJson>,
@@ -196,6 +440,8 @@ pub struct PartitionJson {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct LogOut {
     pub server_token: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -245,15 +491,24 @@ pub struct ShareRequestResponse {
     pub volume_id: String,
 }
 
+//------------------------------ Struct Paquets hors ligne ---------------------------------
+#[derive(Deserialize)]
+pub struct PayloadRecipientResponse {
+    pub target_sn: String,
+    pub target_pubkey_ecdh: String,
+}
+
 #[derive(Serialize)]
 pub struct ShareCompletePayload {
     pub source_sn: String,
     pub target_sn: String,
     pub volume_id: String,
     pub wrapped: String,
+    // Signature des métadonnées par la BindKey source (share_signature.rs)
+    pub signature: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct PendingShare {
     pub share_id: String,
     pub source_sn: String,
@@ -261,6 +516,14 @@ pub struct PendingShare {
     pub slot: u16,
     pub wrapped: String,
     pub volume_id: String,
+    // Clé publique de signature enregistrée à l'enrôlement de la BindKey source
+    #[serde(default)]
+    pub source_pubkey_sign: Option<String>,
+    #[serde(default)]
+    pub signature: Option<String>,
+    // Propriétaire de la BindKey source, pour l'affichage
+    #[serde(default)]
+    pub source_owner: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -268,43 +531,6 @@ pub struct ShareAckPayload {
     pub share_id: String,
 }
 
-pub fn create_secure_client() -> Result<Client, String> {
-    let ip_filename = "server_ip.txt";
-    let default_ip = "10.10.10.187";
-
-    let ip_str = if Path::new(ip_filename).exists() {
-        fs::read_to_string(ip_filename)
-            .map_err(|e| format!("Impossible de lire {}: {}", ip_filename, e))?
-            .trim()
-            .to_string()
-    } else {
-        println!(
-            "⚠️ Fichier {} introuvable, utilisation de l'IP défaut : {}",
-            ip_filename, default_ip
-        );
-        default_ip.to_string()
-    };
-
-    let ip_addr =
-        IpAddr::from_str(&ip_str).map_err(|e| format!("IP invalide '{}': {}", ip_str, e))?;
-
-    let addr = SocketAddr::new(ip_addr, 31278);
-
-    let cert_bytes = include_bytes!("../../bindkey_cert.pem");
-
-    let cert = Certificate::from_pem(cert_bytes)
-        .map_err(|e| format!("Certificat PEM invalide/corrompu : {}", e))?;
-
-    let client = Client::builder()
-        .add_root_certificate(cert)
-        .resolve("api.bindkey.local", addr)
-        .timeout(Duration::from_secs(10))
-        .build()
-        .map_err(|e| format!("Erreur construction client Reqwest : {}", e))?;
-
-    Ok(client)
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -323,12 +549,17 @@ mod tests {
             first_name: "Alice".to_string(),
             last_name: "Smith".to_string(),
             email: "alice@bindkey.com".to_string(),
-            password: "hashed_password".to_string(),
+            credentials: SrpVerifier {
+                srp_salt: "00ff".to_string(),
+                srp_verifier: "abcd".to_string(),
+                kdf_iterations: 100_000,
+            },
             user_role: Role::USER,
             sn: "BK-12345".to_string(),
             bindkey_status: StatusBindkey::ACTIVE,
             pub_sign: "pub_sign_key".to_string(),
             pub_ecdh: "pub_ecdh_key".to_string(),
+            reassignment_id: None,
         };
 
         let json = serde_json::to_string(&payload).unwrap();
@@ -336,6 +567,8 @@ mod tests {
         assert!(json.contains("alice@bindkey.com"));
         assert!(json.contains("USER"));
         assert!(json.contains("ACTIVE"));
+        assert!(json.contains("\"srp_verifier\":\"abcd\""));
+        assert!(!json.contains("password"));
     }
 
     #[test]
@@ -351,5 +584,7 @@ mod tests {
         assert!(result.is_ok());
         let challenge = result.unwrap();
         assert_eq!(challenge.auth_challenge, "random_nonce_123");
+        // Challenge sans signature de l'organisation : jamais transmis à la clé
+        assert!(challenge.server_challenge("BK-0001").is_err());
     }
 }
@@ -1,15 +1,125 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 #[repr(u8)]
 pub enum MessageType {
     Command = 0x01,
     Response = 0x02,
+    Event = 0x03,
     Error = 0xEE,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl TryFrom<u8> for MessageType {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, u8> {
+        match value {
+            0x01 => Ok(MessageType::Command),
+            0x02 => Ok(MessageType::Response),
+            0x03 => Ok(MessageType::Event),
+            0xEE => Ok(MessageType::Error),
+            other => Err(other),
+        }
+    }
+}
+
+// Commandes envoyées à la clé en mode trame (équivalents des lignes du protocole texte)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum UsbCommand {
+    Uid,
+    GetDevice,
+    Challenge {
+        challenge: String,
+    },
+    Enroll,
+    ConfirmModification,
+    RegisterLbaRange {
+        volume_name: String,
+        volume_id: String,
+        lba_start: u64,
+        lba_end: u64,
+    },
+    WrapShare {
+        volume_id: String,
+        target_sn: String,
+        target_pubkey: String,
+        target_slot: u16,
+    },
+    InstallShare {
+        slot: u16,
+        source_pubkey: String,
+        wrapped: String,
+    },
+    DeleteVolume {
+        volume_id: String,
+    },
+    InitFormat,
+    Version,
+    // Mise à jour du firmware : la clé garde les blocs reçus pour une même empreinte,
+    // FirmwareBegin renvoie donc l'offset où reprendre après un débranchement.
+    FirmwareBegin {
+        version: String,
+        size: u64,
+        sha256: String,
+    },
+    FirmwareChunk {
+        offset: u64,
+        data: Vec<u8>,
+    },
+    FirmwareVerify,
+    FirmwareCommit,
+    FirmwareAbort,
+    // Paquets hors ligne : signature de l'empreinte d'un manifeste, déballage de la clé du contenu
+    PayloadSign {
+        digest: String,
+    },
+    PayloadUnwrap {
+        ephemeral_pubkey: String,
+        wrapped: String,
+    },
+    // Autotest des composants de la clé (diagnostic)
+    SelfTest,
+    // Gestion des empreintes : la clé n'accepte List / Enroll / Delete qu'immédiatement
+    // après un FingerprintVerify réussi (une autorisation par commande)
+    FingerprintVerify,
+    FingerprintList,
+    FingerprintEnroll {
+        label: String,
+    },
+    FingerprintDelete {
+        slot: u16,
+    },
+    // Volumes (IDs + plages LBA) et slots de partage actuellement stockés par la clé
+    Inventory,
+    // Interrompt la commande en cours (même numéro de séquence) : elle répond Error("cancelled").
+    // Sans commande en cours, simple Ack.
+    Abort,
+    // Challenge de connexion signé par l'organisation : la clé vérifie la signature avec la
+    // clé publique qu'elle a épinglée, le contexte, son SN et l'expiration, puis demande le
+    // doigt et signe le même message (voir server_challenge.rs). Répond Signature.
+    SignedChallenge {
+        context: String,
+        challenge: String,
+        session_id: String,
+        expires_at: u64,
+        server_signature: String,
+    },
+    // Remise à zéro d'usine : efface l'identité (clés de signature et ECDH), les empreintes,
+    // les volumes et les partages. Confirmée par le doigt d'une empreinte enregistrée. Répond Ack.
+    FactoryWipe,
+    // Même remise à zéro, confirmée par un ordre d'administrateur signé par l'organisation
+    // (contexte "bindkey-wipe-v1", même message que SignedChallenge) : pas de doigt demandé.
+    FactoryWipeAuthorized {
+        context: String,
+        challenge: String,
+        session_id: String,
+        expires_at: u64,
+        server_signature: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[repr(u8)]
 pub enum UsbResponse {
     Success(SuccessData),
@@ -17,7 +127,7 @@ pub enum UsbResponse {
     Error(String),
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[repr(u8)]
 #[serde(rename_all = "camelCase")]
 pub enum SuccessData {
@@ -43,4 +153,310 @@ pub enum SuccessData {
     },
 
     Ack,
+
+    // Variantes ajoutées pour le transport en trames : toujours en fin d'enum,
+    // postcard encode l'index de la variante.
+    Uid {
+        sn: String,
+    },
+    WrappedShare {
+        source_sn: String,
+        wrapped: String,
+    },
+    FormatInitialized {
+        to_delete: Vec<String>,
+    },
+    FirmwareInfo {
+        version: String,
+        capabilities: Vec<String>,
+    },
+    // Prochain offset attendu par la clé
+    FirmwareProgress {
+        offset: u64,
+    },
+    FirmwareHash {
+        sha256: String,
+    },
+    PayloadSignature {
+        signature: String,
+        pub_sign: String,
+    },
+    PayloadKey {
+        key: String,
+    },
+    // Chaque état vaut "ok", "ok:<détail>" ou un code d'erreur du firmware
+    SelfTest {
+        secure_element: String,
+        fingerprint_sensor: String,
+        slave_link: String,
+        medium: String,
+        share_slots_free: u16,
+        share_slots_total: u16,
+    },
+    // Slot de l'empreinte reconnue
+    FingerprintMatch {
+        slot: u16,
+    },
+    FingerprintSlots {
+        slots: Vec<FingerprintSlot>,
+        capacity: u16,
+    },
+    Inventory {
+        volumes: Vec<InventoryVolume>,
+        shares: Vec<InventoryShare>,
+    },
+    // WrappedShare accompagné de la signature des métadonnées par la clé source
+    // (voir share_signature.rs)
+    SignedWrappedShare {
+        source_sn: String,
+        wrapped: String,
+        signature: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FingerprintSlot {
+    pub slot: u16,
+    pub label: String,
+}
+
+// Volume dont la clé détient la clé de chiffrement, avec sa plage LBA (en secteurs)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InventoryVolume {
+    pub volume_id: String,
+    pub name: String,
+    pub lba_start: u64,
+    pub lba_end: u64,
+}
+
+// Part reçue d'une autre BindKey, installée dans un slot de partage
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InventoryShare {
+    pub slot: u16,
+    pub volume_id: String,
+}
+
+// Lignes intermédiaires émises par la clé pendant une commande longue (enrôlement, challenge).
+// En texte : PROMPT=place_finger, PROGRESS=2/3, FP_QUALITY=low ; en trames : MessageType::Event.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum DeviceEvent {
+    Prompt(String),
+    Progress { current: u32, total: u32 },
+    FingerprintQuality(String),
+}
+
+impl DeviceEvent {
+    // None si la ligne n'est pas un événement (ou si sa valeur est illisible)
+    pub fn from_text_line(key: &str, value: &str) -> Option<Self> {
+        match key {
+            "PROMPT" => Some(DeviceEvent::Prompt(value.to_string())),
+            "FP_QUALITY" => Some(DeviceEvent::FingerprintQuality(value.to_string())),
+            "PROGRESS" => {
+                let (current, total) = value.split_once('/')?;
+                Some(DeviceEvent::Progress {
+                    current: current.trim().parse().ok()?,
+                    total: total.trim().parse().ok()?,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn is_event_key(key: &str) -> bool {
+        matches!(key, "PROMPT" | "PROGRESS" | "FP_QUALITY")
+    }
+}
+
+// ==========================================
+// TRANSPORT EN TRAMES
+// ==========================================
+//
+// | MAGIC | LEN (u16 LE) | TYPE | SEQ (u16 LE) | PAYLOAD postcard (LEN octets) | CRC16 (LE) |
+//
+// Le CRC-16/CCITT-FALSE couvre TYPE, SEQ et PAYLOAD. L'octet MAGIC n'est jamais de l'ASCII,
+// ce qui permet de distinguer une trame d'une ligne de log de l'ESP32.
+
+pub const FRAME_MAGIC: u8 = 0xB7;
+pub const FRAME_HEADER_LEN: usize = 6;
+pub const FRAME_CRC_LEN: usize = 2;
+pub const MAX_FRAME_PAYLOAD: usize = 4096;
+
+// Ligne texte envoyée pour basculer en mode trame ; un firmware ancien répond ERR=unknown_command
+pub const FRAMED_NEGOTIATION_COMMAND: &str = "proto=framed";
+pub const FRAMED_NEGOTIATION_REPLY: &str = "framed";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub message_type: MessageType,
+    pub seq: u16,
+    pub payload: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrameError {
+    BadMagic(u8),
+    TooLarge(usize),
+    UnknownType(u8),
+    BadCrc { expected: u16, received: u16 },
+}
+
+impl std::fmt::Display for FrameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrameError::BadMagic(b) => write!(f, "octet de début invalide 0x{:02X}", b),
+            FrameError::TooLarge(len) => write!(f, "trame trop grande ({} octets)", len),
+            FrameError::UnknownType(t) => write!(f, "type de message inconnu 0x{:02X}", t),
+            FrameError::BadCrc { expected, received } => write!(
+                f,
+                "CRC invalide (attendu 0x{:04X}, reçu 0x{:04X})",
+                expected, received
+            ),
+        }
+    }
+}
+
+pub fn crc16_ccitt(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+impl Frame {
+    pub fn new<T: Serialize>(
+        message_type: MessageType,
+        seq: u16,
+        body: &T,
+    ) -> Result<Self, postcard::Error> {
+        Ok(Frame {
+            message_type,
+            seq,
+            payload: postcard::to_stdvec(body)?,
+        })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(FRAME_HEADER_LEN + self.payload.len() + FRAME_CRC_LEN);
+        out.push(FRAME_MAGIC);
+        out.extend_from_slice(&(self.payload.len() as u16).to_le_bytes());
+        out.push(self.message_type as u8);
+        out.extend_from_slice(&self.seq.to_le_bytes());
+        out.extend_from_slice(&self.payload);
+
+        let crc = crc16_ccitt(&out[3..]);
+        out.extend_from_slice(&crc.to_le_bytes());
+        out
+    }
+
+    // Ok(None) tant que le tampon ne contient pas une trame complète.
+    // En cas de succès, renvoie aussi le nombre d'octets consommés.
+    pub fn decode(buffer: &[u8]) -> Result<Option<(Frame, usize)>, FrameError> {
+        let Some(&magic) = buffer.first() else {
+            return Ok(None);
+        };
+        if magic != FRAME_MAGIC {
+            return Err(FrameError::BadMagic(magic));
+        }
+        if buffer.len() < FRAME_HEADER_LEN {
+            return Ok(None);
+        }
+
+        let len = u16::from_le_bytes([buffer[1], buffer[2]]) as usize;
+        if len > MAX_FRAME_PAYLOAD {
+            return Err(FrameError::TooLarge(len));
+        }
+        let total = FRAME_HEADER_LEN + len + FRAME_CRC_LEN;
+        if buffer.len() < total {
+            return Ok(None);
+        }
+
+        let crc_offset = FRAME_HEADER_LEN + len;
+        let expected = crc16_ccitt(&buffer[3..crc_offset]);
+        let received = u16::from_le_bytes([buffer[crc_offset], buffer[crc_offset + 1]]);
+        if expected != received {
+            return Err(FrameError::BadCrc { expected, received });
+        }
+
+        let message_type = MessageType::try_from(buffer[3]).map_err(FrameError::UnknownType)?;
+        let seq = u16::from_le_bytes([buffer[4], buffer[5]]);
+
+        Ok(Some((
+            Frame {
+                message_type,
+                seq,
+                payload: buffer[FRAME_HEADER_LEN..crc_offset].to_vec(),
+            },
+            total,
+        )))
+    }
+
+    pub fn body<T: for<'de> Deserialize<'de>>(&self) -> Result<T, postcard::Error> {
+        postcard::from_bytes(&self.payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc16_ccitt_check_value() {
+        assert_eq!(crc16_ccitt(b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn test_device_event_text_lines() {
+        assert_eq!(
+            DeviceEvent::from_text_line("PROGRESS", "2/3"),
+            Some(DeviceEvent::Progress {
+                current: 2,
+                total: 3
+            })
+        );
+        assert_eq!(DeviceEvent::from_text_line("PROGRESS", "deux"), None);
+        assert_eq!(DeviceEvent::from_text_line("SN", "BK-1"), None);
+    }
+
+    #[test]
+    fn test_frame_round_trip() {
+        let command = UsbCommand::Challenge {
+            challenge: "nonce_123".to_string(),
+        };
+        let frame = Frame::new(MessageType::Command, 42, &command).unwrap();
+        let bytes = frame.encode();
+
+        // Trame tronquée : on attend la suite
+        assert_eq!(Frame::decode(&bytes[..bytes.len() - 1]), Ok(None));
+
+        let (decoded, consumed) = Frame::decode(&bytes).unwrap().unwrap();
+        assert_eq!(consumed, bytes.len());
+        assert_eq!(decoded.seq, 42);
+        assert_eq!(decoded.message_type, MessageType::Command);
+        assert_eq!(decoded.body::<UsbCommand>().unwrap(), command);
+    }
+
+    #[test]
+    fn test_frame_corruption_detected() {
+        let frame = Frame::new(
+            MessageType::Response,
+            1,
+            &UsbResponse::Success(SuccessData::Ack),
+        )
+        .unwrap();
+        let mut bytes = frame.encode();
+        bytes[FRAME_HEADER_LEN] ^= 0xFF;
+
+        assert!(matches!(
+            Frame::decode(&bytes),
+            Err(FrameError::BadCrc { .. })
+        ));
+    }
 }
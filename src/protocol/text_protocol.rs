@@ -0,0 +1,271 @@
+use std::fmt;
+
+// ==========================================
+// PROTOCOLE TEXTE : LIGNES KEY=VALUE
+// ==========================================
+//
+// Une ligne par champ, terminée par '\n'. La clé ne contient que [A-Za-z0-9_] ; la valeur
+// est tout ce qui suit le premier '=', échappée par `escape` :
+//   \\ -> \     \n -> saut de ligne     \r -> retour chariot     \0 -> NUL
+//   \; -> ';'   \: -> ':'   (séparateurs d'éléments et de sous-champs des listes)
+// Une valeur sans '\' se lit telle quelle : les firmwares qui n'échappent rien restent lisibles.
+//
+// L'ESP32 écrit aussi sur cet UART ses messages de démarrage et ses journaux (après le
+// reset DTR de l'ouverture du port) : seuls leurs préfixes connus (ROM "ets ", "rst:",
+// journaux ESP-IDF "I (…)", "W (…)", "E (…)") sont ignorés (TextLine::Noise). Toute autre
+// ligne qui n'a pas la forme KEY=VALUE est une erreur.
+//
+// Une liste s'écrit soit en répétant la clé (une ligne par élément), soit en une seule
+// ligne dont les éléments sont séparés par ';' (forme historique de TO_DEL).
+
+pub const LIST_SEPARATOR: char = ';';
+pub const RECORD_SEPARATOR: char = ':';
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MalformedLine {
+    pub line: String,
+    pub reason: &'static str,
+}
+
+impl fmt::Display for MalformedLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} : \"{}\"", self.reason, self.line)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextLine {
+    Ok,
+    // ERR=<raison>
+    Error(String),
+    // Valeur encore échappée : les listes se découpent avant de déséchapper
+    Field { key: String, raw: String },
+    // Démarrage ou journal du firmware, hors protocole
+    Noise,
+}
+
+pub fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\0' => escaped.push_str("\\0"),
+            LIST_SEPARATOR => escaped.push_str("\\;"),
+            RECORD_SEPARATOR => escaped.push_str("\\:"),
+            other => escaped.push(other),
+        }
+    }
+    escaped
+}
+
+pub fn unescape(raw: &str) -> Result<String, MalformedLine> {
+    let malformed = |reason| MalformedLine {
+        line: raw.to_string(),
+        reason,
+    };
+    let mut value = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => value.push('\\'),
+            Some('n') => value.push('\n'),
+            Some('r') => value.push('\r'),
+            Some('0') => value.push('\0'),
+            Some(';') => value.push(';'),
+            Some(':') => value.push(':'),
+            Some(_) => return Err(malformed("séquence d'échappement inconnue")),
+            None => return Err(malformed("'\\' en fin de valeur")),
+        }
+    }
+    Ok(value)
+}
+
+// Découpe sur les séparateurs non échappés ; les morceaux restent échappés
+pub fn split_unescaped(raw: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in raw.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == separator {
+            parts.push(&raw[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    parts.push(&raw[start..]);
+    parts
+}
+
+// Un élément de liste "a:b:c" en exactement `fields` sous-champs déséchappés
+pub fn split_record(raw: &str, fields: usize) -> Result<Vec<String>, MalformedLine> {
+    let parts = split_unescaped(raw, RECORD_SEPARATOR);
+    if parts.len() != fields {
+        return Err(MalformedLine {
+            line: raw.to_string(),
+            reason: "nombre de sous-champs inattendu",
+        });
+    }
+    parts.into_iter().map(|p| unescape(p.trim())).collect()
+}
+
+// Message de la ROM au démarrage ou journal ESP-IDF ("I (312) wifi: ...")
+fn is_firmware_log(line: &str) -> bool {
+    if line.starts_with("ets ") || line.starts_with("rst:") {
+        return true;
+    }
+    let Some(rest) = ["I (", "W (", "E ("]
+        .iter()
+        .find_map(|p| line.strip_prefix(p))
+    else {
+        return false;
+    };
+    rest.split_once(')')
+        .is_some_and(|(ticks, _)| !ticks.is_empty() && ticks.chars().all(|c| c.is_ascii_digit()))
+}
+
+// Les NUL en bordure viennent du tampon de l'ESP32 et sont ignorés, comme les espaces
+pub fn parse_line(line: &str) -> Result<TextLine, MalformedLine> {
+    let line = line.trim().trim_matches('\0');
+    let malformed = |reason| MalformedLine {
+        line: line.to_string(),
+        reason,
+    };
+
+    if line == "OK" {
+        return Ok(TextLine::Ok);
+    }
+    if is_firmware_log(line) {
+        return Ok(TextLine::Noise);
+    }
+    let Some((key, value)) = line.split_once('=') else {
+        return Err(malformed("ligne sans '='"));
+    };
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(malformed("clé vide"));
+    }
+    if !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(malformed("clé invalide"));
+    }
+    let raw = value.trim().trim_matches('\0');
+    if key == "ERR" {
+        return Ok(TextLine::Error(unescape(raw)?));
+    }
+    Ok(TextLine::Field {
+        key: key.to_string(),
+        raw: raw.to_string(),
+    })
+}
+
+// Champs d'une réponse, dans l'ordre de réception ; une clé peut revenir plusieurs fois
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TextReply {
+    fields: Vec<(String, String)>,
+}
+
+impl TextReply {
+    pub fn push(&mut self, key: String, raw: String) {
+        self.fields.push((key, raw));
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.fields.iter().any(|(k, _)| k == key)
+    }
+
+    // Dernière valeur reçue pour `key`, déséchappée
+    pub fn value(&self, key: &str) -> Result<Option<String>, MalformedLine> {
+        self.raw_values(key).pop().map(unescape).transpose()
+    }
+
+    // Tous les éléments de `key`, lignes répétées et éléments séparés par ';' confondus,
+    // encore échappés (à passer à `unescape` ou `split_record`)
+    pub fn raw_items(&self, key: &str) -> Vec<&str> {
+        self.raw_values(key)
+            .into_iter()
+            .flat_map(|raw| split_unescaped(raw, LIST_SEPARATOR))
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .collect()
+    }
+
+    pub fn list(&self, key: &str) -> Result<Vec<String>, MalformedLine> {
+        self.raw_items(key).into_iter().map(unescape).collect()
+    }
+
+    fn raw_values(&self, key: &str) -> Vec<&str> {
+        self.fields
+            .iter()
+            .filter(|(k, _)| k == key)
+            .map(|(_, raw)| raw.as_str())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escaped_values_survive_a_round_trip() {
+        let name = "Projet;A:B=C\\D\nsuite";
+        let line = format!("DN={}", escape(name));
+        assert!(!line.contains('\n'));
+
+        let Ok(TextLine::Field { key, raw }) = parse_line(&line) else {
+            panic!("ligne refusée : {}", line);
+        };
+        assert_eq!(key, "DN");
+        assert_eq!(unescape(&raw).unwrap(), name);
+
+        let record = format!("{}:{}", escape("id-1"), escape(name));
+        assert_eq!(split_record(&record, 2).unwrap(), vec!["id-1", name]);
+    }
+
+    #[test]
+    fn lists_accept_repeated_keys_and_packed_items() {
+        let mut reply = TextReply::default();
+        for line in ["TO_DEL=a;b", "TO_DEL=c\\;d", "TO_DEL=", "SN=BK-1"] {
+            if let Ok(TextLine::Field { key, raw }) = parse_line(line) {
+                reply.push(key, raw);
+            }
+        }
+        assert_eq!(reply.list("TO_DEL").unwrap(), vec!["a", "b", "c;d"]);
+        assert_eq!(reply.value("SN").unwrap().as_deref(), Some("BK-1"));
+        assert!(reply.list("ABSENT").unwrap().is_empty());
+    }
+
+    #[test]
+    fn malformed_lines_are_reported() {
+        assert_eq!(parse_line("OK\0"), Ok(TextLine::Ok));
+        assert_eq!(
+            parse_line("ERR=no_match"),
+            Ok(TextLine::Error("no_match".to_string()))
+        );
+        assert_eq!(parse_line("ets Jun  8 2016 00:22:57"), Ok(TextLine::Noise));
+        assert_eq!(
+            parse_line("rst:0x1 (POWERON_RESET),boot:0x13"),
+            Ok(TextLine::Noise)
+        );
+        assert_eq!(parse_line("I (312) wifi: mode=sta"), Ok(TextLine::Noise));
+        assert_eq!(
+            parse_line("E (1045) bindkey: se timeout"),
+            Ok(TextLine::Noise)
+        );
+        assert!(parse_line("Guru Meditation Error").is_err());
+        assert!(parse_line("I (x) pas un journal").is_err());
+        assert!(parse_line("=valeur").is_err());
+        assert!(parse_line("MA CLE=1").is_err());
+        assert!(parse_line("ERR=fin\\").is_err());
+        assert!(unescape("fin\\").is_err());
+        assert!(unescape("\\x").is_err());
+    }
+}
@@ -0,0 +1,378 @@
+use crate::bindkey_device::DeviceInventory;
+use crate::partition_table::{Partition, PartitionTable};
+use crate::protocol::protocol::VolumeCreatedInfo;
+use crate::protocol::share_protocol::InventoryVolume;
+use std::collections::HashSet;
+
+// ==========================================
+// RÉCONCILIATION CLÉ / TABLE DE PARTITIONS / SERVEUR
+// ==========================================
+//
+// Un volume sain existe dans les trois sources : sa clé (et sa plage LBA) sur la BindKey,
+// une partition qui couvre exactement cette plage, et son enregistrement sur le serveur.
+// Une création interrompue ou une suppression partielle laisse des restes dans l'une d'elles.
+
+#[derive(Debug, Clone)]
+pub struct ReconciliationReport {
+    pub disk_path: String,
+    pub inventory: DeviceInventory,
+    pub table: PartitionTable,
+    pub server: Vec<VolumeCreatedInfo>,
+    pub findings: Vec<Finding>,
+}
+
+// Propriétaire d'une plage de secteurs
+#[derive(Debug, Clone, PartialEq)]
+pub enum RangeOwner {
+    Device { volume_id: String, name: String },
+    Partition(u32),
+}
+
+impl RangeOwner {
+    fn label(&self) -> String {
+        match self {
+            RangeOwner::Device { name, volume_id } => {
+                format!("le volume {} ({}) de la clé", name, volume_id)
+            }
+            RangeOwner::Partition(number) => format!("la partition {}", number),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RepairStep {
+    // delete_volume sur la BindKey (efface aussi les parts reçues pour cet ID)
+    ForgetOnDevice(String),
+    RemovePartition(u32),
+    DeleteServerRecord(String),
+}
+
+impl RepairStep {
+    pub fn describe(&self) -> String {
+        match self {
+            RepairStep::ForgetOnDevice(id) => format!("oublier l'ID {} sur la BindKey", id),
+            RepairStep::RemovePartition(number) => {
+                format!("supprimer la partition {} (son contenu sera perdu)", number)
+            }
+            RepairStep::DeleteServerRecord(id) => {
+                format!("retirer l'enregistrement {} du serveur", id)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Finding {
+    // La clé détient un ID que le serveur ne connaît pas
+    UnknownToServer {
+        volume: InventoryVolume,
+        partition: Option<u32>,
+    },
+    // Part reçue pour un volume que le serveur ne connaît plus
+    OrphanShare {
+        slot: u16,
+        volume_id: String,
+    },
+    // Le serveur référence un volume dont la clé n'a aucune trace
+    MissingOnDevice {
+        volume_id: String,
+        name: String,
+    },
+    // La clé chiffre une plage qu'aucune partition ne couvre
+    KeyWithoutPartition {
+        volume: InventoryVolume,
+    },
+    // Partition sans clé sur la BindKey : son contenu n'est pas déchiffrable
+    PartitionWithoutKey {
+        partition: Partition,
+    },
+    // Deux plages qui se chevauchent sans coïncider
+    Overlap {
+        first: RangeOwner,
+        second: RangeOwner,
+    },
+}
+
+impl Finding {
+    pub fn title(&self) -> &'static str {
+        match self {
+            Finding::UnknownToServer { .. } => "ID orphelin sur la clé",
+            Finding::OrphanShare { .. } => "Part reçue orpheline",
+            Finding::MissingOnDevice { .. } => "Volume absent de la clé",
+            Finding::KeyWithoutPartition { .. } => "Clé sans partition",
+            Finding::PartitionWithoutKey { .. } => "Partition sans clé",
+            Finding::Overlap { .. } => "Plages qui se chevauchent",
+        }
+    }
+
+    pub fn detail(&self) -> String {
+        match self {
+            Finding::UnknownToServer { volume, partition } => format!(
+                "La clé détient {} ({}, LBA {} → {}) mais le serveur ne le connaît pas{}.",
+                volume.name,
+                volume.volume_id,
+                volume.lba_start,
+                volume.lba_end,
+                match partition {
+                    Some(number) => format!(" ; partition {} associée", number),
+                    None => String::new(),
+                }
+            ),
+            Finding::OrphanShare { slot, volume_id } => format!(
+                "Le slot de partage {} contient le volume {}, inconnu du serveur.",
+                slot, volume_id
+            ),
+            Finding::MissingOnDevice { volume_id, name } => format!(
+                "Le serveur référence {} ({}) mais la BindKey n'en a aucune clé.",
+                name, volume_id
+            ),
+            Finding::KeyWithoutPartition { volume } => format!(
+                "La clé chiffre LBA {} → {} pour {} ({}) mais aucune partition ne couvre cette plage.",
+                volume.lba_start, volume.lba_end, volume.name, volume.volume_id
+            ),
+            Finding::PartitionWithoutKey { partition } => format!(
+                "La partition {} (LBA {} → {}) n'a aucune clé sur la BindKey.",
+                partition.number, partition.start, partition.end
+            ),
+            Finding::Overlap { first, second } => {
+                format!("{} chevauche {}.", first.label(), second.label())
+            }
+        }
+    }
+
+    // Vide : aucune réparation sûre, l'utilisateur doit trancher
+    pub fn repair(&self) -> Vec<RepairStep> {
+        match self {
+            Finding::UnknownToServer { volume, partition } => {
+                let mut steps = vec![RepairStep::ForgetOnDevice(volume.volume_id.clone())];
+                steps.extend(partition.map(RepairStep::RemovePartition));
+                steps
+            }
+            Finding::OrphanShare { volume_id, .. } => {
+                vec![RepairStep::ForgetOnDevice(volume_id.clone())]
+            }
+            Finding::MissingOnDevice { volume_id, .. } => {
+                vec![RepairStep::DeleteServerRecord(volume_id.clone())]
+            }
+            Finding::KeyWithoutPartition { volume } => vec![
+                RepairStep::ForgetOnDevice(volume.volume_id.clone()),
+                RepairStep::DeleteServerRecord(volume.volume_id.clone()),
+            ],
+            Finding::PartitionWithoutKey { partition } => {
+                vec![RepairStep::RemovePartition(partition.number)]
+            }
+            Finding::Overlap { .. } => Vec::new(),
+        }
+    }
+
+    pub fn manual_advice(&self) -> &'static str {
+        "Réparation manuelle : sauvegardez les données, supprimez l'un des deux volumes depuis l'onglet Gestion puis relancez l'analyse."
+    }
+}
+
+fn overlaps(a: (u64, u64), b: (u64, u64)) -> bool {
+    a.0 <= b.1 && b.0 <= a.1
+}
+
+pub fn compare(
+    inventory: &DeviceInventory,
+    table: &PartitionTable,
+    server: &[VolumeCreatedInfo],
+) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let server_ids: HashSet<&str> = server.iter().map(|v| v.id.as_str()).collect();
+    let device_ids: HashSet<&str> = inventory
+        .volumes
+        .iter()
+        .map(|v| v.volume_id.as_str())
+        .chain(inventory.shares.iter().map(|s| s.volume_id.as_str()))
+        .collect();
+
+    for volume in &inventory.volumes {
+        let range = (volume.lba_start, volume.lba_end);
+        let exact = table
+            .partitions
+            .iter()
+            .find(|p| (p.start, p.end) == range)
+            .map(|p| p.number);
+        let covered = table
+            .partitions
+            .iter()
+            .any(|p| overlaps((p.start, p.end), range));
+
+        if !server_ids.contains(volume.volume_id.as_str()) {
+            findings.push(Finding::UnknownToServer {
+                volume: volume.clone(),
+                partition: exact,
+            });
+        } else if !covered {
+            findings.push(Finding::KeyWithoutPartition {
+                volume: volume.clone(),
+            });
+        }
+    }
+
+    for share in &inventory.shares {
+        if !server_ids.contains(share.volume_id.as_str()) {
+            findings.push(Finding::OrphanShare {
+                slot: share.slot,
+                volume_id: share.volume_id.clone(),
+            });
+        }
+    }
+
+    for volume in server {
+        if !device_ids.contains(volume.id.as_str()) {
+            findings.push(Finding::MissingOnDevice {
+                volume_id: volume.id.clone(),
+                name: volume.name.clone(),
+            });
+        }
+    }
+
+    for partition in &table.partitions {
+        let range = (partition.start, partition.end);
+        let mut keyed = false;
+        for volume in &inventory.volumes {
+            let volume_range = (volume.lba_start, volume.lba_end);
+            if volume_range == range {
+                keyed = true;
+            } else if overlaps(volume_range, range) {
+                keyed = true;
+                findings.push(Finding::Overlap {
+                    first: device_owner(volume),
+                    second: RangeOwner::Partition(partition.number),
+                });
+            }
+        }
+        if !keyed {
+            findings.push(Finding::PartitionWithoutKey {
+                partition: *partition,
+            });
+        }
+    }
+
+    // La clé refuse normalement les plages qui se chevauchent : un firmware ancien a pu l'accepter
+    for (i, first) in inventory.volumes.iter().enumerate() {
+        for second in &inventory.volumes[i + 1..] {
+            if overlaps(
+                (first.lba_start, first.lba_end),
+                (second.lba_start, second.lba_end),
+            ) {
+                findings.push(Finding::Overlap {
+                    first: device_owner(first),
+                    second: device_owner(second),
+                });
+            }
+        }
+    }
+
+    findings
+}
+
+fn device_owner(volume: &InventoryVolume) -> RangeOwner {
+    RangeOwner::Device {
+        volume_id: volume.volume_id.clone(),
+        name: volume.name.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::share_protocol::InventoryShare;
+
+    fn volume(id: &str, start: u64, end: u64) -> InventoryVolume {
+        InventoryVolume {
+            volume_id: id.to_string(),
+            name: id.to_uppercase(),
+            lba_start: start,
+            lba_end: end,
+        }
+    }
+
+    fn server_volume(id: &str) -> VolumeCreatedInfo {
+        VolumeCreatedInfo {
+            name: id.to_uppercase(),
+            size_bytes: 1,
+            id: id.to_string(),
+        }
+    }
+
+    fn partition(number: u32, start: u64, end: u64) -> Partition {
+        Partition { number, start, end }
+    }
+
+    #[test]
+    fn flags_each_kind_of_drift() {
+        let inventory = DeviceInventory {
+            volumes: vec![
+                volume("sain", 2048, 4095),
+                volume("orphelin", 4096, 6143),
+                volume("sans_partition", 100_000, 101_999),
+                volume("decale", 8192, 10_239),
+            ],
+            shares: vec![
+                InventoryShare {
+                    slot: 0,
+                    volume_id: "partage".to_string(),
+                },
+                InventoryShare {
+                    slot: 1,
+                    volume_id: "perdu".to_string(),
+                },
+            ],
+        };
+        let table = PartitionTable {
+            disk_size_sectors: 200_000,
+            partitions: vec![
+                partition(1, 2048, 4095),
+                partition(2, 4096, 6143),
+                partition(3, 9000, 12_000),
+                partition(4, 20_000, 30_000),
+            ],
+        };
+        let server: Vec<_> = ["sain", "sans_partition", "decale", "partage", "fantome"]
+            .into_iter()
+            .map(server_volume)
+            .collect();
+
+        let findings = compare(&inventory, &table, &server);
+
+        assert_eq!(
+            findings,
+            vec![
+                Finding::UnknownToServer {
+                    volume: volume("orphelin", 4096, 6143),
+                    partition: Some(2),
+                },
+                Finding::KeyWithoutPartition {
+                    volume: volume("sans_partition", 100_000, 101_999),
+                },
+                Finding::OrphanShare {
+                    slot: 1,
+                    volume_id: "perdu".to_string(),
+                },
+                Finding::MissingOnDevice {
+                    volume_id: "fantome".to_string(),
+                    name: "FANTOME".to_string(),
+                },
+                Finding::Overlap {
+                    first: device_owner(&volume("decale", 8192, 10_239)),
+                    second: RangeOwner::Partition(3),
+                },
+                Finding::PartitionWithoutKey {
+                    partition: partition(4, 20_000, 30_000),
+                },
+            ]
+        );
+        assert_eq!(
+            findings[0].repair(),
+            vec![
+                RepairStep::ForgetOnDevice("orphelin".to_string()),
+                RepairStep::RemovePartition(2),
+            ]
+        );
+        assert!(findings[4].repair().is_empty());
+    }
+}
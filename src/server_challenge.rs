@@ -0,0 +1,185 @@
+use p256::ecdsa::signature::Verifier;
+use p256::ecdsa::{Signature, VerifyingKey};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// ==========================================
+// CHALLENGE DE CONNEXION SIGNÉ PAR L'ORGANISATION
+// ==========================================
+//
+// Le serveur signe chaque challenge avec la clé de l'organisation (ECDSA P-256, r || s).
+// Le message signé lie le challenge à son usage, à la session, à la BindKey visée et à
+// une date d'expiration :
+//   "bindkey-login-v1" 0 challenge 0 session_id 0 SN 0 expiration (secondes Unix, u64 BE)
+// Le client et la clé, qui connaissent tous deux la clé publique de l'organisation,
+// vérifient ce message avant qu'un doigt soit demandé ; la clé signe ensuite ce même
+// message (commande SignedChallenge).
+
+pub const LOGIN_CHALLENGE_CONTEXT: &str = "bindkey-login-v1";
+// Ordre de remise à zéro d'usine donné par un administrateur (FactoryWipeAuthorized)
+pub const WIPE_CHALLENGE_CONTEXT: &str = "bindkey-wipe-v1";
+// Tolérance d'horloge entre le serveur et ce poste
+const CLOCK_SKEW_SECS: u64 = 60;
+// Un challenge valable plus longtemps est refusé, même correctement signé
+const MAX_CHALLENGE_LIFETIME_SECS: u64 = 300;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerChallenge {
+    pub context: String,
+    pub challenge: String,
+    pub session_id: String,
+    pub sn: String,
+    pub expires_at: u64,
+    // Signature de l'organisation, hexadécimal
+    pub signature: String,
+}
+
+pub fn login_message(
+    context: &str,
+    challenge: &str,
+    session_id: &str,
+    sn: &str,
+    expires_at: u64,
+) -> Vec<u8> {
+    let mut message = Vec::new();
+    for field in [context, challenge, session_id, sn] {
+        message.extend_from_slice(field.as_bytes());
+        message.push(0);
+    }
+    message.extend_from_slice(&expires_at.to_be_bytes());
+    message
+}
+
+pub fn verifying_key(public_key_hex: &str) -> Result<VerifyingKey, String> {
+    hex::decode(public_key_hex.trim())
+        .ok()
+        .and_then(|bytes| VerifyingKey::from_sec1_bytes(&bytes).ok())
+        .ok_or_else(|| "Clé publique de l'organisation illisible".to_string())
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl ServerChallenge {
+    pub fn signed_message(&self) -> Vec<u8> {
+        login_message(
+            &self.context,
+            &self.challenge,
+            &self.session_id,
+            &self.sn,
+            self.expires_at,
+        )
+    }
+
+    // `sn` : la BindKey qui va signer ; `now` : secondes Unix
+    pub fn verify(
+        &self,
+        organization_key: &VerifyingKey,
+        sn: &str,
+        now: u64,
+    ) -> Result<(), String> {
+        self.verify_for(LOGIN_CHALLENGE_CONTEXT, organization_key, sn, now)
+    }
+
+    // Même vérification pour un autre usage (ex. WIPE_CHALLENGE_CONTEXT)
+    pub fn verify_for(
+        &self,
+        expected_context: &str,
+        organization_key: &VerifyingKey,
+        sn: &str,
+        now: u64,
+    ) -> Result<(), String> {
+        if self.context != expected_context {
+            return Err(format!(
+                "Challenge destiné à un autre usage ({}) : refusé",
+                self.context
+            ));
+        }
+        if self.sn != sn {
+            return Err(format!(
+                "Challenge émis pour la BindKey {} et non {} : refusé",
+                self.sn, sn
+            ));
+        }
+        // Un champ contenant NUL rendrait le découpage du message ambigu
+        if [&self.challenge, &self.session_id, &self.sn]
+            .iter()
+            .any(|field| field.contains('\0'))
+        {
+            return Err("Challenge mal formé : refusé".to_string());
+        }
+
+        let signature = hex::decode(&self.signature)
+            .ok()
+            .and_then(|bytes| Signature::from_slice(&bytes).ok())
+            .ok_or("Challenge non signé par le serveur : connexion refusée")?;
+        organization_key
+            .verify(&self.signed_message(), &signature)
+            .map_err(|_| {
+                "Signature du challenge invalide : serveur non reconnu par l'organisation"
+                    .to_string()
+            })?;
+
+        if self.expires_at + CLOCK_SKEW_SECS < now {
+            return Err("Challenge expiré : relancez la connexion".to_string());
+        }
+        if self.expires_at > now + MAX_CHALLENGE_LIFETIME_SECS + CLOCK_SKEW_SECS {
+            return Err("Challenge à durée de validité excessive : refusé".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p256::ecdsa::SigningKey;
+    use p256::ecdsa::signature::Signer;
+    use rand_core::OsRng;
+
+    fn signed(key: &SigningKey, expires_at: u64) -> ServerChallenge {
+        let mut challenge = ServerChallenge {
+            context: LOGIN_CHALLENGE_CONTEXT.to_string(),
+            challenge: "nonce-42".to_string(),
+            session_id: "123e4567-e89b-12d3-a456-426614174000".to_string(),
+            sn: "BK-0001".to_string(),
+            expires_at,
+            signature: String::new(),
+        };
+        let signature: Signature = key.sign(&challenge.signed_message());
+        challenge.signature = hex::encode(signature.to_bytes());
+        challenge
+    }
+
+    #[test]
+    fn only_fresh_challenges_signed_by_the_organization_pass() {
+        let organization = SigningKey::random(&mut OsRng);
+        let pinned = *organization.verifying_key();
+        let now = 1_700_000_000;
+
+        let good = signed(&organization, now + 120);
+        assert_eq!(good.verify(&pinned, "BK-0001", now), Ok(()));
+        assert!(good.verify(&pinned, "BK-0002", now).is_err());
+        assert!(good.verify(&pinned, "BK-0001", now + 1_000).is_err());
+
+        let mut tampered = good.clone();
+        tampered.challenge = "nonce-43".to_string();
+        assert!(tampered.verify(&pinned, "BK-0001", now).is_err());
+
+        assert!(
+            good.verify_for(WIPE_CHALLENGE_CONTEXT, &pinned, "BK-0001", now)
+                .is_err()
+        );
+
+        let impostor = signed(&SigningKey::random(&mut OsRng), now + 120);
+        assert!(impostor.verify(&pinned, "BK-0001", now).is_err());
+        assert!(
+            signed(&organization, now + 86_400)
+                .verify(&pinned, "BK-0001", now)
+                .is_err()
+        );
+    }
+}
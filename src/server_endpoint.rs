@@ -0,0 +1,361 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use reqwest::{Certificate, Client};
+use rustls::client::WebPkiServerVerifier;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{CryptoProvider, verify_tls12_signature, verify_tls13_signature};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, RootCertStore, SignatureScheme};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Read;
+use std::net::{IpAddr, SocketAddr};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// ==========================================
+// SERVEUR : ADRESSE, CONFIANCE TLS, DÉCOUVERTE
+// ==========================================
+//
+// Tout vient de la section [server] de bindkey_config.toml :
+//   - host / port : nom attendu dans le certificat et port de l'API ;
+//   - address : adresse IP imposée pour `host` (remplace server_ip.txt) ;
+//   - discovery : à défaut d'adresse, recherche du serveur sur le réseau local (DNS-SD, avahi) ;
+//   - ca_bundle et/ou spki_pins : la confiance TLS, lue au démarrage.
+// Sans élément de confiance, l'application refuse de démarrer : aucun repli sur les
+// autorités du système.
+
+pub const DISCOVERY_SERVICE: &str = "_bindkey._tcp";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// La découverte a lieu au démarrage, avant la première image : elle ne doit pas figer l'interface
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    pub address: Option<IpAddr>,
+    pub discovery: bool,
+    // Fichier PEM des autorités acceptées (une ou plusieurs) ; remplace celles du système
+    pub ca_bundle: Option<String>,
+    // Empreintes SHA-256 de la clé publique (SPKI) du serveur, "sha256/<base64>" ou hexadécimal.
+    // Plusieurs empreintes permettent de préparer un changement de clé.
+    pub spki_pins: Vec<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            host: "api.bindkey.local".to_string(),
+            port: 31278,
+            address: None,
+            discovery: false,
+            ca_bundle: None,
+            spki_pins: Vec::new(),
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn api_url(&self) -> String {
+        format!("https://{}:{}", self.host, self.port)
+    }
+}
+
+pub fn create_secure_client(server: &ServerConfig) -> Result<Client, String> {
+    let mut builder = Client::builder().timeout(REQUEST_TIMEOUT);
+
+    if server.spki_pins.is_empty() {
+        let path = server.ca_bundle.as_ref().ok_or(
+            "Aucun élément de confiance TLS : renseignez ca_bundle ou spki_pins dans la section [server] de bindkey_config.toml",
+        )?;
+        let pem = fs::read(path).map_err(|e| format!("Bundle CA {} illisible : {}", path, e))?;
+        let certs = Certificate::from_pem_bundle(&pem)
+            .map_err(|e| format!("Bundle CA {} invalide : {}", path, e))?;
+        if certs.is_empty() {
+            return Err(format!("Bundle CA {} : aucun certificat", path));
+        }
+        builder = builder.tls_built_in_root_certs(false);
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    } else {
+        builder = builder.use_preconfigured_tls(pinned_tls_config(server)?);
+    }
+
+    if let Some(addr) = resolve_address(server) {
+        builder = builder.resolve(&server.host, addr);
+    }
+
+    builder
+        .build()
+        .map_err(|e| format!("Erreur construction client Reqwest : {}", e))
+}
+
+// Client de substitution quand la configuration serveur est invalide : il ne reconnaît
+// aucune autorité et ne joint donc aucun serveur (l'écran d'erreur bloque l'application)
+pub fn untrusted_client() -> Client {
+    Client::builder()
+        .https_only(true)
+        .tls_built_in_root_certs(false)
+        .build()
+        .unwrap_or_default()
+}
+
+// Adresse de `host` : imposée, découverte sur le réseau local, ou laissée au DNS du système
+fn resolve_address(server: &ServerConfig) -> Option<SocketAddr> {
+    if let Some(ip) = server.address {
+        return Some(SocketAddr::new(ip, server.port));
+    }
+    if !server.discovery {
+        return None;
+    }
+    match discover(&server.host) {
+        Ok(addr) => {
+            println!("[SERVEUR] {} découvert en {}", server.host, addr);
+            if addr.port() != server.port {
+                eprintln!(
+                    "[SERVEUR] Port annoncé {} ignoré : le port configuré ({}) est utilisé",
+                    addr.port(),
+                    server.port
+                );
+            }
+            Some(addr)
+        }
+        Err(e) => {
+            eprintln!(
+                "[SERVEUR] Découverte DNS-SD sans résultat ({}), résolution DNS classique",
+                e
+            );
+            None
+        }
+    }
+}
+
+fn discover(host: &str) -> Result<SocketAddr, String> {
+    let mut child = Command::new("avahi-browse")
+        .args(["--resolve", "--parsable", "--terminate", DISCOVERY_SERVICE])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("avahi-browse introuvable : {}", e))?;
+
+    // Sortie lue à part : un tube plein bloquerait avahi-browse jusqu'au délai
+    let mut stdout = child
+        .stdout
+        .take()
+        .ok_or("sortie d'avahi-browse indisponible")?;
+    let reader = thread::spawn(move || {
+        let mut output = String::new();
+        let _ = stdout.read_to_string(&mut output);
+        output
+    });
+
+    let deadline = Instant::now() + DISCOVERY_TIMEOUT;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(50)),
+            Ok(None) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!(
+                    "pas de réponse d'avahi-browse en {} s",
+                    DISCOVERY_TIMEOUT.as_secs()
+                ));
+            }
+            Err(e) => return Err(format!("avahi-browse : {}", e)),
+        }
+    };
+    if !status.success() {
+        let mut stderr = String::new();
+        if let Some(mut pipe) = child.stderr.take() {
+            let _ = pipe.read_to_string(&mut stderr);
+        }
+        return Err(stderr.trim().to_string());
+    }
+    let output = reader.join().unwrap_or_default();
+    parse_discovery(&output, host)
+        .ok_or_else(|| format!("{} n'annonce pas {}", host, DISCOVERY_SERVICE))
+}
+
+// Lignes résolues d'avahi-browse -p : =;interface;protocole;nom;type;domaine;hôte;adresse;port;txt
+fn parse_discovery(output: &str, host: &str) -> Option<SocketAddr> {
+    let mut found: Vec<SocketAddr> = output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(';').collect();
+            if fields.len() < 9 || fields[0] != "=" || !fields[6].eq_ignore_ascii_case(host) {
+                return None;
+            }
+            let ip = fields[7].parse::<IpAddr>().ok()?;
+            let port = fields[8].parse::<u16>().ok()?;
+            Some(SocketAddr::new(ip, port))
+        })
+        .collect();
+    // IPv4 d'abord : une adresse IPv6 lien-local n'est pas joignable sans son interface
+    found.sort_by_key(|addr| addr.is_ipv6());
+    found.into_iter().next()
+}
+
+// "sha256/<base64>" (format curl) ou 64 caractères hexadécimaux
+pub fn parse_pin(pin: &str) -> Result<[u8; 32], String> {
+    let pin = pin.trim();
+    let bytes = match pin.strip_prefix("sha256/") {
+        Some(encoded) => STANDARD
+            .decode(encoded)
+            .map_err(|e| format!("Empreinte SPKI invalide '{}' : {}", pin, e))?,
+        None => {
+            hex::decode(pin).map_err(|e| format!("Empreinte SPKI invalide '{}' : {}", pin, e))?
+        }
+    };
+    bytes
+        .try_into()
+        .map_err(|_| format!("Empreinte SPKI invalide '{}' : 32 octets attendus", pin))
+}
+
+// Client rustls : la clé publique du serveur doit figurer parmi les empreintes. Avec un
+// bundle CA en plus, la chaîne et le nom sont aussi vérifiés ; sans, l'empreinte suffit.
+fn pinned_tls_config(server: &ServerConfig) -> Result<rustls::ClientConfig, String> {
+    let pins = server
+        .spki_pins
+        .iter()
+        .map(|pin| parse_pin(pin))
+        .collect::<Result<Vec<_>, _>>()?;
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+
+    let chain = match &server.ca_bundle {
+        None => None,
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            let certs = CertificateDer::pem_file_iter(path)
+                .map_err(|e| format!("Bundle CA {} illisible : {}", path, e))?;
+            for cert in certs {
+                let cert = cert.map_err(|e| format!("Bundle CA {} invalide : {}", path, e))?;
+                roots
+                    .add(cert)
+                    .map_err(|e| format!("Bundle CA {} invalide : {}", path, e))?;
+            }
+            let verifier =
+                WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+                    .build()
+                    .map_err(|e| format!("Bundle CA {} inutilisable : {}", path, e))?;
+            Some(verifier)
+        }
+    };
+
+    let verifier = PinnedVerifier {
+        pins,
+        chain,
+        provider: provider.clone(),
+    };
+    let config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| format!("Configuration TLS invalide : {}", e))?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+    Ok(config)
+}
+
+#[derive(Debug)]
+struct PinnedVerifier {
+    pins: Vec<[u8; 32]>,
+    chain: Option<Arc<WebPkiServerVerifier>>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if let Some(chain) = &self.chain {
+            chain.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)?;
+        }
+        let cert = webpki::EndEntityCert::try_from(end_entity).map_err(|e| {
+            rustls::Error::General(format!("certificat du serveur illisible : {:?}", e))
+        })?;
+        let spki: [u8; 32] = Sha256::digest(cert.subject_public_key_info().as_ref()).into();
+        if self.pins.contains(&spki) {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General(format!(
+                "clé publique du serveur non épinglée (sha256/{})",
+                STANDARD.encode(spki)
+            )))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pins_accept_base64_and_hex() {
+        let hex_pin = "ab".repeat(32);
+        let b64_pin = format!("sha256/{}", STANDARD.encode([0xab; 32]));
+        assert_eq!(parse_pin(&hex_pin), Ok([0xab; 32]));
+        assert_eq!(parse_pin(&b64_pin), Ok([0xab; 32]));
+        assert!(parse_pin("sha256/AAAA").is_err());
+        assert!(parse_pin("zz").is_err());
+    }
+
+    #[test]
+    fn discovery_keeps_the_configured_host_and_prefers_ipv4() {
+        let output = "\
++;eth0;IPv4;BindKey API;_bindkey._tcp;local
+=;eth0;IPv6;BindKey API;_bindkey._tcp;local;api.bindkey.local;fe80::1;31278;
+=;eth0;IPv4;Autre;_bindkey._tcp;local;test.local;10.0.0.9;31278;
+=;eth0;IPv4;BindKey API;_bindkey._tcp;local;api.bindkey.local;10.0.0.5;31278;\"v=1\"";
+        assert_eq!(
+            parse_discovery(output, "api.bindkey.local"),
+            Some("10.0.0.5:31278".parse().unwrap())
+        );
+        assert_eq!(parse_discovery(output, "absent.local"), None);
+    }
+}
@@ -0,0 +1,289 @@
+use crate::protocol::protocol::ApiMessage;
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+// ==========================================
+// SESSION SERVEUR : EXPIRATION, RAFRAÎCHISSEMENT, RÉAUTHENTIFICATION
+// ==========================================
+//
+// Toutes les requêtes authentifiées passent par `ServerSession::send`. Un jeton proche de
+// son expiration (ou refusé par un 401) est d'abord rafraîchi sans rien demander
+// (/sessions/refresh) ; si le serveur refuse, la session passe en réauthentification :
+// l'interface demande le doigt (challenge signé, comme à la connexion) et les requêtes en
+// cours attendent le nouveau jeton au lieu d'échouer. Seule la déconnexion les interrompt.
+
+// Rafraîchit le jeton un peu avant l'expiration annoncée par le serveur
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+// Un même appel ne boucle pas indéfiniment sur des 401
+const MAX_ATTEMPTS: u32 = 3;
+
+// Jetons renvoyés par /sessions/verify, /sessions/test et /sessions/refresh
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SessionTokens {
+    pub server_token: String,
+    // Absent chez un serveur qui ne rafraîchit pas : réauthentification à l'expiration
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+    // Durée de validité de server_token, en secondes ; absente : valable jusqu'au premier 401
+    #[serde(default)]
+    pub expires_in: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    LoggedOut,
+    Active,
+    Refreshing,
+    // Le serveur exige une nouvelle preuve biométrique
+    Reauthenticating,
+}
+
+#[derive(Debug, Clone)]
+struct SessionState {
+    phase: Phase,
+    token: String,
+    refresh_token: Option<String>,
+    expires_at: Option<Instant>,
+}
+
+impl SessionState {
+    fn active(tokens: SessionTokens) -> Self {
+        SessionState {
+            phase: Phase::Active,
+            token: tokens.server_token,
+            refresh_token: tokens.refresh_token,
+            expires_at: tokens
+                .expires_in
+                .map(|secs| Instant::now() + Duration::from_secs(secs)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SessionError {
+    Http(reqwest::Error),
+    // Déconnexion pendant l'attente d'un jeton
+    Closed,
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::Http(e) => write!(f, "{}", e),
+            SessionError::Closed => write!(f, "Session fermée : reconnectez-vous"),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ServerSession {
+    state: Arc<watch::Sender<SessionState>>,
+    client: Client,
+    api_url: String,
+    sender: Sender<ApiMessage>,
+}
+
+impl ServerSession {
+    pub fn new(client: Client, api_url: String, sender: Sender<ApiMessage>) -> Self {
+        let (state, _) = watch::channel(SessionState {
+            phase: Phase::LoggedOut,
+            token: String::new(),
+            refresh_token: None,
+            expires_at: None,
+        });
+        ServerSession {
+            state: Arc::new(state),
+            client,
+            api_url,
+            sender,
+        }
+    }
+
+    // Connexion ou réauthentification réussie : les requêtes en attente reprennent
+    pub fn begin(&self, tokens: SessionTokens) {
+        self.state.send_replace(SessionState::active(tokens));
+    }
+
+    // Déconnexion : les requêtes en attente échouent avec SessionError::Closed
+    pub fn end(&self) {
+        self.state.send_replace(SessionState {
+            phase: Phase::LoggedOut,
+            token: String::new(),
+            refresh_token: None,
+            expires_at: None,
+        });
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.state.borrow().phase != Phase::LoggedOut
+    }
+
+    pub fn reauth_required(&self) -> bool {
+        self.state.borrow().phase == Phase::Reauthenticating
+    }
+
+    // Jeton courant, même expiré (déconnexion, demande de réauthentification)
+    pub fn token(&self) -> String {
+        self.state.borrow().token.clone()
+    }
+
+    // À révoquer avec le jeton à la déconnexion : sinon il permettrait d'en obtenir un nouveau
+    pub fn refresh_token(&self) -> Option<String> {
+        self.state.borrow().refresh_token.clone()
+    }
+
+    // `build` reçoit le jeton à présenter ; il est rappelé si la requête doit être rejouée
+    pub async fn send(
+        &self,
+        build: impl Fn(&str) -> RequestBuilder,
+    ) -> Result<Response, SessionError> {
+        let mut attempts = 0;
+        loop {
+            let token = self.valid_token().await?;
+            let response = build(&token).send().await.map_err(SessionError::Http)?;
+            attempts += 1;
+            if response.status() != StatusCode::UNAUTHORIZED || attempts >= MAX_ATTEMPTS {
+                return Ok(response);
+            }
+            println!("[SESSION] Jeton refusé (401) : renouvellement avant de rejouer la requête");
+            self.renew(&token).await;
+        }
+    }
+
+    // Attend un jeton utilisable : pendant un rafraîchissement ou une réauthentification,
+    // la requête est suspendue, pas annulée
+    async fn valid_token(&self) -> Result<String, SessionError> {
+        let mut watcher = self.state.subscribe();
+        loop {
+            let expiring = {
+                let state = watcher.borrow_and_update();
+                match state.phase {
+                    Phase::LoggedOut => return Err(SessionError::Closed),
+                    Phase::Active => {
+                        let expiring = state
+                            .expires_at
+                            .is_some_and(|at| at <= Instant::now() + REFRESH_MARGIN);
+                        if !expiring {
+                            return Ok(state.token.clone());
+                        }
+                        Some(state.token.clone())
+                    }
+                    Phase::Refreshing | Phase::Reauthenticating => None,
+                }
+            };
+            match expiring {
+                Some(token) => self.renew(&token).await,
+                None => watcher.changed().await.map_err(|_| SessionError::Closed)?,
+            }
+        }
+    }
+
+    // Un seul renouvellement à la fois : les autres requêtes attendent son issue
+    async fn renew(&self, stale_token: &str) {
+        let claimed = self.state.send_if_modified(|state| {
+            if state.phase == Phase::Active && state.token == stale_token {
+                state.phase = Phase::Refreshing;
+                true
+            } else {
+                false
+            }
+        });
+        if !claimed {
+            return;
+        }
+
+        let refresh_token = self.state.borrow().refresh_token.clone();
+        if let Some(refresh_token) = refresh_token {
+            match self.refresh(&refresh_token).await {
+                Ok(tokens) => {
+                    println!("[SESSION] Jeton rafraîchi");
+                    // Une déconnexion pendant le rafraîchissement l'emporte
+                    self.state.send_if_modified(|state| {
+                        let current = state.phase == Phase::Refreshing;
+                        if current {
+                            *state = SessionState::active(tokens);
+                        }
+                        current
+                    });
+                    return;
+                }
+                Err(e) => println!("[SESSION] Rafraîchissement impossible : {}", e),
+            }
+        }
+
+        let escalated = self.state.send_if_modified(|state| {
+            if state.phase == Phase::Refreshing {
+                state.phase = Phase::Reauthenticating;
+                true
+            } else {
+                false
+            }
+        });
+        if escalated {
+            let _ = self.sender.send(ApiMessage::ReauthRequired);
+        }
+    }
+
+    async fn refresh(&self, refresh_token: &str) -> Result<SessionTokens, String> {
+        let response = self
+            .client
+            .post(format!("{}/sessions/refresh", self.api_url))
+            .json(&serde_json::json!({ "refresh_token": refresh_token }))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("refusé par le serveur ({})", response.status()));
+        }
+        response
+            .json::<SessionTokens>()
+            .await
+            .map_err(|e| format!("réponse illisible : {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn requests_wait_for_reauthentication_then_resume() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let session = ServerSession::new(Client::new(), "http://127.0.0.1:9".to_string(), sender);
+        // Jeton déjà expiré et pas de refresh_token : seule une nouvelle preuve le remplace
+        session.begin(SessionTokens {
+            server_token: "ancien".to_string(),
+            refresh_token: None,
+            expires_in: Some(0),
+        });
+
+        let waiting = tokio::spawn({
+            let session = session.clone();
+            async move { session.valid_token().await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(session.reauth_required());
+        assert!(matches!(
+            receiver.try_recv(),
+            Ok(ApiMessage::ReauthRequired)
+        ));
+        assert!(!waiting.is_finished());
+
+        session.begin(SessionTokens {
+            server_token: "nouveau".to_string(),
+            ..Default::default()
+        });
+        assert_eq!(waiting.await.unwrap().unwrap(), "nouveau");
+
+        session.end();
+        assert!(matches!(
+            session.valid_token().await,
+            Err(SessionError::Closed)
+        ));
+    }
+}
@@ -0,0 +1,87 @@
+use p256::ecdsa::signature::Verifier;
+use p256::ecdsa::{Signature, VerifyingKey};
+
+// ==========================================
+// SIGNATURE DES MÉTADONNÉES D'UN PARTAGE
+// ==========================================
+//
+// En chiffrant un partage (WrapShare), la BindKey source signe avec sa clé de signature :
+//   "bindkey-share-meta-v2" 0 volume_id 0 SN source 0 PUB_ECDH source (hex) 0 SN cible 0
+//   WRAPPED (hex) 0 slot (u16 BE)
+// Le destinataire vérifie cette signature avec la clé publique de signature enregistrée
+// pour la BindKey source avant de confier WRAPPED à sa propre clé : un serveur ne peut
+// ni fabriquer un partage, ni détourner celui d'une autre BindKey ou d'un autre slot, ni
+// substituer la clé ECDH avec laquelle la clé cible déchiffre WRAPPED.
+
+pub const SHARE_SIGNING_CONTEXT: &str = "bindkey-share-meta-v2";
+
+pub fn signed_message(
+    volume_id: &str,
+    source_sn: &str,
+    source_pubkey_ecdh: &str,
+    target_sn: &str,
+    slot: u16,
+    wrapped: &str,
+) -> Vec<u8> {
+    let mut message = Vec::new();
+    for field in [
+        SHARE_SIGNING_CONTEXT,
+        volume_id,
+        source_sn,
+        source_pubkey_ecdh,
+        target_sn,
+        wrapped,
+    ] {
+        message.extend_from_slice(field.as_bytes());
+        message.push(0);
+    }
+    message.extend_from_slice(&slot.to_be_bytes());
+    message
+}
+
+// `source_pubkey` : clé publique de signature de la BindKey source (SEC1, hexadécimal)
+pub fn verify(message: &[u8], signature: &str, source_pubkey: &str) -> Result<(), String> {
+    let key = hex::decode(source_pubkey)
+        .ok()
+        .and_then(|bytes| VerifyingKey::from_sec1_bytes(&bytes).ok())
+        .ok_or("Clé de signature de l'émetteur illisible")?;
+    let signature = hex::decode(signature)
+        .ok()
+        .and_then(|bytes| Signature::from_slice(&bytes).ok())
+        .ok_or("Signature du partage illisible")?;
+    key.verify(message, &signature)
+        .map_err(|_| "Signature du partage invalide : émetteur non authentifié".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p256::ecdsa::SigningKey;
+    use p256::ecdsa::signature::Signer;
+    use rand_core::OsRng;
+
+    #[test]
+    fn signature_covers_every_share_field() {
+        let source = SigningKey::random(&mut OsRng);
+        let source_pubkey = hex::encode(source.verifying_key().to_encoded_point(false).as_bytes());
+        let message = signed_message("vol-1", "BK-A", "04aa", "BK-B", 3, "c0ffee");
+        let signature: Signature = source.sign(&message);
+        let signature = hex::encode(signature.to_bytes());
+
+        assert_eq!(verify(&message, &signature, &source_pubkey), Ok(()));
+        for altered in [
+            signed_message("vol-2", "BK-A", "04aa", "BK-B", 3, "c0ffee"),
+            signed_message("vol-1", "BK-C", "04aa", "BK-B", 3, "c0ffee"),
+            signed_message("vol-1", "BK-A", "04ab", "BK-B", 3, "c0ffee"),
+            signed_message("vol-1", "BK-A", "04aa", "BK-C", 3, "c0ffee"),
+            signed_message("vol-1", "BK-A", "04aa", "BK-B", 4, "c0ffee"),
+            signed_message("vol-1", "BK-A", "04aa", "BK-B", 3, "c0ffef"),
+        ] {
+            assert!(verify(&altered, &signature, &source_pubkey).is_err());
+        }
+
+        let other = SigningKey::random(&mut OsRng);
+        let other_pubkey = hex::encode(other.verifying_key().to_encoded_point(false).as_bytes());
+        assert!(verify(&message, &signature, &other_pubkey).is_err());
+    }
+}
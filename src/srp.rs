@@ -0,0 +1,369 @@
+use hmac::{Hmac, Mac};
+use num_bigint::BigUint;
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, OpenOptions};
+use std::io::ErrorKind;
+use std::io::Write;
+use std::path::PathBuf;
+
+// ==========================================
+// AUTHENTIFICATION PAR MOT DE PASSE (SRP-6a)
+// ==========================================
+//
+// SRP-6a (RFC 5054) sur le groupe 2048 bits, avec SHA-256. Le serveur ne stocke qu'un
+// vérificateur v = g^x et le mot de passe ne transite jamais, même haché : une écoute ou
+// un rejeu de l'échange ne permet pas de deviner le mot de passe hors ligne.
+//
+//   x  = H(sel | H(identité ":" PBKDF2-SHA256(mot de passe, sel, itérations)))
+//   k  = H(N | PAD(g))            u = H(PAD(A) | PAD(B))
+//   S  = (B - k·g^x)^(a + u·x)    K = H(PAD(S))
+//   M1 = H(H(N) xor H(g) | H(identité) | sel | PAD(A) | PAD(B) | K)
+//   M2 = H(PAD(A) | M1 | K)
+//
+// PAD complète à la taille de N (256 octets), grand-boutiste. L'identité est l'email,
+// sans espaces et en minuscules. Le client vérifie M2 : un faux serveur qui ne connaît
+// pas le vérificateur ne peut pas terminer l'échange.
+
+// Groupe 2048 bits de la RFC 5054 (annexe A), générateur 2
+const N_HEX: &str = "AC6BDB41324A9A9BF166DE5E1389582FAF72B6651987EE07FC3192943DB56050A37329CBB4A099ED8193E0757767A13DD52312AB4B03310DCD7F48A9DA04FD50E8083969EDB767B0CF6095179A163AB3661A05FBD5FAAAE82918A9962F0B93B855F97993EC975EEAA80D740ADBF4FF747359D041D5C33EA71D281E446B14773BCA97B43A23FB801676BD207A436C6481F1D2B9078717461A5B9D32E688F87748544523B524B0D57D5EA77A2775D2ECFA032CFBDBF52FB3786160279004E57AE6AF874E7303CE53299CCC041C7BC308D82A5698F3A8D0C38271AE35F8E9DBFBB694B5C803D89F7AE435DE236D525F54759B65E372FCD68EF20FA7111F9E4AFF73";
+const GENERATOR: u32 = 2;
+const GROUP_BYTES: usize = 256;
+
+// Coût de dérivation imposé aux nouveaux comptes ; en dessous, le client refuse l'échange
+pub const KDF_ITERATIONS: u32 = 100_000;
+const SALT_BYTES: usize = 16;
+const SECRET_BYTES: usize = 32;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Ce que le serveur stocke à l'enregistrement (et à la migration d'un ancien compte)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SrpVerifier {
+    pub srp_salt: String,
+    pub srp_verifier: String,
+    pub kdf_iterations: u32,
+}
+
+// Réponse du client à /sessions/srp/start ; `expected_server_proof` reste côté client
+#[derive(Debug, Clone)]
+pub struct ClientProof {
+    pub client_public: String,
+    pub client_proof: String,
+    expected_server_proof: Vec<u8>,
+}
+
+impl ClientProof {
+    // Le serveur prouve à son tour qu'il détient le vérificateur du compte
+    pub fn verify_server(&self, server_proof: Option<&str>) -> Result<(), String> {
+        let received = server_proof
+            .and_then(|proof| hex::decode(proof).ok())
+            .ok_or("Le serveur n'a pas fourni de preuve SRP")?;
+        if !constant_time_eq(&received, &self.expected_server_proof) {
+            return Err("Preuve SRP du serveur invalide : serveur non authentique".to_string());
+        }
+        Ok(())
+    }
+}
+
+pub fn identity(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+pub fn new_verifier(email: &str, password: &str) -> SrpVerifier {
+    let mut salt = [0u8; SALT_BYTES];
+    OsRng.fill_bytes(&mut salt);
+    let x = private_key(&identity(email), password, &salt, KDF_ITERATIONS);
+    SrpVerifier {
+        srp_salt: hex::encode(salt),
+        srp_verifier: hex::encode(pad(&generator().modpow(&x, &modulus()))),
+        kdf_iterations: KDF_ITERATIONS,
+    }
+}
+
+// Bloquant (PBKDF2) : à appeler depuis spawn_blocking
+pub fn client_proof(
+    email: &str,
+    password: &str,
+    salt_hex: &str,
+    kdf_iterations: u32,
+    server_public_hex: &str,
+) -> Result<ClientProof, String> {
+    if kdf_iterations < KDF_ITERATIONS {
+        return Err(format!(
+            "Paramètres SRP refusés : {} itérations (minimum {})",
+            kdf_iterations, KDF_ITERATIONS
+        ));
+    }
+    let salt = hex::decode(salt_hex).map_err(|_| "Sel SRP illisible".to_string())?;
+    let server_public = hex::decode(server_public_hex)
+        .map(|bytes| BigUint::from_bytes_be(&bytes))
+        .map_err(|_| "Valeur publique SRP du serveur illisible".to_string())?;
+
+    let mut secret = [0u8; SECRET_BYTES];
+    OsRng.fill_bytes(&mut secret);
+    let identity = identity(email);
+    let x = private_key(&identity, password, &salt, kdf_iterations);
+    finish_exchange(
+        &identity,
+        &salt,
+        &x,
+        &BigUint::from_bytes_be(&secret),
+        &server_public,
+    )
+}
+
+fn finish_exchange(
+    identity: &str,
+    salt: &[u8],
+    x: &BigUint,
+    a: &BigUint,
+    server_public: &BigUint,
+) -> Result<ClientProof, String> {
+    let n = modulus();
+    let g = generator();
+    // B ≡ 0 mod N rendrait la clé de session prévisible
+    if (server_public % &n).bits() == 0 {
+        return Err("Valeur publique SRP du serveur invalide".to_string());
+    }
+
+    let client_public = g.modpow(a, &n);
+    let u = hash_to_int(&[&pad(&client_public), &pad(server_public)]);
+    if u.bits() == 0 {
+        return Err("Échange SRP invalide (u = 0)".to_string());
+    }
+    let k = hash_to_int(&[&pad(&n), &pad(&g)]);
+
+    let kgx = (k * g.modpow(x, &n)) % &n;
+    let base = (server_public + &n - kgx) % &n;
+    let shared = base.modpow(&(a + &u * x), &n);
+    let session_key = sha256(&[&pad(&shared)]);
+
+    let group_hash: Vec<u8> = sha256(&[&pad(&n)])
+        .iter()
+        .zip(sha256(&[&pad(&g)]).iter())
+        .map(|(a, b)| a ^ b)
+        .collect();
+    let client_proof = sha256(&[
+        &group_hash,
+        &sha256(&[identity.as_bytes()]),
+        salt,
+        &pad(&client_public),
+        &pad(server_public),
+        &session_key,
+    ]);
+    let server_proof = sha256(&[&pad(&client_public), &client_proof, &session_key]);
+
+    Ok(ClientProof {
+        client_public: hex::encode(pad(&client_public)),
+        client_proof: hex::encode(client_proof),
+        expected_server_proof: server_proof,
+    })
+}
+
+fn private_key(identity: &str, password: &str, salt: &[u8], kdf_iterations: u32) -> BigUint {
+    let stretched = pbkdf2_sha256(password.as_bytes(), salt, kdf_iterations);
+    let inner = sha256(&[identity.as_bytes(), b":", &stretched]);
+    hash_to_int(&[salt, &inner])
+}
+
+// PBKDF2-HMAC-SHA256, un seul bloc de 32 octets
+fn pbkdf2_sha256(password: &[u8], salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mac = HmacSha256::new_from_slice(password).expect("HMAC accepte toute taille de clé");
+    let mut first = mac.clone();
+    first.update(salt);
+    first.update(&1u32.to_be_bytes());
+    let mut block = first.finalize().into_bytes();
+    let mut output: [u8; 32] = block.into();
+
+    for _ in 1..iterations {
+        let mut next = mac.clone();
+        next.update(&block);
+        block = next.finalize().into_bytes();
+        for (out, byte) in output.iter_mut().zip(block.iter()) {
+            *out ^= byte;
+        }
+    }
+    output
+}
+
+fn modulus() -> BigUint {
+    BigUint::parse_bytes(N_HEX.as_bytes(), 16).expect("groupe SRP valide")
+}
+
+fn generator() -> BigUint {
+    BigUint::from(GENERATOR)
+}
+
+fn pad(value: &BigUint) -> Vec<u8> {
+    let bytes = value.to_bytes_be();
+    let mut padded = vec![0u8; GROUP_BYTES.saturating_sub(bytes.len())];
+    padded.extend_from_slice(&bytes);
+    padded
+}
+
+fn sha256(parts: &[&[u8]]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().to_vec()
+}
+
+fn hash_to_int(parts: &[&[u8]]) -> BigUint {
+    BigUint::from_bytes_be(&sha256(parts))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// ==========================================
+// ANCIEN SCHÉMA (migration uniquement)
+// ==========================================
+
+// SHA-256(mot de passe + "bindkey.com") : envoyé une dernière fois, avec un vérificateur
+// SRP, par un compte que le serveur n'a pas encore migré
+pub fn legacy_password_hash(password: &str) -> String {
+    hex::encode(sha256(&[password.as_bytes(), b"bindkey.com"]))
+}
+
+// Comptes qui ont déjà réussi un échange SRP sur ce poste (SHA-256 de l'identité, un par
+// ligne). Pour eux, une réponse "legacy" de /sessions/srp/start ne peut venir que d'un faux
+// serveur qui cherche à obtenir l'ancien hash et un vérificateur attaquable hors ligne.
+// La liste est dans le dossier de données de l'utilisateur et non dans le dossier courant :
+// lancée depuis un autre dossier, l'application ne doit pas oublier ces comptes.
+pub const SRP_ACCOUNTS_FILE: &str = "srp_accounts";
+
+// $XDG_DATA_HOME/bindkey, ou ~/.local/share/bindkey (spécification XDG)
+fn data_dir() -> Result<PathBuf, String> {
+    let absolute = |var: &str| {
+        std::env::var_os(var)
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+    };
+    let base = match absolute("XDG_DATA_HOME") {
+        Some(dir) => dir,
+        None => absolute("HOME")
+            .map(|home| home.join(".local/share"))
+            .ok_or("Dossier de données introuvable (ni XDG_DATA_HOME ni HOME)")?,
+    };
+    Ok(base.join("bindkey"))
+}
+
+pub struct SrpAccounts {
+    path: PathBuf,
+}
+
+impl SrpAccounts {
+    pub fn local() -> Result<Self, String> {
+        Ok(Self::at(data_dir()?.join(SRP_ACCOUNTS_FILE)))
+    }
+
+    pub fn at(path: impl Into<PathBuf>) -> Self {
+        SrpAccounts { path: path.into() }
+    }
+
+    // Une liste illisible n'est pas une liste vide : l'appelant refuse la connexion
+    pub fn contains(&self, email: &str) -> Result<bool, String> {
+        let entry = account_entry(email);
+        match fs::read_to_string(&self.path) {
+            Ok(content) => Ok(content.lines().any(|line| line.trim() == entry)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(format!("{} : {}", self.path.display(), e)),
+        }
+    }
+
+    pub fn insert(&self, email: &str) -> Result<(), String> {
+        if self.contains(email)? {
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(|e| format!("{} : {}", dir.display(), e))?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| format!("{} : {}", self.path.display(), e))?;
+        writeln!(file, "{}", account_entry(email))
+            .map_err(|e| format!("{} : {}", self.path.display(), e))
+    }
+}
+
+fn account_entry(email: &str) -> String {
+    hex::encode(sha256(&[identity(email).as_bytes()]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_and_server_agree_on_the_session() {
+        let salt = [7u8; SALT_BYTES];
+        let identity = identity(" Jean.Mattei@Entreprise.fr ");
+        // Peu d'itérations : seul l'échange est testé ici
+        let x = private_key(&identity, "correct horse", &salt, 2);
+        let n = modulus();
+        let g = generator();
+        let verifier = g.modpow(&x, &n);
+
+        // Côté serveur : B = k·v + g^b, S = (A·v^u)^b, puis M2 = H(PAD(A) | M1 | K)
+        let b = BigUint::from(0x1234_5678_9abc_u64);
+        let k = hash_to_int(&[&pad(&n), &pad(&g)]);
+        let server_public = (k * &verifier + g.modpow(&b, &n)) % &n;
+        let server_m2 = |proof: &ClientProof| {
+            let a_bytes = hex::decode(&proof.client_public).unwrap();
+            let client_public = BigUint::from_bytes_be(&a_bytes);
+            let u = hash_to_int(&[&a_bytes, &pad(&server_public)]);
+            let shared = (client_public * verifier.modpow(&u, &n)).modpow(&b, &n);
+            let session_key = sha256(&[&pad(&shared)]);
+            let m1 = hex::decode(&proof.client_proof).unwrap();
+            hex::encode(sha256(&[&a_bytes, &m1, &session_key]))
+        };
+        let a = BigUint::from(0xfeed_u32);
+
+        let proof = finish_exchange(&identity, &salt, &x, &a, &server_public).unwrap();
+        assert!(proof.verify_server(Some(&server_m2(&proof))).is_ok());
+        assert!(proof.verify_server(Some(&hex::encode([0u8; 32]))).is_err());
+        assert!(proof.verify_server(None).is_err());
+
+        // Mauvais mot de passe : les deux côtés n'obtiennent pas la même clé de session
+        let wrong_x = private_key(&identity, "tr0ub4dor", &salt, 2);
+        let wrong = finish_exchange(&identity, &salt, &wrong_x, &a, &server_public).unwrap();
+        assert!(wrong.verify_server(Some(&server_m2(&wrong))).is_err());
+
+        assert!(finish_exchange(&identity, &salt, &x, &a, &n).is_err());
+    }
+
+    #[test]
+    fn pbkdf2_matches_reference_vector() {
+        assert_eq!(
+            hex::encode(pbkdf2_sha256(b"password", b"salt", 4096)),
+            "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a"
+        );
+        assert!(client_proof("a@b.fr", "x", "00", 10, "02").is_err());
+    }
+
+    #[test]
+    fn srp_accounts_remember_identities() {
+        let dir = std::env::temp_dir().join(format!("bindkey-srp-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("bindkey").join(SRP_ACCOUNTS_FILE);
+        let accounts = SrpAccounts::at(&path);
+
+        assert_eq!(accounts.contains("jean.mattei@entreprise.fr"), Ok(false));
+        accounts.insert(" Jean.Mattei@Entreprise.fr").unwrap();
+        accounts.insert("jean.mattei@entreprise.fr").unwrap();
+        assert_eq!(accounts.contains("jean.mattei@entreprise.fr"), Ok(true));
+        assert_eq!(accounts.contains("autre@entreprise.fr"), Ok(false));
+        // Seule l'empreinte de l'identité est écrite, une seule fois
+        let content = fs::read_to_string(&path).unwrap();
+        assert_eq!(content.lines().count(), 1);
+        assert!(!content.contains("mattei"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
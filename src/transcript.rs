@@ -0,0 +1,458 @@
+use crate::config::AppConfig;
+use crate::protocol::share_protocol::{
+    DeviceEvent, Frame, MessageType, SuccessData, UsbCommand, UsbResponse,
+};
+use serde::{Deserialize, Serialize};
+use serialport::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, StopBits};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// ==========================================
+// TRANSCRIPTS DU PORT SÉRIE
+// ==========================================
+//
+// Une ligne JSON par échange, horodatée en millisecondes Unix :
+// {"ts_ms":1718000000000,"dir":"tx","body":{"Line":"uid"}}
+// Les secrets (signatures, clés enveloppées, clés publiques, challenges) sont masqués
+// sauf si `transcript_redact = false` dans bindkey_config.toml.
+
+pub const REDACTED: &str = "<masqué>";
+
+// Préfixe de port reconnu par BindKeyDevice::open pour rejouer un transcript
+pub const REPLAY_PORT_PREFIX: &str = "replay:";
+
+// Clés texte dont la valeur ne doit jamais apparaître en clair dans les logs
+const SECRET_TEXT_KEYS: [&str; 10] = [
+    "SIG",
+    "WRAPPED",
+    "challenge",
+    "signed_challenge",
+    "factory_wipe",
+    "share_target_pubkey",
+    "recv_share_source_pubkey",
+    "recv_share_wrapped",
+    "PAYLOAD_KEY",
+    "payload_unwrap",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    // Application -> clé
+    Tx,
+    // Clé -> application
+    Rx,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum TranscriptBody {
+    Line(String),
+    Command { seq: u16, command: UsbCommand },
+    Response { seq: u16, response: UsbResponse },
+    Event { seq: u16, event: DeviceEvent },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TranscriptEntry {
+    pub ts_ms: u64,
+    pub dir: Direction,
+    pub body: TranscriptBody,
+}
+
+struct Recorder {
+    file: File,
+    redact: bool,
+}
+
+static RECORDER: Mutex<Option<Recorder>> = Mutex::new(None);
+
+// Appelé au démarrage : n'enregistre rien tant que `transcript_path` n'est pas configuré
+pub fn init(config: &AppConfig) {
+    let Some(path) = &config.transcript_path else {
+        return;
+    };
+
+    match OpenOptions::new().create(true).append(true).open(path) {
+        Ok(file) => {
+            println!(
+                "[TRANSCRIPT] Enregistrement du trafic série dans {} (masquage : {})",
+                path, config.transcript_redact
+            );
+            if let Ok(mut recorder) = RECORDER.lock() {
+                *recorder = Some(Recorder {
+                    file,
+                    redact: config.transcript_redact,
+                });
+            }
+        }
+        Err(e) => eprintln!("[TRANSCRIPT] Impossible d'ouvrir {} : {}", path, e),
+    }
+}
+
+pub fn record(dir: Direction, body: TranscriptBody) {
+    let Ok(mut guard) = RECORDER.lock() else {
+        return;
+    };
+    let Some(recorder) = guard.as_mut() else {
+        return;
+    };
+
+    let body = if recorder.redact {
+        redact_body(body)
+    } else {
+        body
+    };
+    let entry = TranscriptEntry {
+        ts_ms: now_ms(),
+        dir,
+        body,
+    };
+
+    if let Ok(json) = serde_json::to_string(&entry) {
+        let _ = writeln!(recorder.file, "{}", json);
+        let _ = recorder.file.flush();
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+// ==========================================
+// MASQUAGE
+// ==========================================
+
+fn is_secret_key(key: &str) -> bool {
+    key.starts_with("PUB_") || SECRET_TEXT_KEYS.contains(&key)
+}
+
+// Masque chaque ligne "clé=valeur" sensible (les commandes multi-lignes sont traitées ligne à ligne)
+pub fn redact_text(text: &str) -> String {
+    text.split('\n')
+        .map(|line| match line.split_once('=') {
+            Some((key, _)) if is_secret_key(key.trim()) => format!("{}={}", key, REDACTED),
+            _ => line.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn redact_command(command: &UsbCommand) -> UsbCommand {
+    let mut command = command.clone();
+    match &mut command {
+        UsbCommand::Challenge { challenge } => *challenge = REDACTED.to_string(),
+        UsbCommand::SignedChallenge { challenge, .. }
+        | UsbCommand::FactoryWipeAuthorized { challenge, .. } => *challenge = REDACTED.to_string(),
+        UsbCommand::WrapShare { target_pubkey, .. } => *target_pubkey = REDACTED.to_string(),
+        UsbCommand::InstallShare {
+            source_pubkey,
+            wrapped,
+            ..
+        } => {
+            *source_pubkey = REDACTED.to_string();
+            *wrapped = REDACTED.to_string();
+        }
+        UsbCommand::PayloadUnwrap { wrapped, .. } => *wrapped = REDACTED.to_string(),
+        _ => {}
+    }
+    command
+}
+
+pub fn redact_response(response: &UsbResponse) -> UsbResponse {
+    let mut response = response.clone();
+    if let UsbResponse::Success(data) = &mut response {
+        match data {
+            SuccessData::EnrollmentInfo {
+                pub_sign, pub_ecdh, ..
+            } => {
+                *pub_sign = REDACTED.to_string();
+                *pub_ecdh = REDACTED.to_string();
+            }
+            SuccessData::Signature { signature } => *signature = REDACTED.to_string(),
+            SuccessData::WrappedShare { wrapped, .. } => *wrapped = REDACTED.to_string(),
+            SuccessData::SignedWrappedShare {
+                wrapped, signature, ..
+            } => {
+                *wrapped = REDACTED.to_string();
+                *signature = REDACTED.to_string();
+            }
+            // Même règle que EnrollmentInfo : les clés publiques de la BindKey sont masquées
+            SuccessData::PayloadSignature {
+                signature,
+                pub_sign,
+            } => {
+                *signature = REDACTED.to_string();
+                *pub_sign = REDACTED.to_string();
+            }
+            SuccessData::PayloadKey { key } => *key = REDACTED.to_string(),
+            _ => {}
+        }
+    }
+    response
+}
+
+fn redact_body(body: TranscriptBody) -> TranscriptBody {
+    match body {
+        TranscriptBody::Line(line) => TranscriptBody::Line(redact_text(&line)),
+        TranscriptBody::Command { seq, command } => TranscriptBody::Command {
+            seq,
+            command: redact_command(&command),
+        },
+        TranscriptBody::Response { seq, response } => TranscriptBody::Response {
+            seq,
+            response: redact_response(&response),
+        },
+        event @ TranscriptBody::Event { .. } => event,
+    }
+}
+
+// ==========================================
+// REJEU : un faux port série qui renvoie les réponses enregistrées
+// ==========================================
+//
+// Chaque écriture de l'application consomme la prochaine entrée "tx" du transcript,
+// puis toutes les entrées "rx" qui la suivent deviennent lisibles sur le port.
+
+pub struct ReplayPort {
+    name: String,
+    entries: VecDeque<TranscriptEntry>,
+    pending: VecDeque<u8>,
+    timeout: Duration,
+}
+
+impl ReplayPort {
+    pub fn open(path: &str) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let mut entries = VecDeque::new();
+
+        for (number, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: TranscriptEntry = serde_json::from_str(&line).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("ligne {} du transcript illisible : {}", number + 1, e),
+                )
+            })?;
+            entries.push_back(entry);
+        }
+
+        Ok(Self::from_entries(path, entries))
+    }
+
+    pub fn from_entries(name: &str, entries: VecDeque<TranscriptEntry>) -> Self {
+        ReplayPort {
+            name: format!("{}{}", REPLAY_PORT_PREFIX, name),
+            entries,
+            pending: VecDeque::new(),
+            timeout: Duration::from_millis(500),
+        }
+    }
+
+    fn queue_device_bytes(&mut self, body: &TranscriptBody) {
+        let bytes = match body {
+            TranscriptBody::Line(line) => format!("{}\n", line).into_bytes(),
+            TranscriptBody::Response { seq, response } => {
+                let message_type = match response {
+                    UsbResponse::Success(_) => MessageType::Response,
+                    UsbResponse::Error(_) => MessageType::Error,
+                };
+                Frame::new(message_type, *seq, response)
+                    .map(|f| f.encode())
+                    .unwrap_or_default()
+            }
+            TranscriptBody::Event { seq, event } => Frame::new(MessageType::Event, *seq, event)
+                .map(|f| f.encode())
+                .unwrap_or_default(),
+            TranscriptBody::Command { .. } => Vec::new(),
+        };
+        self.pending.extend(bytes);
+    }
+}
+
+impl io::Read for ReplayPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            if self.entries.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "fin du transcript rejoué",
+                ));
+            }
+            // Comme un vrai port : rien à lire avant la prochaine commande
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "rien à rejouer"));
+        }
+
+        let n = buf.len().min(self.pending.len());
+        for (slot, byte) in buf.iter_mut().zip(self.pending.drain(..n)) {
+            *slot = byte;
+        }
+        Ok(n)
+    }
+}
+
+impl io::Write for ReplayPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Les réponses d'une éventuelle commande précédente non lues sont abandonnées
+        while let Some(entry) = self.entries.pop_front() {
+            if entry.dir == Direction::Tx {
+                println!("[REJEU] Envoi attendu : {:?}", entry.body);
+                break;
+            }
+        }
+        while self.entries.front().map(|e| e.dir) == Some(Direction::Rx) {
+            if let Some(entry) = self.entries.pop_front() {
+                self.queue_device_bytes(&entry.body);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SerialPort for ReplayPort {
+    fn name(&self) -> Option<String> {
+        Some(self.name.clone())
+    }
+    fn baud_rate(&self) -> serialport::Result<u32> {
+        Ok(crate::bindkey_device::BINDKEY_BAUD_RATE)
+    }
+    fn data_bits(&self) -> serialport::Result<DataBits> {
+        Ok(DataBits::Eight)
+    }
+    fn flow_control(&self) -> serialport::Result<FlowControl> {
+        Ok(FlowControl::None)
+    }
+    fn parity(&self) -> serialport::Result<Parity> {
+        Ok(Parity::None)
+    }
+    fn stop_bits(&self) -> serialport::Result<StopBits> {
+        Ok(StopBits::One)
+    }
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+    fn set_baud_rate(&mut self, _: u32) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn set_data_bits(&mut self, _: DataBits) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn set_flow_control(&mut self, _: FlowControl) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn set_parity(&mut self, _: Parity) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn set_stop_bits(&mut self, _: StopBits) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+    fn write_request_to_send(&mut self, _: bool) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn write_data_terminal_ready(&mut self, _: bool) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+        Ok(true)
+    }
+    fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+        Ok(true)
+    }
+    fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+    fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+        Ok(true)
+    }
+    fn bytes_to_read(&self) -> serialport::Result<u32> {
+        Ok(self.pending.len() as u32)
+    }
+    fn bytes_to_write(&self) -> serialport::Result<u32> {
+        Ok(0)
+    }
+    // send_text_command vide le tampon avant chaque envoi : rien n'est encore en attente à ce moment
+    fn clear(&self, _: ClearBuffer) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+        Err(serialport::Error::new(
+            serialport::ErrorKind::Unknown,
+            "un port de rejeu ne peut pas être cloné",
+        ))
+    }
+    fn set_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn clear_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bindkey_device::BindKeyDevice;
+
+    fn entry(dir: Direction, line: &str) -> TranscriptEntry {
+        TranscriptEntry {
+            ts_ms: 0,
+            dir,
+            body: TranscriptBody::Line(line.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_redact_text_masks_secrets_only() {
+        let cmd = "recv_share_slot=3\nrecv_share_source_pubkey=04abcd\nrecv_share_wrapped=ffee\n";
+        let redacted = redact_text(cmd);
+        assert!(redacted.contains("recv_share_slot=3"));
+        assert!(!redacted.contains("04abcd"));
+        assert!(!redacted.contains("ffee"));
+
+        assert_eq!(
+            redact_text("PUB_ECDH=04ff"),
+            format!("PUB_ECDH={}", REDACTED)
+        );
+        assert_eq!(redact_text("SN=BK-1"), "SN=BK-1");
+
+        let response = UsbResponse::Success(SuccessData::PayloadSignature {
+            signature: "3045ab".to_string(),
+            pub_sign: "04cafe".to_string(),
+        });
+        let redacted = format!("{:?}", redact_response(&response));
+        assert!(!redacted.contains("3045ab"));
+        assert!(!redacted.contains("04cafe"));
+    }
+
+    #[test]
+    fn test_replay_text_session() {
+        // Session enregistrée sur un firmware texte : la négociation est refusée
+        let entries = VecDeque::from(vec![
+            entry(Direction::Tx, "proto=framed"),
+            entry(Direction::Rx, "ERR=unknown_command"),
+            entry(Direction::Tx, "uid"),
+            entry(Direction::Rx, "SN=BK-REPLAY"),
+            entry(Direction::Rx, "OK"),
+        ]);
+
+        let port = ReplayPort::from_entries("test", entries);
+        let mut device = BindKeyDevice::from_port(Box::new(port));
+        assert_eq!(device.read_uid(), Ok("BK-REPLAY".to_string()));
+    }
+}
@@ -1,30 +1,123 @@
+use crate::bindkey_device::DeviceError;
+use crate::protocol::share_protocol::{
+    DeviceEvent, FRAME_MAGIC, Frame, MessageType, SuccessData, UsbCommand, UsbResponse,
+};
+use crate::protocol::text_protocol::{self, TextLine, TextReply};
+use crate::transcript::{self, Direction, TranscriptBody};
 use serialport::SerialPort;
-use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{Duration, Instant};
 
+// ==========================================
+// DÉLAIS PAR COMMANDE
+// ==========================================
+
+// Réponse immédiate attendue (lecture d'état)
+const QUICK_TIMEOUT: Duration = Duration::from_secs(5);
+// Écriture en flash ou calcul sur la clé, sans intervention de l'utilisateur
+const STANDARD_TIMEOUT: Duration = Duration::from_secs(15);
+// La clé attend un doigt : l'utilisateur peut mettre du temps à le poser
+const FINGER_TIMEOUT: Duration = Duration::from_secs(60);
+// Enrôlement d'une empreinte : plusieurs poses successives du même doigt
+const FINGER_ENROLL_TIMEOUT: Duration = Duration::from_secs(90);
+// Formatage : la clé efface toutes ses zones de stockage
+const FORMAT_TIMEOUT: Duration = Duration::from_secs(30);
+// Attente de la réponse à la commande interrompue après l'envoi d'un Abort
+const ABORT_GRACE: Duration = Duration::from_secs(2);
+
+pub fn command_timeout(command: &UsbCommand) -> Duration {
+    match command {
+        UsbCommand::Uid
+        | UsbCommand::GetDevice
+        | UsbCommand::Version
+        | UsbCommand::FirmwareVerify
+        | UsbCommand::FingerprintList
+        | UsbCommand::Inventory
+        | UsbCommand::Abort => QUICK_TIMEOUT,
+        UsbCommand::ConfirmModification
+        | UsbCommand::RegisterLbaRange { .. }
+        | UsbCommand::InstallShare { .. }
+        | UsbCommand::DeleteVolume { .. }
+        | UsbCommand::FirmwareBegin { .. }
+        | UsbCommand::FirmwareChunk { .. }
+        | UsbCommand::FirmwareCommit
+        | UsbCommand::FirmwareAbort
+        | UsbCommand::SelfTest
+        | UsbCommand::FingerprintDelete { .. } => STANDARD_TIMEOUT,
+        UsbCommand::InitFormat | UsbCommand::FactoryWipeAuthorized { .. } => FORMAT_TIMEOUT,
+        UsbCommand::Challenge { .. }
+        | UsbCommand::SignedChallenge { .. }
+        | UsbCommand::WrapShare { .. }
+        | UsbCommand::PayloadSign { .. }
+        | UsbCommand::PayloadUnwrap { .. }
+        | UsbCommand::FingerprintVerify
+        | UsbCommand::FactoryWipe => FINGER_TIMEOUT,
+        UsbCommand::Enroll | UsbCommand::FingerprintEnroll { .. } => FINGER_ENROLL_TIMEOUT,
+    }
+}
+
+// Ce qui interrompt l'attente d'une réponse avant le timeout :
+// `detached` passe à true quand la clé est débranchée, `cancel` quand l'utilisateur annule
+pub struct Interrupts<'a> {
+    pub detached: &'a AtomicBool,
+    pub cancel: &'a AtomicBool,
+}
+
+impl Interrupts<'_> {
+    pub fn check(&self) -> Result<(), DeviceError> {
+        if self.detached.load(Ordering::SeqCst) {
+            return Err(DeviceError::Disconnected);
+        }
+        if self.cancel.load(Ordering::SeqCst) {
+            return Err(DeviceError::Cancelled);
+        }
+        Ok(())
+    }
+}
+
+// Les lignes PROMPT= / PROGRESS= / FP_QUALITY= sont remontées à `on_event` au fil de l'eau
+// et n'apparaissent pas dans la réponse. Les lignes hors protocole (démarrage, journaux du
+// firmware) sont ignorées ; une valeur mal échappée arrête l'échange (DeviceError::MalformedLine).
+// Sur Timeout ou Cancelled, la clé travaille peut-être encore : voir `abort_text_command`.
 pub fn send_text_command(
     port: &mut dyn SerialPort,
     command: &str,
-) -> Result<HashMap<String, String>, String> {
+    timeout: Duration,
+    interrupts: &Interrupts,
+    on_event: &mut dyn FnMut(DeviceEvent),
+) -> Result<TextReply, DeviceError> {
     let _ = port.clear(serialport::ClearBuffer::All);
 
     let command_with_newline = format!("{}\n", command);
 
     if let Err(e) = port.write_all(command_with_newline.as_bytes()) {
-        return Err(format!("Erreur écriture: {}", e));
+        return Err(DeviceError::Io(e.to_string()));
     }
     let _ = port.flush();
 
-    println!(">> USB ENVOI : {}", command);
+    println!(">> USB ENVOI : {}", transcript::redact_text(command));
+    transcript::record(Direction::Tx, TranscriptBody::Line(command.to_string()));
 
-    let mut results = HashMap::new();
+    read_text_reply(port, timeout, interrupts, on_event)
+}
+
+fn read_text_reply(
+    port: &mut dyn SerialPort,
+    timeout: Duration,
+    interrupts: &Interrupts,
+    on_event: &mut dyn FnMut(DeviceEvent),
+) -> Result<TextReply, DeviceError> {
+    let mut results = TextReply::default();
     let start = Instant::now();
-    let timeout = Duration::from_secs(60);
 
     let mut buffer: Vec<u8> = Vec::new();
     let mut byte_buf = [0u8; 1];
 
     while start.elapsed() < timeout {
+        // Entre deux lignes seulement : une ligne à moitié lue serait perdue pour l'Abort
+        if buffer.is_empty() {
+            interrupts.check()?;
+        }
         match port.read(&mut byte_buf) {
             Ok(1) => {
                 let c = byte_buf[0];
@@ -32,27 +125,39 @@ pub fn send_text_command(
                     let line = String::from_utf8_lossy(&buffer).trim().to_string();
                     buffer.clear();
 
-                    if line.is_empty() {
+                    if line.trim_matches('\0').is_empty() {
                         continue;
                     }
-                    println!("<< USB REÇU : {}", line);
-
-                    if line == "OK" {
-                        return Ok(results);
-                    } else if line.starts_with("ERR=") {
-                        return Err(format!("Erreur Clé : {}", &line[4..]));
-                    } else if let Some((key, value)) = line.split_once('=') {
-                        let clean_key = key.trim().to_string();
-                        let clean_val = value.trim().trim_matches('\0').to_string();
-
-                        results.insert(clean_key.to_string(), clean_val.to_string());
-
-                        if clean_key == "STATUS" {
-                            if clean_val.starts_with("ERR=") {
-                                return Err(format!("Erreur Clé : {}", &clean_val[4..]));
-                            }
-                            return Ok(results); // On rend la main au programme principal !
+                    println!("<< USB REÇU : {}", transcript::redact_text(&line));
+                    transcript::record(Direction::Rx, TranscriptBody::Line(line.clone()));
+
+                    let (key, raw) = match text_protocol::parse_line(&line)? {
+                        TextLine::Ok => return Ok(results),
+                        TextLine::Error(reason) => return Err(DeviceError::Rejected(reason)),
+                        TextLine::Field { key, raw } => (key, raw),
+                        TextLine::Noise => {
+                            println!("[USB] Ligne hors protocole ignorée");
+                            continue;
                         }
+                    };
+
+                    if DeviceEvent::is_event_key(&key) {
+                        if let Some(event) =
+                            DeviceEvent::from_text_line(&key, &text_protocol::unescape(&raw)?)
+                        {
+                            on_event(event);
+                        }
+                        continue;
+                    }
+
+                    // STATUS termine la réponse des commandes d'écriture (STATUS=OK ou STATUS=ERR=...)
+                    let status = (key == "STATUS").then(|| raw.clone());
+                    results.push(key, raw);
+                    if let Some(status) = status {
+                        if let Some(reason) = status.strip_prefix("ERR=") {
+                            return Err(DeviceError::Rejected(text_protocol::unescape(reason)?));
+                        }
+                        return Ok(results); // On rend la main au programme principal !
                     }
                 } else {
                     buffer.push(c);
@@ -60,9 +165,225 @@ pub fn send_text_command(
             }
             Ok(_) => continue,
             Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => continue,
-            Err(e) => return Err(format!("Erreur I/O: {}", e)),
+            Err(e) => return Err(DeviceError::Io(e.to_string())),
+        }
+    }
+
+    Err(DeviceError::Timeout)
+}
+
+// ==========================================
+// TRANSPORT EN TRAMES (après négociation "proto=framed")
+// ==========================================
+
+pub fn send_framed_command(
+    port: &mut dyn SerialPort,
+    seq: u16,
+    command: &UsbCommand,
+    timeout: Duration,
+    interrupts: &Interrupts,
+    on_event: &mut dyn FnMut(DeviceEvent),
+) -> Result<SuccessData, DeviceError> {
+    let frame = Frame::new(MessageType::Command, seq, command)
+        .map_err(|e| DeviceError::Io(format!("Encodage postcard : {}", e)))?;
+
+    if let Err(e) = port.write_all(&frame.encode()) {
+        return Err(DeviceError::Io(e.to_string()));
+    }
+    let _ = port.flush();
+
+    match command {
+        // Pas de vidage des blocs de firmware dans la console
+        UsbCommand::FirmwareChunk { offset, data } => println!(
+            ">> USB ENVOI [#{}] : FirmwareChunk {{ offset: {}, {} octets }}",
+            seq,
+            offset,
+            data.len()
+        ),
+        _ => println!(
+            ">> USB ENVOI [#{}] : {:?}",
+            seq,
+            transcript::redact_command(command)
+        ),
+    }
+    transcript::record(
+        Direction::Tx,
+        TranscriptBody::Command {
+            seq,
+            command: command.clone(),
+        },
+    );
+
+    read_framed_reply(port, seq, timeout, interrupts, on_event)
+}
+
+fn read_framed_reply(
+    port: &mut dyn SerialPort,
+    seq: u16,
+    timeout: Duration,
+    interrupts: &Interrupts,
+    on_event: &mut dyn FnMut(DeviceEvent),
+) -> Result<SuccessData, DeviceError> {
+    let start = Instant::now();
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut chunk = [0u8; 256];
+
+    while start.elapsed() < timeout {
+        if buffer.is_empty() {
+            interrupts.check()?;
+        }
+        match port.read(&mut chunk) {
+            Ok(0) => continue,
+            Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+            Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => continue,
+            Err(e) => return Err(DeviceError::Io(e.to_string())),
         }
+
+        loop {
+            // Tout ce qui précède l'octet MAGIC (logs de l'ESP32, bruit) est ignoré
+            match buffer.iter().position(|&b| b == FRAME_MAGIC) {
+                Some(0) => {}
+                Some(pos) => {
+                    buffer.drain(..pos);
+                }
+                None => {
+                    buffer.clear();
+                    break;
+                }
+            }
+
+            let (reply, consumed) = match Frame::decode(&buffer) {
+                Ok(Some(decoded)) => decoded,
+                Ok(None) => break,
+                Err(e) => {
+                    // Trame corrompue : on saute l'octet MAGIC et on se resynchronise
+                    println!("<< USB trame rejetée : {}", e);
+                    buffer.drain(..1);
+                    continue;
+                }
+            };
+            buffer.drain(..consumed);
+
+            if reply.seq != seq {
+                println!("<< USB réponse périmée ignorée [#{}]", reply.seq);
+                continue;
+            }
+
+            if reply.message_type == MessageType::Event {
+                match reply.body::<DeviceEvent>() {
+                    Ok(event) => {
+                        println!("<< USB ÉVÉNEMENT [#{}] : {:?}", seq, event);
+                        transcript::record(
+                            Direction::Rx,
+                            TranscriptBody::Event {
+                                seq,
+                                event: event.clone(),
+                            },
+                        );
+                        on_event(event);
+                    }
+                    Err(e) => println!("<< USB événement illisible : {}", e),
+                }
+                continue;
+            }
+
+            return decode_reply(&reply);
+        }
+    }
+
+    Err(DeviceError::Timeout)
+}
+
+// ==========================================
+// ABANDON D'UNE COMMANDE EN COURS
+// ==========================================
+//
+// Après un Timeout ou une annulation, la clé peut encore attendre un doigt ou écrire en flash.
+// Un firmware qui annonce la capacité "abort" interrompt alors la commande en cours et y répond
+// par ERR=cancelled (trame Error du même numéro). S'il avait déjà fini, sa réponse normale
+// arrive à la place, suivie de l'acquittement de l'Abort lui-même, qui est jeté.
+// Renvoie true si la commande interrompue a bien reçu sa réponse : la clé est de nouveau
+// à l'écoute et le port peut servir à la commande suivante.
+
+pub fn abort_text_command(port: &mut dyn SerialPort, detached: &AtomicBool) -> bool {
+    // Pas de vidage du tampon : la réponse de la commande interrompue y est peut-être déjà
+    if port.write_all(b"abort\n").is_err() {
+        return false;
     }
+    let _ = port.flush();
+    println!(">> USB ENVOI : abort");
+    transcript::record(Direction::Tx, TranscriptBody::Line("abort".to_string()));
 
-    Err("Timeout: Pas de OK final".to_string())
+    let interrupts = Interrupts {
+        detached,
+        cancel: &AtomicBool::new(false),
+    };
+    let acknowledged = matches!(
+        read_text_reply(port, ABORT_GRACE, &interrupts, &mut |_| {}),
+        Ok(_) | Err(DeviceError::Rejected(_))
+    );
+    discard_late_replies(port);
+    acknowledged
+}
+
+pub fn abort_framed_command(port: &mut dyn SerialPort, seq: u16, detached: &AtomicBool) -> bool {
+    let Ok(frame) = Frame::new(MessageType::Command, seq, &UsbCommand::Abort) else {
+        return false;
+    };
+    if port.write_all(&frame.encode()).is_err() {
+        return false;
+    }
+    let _ = port.flush();
+    println!(">> USB ENVOI [#{}] : Abort", seq);
+    transcript::record(
+        Direction::Tx,
+        TranscriptBody::Command {
+            seq,
+            command: UsbCommand::Abort,
+        },
+    );
+
+    let interrupts = Interrupts {
+        detached,
+        cancel: &AtomicBool::new(false),
+    };
+    let acknowledged = matches!(
+        read_framed_reply(port, seq, ABORT_GRACE, &interrupts, &mut |_| {}),
+        Ok(_) | Err(DeviceError::Rejected(_))
+    );
+    discard_late_replies(port);
+    acknowledged
+}
+
+fn discard_late_replies(port: &mut dyn SerialPort) {
+    std::thread::sleep(Duration::from_millis(100));
+    let _ = port.clear(serialport::ClearBuffer::Input);
+}
+
+fn decode_reply(reply: &Frame) -> Result<SuccessData, DeviceError> {
+    let response: UsbResponse = reply
+        .body()
+        .map_err(|e| DeviceError::UnexpectedResponse(format!("Décodage postcard : {}", e)))?;
+
+    println!(
+        "<< USB REÇU [#{}] : {:?}",
+        reply.seq,
+        transcript::redact_response(&response)
+    );
+    transcript::record(
+        Direction::Rx,
+        TranscriptBody::Response {
+            seq: reply.seq,
+            response: response.clone(),
+        },
+    );
+
+    match (reply.message_type, response) {
+        (MessageType::Response, UsbResponse::Success(data)) => Ok(data),
+        (_, UsbResponse::Error(reason)) => Err(DeviceError::Rejected(reason)),
+        (other, _) => Err(DeviceError::UnexpectedResponse(format!(
+            "type de trame {:?}",
+            other
+        ))),
+    }
 }